@@ -0,0 +1,157 @@
+//! This module adds the manufacturer, model and ICAO type designator of each aircraft from a
+//! public aircraft database, so that traffic can be broken down by aircraft type.
+//!
+//! Two databases are supported: the OpenSky Network's `aircraftDatabase.csv`, and the
+//! Mictronics database as distributed in `indexedDB.zip` (its `aircrafts.json` and
+//! `types.json`). Either is read into memory whole at startup, sharing each aircraft type
+//! between the aircraft of that type. The `update-aircraft-db` subcommand downloads a fresh
+//! copy, e.g. from cron.
+
+use crate::parse::{Icao24, SBS1Message};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where the OpenSky Network publishes its aircraft database.
+pub const OPENSKY_URL: &str = "https://opensky-network.org/datasets/metadata/aircraftDatabase.csv";
+
+/// Where Mictronics publishes its aircraft database.
+pub const MICTRONICS_URL: &str = "https://www.mictronics.de/aircraft-database/indexedDB.php";
+
+/// An aircraft type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct AircraftType {
+    /// The manufacturer, e.g. `Airbus`.
+    pub manufacturer: Option<String>,
+    /// The model, e.g. `A319-112`.
+    pub model: Option<String>,
+    /// The ICAO type designator, e.g. `A319`.
+    pub type_code: Option<String>,
+}
+
+/// The type of each aircraft in the database.
+pub struct AircraftDb {
+    aircraft: HashMap<Icao24, Arc<AircraftType>>,
+}
+
+impl AircraftDb {
+    /// Reads an aircraft database: a Mictronics `indexedDB.zip` if the path ends in `.zip`,
+    /// and an OpenSky CSV file otherwise.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the database, or an error if the file could not be read.
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut types = HashMap::new();
+        let aircraft = if path.to_lowercase().ends_with(".zip") { read_mictronics(path, &mut types)? } else { read_opensky(path, &mut types)? };
+        println!("Read {} aircraft of {} types from {}", aircraft.len(), types.len(), path);
+        Ok(AircraftDb { aircraft })
+    }
+
+    /// Returns the type of an aircraft, if it is in the database.
+    pub fn lookup(&self, icao24: Icao24) -> Option<&AircraftType> {
+        self.aircraft.get(&icao24).map(Arc::as_ref)
+    }
+
+    /// Adds the manufacturer and model of the aircraft a message is from, and its type
+    /// designator unless the registration database already gave one.
+    pub fn enrich(&self, message: &mut SBS1Message) {
+        let Some(aircraft_type) = message.icao24.and_then(|icao24| self.lookup(icao24)) else {
+            return;
+        };
+        message.manufacturer = aircraft_type.manufacturer.clone();
+        message.model = aircraft_type.model.clone();
+        if message.type_code.is_none() {
+            message.type_code = aircraft_type.type_code.clone();
+        }
+    }
+}
+
+/// Returns the shared copy of an aircraft type, adding it if it is new.
+fn intern(types: &mut HashMap<AircraftType, Arc<AircraftType>>, aircraft_type: AircraftType) -> Arc<AircraftType> {
+    types.entry(aircraft_type).or_insert_with_key(|aircraft_type| Arc::new(aircraft_type.clone())).clone()
+}
+
+/// Reads the OpenSky Network's aircraft database, whose newer releases quote fields with
+/// single quotes.
+fn read_opensky(path: &str, types: &mut HashMap<AircraftType, Arc<AircraftType>>) -> Result<HashMap<Icao24, Arc<AircraftType>>, Box<dyn Error + Send + Sync>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let quote = if file.fill_buf()?.first() == Some(&b'\'') { b'\'' } else { b'"' };
+    let mut reader = csv::ReaderBuilder::new().quote(quote).flexible(true).from_reader(file);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+    let Some(icao24_column) = column("icao24") else {
+        return Err(format!("{} has no icao24 column", path).into());
+    };
+    let (manufacturer_column, model_column, type_code_column) = (column("manufacturername"), column("model"), column("typecode"));
+
+    let mut aircraft = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let Some(icao24) = record.get(icao24_column).and_then(|icao24| icao24.parse().ok()) else {
+            continue;
+        };
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column)).map(str::trim).filter(|field| !field.is_empty()).map(str::to_string);
+        let aircraft_type = AircraftType { manufacturer: field(manufacturer_column), model: field(model_column), type_code: field(type_code_column) };
+        if aircraft_type != AircraftType::default() {
+            aircraft.insert(icao24, intern(types, aircraft_type));
+        }
+    }
+    Ok(aircraft)
+}
+
+/// Reads the Mictronics aircraft database from `indexedDB.zip`. `aircrafts.json` maps each
+/// address to `[registration, type designator, flags, ...]`, and `types.json` each type
+/// designator to `[model, ...]`. It names no manufacturers.
+fn read_mictronics(path: &str, types: &mut HashMap<AircraftType, Arc<AircraftType>>) -> Result<HashMap<Icao24, Arc<AircraftType>>, Box<dyn Error + Send + Sync>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut read_json = |name: &str| -> Result<HashMap<String, Vec<Value>>, Box<dyn Error + Send + Sync>> {
+        let mut json = String::new();
+        archive.by_name(name)?.read_to_string(&mut json)?;
+        Ok(serde_json::from_str(&json)?)
+    };
+    let models = read_json("types.json")?;
+    let entries = read_json("aircrafts.json")?;
+
+    let text = |values: Option<&Vec<Value>>, index: usize| values.and_then(|values| values.get(index)?.as_str()).map(str::trim).filter(|text| !text.is_empty()).map(str::to_string);
+    let mut aircraft = HashMap::new();
+    for (icao24, entry) in &entries {
+        let (Ok(icao24), Some(type_code)) = (icao24.parse::<Icao24>(), text(Some(entry), 1)) else {
+            continue;
+        };
+        let aircraft_type = AircraftType { manufacturer: None, model: text(models.get(&type_code), 0), type_code: Some(type_code) };
+        aircraft.insert(icao24, intern(types, aircraft_type));
+    }
+    Ok(aircraft)
+}
+
+/// Downloads a fresh copy of an aircraft database, replacing the file only once the download
+/// has completed.
+///
+/// # Arguments
+///
+/// * `url` - Where to download the database from.
+/// * `path` - The file to save it to.
+///
+/// # Returns
+///
+/// A `Result` that is an error if the download failed or the file could not be written.
+pub async fn download(url: &str, path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("Downloading the aircraft database from {} to {}", url, path);
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    let partial = format!("{}.partial", path);
+    let mut file = File::create(&partial)?;
+    let mut bytes = 0;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        bytes += chunk.len();
+    }
+    file.sync_all()?;
+    std::fs::rename(&partial, Path::new(path))?;
+    println!("Downloaded {} bytes", bytes);
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! This module limits what is forwarded to the traffic within an altitude band and a speed
+//! range, so that a site interested only in low-level traffic (or only in en-route traffic)
+//! can cut volume at the source.
+//!
+//! A message without an altitude or speed is judged by its aircraft's last known one.
+
+/// The altitudes and speeds of the traffic to forward. Bounds are inclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bands {
+    /// The lowest barometric altitude in feet.
+    pub min_altitude: Option<i32>,
+    /// The highest barometric altitude in feet.
+    pub max_altitude: Option<i32>,
+    /// The lowest ground speed in knots.
+    pub min_speed: Option<f32>,
+    /// The highest ground speed in knots.
+    pub max_speed: Option<f32>,
+}
+
+impl Bands {
+    /// Returns whether the bands let everything through.
+    pub fn is_empty(&self) -> bool {
+        *self == Bands::default()
+    }
+
+    /// Returns whether traffic at an altitude and speed is forwarded. Traffic whose altitude
+    /// (or speed) is not known is only forwarded if no altitude (or speed) bound is set.
+    pub fn allows(&self, altitude: Option<i32>, speed: Option<f32>) -> bool {
+        within(altitude, self.min_altitude, self.max_altitude) && within(speed, self.min_speed, self.max_speed)
+    }
+}
+
+/// Returns whether a value is within optional bounds, or there are none.
+fn within<T: PartialOrd>(value: Option<T>, min: Option<T>, max: Option<T>) -> bool {
+    match value {
+        Some(value) => min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max),
+        None => min.is_none() && max.is_none(),
+    }
+}
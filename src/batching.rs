@@ -0,0 +1,110 @@
+//! This module scales the batch size between a minimum and a maximum with the observed
+//! message rate and send latency, so that one configuration suits both a quiet receiver
+//! (small, frequent batches) and a busy one (large batches).
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How much of each new observation goes into the moving averages.
+const SMOOTHING: f64 = 0.3;
+
+/// The average time sending a batch to the sinks takes, shared between the task that sends
+/// and the loop that sizes batches.
+#[derive(Debug, Default)]
+pub struct Latency {
+    /// The moving average, in microseconds, or 0 before the first send.
+    micros: AtomicU64,
+}
+
+impl Latency {
+    /// Records how long sending a batch took.
+    pub fn record(&self, elapsed: Duration) {
+        let elapsed = elapsed.as_micros() as f64;
+        let average = self.micros.load(Ordering::Relaxed) as f64;
+        let average = if average == 0.0 { elapsed } else { average + SMOOTHING * (elapsed - average) };
+        self.micros.store(average.max(1.0) as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the average time sending a batch takes, or `None` before the first send.
+    pub fn get(&self) -> Option<Duration> {
+        match self.micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+}
+
+/// Picks the size at which batches are sent.
+#[derive(Debug)]
+pub struct BatchSizer {
+    min: usize,
+    max: usize,
+    size: usize,
+    /// The moving average of messages queued per second, once a batch has been taken.
+    rate: Option<f64>,
+    /// When the last batch was taken.
+    taken: Instant,
+    latency: Arc<Latency>,
+}
+
+impl BatchSizer {
+    /// Creates a batch sizer.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - The smallest batch size.
+    /// * `max` - The largest batch size.
+    /// * `initial` - The batch size until there is a rate to go by.
+    /// * `latency` - The send latency, recorded by the task that sends batches.
+    pub fn new(min: usize, max: usize, initial: usize, latency: Arc<Latency>) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        BatchSizer { min, max, size: initial.clamp(min, max), rate: None, taken: Instant::now(), latency }
+    }
+
+    /// Returns the current batch size.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Records that a batch was taken and adjusts the batch size.
+    ///
+    /// A batch holds at least a second of messages, so that a busy feed is not sent in many
+    /// small requests, and twice what arrives while one batch is being sent, so that sending
+    /// keeps up with the feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The number of messages and failures in the batch.
+    pub fn taken(&mut self, len: usize) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.taken).as_secs_f64();
+        self.taken = now;
+        if elapsed <= 0.0 {
+            return;
+        }
+        let rate = len as f64 / elapsed;
+        let rate = match self.rate {
+            Some(average) => average + SMOOTHING * (rate - average),
+            None => rate,
+        };
+        self.rate = Some(rate);
+        let latency = self.latency.get().map_or(0.0, |latency| latency.as_secs_f64());
+        let size = rate * (latency * 2.0).max(1.0);
+        self.size = (size.round() as usize).clamp(self.min, self.max);
+    }
+}
+
+impl fmt::Display for BatchSizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "size={} rate={:.1}/s latency_ms={}",
+            self.size,
+            self.rate.unwrap_or_default(),
+            self.latency.get().map_or(0, |latency| latency.as_millis()),
+        )
+    }
+}
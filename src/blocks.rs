@@ -0,0 +1,107 @@
+//! This module maps blocks of ICAO 24-bit addresses to what they were allocated to, such as a
+//! country or an air force, read from CSV with the columns `start`, `end` (both six hex
+//! digits, inclusive) and `label`.
+
+use crate::parse::Icao24;
+use std::error::Error;
+use std::io::Read;
+
+/// Blocks of addresses, each with a label.
+#[derive(Debug, Clone, Default)]
+pub struct AddressBlocks {
+    blocks: Vec<(Icao24, Icao24, String)>,
+}
+
+impl AddressBlocks {
+    /// Reads blocks from CSV with a header row, adding them to those already read.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if the CSV could not be read, lacks a column, or holds an
+    /// invalid address or a block that starts after it ends.
+    pub fn read(&mut self, csv: impl Read) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut reader = csv::Reader::from_reader(csv);
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+        let (Some(start_column), Some(end_column), Some(label_column)) = (column("start"), column("end"), column("label")) else {
+            return Err("address blocks need start, end and label columns".into());
+        };
+        for record in reader.records() {
+            let record = record?;
+            let line = record.position().map_or(0, |position| position.line());
+            let address = |column: usize| record.get(column).unwrap_or_default().parse::<Icao24>().map_err(|err| format!("line {}: {}", line, err));
+            let (start, end) = (address(start_column)?, address(end_column)?);
+            if start > end {
+                return Err(format!("line {}: the block starts at {} after it ends at {}", line, start, end).into());
+            }
+            self.blocks.push((start, end, record.get(label_column).unwrap_or_default().trim().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Reads blocks from a CSV file, adding them to those already read.
+    pub fn read_file(&mut self, path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.read(std::fs::File::open(path)?).map_err(|err| format!("{}: {}", path, err).into())
+    }
+
+    /// Returns the label of the narrowest block an address is in, e.g. a territory's block
+    /// inside its country's.
+    pub fn find(&self, icao24: Icao24) -> Option<&str> {
+        self.blocks
+            .iter()
+            .filter(|(start, end, _)| (*start..=*end).contains(&icao24))
+            .min_by_key(|(start, end, _)| end.as_u32() - start.as_u32())
+            .map(|(_, _, label)| label.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(csv: &str) -> AddressBlocks {
+        let mut blocks = AddressBlocks::default();
+        blocks.read(csv.as_bytes()).unwrap();
+        blocks
+    }
+
+    fn find<'a>(blocks: &'a AddressBlocks, address: &str) -> Option<&'a str> {
+        blocks.find(address.parse().unwrap())
+    }
+
+    #[test]
+    fn finds_the_narrowest_block() {
+        let blocks = blocks("start,end,label\n400000,43ffff,United Kingdom\n424000,4247ff,Cayman Islands\n424400,4244ff,Example\n");
+        assert_eq!(find(&blocks, "400000"), Some("United Kingdom"));
+        assert_eq!(find(&blocks, "43ffff"), Some("United Kingdom"));
+        assert_eq!(find(&blocks, "424000"), Some("Cayman Islands"));
+        assert_eq!(find(&blocks, "4244FF"), Some("Example"));
+        assert_eq!(find(&blocks, "424500"), Some("Cayman Islands"));
+        assert_eq!(find(&blocks, "3fffff"), None);
+        assert_eq!(find(&blocks, "440000"), None);
+    }
+
+    #[test]
+    fn finds_no_block_for_non_icao_addresses() {
+        let blocks = blocks("start,end,label\n000000,ffffff,Everywhere\n");
+        assert_eq!(find(&blocks, "40621d"), Some("Everywhere"));
+        assert_eq!(find(&blocks, "~40621d"), None);
+    }
+
+    #[test]
+    fn reads_columns_in_any_order_and_adds_to_what_was_read() {
+        let mut blocks = blocks("Label, Start ,END\nUnited Kingdom,400000,43ffff\n");
+        blocks.read("start,end,label\n424000,4247ff,Cayman Islands\n".as_bytes()).unwrap();
+        assert_eq!(find(&blocks, "40621d"), Some("United Kingdom"));
+        assert_eq!(find(&blocks, "424000"), Some("Cayman Islands"));
+    }
+
+    #[test]
+    fn rejects_invalid_blocks() {
+        let error = |csv: &str| AddressBlocks::default().read(csv.as_bytes()).unwrap_err().to_string();
+        assert!(error("start,end\n400000,43ffff\n").contains("label"));
+        assert!(error("start,end,label\n400000,43ffff,A\n40000g,43ffff,B\n").starts_with("line 3: "));
+        let reversed = error("start,end,label\n400000,43ffff,A\n43ffff,400000,B\n");
+        assert!(reversed.starts_with("line 3: ") && reversed.contains("43ffff") && reversed.contains("400000"), "{}", reversed);
+    }
+}
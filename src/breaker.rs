@@ -0,0 +1,233 @@
+//! This module holds the circuit breakers that stop sending to a sink after repeated
+//! failures, so that a down endpoint is not hammered with retries. While a breaker is open,
+//! batches wait in the buffer (on disk with QUEUE_DIR), or in the sink's own queue if sinks
+//! are isolated; once its cooldown has passed, a single probe request decides whether it
+//! closes again. Before it opens, failed batches are retried after a delay that doubles with
+//! each failure.
+
+use crate::parse::SBS1Message;
+use std::time::{Duration, Instant};
+
+/// How long to wait before retrying after the first failure while the breaker is closed.
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The state of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Batches are sent.
+    Closed,
+    /// Nothing is sent until the cooldown has passed.
+    Open,
+    /// The cooldown has passed and a probe is being sent.
+    HalfOpen,
+}
+
+impl State {
+    fn event(self) -> &'static str {
+        match self {
+            State::Closed => "circuit_closed",
+            State::Open => "circuit_open",
+            State::HalfOpen => "circuit_half_open",
+        }
+    }
+}
+
+/// The circuit breaker of one sink.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    sink: &'static str,
+    /// How many batches in a row may fail before the breaker opens.
+    threshold: u32,
+    cooldown: Duration,
+    state: State,
+    /// Batches failed in a row.
+    failures: u32,
+    /// When the breaker last opened.
+    opened: Instant,
+    /// When a batch may be retried after a failure while the breaker is closed.
+    retry_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit breaker.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The name of the sink, for log messages and events.
+    /// * `threshold` - How many batches in a row may fail before the breaker opens; at
+    ///   least 1.
+    /// * `cooldown` - How long the breaker stays open before a probe is sent.
+    pub fn new(sink: &'static str, threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker { sink, threshold: threshold.max(1), cooldown, state: State::Closed, failures: 0, opened: Instant::now(), retry_at: None }
+    }
+
+    /// Returns the state of the breaker.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns when the sink may next be sent to, or `None` if it may be now. Once the
+    /// cooldown has passed, the breaker turns half-open and the next request is the probe.
+    ///
+    /// # Returns
+    ///
+    /// The time, and a state-change event if the breaker turned half-open.
+    pub fn ready(&mut self) -> (Option<Instant>, Option<SBS1Message>) {
+        if self.state != State::Open {
+            return (self.retry_at.filter(|retry_at| Instant::now() < *retry_at), None);
+        }
+        let until = self.opened + self.cooldown;
+        if Instant::now() < until {
+            return (Some(until), None);
+        }
+        (None, self.transition(State::HalfOpen))
+    }
+
+    /// Records that a batch was sent, closing the breaker.
+    ///
+    /// # Returns
+    ///
+    /// A state-change event if the breaker closed.
+    pub fn succeeded(&mut self) -> Option<SBS1Message> {
+        self.failures = 0;
+        self.retry_at = None;
+        self.transition(State::Closed)
+    }
+
+    /// Records that sending a batch failed, opening the breaker if too many have in a row or
+    /// the probe failed, and otherwise backing off before the retry.
+    ///
+    /// # Returns
+    ///
+    /// When the sink may next be sent to, and a state-change event if the breaker opened.
+    pub fn failed(&mut self) -> (Instant, Option<SBS1Message>) {
+        self.failures += 1;
+        if self.state == State::HalfOpen || self.failures >= self.threshold {
+            self.opened = Instant::now();
+            self.retry_at = None;
+            (self.opened + self.cooldown, self.transition(State::Open))
+        } else {
+            // Doubling from the first failure, but never longer than the breaker would stay open.
+            let backoff = RETRY_BACKOFF.saturating_mul(1 << (self.failures - 1).min(16)).min(self.cooldown);
+            let retry_at = Instant::now() + backoff;
+            self.retry_at = Some(retry_at);
+            (retry_at, None)
+        }
+    }
+
+    /// Moves to a state, logging the change.
+    ///
+    /// # Returns
+    ///
+    /// A `STA` message reporting the change, for the sinks that are still working, or
+    /// `None` if the state is unchanged.
+    fn transition(&mut self, state: State) -> Option<SBS1Message> {
+        if self.state == state {
+            return None;
+        }
+        self.state = state;
+        match state {
+            State::Open => eprintln!("Circuit breaker for sink {} opened after {} failed batch(es); retrying in {:?}", self.sink, self.failures, self.cooldown),
+            State::HalfOpen => println!("Circuit breaker for sink {} half-open: sending a probe", self.sink),
+            State::Closed => println!("Circuit breaker for sink {} closed: sending resumed", self.sink),
+        }
+        let mut message = SBS1Message::new();
+        message.message_type = Some("STA".to_string());
+        message.event = Some(state.event().to_string());
+        message.extras.insert("sink".to_string(), self.sink.to_string());
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the event name of a state change, checking that it is a `STA` message naming
+    /// the sink.
+    fn event(message: Option<SBS1Message>) -> Option<String> {
+        let message = message?;
+        assert_eq!(message.message_type.as_deref(), Some("STA"));
+        assert_eq!(message.extras.get("sink").map(String::as_str), Some("test"));
+        message.event
+    }
+
+    /// Calls `ready`, returning the event name of its state change.
+    fn ready(breaker: &mut CircuitBreaker) -> (Option<Instant>, Option<String>) {
+        let (until, change) = breaker.ready();
+        (until, event(change))
+    }
+
+    /// Calls `failed`, returning how long from now the sink may be sent to again, to the
+    /// nearest tenth of a second, and the event name of its state change.
+    fn failed(breaker: &mut CircuitBreaker) -> (Duration, Option<String>) {
+        let (until, change) = breaker.failed();
+        let wait = until.saturating_duration_since(Instant::now()).as_secs_f64();
+        (Duration::from_millis(((wait * 10.0).round() * 100.0) as u64), event(change))
+    }
+
+    #[test]
+    fn doubles_the_backoff_until_it_opens() {
+        let mut breaker = CircuitBreaker::new("test", 4, Duration::from_secs(30));
+        assert_eq!(ready(&mut breaker), (None, None));
+        for backoff in [1, 2, 4] {
+            assert_eq!(failed(&mut breaker), (Duration::from_secs(backoff), None));
+            assert_eq!(breaker.state(), State::Closed);
+            // The retry waits for the backoff even though the breaker is closed.
+            assert!(ready(&mut breaker).0.is_some());
+        }
+
+        assert_eq!(failed(&mut breaker), (Duration::from_secs(30), Some("circuit_open".to_string())));
+        assert_eq!(breaker.state(), State::Open);
+        let (until, change) = ready(&mut breaker);
+        assert!(until.is_some_and(|until| until > Instant::now() + Duration::from_secs(29)));
+        assert_eq!(change, None);
+    }
+
+    #[test]
+    fn caps_the_backoff_at_the_cooldown() {
+        let mut breaker = CircuitBreaker::new("test", 10, Duration::from_secs(3));
+        let waits: Vec<Duration> = (0..4).map(|_| failed(&mut breaker).0).collect();
+        assert_eq!(waits, [1, 2, 3, 3].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn resets_after_a_success() {
+        let mut breaker = CircuitBreaker::new("test", 2, Duration::from_secs(30));
+        failed(&mut breaker);
+        // Closing a breaker that is already closed is no state change.
+        assert_eq!(event(breaker.succeeded()), None);
+        assert_eq!(ready(&mut breaker), (None, None));
+        assert_eq!(failed(&mut breaker), (Duration::from_secs(1), None));
+        assert_eq!(breaker.state(), State::Closed);
+    }
+
+    #[test]
+    fn probes_once_the_cooldown_has_passed() {
+        let mut breaker = CircuitBreaker::new("test", 1, Duration::ZERO);
+        assert_eq!(failed(&mut breaker).1.as_deref(), Some("circuit_open"));
+
+        // A failed probe opens the breaker again.
+        assert_eq!(ready(&mut breaker), (None, Some("circuit_half_open".to_string())));
+        assert_eq!(breaker.state(), State::HalfOpen);
+        assert_eq!(failed(&mut breaker).1.as_deref(), Some("circuit_open"));
+
+        // A successful one closes it.
+        assert_eq!(ready(&mut breaker).1.as_deref(), Some("circuit_half_open"));
+        assert_eq!(event(breaker.succeeded()).as_deref(), Some("circuit_closed"));
+        assert_eq!(breaker.state(), State::Closed);
+        assert_eq!(ready(&mut breaker), (None, None));
+    }
+
+    #[test]
+    fn opens_on_a_failed_probe_whatever_the_threshold() {
+        let mut breaker = CircuitBreaker::new("test", 3, Duration::ZERO);
+        for _ in 0..3 {
+            failed(&mut breaker);
+        }
+        assert_eq!(breaker.state(), State::Open);
+        ready(&mut breaker);
+        assert_eq!(breaker.state(), State::HalfOpen);
+        assert_eq!(failed(&mut breaker).1.as_deref(), Some("circuit_open"));
+    }
+}
@@ -0,0 +1,390 @@
+//! This module buffers batches between reading the feed and sending them to the sinks, so
+//! that a slow or unreachable sink does not stall reading, within limits that bound how
+//! much is held in memory.
+
+use crate::memory::{self, MemoryBudget};
+use crate::parse::{ParseError, SBS1Message};
+use crate::spool::Spool;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// What happens to a batch that does not fit in the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop the oldest batches until it fits, keeping the most recent data.
+    DropOldest,
+    /// Drop the new batch.
+    DropNewest,
+    /// Wait until it fits, which stops reading from the feed.
+    Block,
+}
+
+impl FromStr for DropPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "drop-oldest" => Ok(DropPolicy::DropOldest),
+            "drop-newest" => Ok(DropPolicy::DropNewest),
+            "block" => Ok(DropPolicy::Block),
+            _ => Err(format!("unknown buffer policy {:?} (expected drop-oldest, drop-newest or block)", s)),
+        }
+    }
+}
+
+/// The sequence number of the next batch taken.
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Parsed messages and parse failures that are sent to the sinks together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    /// Identifies the batch, and stays the same when it is sent again, e.g. after a restart,
+    /// so that sinks able to can discard what they have already received.
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    /// The number of the batch among those taken since the collector started, from 1. Like
+    /// the id, it stays with the batch when it is sent again, so that gaps and repeats show.
+    #[serde(default)]
+    pub seq: u64,
+    /// The parsed messages, oldest first.
+    pub messages: Vec<SBS1Message>,
+    /// Parse failures and their lines, for sinks that forward them.
+    pub diagnostics: Vec<(ParseError, String)>,
+    /// How far the capture file being replayed had been read when the batch was taken, so
+    /// that the replay checkpoint moves past it once it has been sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+}
+
+impl Batch {
+    /// Creates a batch with a new id and the next sequence number.
+    pub fn new(messages: Vec<SBS1Message>, diagnostics: Vec<(ParseError, String)>) -> Self {
+        Batch { id: Uuid::new_v4(), seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed), messages, diagnostics, offset: None }
+    }
+
+    /// Returns how many messages and parse failures the batch holds.
+    pub fn len(&self) -> usize {
+        self.messages.len() + self.diagnostics.len()
+    }
+
+    /// Returns whether the batch holds nothing.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimates the memory the batch takes from its size as JSON.
+    fn bytes(&self) -> usize {
+        let messages: usize = self.messages.iter().map(|message| serde_json::to_vec(message).map_or(0, |json| json.len())).sum();
+        messages + self.diagnostics.iter().map(|(_, line)| line.len()).sum::<usize>()
+    }
+}
+
+/// Where queued batches are kept.
+enum Storage {
+    /// In memory, with their estimated sizes as JSON and in memory, and when they were
+    /// queued.
+    Memory(VecDeque<(Batch, usize, usize, Instant)>),
+    /// On disk, to survive restarts.
+    Disk(Spool),
+}
+
+/// The batches waiting to be sent.
+struct Queue {
+    storage: Storage,
+    /// The number of batches.
+    batches: usize,
+    /// The number of messages and parse failures in the batches.
+    len: usize,
+    bytes: usize,
+    /// The memory budget the batches held in memory count against, if any.
+    budget: Option<Arc<MemoryBudget>>,
+    /// Set once nothing more is pushed, or nothing more is taken.
+    closed: bool,
+    /// Messages and parse failures dropped so far.
+    dropped: u64,
+    /// Batches dropped so far.
+    dropped_batches: u64,
+    /// Batches that had to wait for room, with reading paused, and how long they waited.
+    blocked: u64,
+    blocked_time: Duration,
+}
+
+impl Queue {
+    fn new(storage: Storage) -> Self {
+        let (batches, len, bytes) = match &storage {
+            Storage::Memory(_) => (0, 0, 0),
+            Storage::Disk(spool) => (spool.batches(), spool.len(), spool.bytes() as usize),
+        };
+        Queue { storage, batches, len, bytes, budget: None, closed: false, dropped: 0, dropped_batches: 0, blocked: 0, blocked_time: Duration::ZERO }
+    }
+
+    fn push(&mut self, batch: Batch, bytes: usize, memory: usize) -> io::Result<()> {
+        let len = batch.len();
+        let bytes = match &mut self.storage {
+            Storage::Memory(batches) => {
+                batches.push_back((batch, bytes, memory, Instant::now()));
+                if let Some(budget) = &self.budget {
+                    budget.add(memory);
+                }
+                bytes
+            }
+            Storage::Disk(spool) => spool.append(&batch)? as usize,
+        };
+        self.batches += 1;
+        self.len += len;
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    /// Takes the oldest batch, dropping those that cannot be read back.
+    fn pop(&mut self) -> Option<Batch> {
+        loop {
+            let (batch, len, bytes) = match &mut self.storage {
+                Storage::Memory(batches) => batches.pop_front().map(|(batch, bytes, memory, _)| {
+                    if let Some(budget) = &self.budget {
+                        budget.remove(memory);
+                    }
+                    let len = batch.len();
+                    (Ok(batch), len, bytes as u64)
+                })?,
+                Storage::Disk(spool) => spool.take()?,
+            };
+            self.batches -= 1;
+            self.len -= len;
+            self.bytes -= bytes as usize;
+            match batch {
+                Ok(batch) => return Some(batch),
+                Err(err) => {
+                    eprintln!("Dropping a queued batch of {} messages that cannot be read: {}", len, err);
+                    self.count_dropped(len);
+                }
+            }
+        }
+    }
+
+    /// Drops the oldest batch.
+    fn drop_oldest(&mut self) {
+        let dropped = match &mut self.storage {
+            Storage::Memory(batches) => batches.pop_front().map(|(batch, bytes, memory, _)| {
+                if let Some(budget) = &self.budget {
+                    budget.remove(memory);
+                }
+                (batch.len(), bytes as u64)
+            }),
+            Storage::Disk(spool) => spool.skip(),
+        };
+        if let Some((len, bytes)) = dropped {
+            self.batches -= 1;
+            self.len -= len;
+            self.bytes -= bytes as usize;
+            self.count_dropped(len);
+        }
+    }
+
+    fn count_dropped(&mut self, len: usize) {
+        self.dropped += len as u64;
+        self.dropped_batches += 1;
+    }
+}
+
+/// A queue of batches limited by the number of messages and bytes it holds.
+pub struct Buffer {
+    queue: Mutex<Queue>,
+    /// Notified when a batch is pushed or taken, and when the buffer is closed.
+    changed: Notify,
+    /// The most messages and parse failures held, or 0 for no limit.
+    max_messages: usize,
+    /// The most bytes held, or 0 for no limit.
+    max_bytes: usize,
+    policy: DropPolicy,
+}
+
+impl Buffer {
+    /// Creates a buffer in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_messages` - The most messages and parse failures to hold, or 0 for no limit.
+    /// * `max_bytes` - The most bytes to hold, as estimated from their size as JSON, or 0
+    ///   for no limit.
+    /// * `policy` - What happens to a batch that does not fit.
+    pub fn new(max_messages: usize, max_bytes: usize, policy: DropPolicy) -> Self {
+        Buffer {
+            queue: Mutex::new(Queue::new(Storage::Memory(VecDeque::new()))),
+            changed: Notify::new(),
+            max_messages,
+            max_bytes,
+            policy,
+        }
+    }
+
+    /// Counts the batches held in memory against a budget, which may be shared with other
+    /// buffers. A batch that does not fit in the budget is handled like one that does not
+    /// fit in the buffer.
+    pub fn with_budget(self, budget: Arc<MemoryBudget>) -> Self {
+        self.queue.lock().unwrap().budget = Some(budget);
+        self
+    }
+
+    /// Creates a buffer on disk, starting with the batches an earlier run left unsent.
+    ///
+    /// # Arguments
+    ///
+    /// * `spool` - The queue on disk.
+    /// * `max_bytes` - The most disk space the queued batches may take, or 0 for no limit.
+    /// * `policy` - What happens to a batch that does not fit.
+    pub fn on_disk(spool: Spool, max_bytes: usize, policy: DropPolicy) -> Self {
+        Buffer {
+            queue: Mutex::new(Queue::new(Storage::Disk(spool))),
+            changed: Notify::new(),
+            max_messages: 0,
+            max_bytes,
+            policy,
+        }
+    }
+
+    /// Returns whether a batch fits next to the queued ones. Anything fits in an empty
+    /// buffer, so that a batch larger than the limits is not held back forever.
+    fn fits(&self, queue: &Queue, len: usize, bytes: usize, memory: usize) -> bool {
+        let in_budget = match (&queue.storage, &queue.budget) {
+            (Storage::Memory(_), Some(budget)) => budget.fits(memory),
+            _ => true,
+        };
+        queue.batches == 0
+            || ((self.max_messages == 0 || queue.len + len <= self.max_messages) && (self.max_bytes == 0 || queue.bytes + bytes <= self.max_bytes) && in_budget)
+    }
+
+    /// Queues a batch, applying the drop policy if it does not fit.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the buffer has been closed because batches are no longer being sent, in
+    /// which case the batch is discarded.
+    pub async fn push(&self, mut batch: Batch) -> bool {
+        let bytes = batch.bytes();
+        let memory = memory::batch_size(&batch);
+        let mut blocked: Option<Instant> = None;
+        loop {
+            let changed = self.changed.notified();
+            match self.try_push(batch, bytes, memory) {
+                Ok(open) => {
+                    if let Some(since) = blocked {
+                        let mut queue = self.queue.lock().unwrap();
+                        queue.blocked += 1;
+                        queue.blocked_time += since.elapsed();
+                    }
+                    return open;
+                }
+                Err(waiting) => {
+                    batch = waiting;
+                    blocked.get_or_insert_with(Instant::now);
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Queues a batch unless it has to wait for room.
+    ///
+    /// # Returns
+    ///
+    /// Whether the buffer is still open, or the batch back if it has to wait.
+    fn try_push(&self, batch: Batch, bytes: usize, memory: usize) -> Result<bool, Batch> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return Ok(false);
+        }
+        if !self.fits(&queue, batch.len(), bytes, memory) {
+            match self.policy {
+                DropPolicy::DropNewest => {
+                    queue.count_dropped(batch.len());
+                    return Ok(true);
+                }
+                DropPolicy::DropOldest => {
+                    while !self.fits(&queue, batch.len(), bytes, memory) {
+                        queue.drop_oldest();
+                    }
+                }
+                DropPolicy::Block => return Err(batch),
+            }
+        }
+        let len = batch.len();
+        if let Err(err) = queue.push(batch, bytes, memory) {
+            eprintln!("Dropping a batch of {} messages that cannot be queued: {}", len, err);
+            queue.count_dropped(len);
+        }
+        drop(queue);
+        self.changed.notify_waiters();
+        Ok(true)
+    }
+
+    /// Takes the oldest batch, waiting for one to be pushed if the buffer is empty. On disk,
+    /// it is replayed after a restart until `done` is called.
+    ///
+    /// # Returns
+    ///
+    /// The batch, or `None` once the buffer is closed and empty.
+    pub async fn pop(&self) -> Option<Batch> {
+        loop {
+            let changed = self.changed.notified();
+            {
+                let mut queue = self.queue.lock().unwrap();
+                if let Some(batch) = queue.pop() {
+                    drop(queue);
+                    self.changed.notify_waiters();
+                    return Some(batch);
+                }
+                if queue.closed {
+                    return None;
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Records that the batches taken so far have been sent.
+    pub fn done(&self) {
+        if let Storage::Disk(spool) = &mut self.queue.lock().unwrap().storage {
+            if let Err(err) = spool.commit() {
+                eprintln!("Error recording sent batches in the queue directory: {}", err);
+            }
+        }
+    }
+
+    /// Closes the buffer. Batches already queued can still be taken; pushing fails.
+    pub fn close(&self) {
+        self.queue.lock().unwrap().closed = true;
+        self.changed.notify_waiters();
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let queue = self.queue.lock().unwrap();
+        write!(
+            f,
+            "queued_batches={} queued={} queued_bytes={} dropped={} dropped_batches={} blocked={} blocked_secs={:.1}",
+            queue.batches,
+            queue.len,
+            queue.bytes,
+            queue.dropped,
+            queue.dropped_batches,
+            queue.blocked,
+            queue.blocked_time.as_secs_f64()
+        )?;
+        // How far sending is behind reading, as the age of the oldest batch waiting; only
+        // known for batches held in memory.
+        if let Storage::Memory(batches) = &queue.storage {
+            write!(f, " lag_secs={:.1}", batches.front().map_or(0.0, |(_, _, _, queued)| queued.elapsed().as_secs_f64()))?;
+        }
+        Ok(())
+    }
+}
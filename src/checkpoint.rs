@@ -0,0 +1,176 @@
+//! This module records how far a capture file has been replayed, so that an interrupted
+//! replay or backfill resumes where it left off instead of sending everything again.
+//!
+//! The checkpoint is the byte offset in the file up to which every message has been sent,
+//! written to its own file every so often and once the replay ends. Batches sent on their
+//! own, such as those of the priority lane, hold it back until they have been sent.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// The offset sent up to, the batches holding it back, and the offset last written.
+struct State {
+    offset: u64,
+    /// Where the earliest line of each batch not yet sent starts, by batch id.
+    held: HashMap<Uuid, u64>,
+    written: Instant,
+    saved: u64,
+}
+
+impl State {
+    /// Returns the offset to resume from: the one sent up to, or the earliest held back.
+    fn resume(&self) -> u64 {
+        self.held.values().copied().fold(self.offset, u64::min)
+    }
+}
+
+/// The replay checkpoint of a capture file.
+pub struct Checkpoint {
+    path: PathBuf,
+    /// How often the checkpoint is written while the replay goes on.
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint of a capture file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The checkpoint file, which need not exist yet.
+    /// * `interval` - How often the checkpoint is written while the replay goes on.
+    /// * `file_len` - The size of the capture file, to tell a checkpoint that cannot belong
+    ///   to it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the checkpoint and the offset to resume the replay from, or an
+    /// error if the checkpoint file cannot be read.
+    pub fn open(path: &Path, interval: Duration, file_len: u64) -> io::Result<(Self, u64)> {
+        let offset = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid replay checkpoint {:?} in {}", contents, path.display())))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let offset = if offset > file_len {
+            eprintln!("Ignoring the replay checkpoint in {}: it is past the end of the file", path.display());
+            0
+        } else {
+            offset
+        };
+        let state = State { offset, held: HashMap::new(), written: Instant::now(), saved: offset };
+        Ok((Checkpoint { path: path.to_path_buf(), interval, state: Mutex::new(state) }, offset))
+    }
+
+    /// Records that everything before an offset has been sent, and writes the checkpoint if
+    /// it has not been written for the interval.
+    pub fn sent(&self, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.offset = state.offset.max(offset);
+        if state.resume() != state.saved && state.written.elapsed() >= self.interval {
+            self.write(&mut state);
+        }
+    }
+
+    /// Holds the checkpoint back before the line a batch sent on its own starts at, until
+    /// `release` is called with the batch's id.
+    pub fn hold(&self, batch: Uuid, offset: u64) {
+        self.state.lock().unwrap().held.insert(batch, offset);
+    }
+
+    /// Lets the checkpoint move past a batch once it has been sent. Batches that were not
+    /// held are ignored.
+    pub fn release(&self, batch: Uuid) {
+        self.state.lock().unwrap().held.remove(&batch);
+    }
+
+    /// Writes the checkpoint if it has moved since it was last written, e.g. once the
+    /// replay ends.
+    pub fn save(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.resume() != state.saved {
+            self.write(&mut state);
+        }
+    }
+
+    fn write(&self, state: &mut State) {
+        // Write to a temporary file first, so that a crash never leaves a torn checkpoint.
+        let temporary = self.path.with_extension("tmp");
+        let offset = state.resume();
+        let written = fs::write(&temporary, format!("{}\n", offset)).and_then(|()| fs::rename(&temporary, &self.path));
+        match written {
+            Ok(()) => state.saved = offset,
+            Err(err) => eprintln!("Error writing the replay checkpoint {}: {}", self.path.display(), err),
+        }
+        state.written = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a checkpoint path of its own under the system's temporary directory.
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("adsb-{}.checkpoint", Uuid::new_v4()))
+    }
+
+    fn saved(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn stays_behind_held_batches_until_they_are_released() {
+        let path = temp_path();
+        let (checkpoint, offset) = Checkpoint::open(&path, Duration::ZERO, 1_000).unwrap();
+        assert_eq!(offset, 0);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        checkpoint.hold(first, 100);
+        checkpoint.hold(second, 300);
+        checkpoint.sent(500);
+        assert_eq!(saved(&path), "100\n");
+
+        // Released out of order: the earlier batch still holds the checkpoint back.
+        checkpoint.release(second);
+        checkpoint.sent(600);
+        assert_eq!(saved(&path), "100\n");
+        checkpoint.release(first);
+        checkpoint.sent(600);
+        assert_eq!(saved(&path), "600\n");
+
+        // Releasing a batch that was not held changes nothing, nor does an older offset.
+        checkpoint.release(Uuid::new_v4());
+        checkpoint.sent(400);
+        assert_eq!(saved(&path), "600\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resumes_from_what_was_saved() {
+        let path = temp_path();
+        let (checkpoint, _) = Checkpoint::open(&path, Duration::from_secs(3_600), 1_000).unwrap();
+        checkpoint.sent(200);
+        // Not written before the interval has passed.
+        assert!(!path.exists());
+        let held = Uuid::new_v4();
+        checkpoint.hold(held, 150);
+        checkpoint.sent(400);
+        checkpoint.save();
+        drop(checkpoint);
+        assert_eq!(Checkpoint::open(&path, Duration::ZERO, 1_000).unwrap().1, 150);
+
+        // A checkpoint past the end of the file belongs to another file.
+        assert_eq!(Checkpoint::open(&path, Duration::ZERO, 100).unwrap().1, 0);
+        fs::write(&path, "not an offset").unwrap();
+        assert!(Checkpoint::open(&path, Duration::ZERO, 1_000).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
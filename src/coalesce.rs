@@ -0,0 +1,251 @@
+//! This module merges the messages of each aircraft within a short window into a single
+//! consolidated message carrying the latest value of every field, which cuts the number of
+//! events sharply without losing state.
+
+use crate::parse::{Icao24, SBS1Message};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// A consolidated message being built.
+struct Pending {
+    /// When its first message arrived.
+    started: Instant,
+    fields: Map<String, Value>,
+    /// How many messages it merges.
+    merged: u32,
+    /// Where the line of its first message starts in the capture file being replayed, if
+    /// one is.
+    offset: Option<u64>,
+}
+
+/// Merges the messages of each aircraft received within a window of the first.
+pub struct Coalescer {
+    window: Duration,
+    pending: HashMap<Icao24, Pending>,
+    /// The aircraft with a pending message, in the order their windows started.
+    started: VecDeque<(Instant, Icao24)>,
+}
+
+impl Coalescer {
+    /// Creates a coalescer.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - How long after an aircraft's first message its later ones are merged
+    ///   into it.
+    pub fn new(window: Duration) -> Self {
+        Coalescer { window, pending: HashMap::new(), started: VecDeque::new() }
+    }
+
+    /// Adds a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message.
+    /// * `offset` - Where the message's line starts in the capture file being replayed, if
+    ///   one is, so that the replay checkpoint stays behind it while it is held.
+    ///
+    /// # Returns
+    ///
+    /// A message to forward now, if any: the message itself if it belongs to no particular
+    /// aircraft, or the aircraft's consolidated message if its window has passed.
+    pub fn add(&mut self, message: SBS1Message, offset: Option<u64>) -> Option<SBS1Message> {
+        let Some(icao24) = message.icao24 else {
+            return Some(message);
+        };
+        let Ok(Value::Object(fields)) = serde_json::to_value(&message) else {
+            return Some(message);
+        };
+
+        let now = Instant::now();
+        let done = match self.pending.get_mut(&icao24) {
+            Some(pending) if now.duration_since(pending.started) < self.window => {
+                merge(&mut pending.fields, fields);
+                pending.merged += 1;
+                return None;
+            }
+            Some(_) => self.pending.remove(&icao24).and_then(finish),
+            None => None,
+        };
+        self.pending.insert(icao24, Pending { started: now, fields, merged: 1, offset });
+        self.started.push_back((now, icao24));
+        done
+    }
+
+    /// Returns when the oldest window passes, if any message is pending.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.started.iter().find(|(started, icao24)| self.pending.get(icao24).is_some_and(|pending| pending.started == *started)).map(|(started, _)| *started + self.window)
+    }
+
+    /// Returns where the line of the earliest message still held starts in the capture file
+    /// being replayed, if any is held.
+    pub fn held_from(&self) -> Option<u64> {
+        self.pending.values().filter_map(|pending| pending.offset).min()
+    }
+
+    /// Takes the consolidated messages whose window has passed, oldest first.
+    pub fn expired(&mut self) -> Vec<SBS1Message> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        while let Some(&(started, icao24)) = self.started.front() {
+            if now.duration_since(started) < self.window {
+                break;
+            }
+            self.started.pop_front();
+            // The window may already have been closed by a later message.
+            if self.pending.get(&icao24).is_some_and(|pending| pending.started == started) {
+                expired.extend(self.pending.remove(&icao24).and_then(finish));
+            }
+        }
+        expired
+    }
+
+    /// Takes every pending consolidated message, oldest first, e.g. when the input ends.
+    pub fn drain(&mut self) -> Vec<SBS1Message> {
+        let mut pending: Vec<Pending> = self.pending.drain().map(|(_, pending)| pending).collect();
+        pending.sort_by_key(|pending| pending.started);
+        self.started.clear();
+        pending.into_iter().filter_map(finish).collect()
+    }
+}
+
+/// Overlays the fields of a newer message on a consolidated one. All-call replies do not
+/// override the transmission type of a richer message, so that the result is not taken for
+/// a mere presence report.
+fn merge(fields: &mut Map<String, Value>, newer: Map<String, Value>) {
+    let presence = newer.get("transmission_type") == Some(&Value::from(8));
+    for (name, value) in newer {
+        if value.is_null() || (presence && name == "transmission_type" && fields.get(&name).is_some_and(|kind| !kind.is_null())) {
+            continue;
+        }
+        fields.insert(name, value);
+    }
+}
+
+/// Turns a consolidated message back into a message, noting how many it merges.
+fn finish(pending: Pending) -> Option<SBS1Message> {
+    let mut message: SBS1Message = serde_json::from_value(Value::Object(pending.fields)).ok()?;
+    message.coalesced = (pending.merged > 1).then_some(pending.merged);
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    fn message(icao24: &str, transmission_type: i32) -> SBS1Message {
+        let mut message = SBS1Message::new();
+        message.message_type = Some("MSG".to_string());
+        message.transmission_type = Some(transmission_type);
+        message.icao24 = icao24.parse().ok();
+        message
+    }
+
+    fn icao24s(messages: &[SBS1Message]) -> Vec<String> {
+        messages.iter().map(|message| message.icao24.unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn keeps_the_latest_value_of_every_field() {
+        let mut coalescer = Coalescer::new(Duration::from_secs(60));
+        let mut first = message("40621d", 1);
+        first.callsign = Some("KLM1023".to_string());
+        first.altitude_baro = Some(38_000);
+        let mut second = message("40621d", 3);
+        second.altitude_baro = Some(38_025);
+        second.lat = Some(52.2572);
+        second.lon = Some(3.9194);
+        let mut third = message("40621d", 4);
+        third.ground_speed = Some(420.0);
+        assert!(coalescer.add(first, None).is_none());
+        assert!(coalescer.add(second, None).is_none());
+        assert!(coalescer.add(third, None).is_none());
+
+        let merged = coalescer.drain().pop().unwrap();
+        // Fields a newer message leaves unset keep their earlier values.
+        assert_eq!(merged.callsign.as_deref(), Some("KLM1023"));
+        assert_eq!(merged.altitude_baro, Some(38_025));
+        assert_eq!((merged.lat, merged.lon), (Some(52.2572), Some(3.9194)));
+        assert_eq!(merged.ground_speed, Some(420.0));
+        assert_eq!(merged.transmission_type, Some(4));
+        assert_eq!(merged.coalesced, Some(3));
+    }
+
+    #[test]
+    fn does_not_let_all_call_replies_override_the_transmission_type() {
+        let mut coalescer = Coalescer::new(Duration::from_secs(60));
+        let mut position = message("40621d", 3);
+        position.altitude_baro = Some(38_000);
+        coalescer.add(position, None);
+        let mut presence = message("40621d", 8);
+        presence.on_ground = Some(false);
+        coalescer.add(presence, None);
+
+        let merged = coalescer.drain().pop().unwrap();
+        assert_eq!(merged.transmission_type, Some(3));
+        assert_eq!(merged.on_ground, Some(false));
+
+        // Alone, an all-call reply keeps its own type.
+        coalescer.add(message("40621d", 8), None);
+        let merged = coalescer.drain().pop().unwrap();
+        assert_eq!(merged.transmission_type, Some(8));
+        assert_eq!(merged.coalesced, None);
+    }
+
+    #[test]
+    fn forwards_messages_of_no_aircraft_at_once() {
+        let mut coalescer = Coalescer::new(Duration::from_secs(60));
+        let mut status = message("", 0);
+        status.message_type = Some("STA".to_string());
+        assert_eq!(coalescer.add(status, None).and_then(|message| message.message_type).as_deref(), Some("STA"));
+        assert!(coalescer.next_expiry().is_none());
+    }
+
+    #[test]
+    fn closes_windows_oldest_first() {
+        let mut coalescer = Coalescer::new(Duration::from_millis(50));
+        coalescer.add(message("40621d", 3), None);
+        coalescer.add(message("4ca7b4", 3), None);
+        coalescer.add(message("40621d", 4), None);
+        assert!(coalescer.expired().is_empty());
+        assert!(coalescer.next_expiry().is_some());
+
+        sleep(Duration::from_millis(60));
+        let expired = coalescer.expired();
+        assert_eq!(icao24s(&expired), ["40621d", "4ca7b4"]);
+        assert_eq!(expired[0].coalesced, Some(2));
+        assert!(coalescer.next_expiry().is_none());
+    }
+
+    #[test]
+    fn starts_a_new_window_after_the_last_has_passed() {
+        let mut coalescer = Coalescer::new(Duration::from_millis(50));
+        coalescer.add(message("40621d", 3), None);
+        sleep(Duration::from_millis(60));
+        let mut later = message("40621d", 4);
+        later.altitude_baro = Some(38_000);
+        // The message closes the window that passed rather than merging into it.
+        let done = coalescer.add(later, None).unwrap();
+        assert_eq!((done.transmission_type, done.altitude_baro), (Some(3), None));
+
+        coalescer.add(message("4ca7b4", 3), None);
+        // The closed window of the first aircraft is skipped; its new one is older.
+        assert_eq!(icao24s(&coalescer.drain()), ["40621d", "4ca7b4"]);
+        assert!(coalescer.expired().is_empty());
+    }
+
+    #[test]
+    fn holds_the_checkpoint_at_the_earliest_line_held() {
+        let mut coalescer = Coalescer::new(Duration::from_secs(60));
+        assert_eq!(coalescer.held_from(), None);
+        coalescer.add(message("40621d", 3), Some(100));
+        coalescer.add(message("4ca7b4", 3), Some(150));
+        // Merged messages are behind the first of their window.
+        coalescer.add(message("40621d", 4), Some(200));
+        assert_eq!(coalescer.held_from(), Some(100));
+        coalescer.drain();
+        assert_eq!(coalescer.held_from(), None);
+    }
+}
@@ -0,0 +1,87 @@
+//! This module derives the country an aircraft is registered in from its ICAO 24-bit
+//! address, which ICAO allocates to states in blocks (Annex 10, Volume III), so that traffic
+//! can be broken down by country without an aircraft database.
+//!
+//! The allocation table is built in. A few states hand addresses from their block to aircraft
+//! registered elsewhere, so the country is that of the address, not always of the aircraft.
+
+use crate::blocks::AddressBlocks;
+use crate::parse::{Icao24, SBS1Message};
+
+/// The built-in ICAO allocation table.
+const BUILTIN: &str = include_str!("countries.csv");
+
+/// The countries ICAO has allocated blocks of addresses to.
+pub struct Countries {
+    blocks: AddressBlocks,
+}
+
+impl Countries {
+    /// Creates the built-in allocation table.
+    pub fn builtin() -> Self {
+        let mut blocks = AddressBlocks::default();
+        blocks.read(BUILTIN.as_bytes()).expect("the built-in ICAO allocation table is valid");
+        Countries { blocks }
+    }
+
+    /// Returns the country an address is allocated to, if it is in an allocated block.
+    pub fn lookup(&self, icao24: Icao24) -> Option<&str> {
+        self.blocks.find(icao24)
+    }
+
+    /// Adds the country of registration to a message from an aircraft with an allocated
+    /// address.
+    pub fn enrich(&self, message: &mut SBS1Message) {
+        message.registration_country = message.icao_address().and_then(|icao24| self.lookup(icao24)).map(str::to_string);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_allocated_blocks() {
+        let countries = Countries::builtin();
+        let lookup = |address: &str| countries.lookup(address.parse().unwrap());
+        assert_eq!(lookup("40621d"), Some("United Kingdom"));
+        assert_eq!(lookup("400000"), Some("United Kingdom"));
+        assert_eq!(lookup("3fffff"), Some("Germany"));
+        assert_eq!(lookup("A835AF"), Some("United States"));
+        assert_eq!(lookup("0043ff"), Some("Zimbabwe"));
+        // Between the blocks of Zimbabwe and Mozambique.
+        assert_eq!(lookup("004400"), None);
+        assert_eq!(lookup("~40621d"), None);
+    }
+
+    #[test]
+    fn has_no_overlapping_blocks() {
+        let mut blocks: Vec<(u32, u32)> = BUILTIN
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let columns: Vec<&str> = line.split(',').collect();
+                (u32::from_str_radix(columns[0], 16).unwrap(), u32::from_str_radix(columns[1], 16).unwrap())
+            })
+            .collect();
+        blocks.sort();
+        for pair in blocks.windows(2) {
+            assert!(pair[0].1 < pair[1].0, "{:06x}-{:06x} overlaps {:06x}-{:06x}", pair[0].0, pair[0].1, pair[1].0, pair[1].1);
+        }
+    }
+
+    #[test]
+    fn leaves_non_icao_and_unallocated_addresses_without_a_country() {
+        let countries = Countries::builtin();
+        let country = |address: &str| {
+            let mut message = SBS1Message::new();
+            message.icao24 = address.parse().ok();
+            countries.enrich(&mut message);
+            message.registration_country
+        };
+        assert_eq!(country("40621d").as_deref(), Some("United Kingdom"));
+        assert_eq!(country("~40621d"), None);
+        assert_eq!(country("004400"), None);
+        assert_eq!(country(""), None);
+    }
+}
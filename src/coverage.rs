@@ -0,0 +1,157 @@
+//! This module keeps the receiver's coverage: the farthest position heard in each bearing
+//! sector around it. A summary is sent as an event at an interval, and the farthest positions
+//! since startup can be written as JSON for a polar plot, so that antenna setups can be
+//! compared over time.
+//!
+//! Only positions an aircraft reported itself count; positions filled in from earlier
+//! messages do not.
+
+use crate::parse::{Icao24, SBS1Message};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Ranges beyond this are taken for bad positions rather than record receptions.
+const MAX_RANGE_NM: f64 = 500.0;
+
+/// The farthest position heard in each sector over an interval.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageSummary {
+    /// How long the summary covers, in seconds.
+    pub interval_secs: u64,
+    /// The width of each sector in degrees. The first starts at true north.
+    pub sector_deg: f64,
+    /// The farthest range heard in each sector in nautical miles, clockwise from north, or
+    /// `None` where nothing was heard.
+    pub max_range_nm: Vec<Option<f64>>,
+    /// The farthest range heard in any sector.
+    pub overall_max_range_nm: Option<f64>,
+    /// The share of sectors anything was heard in, from 0 to 1.
+    pub sectors_heard: f64,
+}
+
+/// The farthest position heard in a sector.
+#[derive(Debug, Clone, Serialize)]
+struct Farthest {
+    range_nm: f64,
+    bearing_deg: f64,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    altitude: Option<i32>,
+    icao24: Option<Icao24>,
+    timestamp: String,
+}
+
+/// The farthest positions heard around the receiver.
+pub struct Coverage {
+    sector_deg: f64,
+    interval: Duration,
+    /// When the next summary is due.
+    due: Instant,
+    /// The farthest range in each sector since the last summary.
+    recent: Vec<Option<f64>>,
+    /// The farthest position in each sector since startup.
+    farthest: Vec<Option<Farthest>>,
+    /// Where to write the farthest positions, if anywhere.
+    path: Option<String>,
+}
+
+impl Coverage {
+    /// Creates an empty coverage.
+    ///
+    /// # Arguments
+    ///
+    /// * `sectors` - How many sectors to divide the bearings into, at least 1.
+    /// * `interval` - How often to summarize the coverage.
+    /// * `path` - Where to write the farthest positions since startup at each summary, if
+    ///   anywhere.
+    pub fn new(sectors: usize, interval: Duration, path: Option<String>) -> Self {
+        let sectors = sectors.max(1);
+        Coverage {
+            sector_deg: 360.0 / sectors as f64,
+            interval,
+            due: Instant::now() + interval,
+            recent: vec![None; sectors],
+            farthest: (0..sectors).map(|_| None).collect(),
+            path,
+        }
+    }
+
+    /// Records the range of a message with a distance and bearing from the receiver.
+    pub fn record(&mut self, message: &SBS1Message) {
+        let (Some(range_nm), Some(bearing_deg)) = (message.distance_nm, message.bearing_deg) else {
+            return;
+        };
+        if range_nm > MAX_RANGE_NM || message.enriched.iter().any(|field| field == "lat") {
+            return;
+        }
+        let sector = ((bearing_deg.rem_euclid(360.0) / self.sector_deg) as usize).min(self.recent.len() - 1);
+        if self.recent[sector].is_none_or(|recent| range_nm > recent) {
+            self.recent[sector] = Some(range_nm);
+        }
+        if self.farthest[sector].as_ref().is_none_or(|farthest| range_nm > farthest.range_nm) {
+            self.farthest[sector] = Some(Farthest {
+                range_nm,
+                bearing_deg,
+                lat: message.lat,
+                lon: message.lon,
+                altitude: message.altitude_baro,
+                icao24: message.icao24,
+                timestamp: message.timestamp.clone(),
+            });
+        }
+    }
+
+    /// Returns when the next summary is due.
+    pub fn next_due(&self) -> Instant {
+        self.due
+    }
+
+    /// Summarizes the coverage since the last summary, if one is due, and writes the farthest
+    /// positions since startup. A file that cannot be written is logged.
+    ///
+    /// # Returns
+    ///
+    /// A `STA` message with the event `coverage` carrying the summary.
+    pub fn summary(&mut self) -> Option<SBS1Message> {
+        let now = Instant::now();
+        if now < self.due {
+            return None;
+        }
+        self.due = now + self.interval;
+        if let Some(path) = &self.path {
+            if let Err(err) = self.write(path) {
+                eprintln!("Error writing the coverage to {}: {}", path, err);
+            }
+        }
+
+        let max_range_nm = std::mem::replace(&mut self.recent, vec![None; self.farthest.len()]);
+        let heard = max_range_nm.iter().flatten().count();
+        let mut message = SBS1Message::new();
+        message.message_type = Some("STA".to_string());
+        message.event = Some("coverage".to_string());
+        message.coverage = Some(CoverageSummary {
+            interval_secs: self.interval.as_secs(),
+            sector_deg: self.sector_deg,
+            overall_max_range_nm: max_range_nm.iter().flatten().copied().reduce(f64::max),
+            sectors_heard: heard as f64 / max_range_nm.len() as f64,
+            max_range_nm,
+        });
+        Some(message)
+    }
+
+    /// Writes the farthest position in each sector since startup as JSON, replacing the file
+    /// only once it is complete.
+    fn write(&self, path: &str) -> std::io::Result<()> {
+        let sectors: Vec<_> = self
+            .farthest
+            .iter()
+            .enumerate()
+            .map(|(sector, farthest)| json!({"from_deg": sector as f64 * self.sector_deg, "to_deg": (sector + 1) as f64 * self.sector_deg, "farthest": farthest}))
+            .collect();
+        let polar = json!({"sector_deg": self.sector_deg, "updated": crate::tracker::now(), "sectors": sectors});
+        let partial = format!("{}.partial", path);
+        std::fs::write(&partial, serde_json::to_vec_pretty(&polar)?)?;
+        std::fs::rename(&partial, path)
+    }
+}
@@ -0,0 +1,129 @@
+//! This module suppresses messages that repeat what an aircraft sent moments before, as
+//! dump1090 often emits the same position or callsign several times a second.
+
+use crate::parse::SBS1Message;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The fields compared by default.
+pub const DEFAULT_FIELDS: &str = "callsign,altitude_baro,ground_speed,track,lat,lon,vertical_rate,squawk,on_ground";
+
+/// Remembers the messages forwarded recently, keyed on the aircraft, the transmission type
+/// and the compared fields.
+pub struct Dedup {
+    window: Duration,
+    fields: Vec<String>,
+    /// When each key was last forwarded.
+    forwarded: HashMap<String, Instant>,
+    /// When keys that fell out of the window were last forgotten.
+    pruned: Instant,
+}
+
+impl Dedup {
+    /// Creates a deduplication stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - How long after a message identical ones are suppressed. A value that
+    ///   stays the same is still forwarded once per window.
+    /// * `fields` - The names of the message fields compared, as sent in JSON.
+    pub fn new(window: Duration, fields: Vec<String>) -> Self {
+        Dedup { window, fields, forwarded: HashMap::new(), pruned: Instant::now() }
+    }
+
+    /// Returns whether a message repeats one forwarded within the window, and otherwise
+    /// remembers it as forwarded. Messages of no particular aircraft are never duplicates.
+    pub fn is_duplicate(&mut self, message: &SBS1Message) -> bool {
+        let Some(icao24) = message.icao24 else {
+            return false;
+        };
+        let Ok(Value::Object(values)) = serde_json::to_value(message) else {
+            return false;
+        };
+        let mut key = format!("{} {:?}", icao24, message.transmission_type);
+        for field in &self.fields {
+            key.push('|');
+            key.push_str(&values.get(field).map_or_else(String::new, Value::to_string));
+        }
+
+        let now = Instant::now();
+        if now.duration_since(self.pruned) >= self.window {
+            self.forwarded.retain(|_, forwarded| now.duration_since(*forwarded) < self.window);
+            self.pruned = now;
+        }
+        match self.forwarded.get(&key) {
+            Some(forwarded) if now.duration_since(*forwarded) < self.window => true,
+            _ => {
+                self.forwarded.insert(key, now);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    fn dedup(window: Duration) -> Dedup {
+        Dedup::new(window, DEFAULT_FIELDS.split(',').map(str::to_string).collect())
+    }
+
+    fn message(icao24: &str, altitude: i32) -> SBS1Message {
+        let mut message = SBS1Message::new();
+        message.transmission_type = Some(3);
+        message.icao24 = icao24.parse().ok();
+        message.altitude_baro = Some(altitude);
+        message
+    }
+
+    #[test]
+    fn suppresses_repeats_within_the_window() {
+        let mut dedup = dedup(Duration::from_secs(60));
+        assert!(!dedup.is_duplicate(&message("40621d", 38_000)));
+        assert!(dedup.is_duplicate(&message("40621d", 38_000)));
+        // A changed value, another aircraft or another transmission type is no repeat.
+        assert!(!dedup.is_duplicate(&message("40621d", 38_025)));
+        assert!(!dedup.is_duplicate(&message("4ca7b4", 38_000)));
+        let mut other = message("40621d", 38_000);
+        other.transmission_type = Some(5);
+        assert!(!dedup.is_duplicate(&other));
+        // Fields that are not compared are ignored.
+        let mut signal = message("40621d", 38_000);
+        signal.signal = Some(-12.5);
+        assert!(dedup.is_duplicate(&signal));
+    }
+
+    #[test]
+    fn forwards_a_repeat_once_the_window_has_passed() {
+        let mut dedup = dedup(Duration::from_millis(50));
+        assert!(!dedup.is_duplicate(&message("40621d", 38_000)));
+        assert!(dedup.is_duplicate(&message("40621d", 38_000)));
+        sleep(Duration::from_millis(60));
+        assert!(!dedup.is_duplicate(&message("40621d", 38_000)));
+        assert!(dedup.is_duplicate(&message("40621d", 38_000)));
+    }
+
+    #[test]
+    fn forgets_keys_that_fell_out_of_the_window() {
+        let mut dedup = dedup(Duration::from_millis(50));
+        for altitude in (0..100).map(|step| step * 25) {
+            dedup.is_duplicate(&message("40621d", altitude));
+        }
+        assert_eq!(dedup.forwarded.len(), 100);
+        sleep(Duration::from_millis(60));
+        dedup.is_duplicate(&message("40621d", 38_000));
+        assert_eq!(dedup.forwarded.len(), 1);
+    }
+
+    #[test]
+    fn never_suppresses_messages_of_no_aircraft() {
+        let mut dedup = dedup(Duration::from_secs(60));
+        let mut message = message("40621d", 38_000);
+        message.icao24 = None;
+        assert!(!dedup.is_duplicate(&message));
+        assert!(!dedup.is_duplicate(&message));
+    }
+}
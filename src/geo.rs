@@ -0,0 +1,40 @@
+//! This module computes distances and bearings between positions on the Earth's surface.
+
+/// Mean radius of the Earth in nautical miles.
+pub const EARTH_RADIUS_NM: f64 = 3_440.065;
+
+/// Computes the great-circle distance between two positions with the haversine formula.
+///
+/// # Arguments
+///
+/// * `from` - The first position as (latitude, longitude) in degrees.
+/// * `to` - The second position as (latitude, longitude) in degrees.
+///
+/// # Returns
+///
+/// The distance in nautical miles.
+pub fn distance_nm(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (to.1 - from.1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_NM * a.sqrt().asin()
+}
+
+/// Computes the initial great-circle bearing from one position to another.
+///
+/// # Arguments
+///
+/// * `from` - The position to look from as (latitude, longitude) in degrees.
+/// * `to` - The position to look at as (latitude, longitude) in degrees.
+///
+/// # Returns
+///
+/// The bearing in degrees clockwise from true north, from 0 up to 360.
+pub fn bearing_deg(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let dlon = (to.1 - from.1).to_radians();
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
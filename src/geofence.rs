@@ -0,0 +1,151 @@
+//! This module limits what is forwarded to the traffic inside (or outside) given areas, so
+//! that a site can forward e.g. only the traffic around an airport, and drop the rest before
+//! it is sent anywhere.
+//!
+//! Areas are bounding boxes (`min_lon,min_lat,max_lon,max_lat`, as in GeoJSON) or GeoJSON
+//! polygons and multipolygons, given inline or as the path of a GeoJSON file. A message
+//! without a position is judged by its aircraft's last known position.
+
+use serde_json::Value;
+
+/// A polygon as its rings of (lon, lat) points: the outline, then any holes.
+type Polygon = Vec<Vec<(f64, f64)>>;
+
+/// An area on the map.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Area {
+    /// `[min_lon, min_lat, max_lon, max_lat]`.
+    BBox([f64; 4]),
+    /// One or more polygons.
+    Polygons(Vec<Polygon>),
+}
+
+impl Area {
+    /// Returns whether a (lon, lat) position is inside the area.
+    pub fn contains(&self, position: (f64, f64)) -> bool {
+        match self {
+            Area::BBox([min_lon, min_lat, max_lon, max_lat]) => (*min_lon..=*max_lon).contains(&position.0) && (*min_lat..=*max_lat).contains(&position.1),
+            // Inside the outline and not in a hole.
+            Area::Polygons(polygons) => polygons.iter().any(|rings| rings.iter().filter(|ring| in_ring(ring, position)).count() % 2 == 1),
+        }
+    }
+}
+
+/// Returns whether a point is inside a ring, by counting the edges a ray from it crosses.
+fn in_ring(ring: &[(f64, f64)], (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+    for (i, &(x1, y1)) in ring.iter().enumerate() {
+        let (x2, y2) = ring[(i + 1) % ring.len()];
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// The areas traffic must be inside of, and those it must be outside of.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Geofence {
+    include: Vec<Area>,
+    exclude: Vec<Area>,
+}
+
+impl Geofence {
+    /// Parses the areas to include and exclude.
+    ///
+    /// # Arguments
+    ///
+    /// * `include` - The areas to forward the traffic of, or empty for everywhere.
+    /// * `exclude` - The areas to drop the traffic of.
+    ///
+    /// Each is a `;`-separated list of bounding boxes and GeoJSON file paths, or a single
+    /// inline GeoJSON object.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the geofence, or an error describing an area that could not be
+    /// read.
+    pub fn parse(include: &str, exclude: &str) -> Result<Self, String> {
+        Ok(Geofence { include: parse_areas(include)?, exclude: parse_areas(exclude)? })
+    }
+
+    /// Returns whether the geofence lets everything through.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Returns whether traffic at a (lon, lat) position is forwarded. Traffic whose position
+    /// is not known is only forwarded if no areas are included.
+    pub fn allows(&self, position: Option<(f64, f64)>) -> bool {
+        match position {
+            Some(position) => {
+                (self.include.is_empty() || self.include.iter().any(|area| area.contains(position)))
+                    && !self.exclude.iter().any(|area| area.contains(position))
+            }
+            None => self.include.is_empty(),
+        }
+    }
+}
+
+/// Parses a list of areas.
+fn parse_areas(areas: &str) -> Result<Vec<Area>, String> {
+    let areas = areas.trim();
+    if areas.starts_with('{') {
+        let geojson: Value = serde_json::from_str(areas).map_err(|err| format!("invalid GeoJSON: {}", err))?;
+        return from_geojson(&geojson);
+    }
+    let mut parsed = Vec::new();
+    for area in areas.split(';').map(str::trim).filter(|area| !area.is_empty()) {
+        let bounds: Result<Vec<f64>, _> = area.split(',').map(|bound| bound.trim().parse::<f64>()).collect();
+        match bounds.as_deref() {
+            Ok(&[min_lon, min_lat, max_lon, max_lat]) => parsed.push(Area::BBox([min_lon, min_lat, max_lon, max_lat])),
+            Ok(_) => return Err(format!("invalid bbox {:?} (expected min_lon,min_lat,max_lon,max_lat)", area)),
+            Err(_) => {
+                let json = std::fs::read_to_string(area).map_err(|err| format!("cannot read {}: {}", area, err))?;
+                let geojson: Value = serde_json::from_str(&json).map_err(|err| format!("invalid GeoJSON in {}: {}", area, err))?;
+                parsed.extend(from_geojson(&geojson)?);
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Collects the polygons of a GeoJSON object: a geometry, a feature, or a feature
+/// collection. Other geometries, such as points, have no area and are left out.
+fn from_geojson(geojson: &Value) -> Result<Vec<Area>, String> {
+    let coordinates = &geojson["coordinates"];
+    let polygons = match geojson["type"].as_str() {
+        Some("FeatureCollection") => {
+            let features = geojson["features"].as_array().ok_or("a FeatureCollection without features")?;
+            return features.iter().map(from_geojson).collect::<Result<Vec<_>, _>>().map(|areas| areas.concat());
+        }
+        Some("Feature") => return from_geojson(&geojson["geometry"]),
+        Some("GeometryCollection") => {
+            let geometries = geojson["geometries"].as_array().ok_or("a GeometryCollection without geometries")?;
+            return geometries.iter().map(from_geojson).collect::<Result<Vec<_>, _>>().map(|areas| areas.concat());
+        }
+        Some("Polygon") => vec![polygon(coordinates)?],
+        Some("MultiPolygon") => coordinates.as_array().ok_or("a MultiPolygon without coordinates")?.iter().map(polygon).collect::<Result<_, _>>()?,
+        Some(_) => return Ok(Vec::new()),
+        None => return Err("GeoJSON without a type".to_string()),
+    };
+    Ok(vec![Area::Polygons(polygons)])
+}
+
+/// Reads the rings of a GeoJSON polygon.
+fn polygon(coordinates: &Value) -> Result<Polygon, String> {
+    let rings = coordinates.as_array().ok_or("a Polygon without coordinates")?;
+    rings
+        .iter()
+        .map(|ring| {
+            let points = ring.as_array().ok_or("a Polygon ring that is not a list of points")?;
+            points
+                .iter()
+                .map(|point| match (point[0].as_f64(), point[1].as_f64()) {
+                    (Some(lon), Some(lat)) => Ok((lon, lat)),
+                    _ => Err(format!("invalid point {} in a Polygon", point)),
+                })
+                .collect()
+        })
+        .collect()
+}
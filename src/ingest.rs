@@ -0,0 +1,115 @@
+//! This module turns raw bytes read from the feed into lines the parser can handle.
+//!
+//! Long-lived streams occasionally deliver invalid UTF-8, embedded NULs, or partial lines
+//! (for example right after a reconnect). These are classified here rather than being
+//! allowed to stop the reader or reach the parser.
+
+use crate::parse::MSG_FIELD_COUNT;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The format of the data served by dump1090 on the configured port.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    /// SBS-1 (BaseStation) text lines, usually on port 30003.
+    Sbs,
+    /// Raw AVR hex frames such as `*8D4840D6202CC371C32CE0576098;`, usually on port 30002.
+    Raw,
+    /// Binary Beast frames, usually on port 30005.
+    Beast,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sbs" => Ok(InputFormat::Sbs),
+            "raw" => Ok(InputFormat::Raw),
+            "beast" => Ok(InputFormat::Beast),
+            _ => Err(format!("unknown input format {:?} (expected sbs, raw or beast)", s)),
+        }
+    }
+}
+
+/// Where the feed is read from.
+pub enum Input {
+    /// A connection to dump1090.
+    Tcp(TcpStream),
+    /// A capture file being replayed.
+    File(File),
+}
+
+impl Input {
+    /// Sets how long a read waits for data before failing. Files never wait.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Input::Tcp(stream) => stream.set_read_timeout(timeout),
+            Input::File(_) => Ok(()),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Tcp(stream) => stream.read(buf),
+            Input::File(file) => file.read(buf),
+        }
+    }
+}
+
+/// Only files can seek, e.g. to find how far they have been read.
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Input::Tcp(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "a TCP feed cannot seek")),
+            Input::File(file) => file.seek(pos),
+        }
+    }
+}
+
+/// A raw line from the feed after cleaning.
+#[derive(Debug, PartialEq)]
+pub enum RawLine {
+    /// The line was valid as received.
+    Clean(String),
+    /// The line held NULs or other control characters, which were removed.
+    Repaired(String),
+    /// The line was not valid UTF-8.
+    Garbled(String),
+    /// The line was cut short: it had no terminating newline or too few columns.
+    Truncated(String),
+}
+
+/// Cleans one raw line, including its terminating newline if there was one.
+///
+/// # Arguments
+///
+/// * `raw` - The bytes of the line as read from the stream.
+///
+/// # Returns
+///
+/// A `RawLine` holding the lossily decoded text and how it was classified.
+pub fn clean_line(raw: &[u8]) -> RawLine {
+    let terminated = raw.last() == Some(&b'\n');
+    let decoded = String::from_utf8_lossy(raw);
+    let valid_utf8 = matches!(decoded, std::borrow::Cow::Borrowed(_));
+
+    let trimmed = decoded.trim_end_matches(['\r', '\n']);
+    let text: String = trimmed.chars().filter(|c| !c.is_control()).collect();
+    let repaired = text.len() != trimmed.len();
+
+    if !valid_utf8 {
+        RawLine::Garbled(text)
+    } else if !terminated || ((text.starts_with("MSG,") || text.starts_with("MLAT,")) && text.split(',').count() < MSG_FIELD_COUNT) {
+        RawLine::Truncated(text)
+    } else if repaired {
+        RawLine::Repaired(text)
+    } else {
+        RawLine::Clean(text)
+    }
+}
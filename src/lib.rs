@@ -0,0 +1,35 @@
+//! Library side of the dump1090 SBS-1 forwarder.
+//!
+//! The parser is exposed here so it can be used and tested independently of the
+//! forwarding binary.
+
+pub mod aircraftdb;
+pub mod bands;
+pub mod batching;
+pub mod blocks;
+pub mod breaker;
+pub mod buffer;
+pub mod checkpoint;
+pub mod coalesce;
+pub mod countries;
+pub mod coverage;
+pub mod dedup;
+pub mod geo;
+pub mod geofence;
+pub mod ingest;
+pub mod memory;
+pub mod military;
+pub mod modes;
+pub mod operators;
+pub mod parse;
+pub mod priority;
+pub mod ratelimit;
+pub mod registry;
+pub mod routes;
+pub mod sample;
+pub mod sink;
+pub mod spool;
+pub mod stats;
+pub mod supervisor;
+pub mod tracker;
+pub mod validate;
@@ -1,9 +1,111 @@
 //! This module connects to a specified TCP stream, reads lines from it, and sends
-//! batches of parsed messages to a web service.
+//! batches of parsed messages to one or more sinks, by default the DataSet web service.
 //!
 //! Configuration options can be set through command line arguments or environment
-//! variables. Mandatory configurations include DATASET_API_WRITE_TOKEN, DUMP1090_HOST, 
-//! and DUMP1090_PORT. BATCH_SIZE is optional and defaults to 500.
+//! variables. Mandatory configurations include DATASET_API_WRITE_TOKEN (or
+//! DATASET_API_WRITE_TOKEN_FILE), DUMP1090_HOST, and DUMP1090_PORT. BATCH_SIZE is optional and defaults to 500.
+//! INPUT_FILE replays a capture file in INPUT_FORMAT instead of connecting to dump1090. How
+//! far it has been sent is saved to REPLAY_CHECKPOINT (default INPUT_FILE with `.checkpoint`
+//! appended) every REPLAY_CHECKPOINT_SECS (default 5) and at the end, and an interrupted
+//! replay resumes from there.
+//! BATCH_SIZE_MAX (default 0, off) lets the batch size adapt instead, between BATCH_SIZE_MIN
+//! (default 10) and BATCH_SIZE_MAX, to the observed message rate and send latency.
+//! SINK_BATCH_SIZES, SINK_FLUSH_INTERVALS, SINK_MAX_ATTEMPTS and SINK_RETRY_BACKOFF_MS
+//! (comma-separated `sink=value` lists) give sinks batches and retries of their own.
+//! SINK_ISOLATION=true gives each sink a queue and task of its own, of at most
+//! SINK_QUEUE_MAX_MESSAGES messages (default 100000) under SINK_QUEUE_POLICY (default
+//! `drop-oldest`), so that a slow or failing sink does not hold up the others.
+//! FLUSH_INTERVAL_SECS also sends a batch once its oldest message has waited that long, so a
+//! quiet feed is not held back; it defaults to 0, which sends batches only when full.
+//! Batches are sent in the background, queued in a buffer of at most BUFFER_MAX_MESSAGES
+//! messages (default 100000) and BUFFER_MAX_BYTES bytes (default 0, no limit); when it is
+//! full, BUFFER_POLICY decides whether the oldest batches are dropped (`drop-oldest`), the
+//! new one is (`drop-newest`), or reading waits until there is room (`block`, the default).
+//! QUEUE_DIR keeps the buffer on disk instead, in segment files of QUEUE_SEGMENT_MB (default
+//! 16) limited to QUEUE_MAX_MB in all (default 1024), so that batches survive restarts and
+//! long outages; batches left unsent are replayed on startup. QUEUE_COMPRESSION=gzip keeps
+//! them compressed, e.g. for receivers that are offline for long.
+//! MAX_MEMORY_MB (default 0, off) caps the estimated memory of the batches buffered in
+//! memory, on both lanes; a batch beyond it is handled by BUFFER_POLICY as when the buffer
+//! is full.
+//! DELIVERY=at-least-once only counts a batch as sent once every sink has accepted it, so
+//! that with QUEUE_DIR a crash or an outage that outlasts retrying does not lose it; the
+//! default, `at-most-once`, drops what still fails after retrying.
+//! BREAKER_FAILURES (default 0, off) opens a circuit breaker on a sink once that many
+//! batches in a row have failed: batches then wait in the buffer instead of being sent to
+//! it, and after BREAKER_COOLDOWN_SECS (default 30) a probe decides whether sending resumes.
+//! With SINK_ISOLATION, they wait in the sink's own queue, and the other sinks carry on.
+//! On SIGINT (Ctrl-C) or SIGTERM, reading stops and what has been read is sent to the sinks
+//! within SHUTDOWN_TIMEOUT_SECS (default 30) before exiting; a second signal exits at once.
+//! STALL_TIMEOUT_SECS (default 0, off) restarts the pipeline in place when reading, parsing
+//! or sending has made no progress for that long, after sending what has been read.
+//! SINKS is a comma-separated list of destinations and defaults to `dataset`. Each sink
+//! reads its own settings, listed in the README, only when selected; the DataSet sink
+//! requires DATASET_API_WRITE_TOKEN, or DATASET_API_WRITE_TOKEN_FILE naming a file that
+//! holds it, and uses 1090_COLLECTOR as its source and
+//! DATASET_API_URL as its server.
+//! `--dry-run` (or DRY_RUN=true) replaces the sinks with `stdout`, which prints messages
+//! as JSON, so parsing can be checked locally without a DataSet token.
+//! STRICT_PARSING rejects malformed lines with a diagnostic instead of tolerating them,
+//! and FORWARD_PARSE_ERRORS sends those diagnostics to DataSet as low-severity events.
+//! INPUT_FORMAT selects what DUMP1090_PORT serves: `sbs` (SBS-1 lines, the default, port
+//! 30003), `raw` (AVR hex frames, port 30002), or `beast` (binary Beast frames, port 30005).
+//! RECEIVER_LAT and RECEIVER_LON give the receiver's position, which raw and Beast input
+//! need to resolve surface positions of aircraft that have not been seen airborne. With
+//! them, messages with a position also carry their distance (`distance_nm`) and bearing
+//! (`bearing_deg`) from the receiver.
+//! COVERAGE_INTERVAL_SECS (default 0, off) sends a `coverage` event at that interval with
+//! the farthest range heard in each of COVERAGE_SECTORS bearing sectors (default 36) around
+//! the receiver, which needs RECEIVER_LAT and RECEIVER_LON. COVERAGE_FILE names a JSON file
+//! the farthest position in each sector since startup is written to at each interval.
+//! GEOFENCE_INCLUDE forwards only the traffic inside its areas, and GEOFENCE_EXCLUDE drops
+//! the traffic inside its areas. Each is a `;`-separated list of bounding boxes
+//! (`min_lon,min_lat,max_lon,max_lat`) and GeoJSON files, or an inline GeoJSON object; both
+//! default to empty, everywhere.
+//! MIN_ALTITUDE and MAX_ALTITUDE (in feet) and MIN_SPEED and MAX_SPEED (ground speed in
+//! knots) forward only the traffic within those bounds; each defaults to empty, no bound.
+//! DECODE_MET enables decoding of meteorological reports (wind, temperature) from Comm-B
+//! replies in raw and Beast input; they are sent as events with the `modes-met` parser.
+//! BEAST_LISTEN (e.g. `0.0.0.0:30105`) re-serves every frame read from raw or Beast input in
+//! the Beast format, so mlat-client and feeders can share this process's upstream connection.
+//! VALIDATION controls how physically impossible values are handled: `sanitize` (the
+//! default) clears them, `reject` drops the message, and `off` forwards them untouched.
+//! DEDUP_WINDOW_MS drops messages that repeat, for the same aircraft and transmission type,
+//! the DEDUP_FIELDS (comma-separated) of one forwarded less than that long ago; it defaults
+//! to 0, which forwards every message.
+//! AIRCRAFT_MAX_RATE caps the messages forwarded per aircraft and second, keeping the most
+//! complete (and then the newest) of each second's messages; it defaults to 0, no limit.
+//! SAMPLE_RATE forwards only a random share of messages (e.g. `0.1` or `1/10`; default 1),
+//! and SAMPLE_RATES sets the share per transmission type (e.g. `8=1/10,3=1`). Emergencies
+//! are always forwarded.
+//! COALESCE_WINDOW_MS merges the messages of each aircraft received within that long of its
+//! first into a single message with the latest value of every field; it defaults to 0, off.
+//! PRIORITY_LANE sends critical messages at once, each on their own, ahead of the batches:
+//! emergencies and messages from the aircraft on WATCHLIST (comma-separated addresses or
+//! callsigns). AIRCRAFT_LOST_SECS reports aircraft not heard from for that long as `lost`
+//! events, on the priority lane if it is on; it defaults to 0, off.
+//! TRACKER_EXPIRE_SECS is how long the latest state of an aircraft is kept after it was last
+//! heard; it defaults to 300, and is kept longer when AIRCRAFT_LOST_SECS needs it.
+//! ENRICH fills in the callsign, squawk and position a message leaves out from what its
+//! aircraft last reported; it defaults to false.
+//! REGISTRY_DB adds the registration, type code and owner of each aircraft from a
+//! BaseStation.sqb database (with the `sqlite` feature) or a CSV file; REGISTRY_CACHE_SIZE
+//! is how many aircraft looked up in a BaseStation database are cached (default 10000).
+//! AIRCRAFT_DB adds the manufacturer, model and type designator of each aircraft from the
+//! OpenSky Network's aircraft database (a CSV file) or Mictronics' (`indexedDB.zip`).
+//! `adsb-rust-dataset update-aircraft-db` downloads it from AIRCRAFT_DB_URL, which defaults
+//! to where OpenSky or Mictronics publish it, and exits.
+//! OPERATORS adds the airline and its country to each message with an airline callsign,
+//! from a built-in table (default false). OPERATORS_CSV adds airlines to the table from a CSV
+//! file with `prefix`, `name` and `country` columns, and turns the lookup on.
+//! MILITARY tags each message from an aircraft whose address is in a block used by military
+//! aircraft with `military` and the force or country (default false). MILITARY_CSV adds
+//! blocks from a CSV file with `start`, `end` and `label` columns, and turns the tagging on.
+//! ROUTE_API (`adsbdb` or `adsblol`; default empty, off) adds the origin and destination of
+//! each flight, looked up by callsign in the background, to messages with a callsign.
+//! ROUTE_API_URL overrides where the API is served, ROUTE_CACHE_SECS is how long routes are
+//! cached (default 3600) and ROUTE_API_MAX_IN_FLIGHT how many lookups may run at once
+//! (default 4).
 //! If a required configuration is not set, the application will exit with a descriptive
 //! error message.
 //!
@@ -12,30 +114,101 @@
 //! export DATASET_API_WRITE_TOKEN=your_token
 //! export DUMP1090_HOST=your_host
 //! export DUMP1090_PORT=your_port
+//! export INPUT_FILE=/var/log/adsb/capture.sbs
 //! export BATCH_SIZE=your_batch_size
+//! export BATCH_SIZE_MAX=5000
+//! export FLUSH_INTERVAL_SECS=30
+//! export SINK_BATCH_SIZES=dataset=500,mqtt=1
+//! export SINK_ISOLATION=true
+//! export BUFFER_MAX_MESSAGES=100000
+//! export BUFFER_POLICY=drop-oldest
+//! export MAX_MEMORY_MB=64
+//! export QUEUE_DIR=/var/lib/adsb/queue
+//! export DELIVERY=at-least-once
+//! export BREAKER_FAILURES=3
 //! export 1090_COLLECTOR=your_collector
+//! export STRICT_PARSING=true
+//! export FORWARD_PARSE_ERRORS=true
+//! export VALIDATION=sanitize
+//! export DEDUP_WINDOW_MS=1000
+//! export AIRCRAFT_MAX_RATE=2
+//! export SAMPLE_RATES=8=1/10
+//! export COALESCE_WINDOW_MS=1000
+//! export PRIORITY_LANE=true
+//! export WATCHLIST=3c6444,DLH4AB
+//! export AIRCRAFT_LOST_SECS=60
+//! export TRACKER_EXPIRE_SECS=300
+//! export ENRICH=true
+//! export REGISTRY_DB=/var/lib/adsb/BaseStation.sqb
+//! export AIRCRAFT_DB=/var/lib/adsb/aircraftDatabase.csv
+//! export OPERATORS=true
+//! export MILITARY=true
+//! export ROUTE_API=adsbdb
+//! export INPUT_FORMAT=sbs
+//! export SINKS=dataset
+//! export RECEIVER_LAT=52.3086
+//! export RECEIVER_LON=4.7639
+//! export COVERAGE_INTERVAL_SECS=3600
+//! export COVERAGE_FILE=/var/lib/adsb/coverage.json
+//! export GEOFENCE_INCLUDE=/etc/adsb/eham.geojson
+//! export MAX_ALTITUDE=10000
+//! export DECODE_MET=true
+//! export BEAST_LISTEN=0.0.0.0:30105
+//! export STALL_TIMEOUT_SECS=300
 //! ```
 //!
 //! Alternatively, they can be provided as command line arguments in the format:
 //! `--arg_name arg_value`, e.g. `--DATASET_API_WRITE_TOKEN your_token`
 
 use std::net::TcpStream;
-use std::io::{BufRead, BufReader};
-use reqwest;
-use serde_json::{json, Value};
-use uuid::Uuid;
+use std::io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom};
 use std::collections::VecDeque;
 use std::env;
-use crate::parse::{parse, SBS1Message};
-
-mod parse;
+use adsb::aircraftdb::{self, AircraftDb};
+use adsb::bands::Bands;
+use adsb::batching::{BatchSizer, Latency};
+use adsb::breaker::{self, CircuitBreaker};
+use adsb::buffer::{Batch, Buffer, DropPolicy};
+use adsb::coalesce::Coalescer;
+use adsb::countries::Countries;
+use adsb::coverage::Coverage;
+use adsb::dedup::{self, Dedup};
+use adsb::checkpoint::Checkpoint;
+use adsb::geo;
+use adsb::geofence::Geofence;
+use adsb::ingest::{clean_line, Input, InputFormat, RawLine};
+use adsb::memory::MemoryBudget;
+use adsb::military::Military;
+use adsb::modes::{avr, beast, Decoder, Frame};
+use adsb::operators::Operators;
+use adsb::parse::{parse, parse_opt, ParseError, SBS1Message};
+use adsb::priority::Priority;
+use adsb::registry::Registry;
+use adsb::ratelimit::AircraftRateLimit;
+use adsb::routes::{RouteApi, Routes};
+use adsb::sample::Sampler;
+use adsb::sink::batched::{BatchedSink, SinkBatching};
+use adsb::sink::bulkhead::Bulkhead;
+use adsb::sink::{self, Delivery, Sink, SinkError};
+use adsb::spool::Spool;
+use adsb::stats::Stats;
+use adsb::supervisor::{self, Stage, Supervisor};
+use adsb::tracker::Tracker;
+use adsb::validate::{self, ValidationMode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 
 fn get_argument_or_env(var_name: &str, default_value: Option<&str>) -> String {
-    let arg_prefix = format!("--{}", var_name.to_lowercase());
+    let arg_name = format!("--{}", var_name.to_lowercase());
 
     let arg_value = env::args()
         .find_map(|arg| {
-            if arg.to_lowercase().starts_with(&arg_prefix) {
+            // Match the whole name, so that e.g. `--dataset_api_write_token_file` is not taken
+            // for `--dataset_api_write_token`.
+            let lower = arg.to_lowercase();
+            if lower == arg_name || lower.starts_with(&format!("{}=", arg_name)) {
                 if let Some(index) = arg.find('=') {
                     // Extract the value after '='
                     Some(arg[index + 1..].to_string())
@@ -65,97 +238,854 @@ const DEFAULT_BATCH_SIZE: usize = 500;
 /// The main entry point of the application.
 ///
 /// This function connects to the DUMP1090 TCP service, reads messages, parses them,
-/// and sends them in batches to the DataSet web service.
+/// and sends them in batches to the configured sinks.
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let dataset_api_write_token = get_argument_or_env("DATASET_API_WRITE_TOKEN", None);
-    let dump1090_host = get_argument_or_env("DUMP1090_HOST", None);
-    let dump1090_port: u32 = get_argument_or_env("DUMP1090_PORT", None).parse().unwrap();
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // The aircraft database is downloaded by a subcommand of its own, e.g. from cron.
+    if env::args().nth(1).as_deref() == Some("update-aircraft-db") {
+        let path = get_argument_or_env("AIRCRAFT_DB", None);
+        let default_url = if path.to_lowercase().ends_with(".zip") { aircraftdb::MICTRONICS_URL } else { aircraftdb::OPENSKY_URL };
+        return aircraftdb::download(&get_argument_or_env("AIRCRAFT_DB_URL", Some(default_url)), &path).await;
+    }
     let batch_size: usize = get_argument_or_env("BATCH_SIZE", Some(&DEFAULT_BATCH_SIZE.to_string())).parse().unwrap();
-    let collector = get_argument_or_env("1090_COLLECTOR", Some("dump1090"));
+    let batch_size_min: usize = get_argument_or_env("BATCH_SIZE_MIN", Some("10")).parse().unwrap();
+    let batch_size_max: usize = get_argument_or_env("BATCH_SIZE_MAX", Some("0")).parse().unwrap();
+    let latency = Arc::new(Latency::default());
+    let mut sizer = (batch_size_max > 0).then(|| BatchSizer::new(batch_size_min, batch_size_max, batch_size, latency.clone()));
+    let flush_interval_secs: u64 = get_argument_or_env("FLUSH_INTERVAL_SECS", Some("0")).parse().unwrap();
+    let flush_interval = (flush_interval_secs > 0).then(|| Duration::from_secs(flush_interval_secs));
+    let strict_parsing: bool = get_argument_or_env("STRICT_PARSING", Some("false")).parse().unwrap();
+    let forward_parse_errors: bool = get_argument_or_env("FORWARD_PARSE_ERRORS", Some("false")).parse().unwrap();
+    let validation: ValidationMode = get_argument_or_env("VALIDATION", Some("sanitize")).parse().unwrap();
+    let input_format: InputFormat = get_argument_or_env("INPUT_FORMAT", Some("sbs")).parse().unwrap();
+    let dedup_window_ms: u64 = get_argument_or_env("DEDUP_WINDOW_MS", Some("0")).parse().unwrap();
+    let aircraft_max_rate: usize = get_argument_or_env("AIRCRAFT_MAX_RATE", Some("0")).parse().unwrap();
+    let mut rate_limit = (aircraft_max_rate > 0).then(|| AircraftRateLimit::new(aircraft_max_rate));
+    let sampler = Sampler::parse(&get_argument_or_env("SAMPLE_RATE", Some("1")), &get_argument_or_env("SAMPLE_RATES", Some(""))).unwrap();
+    let sampler = (!sampler.is_all()).then_some(sampler);
+    let coalesce_window_ms: u64 = get_argument_or_env("COALESCE_WINDOW_MS", Some("0")).parse().unwrap();
+    let mut coalescer = (coalesce_window_ms > 0).then(|| Coalescer::new(Duration::from_millis(coalesce_window_ms)));
+    let priority_lane: bool = get_argument_or_env("PRIORITY_LANE", Some("false")).parse().unwrap();
+    let aircraft_lost_secs: u64 = get_argument_or_env("AIRCRAFT_LOST_SECS", Some("0")).parse().unwrap();
+    let mut priority = Priority::new(
+        &get_argument_or_env("WATCHLIST", Some("")),
+        (aircraft_lost_secs > 0).then(|| Duration::from_secs(aircraft_lost_secs)),
+    );
+    // The latest state of each aircraft, kept long enough for lost aircraft to be reported.
+    let tracker_expire_secs: u64 = get_argument_or_env("TRACKER_EXPIRE_SECS", Some("300")).parse().unwrap();
+    let tracker = Arc::new(Tracker::new(Duration::from_secs(tracker_expire_secs.max(aircraft_lost_secs * 2))));
+    let enrich: bool = get_argument_or_env("ENRICH", Some("false")).parse().unwrap();
+    let registry_db = get_argument_or_env("REGISTRY_DB", Some(""));
+    let registry_cache_size: usize = get_argument_or_env("REGISTRY_CACHE_SIZE", Some("10000")).parse().unwrap();
+    let mut registry = if registry_db.is_empty() { None } else { Some(Registry::open(&registry_db, registry_cache_size)?) };
+    let aircraft_db_path = get_argument_or_env("AIRCRAFT_DB", Some(""));
+    let aircraft_db = if aircraft_db_path.is_empty() { None } else { Some(AircraftDb::open(&aircraft_db_path)?) };
+    let operators_csv = get_argument_or_env("OPERATORS_CSV", Some(""));
+    let operators = if !operators_csv.is_empty() {
+        Some(Operators::with_file(&operators_csv)?)
+    } else if get_argument_or_env("OPERATORS", Some("false")).parse().unwrap() {
+        Some(Operators::builtin())
+    } else {
+        None
+    };
+    let countries = Countries::builtin();
+    let military_csv = get_argument_or_env("MILITARY_CSV", Some(""));
+    let military = if !military_csv.is_empty() {
+        Some(Military::with_file(&military_csv)?)
+    } else if get_argument_or_env("MILITARY", Some("false")).parse().unwrap() {
+        Some(Military::builtin())
+    } else {
+        None
+    };
+    let geofence = Geofence::parse(&get_argument_or_env("GEOFENCE_INCLUDE", Some("")), &get_argument_or_env("GEOFENCE_EXCLUDE", Some(""))).unwrap();
+    let geofence = (!geofence.is_empty()).then_some(geofence);
+    let optional = |name: &str| Some(get_argument_or_env(name, Some(""))).filter(|value| !value.is_empty());
+    let bands = Bands {
+        min_altitude: optional("MIN_ALTITUDE").map(|value| value.parse().unwrap()),
+        max_altitude: optional("MAX_ALTITUDE").map(|value| value.parse().unwrap()),
+        min_speed: optional("MIN_SPEED").map(|value| value.parse().unwrap()),
+        max_speed: optional("MAX_SPEED").map(|value| value.parse().unwrap()),
+    };
+    let bands = (!bands.is_empty()).then_some(bands);
+    let route_api = get_argument_or_env("ROUTE_API", Some(""));
+    let routes = (!route_api.is_empty()).then(|| {
+        let api: RouteApi = route_api.parse().unwrap();
+        Routes::new(
+            api,
+            &get_argument_or_env("ROUTE_API_URL", Some(api.default_url())),
+            Duration::from_secs(get_argument_or_env("ROUTE_CACHE_SECS", Some("3600")).parse().unwrap()),
+            get_argument_or_env("ROUTE_API_MAX_IN_FLIGHT", Some("4")).parse().unwrap(),
+        )
+    });
+    let mut dedup = (dedup_window_ms > 0).then(|| {
+        let fields = get_argument_or_env("DEDUP_FIELDS", Some(dedup::DEFAULT_FIELDS));
+        Dedup::new(Duration::from_millis(dedup_window_ms), fields.split(',').map(|field| field.trim().to_string()).collect())
+    });
+    // A dry run prints messages instead of sending them anywhere.
+    let dry_run = env::args().any(|arg| arg == "--dry-run" || arg == "--dry_run")
+        || get_argument_or_env("DRY_RUN", Some("false")).parse::<bool>().unwrap();
+    let sink_names = if dry_run { "stdout".to_string() } else { get_argument_or_env("SINKS", Some("dataset")) };
+    let max_memory_mb: usize = get_argument_or_env("MAX_MEMORY_MB", Some("0")).parse().unwrap();
+    let budget = (max_memory_mb > 0).then(|| Arc::new(MemoryBudget::new(max_memory_mb * 1_000_000)));
+    // Sinks with batching of their own regroup and retry the batches sent to them.
+    let mut batching = SinkBatching::from_settings(get_argument_or_env).unwrap();
+    let breaker_failures: u32 = get_argument_or_env("BREAKER_FAILURES", Some("0")).parse().unwrap();
+    let breaker_cooldown = Duration::from_secs(get_argument_or_env("BREAKER_COOLDOWN_SECS", Some("30")).parse().unwrap());
+    let buffer_policy: DropPolicy = get_argument_or_env("BUFFER_POLICY", Some("block")).parse().unwrap();
+    let queue_dir = get_argument_or_env("QUEUE_DIR", Some(""));
+    let buffer = Arc::new(if queue_dir.is_empty() {
+        let buffer = Buffer::new(
+            get_argument_or_env("BUFFER_MAX_MESSAGES", Some("100000")).parse().unwrap(),
+            get_argument_or_env("BUFFER_MAX_BYTES", Some("0")).parse().unwrap(),
+            buffer_policy,
+        );
+        match &budget {
+            Some(budget) => buffer.with_budget(budget.clone()),
+            None => buffer,
+        }
+    } else {
+        let segment_mb: u64 = get_argument_or_env("QUEUE_SEGMENT_MB", Some("16")).parse().unwrap();
+        let max_mb: usize = get_argument_or_env("QUEUE_MAX_MB", Some("1024")).parse().unwrap();
+        let compress = match get_argument_or_env("QUEUE_COMPRESSION", Some("none")).to_lowercase().as_str() {
+            "gzip" => true,
+            "none" => false,
+            other => return Err(format!("unknown queue compression {:?} (expected gzip or none)", other).into()),
+        };
+        let spool = Spool::open(std::path::Path::new(&queue_dir), segment_mb * 1_000_000, compress)?;
+        Buffer::on_disk(spool, max_mb * 1_000_000, buffer_policy)
+    });
+    // Isolated sinks each send from a queue of their own, so that one sink falling behind
+    // does not hold up the others.
+    let isolation: bool = get_argument_or_env("SINK_ISOLATION", Some("false")).parse().unwrap();
+    let sink_queue_max: usize = get_argument_or_env("SINK_QUEUE_MAX_MESSAGES", Some("100000")).parse().unwrap();
+    let sink_queue_policy: DropPolicy = get_argument_or_env("SINK_QUEUE_POLICY", Some("drop-oldest")).parse().unwrap();
+    let sinks: Arc<Vec<Box<dyn Sink>>> = Arc::new(sink_names
+        .split(',')
+        .map(|name| {
+            let mut sink = sink::create(name, get_argument_or_env, &tracker).unwrap();
+            if let Some(batching) = batching.remove(&name.trim().to_lowercase()) {
+                sink = Box::new(BatchedSink::new(sink, batching));
+            }
+            if isolation {
+                let queue = Buffer::new(sink_queue_max, 0, sink_queue_policy);
+                let queue = match &budget {
+                    Some(budget) => queue.with_budget(budget.clone()),
+                    None => queue,
+                };
+                sink = if breaker_failures > 0 {
+                    // A sink that is down holds its batches in its own queue, and its breaker's
+                    // state changes go to every sink through the main buffer.
+                    let breaker = CircuitBreaker::new(sink.name(), breaker_failures, breaker_cooldown);
+                    Box::new(Bulkhead::with_breaker(sink, queue, breaker, buffer.clone()))
+                } else {
+                    Box::new(Bulkhead::new(sink, queue))
+                };
+            }
+            sink
+        })
+        .collect());
+    for name in batching.keys() {
+        eprintln!("Ignoring the batching settings of sink {:?}, which is not in SINKS", name);
+    }
+    // Both lanes share the breakers, so that a sink is judged on everything sent to it.
+    // Isolated sinks have theirs in their bulkhead instead.
+    let breakers = (breaker_failures > 0 && !isolation)
+        .then(|| Arc::new(Mutex::new(sinks.iter().map(|sink| CircuitBreaker::new(sink.name(), breaker_failures, breaker_cooldown)).collect::<Vec<_>>())));
+    let delivery: Delivery = get_argument_or_env("DELIVERY", Some("at-most-once")).parse().unwrap();
+    if delivery == Delivery::AtLeastOnce && queue_dir.is_empty() {
+        eprintln!("DELIVERY=at-least-once keeps unsent batches across restarts only with QUEUE_DIR");
+    }
+    let receiver_lat = get_argument_or_env("RECEIVER_LAT", Some(""));
+    let receiver_lon = get_argument_or_env("RECEIVER_LON", Some(""));
+    let receiver: Option<(f64, f64)> = (!receiver_lat.is_empty() && !receiver_lon.is_empty()).then(|| (receiver_lat.parse().unwrap(), receiver_lon.parse().unwrap()));
+    let coverage_interval: u64 = get_argument_or_env("COVERAGE_INTERVAL_SECS", Some("0")).parse().unwrap();
+    let mut coverage = if coverage_interval == 0 {
+        None
+    } else if receiver.is_none() {
+        eprintln!("COVERAGE_INTERVAL_SECS is ignored without RECEIVER_LAT and RECEIVER_LON");
+        None
+    } else {
+        let sectors: usize = get_argument_or_env("COVERAGE_SECTORS", Some("36")).parse().unwrap();
+        let path = get_argument_or_env("COVERAGE_FILE", Some(""));
+        Some(Coverage::new(sectors, Duration::from_secs(coverage_interval), (!path.is_empty()).then_some(path)))
+    };
+    let decode_met: bool = get_argument_or_env("DECODE_MET", Some("false")).parse().unwrap();
+    let shutdown_timeout = Duration::from_secs(get_argument_or_env("SHUTDOWN_TIMEOUT_SECS", Some("30")).parse().unwrap());
+    let stall_timeout: u64 = get_argument_or_env("STALL_TIMEOUT_SECS", Some("0")).parse().unwrap();
+    let supervisor = (stall_timeout > 0).then(|| Arc::new(Supervisor::new(Duration::from_secs(stall_timeout))));
 
-    // Connecting to a TCP stream
-    let stream = TcpStream::connect(format!("{}:{}", dump1090_host, dump1090_port.to_string()))?;
-    let reader = BufReader::new(stream);
+    // Stop reading on SIGINT or SIGTERM, so that what has been read is still sent.
+    let stopping = Arc::new(AtomicBool::new(false));
+    let input_file = get_argument_or_env("INPUT_FILE", Some(""));
+    let (input, checkpoint) = if input_file.is_empty() {
+        // Connecting to a TCP stream
+        let dump1090_host = get_argument_or_env("DUMP1090_HOST", None);
+        let dump1090_port: u32 = get_argument_or_env("DUMP1090_PORT", None).parse().unwrap();
+        let stream = TcpStream::connect(format!("{}:{}", dump1090_host, dump1090_port))?;
+        tokio::spawn(stop_on_signal(Some(stream.try_clone()?), stopping.clone()));
+        if let Some(supervisor) = &supervisor {
+            tokio::spawn(supervise(supervisor.clone(), Some(stream.try_clone()?), stopping.clone(), shutdown_timeout));
+        }
+        (Input::Tcp(stream), None)
+    } else {
+        // Replay a capture file, resuming where an earlier replay of it left off.
+        let mut file = std::fs::File::open(&input_file)?;
+        let checkpoint_path = get_argument_or_env("REPLAY_CHECKPOINT", Some(&format!("{}.checkpoint", input_file)));
+        let checkpoint_secs: u64 = get_argument_or_env("REPLAY_CHECKPOINT_SECS", Some("5")).parse().unwrap();
+        let (checkpoint, offset) = Checkpoint::open(std::path::Path::new(&checkpoint_path), Duration::from_secs(checkpoint_secs), file.metadata()?.len())?;
+        if offset > 0 {
+            println!("Resuming the replay of {} at byte {} (delete {} to start over)", input_file, offset, checkpoint_path);
+            file.seek(SeekFrom::Start(offset))?;
+        }
+        tokio::spawn(stop_on_signal(None, stopping.clone()));
+        if let Some(supervisor) = &supervisor {
+            tokio::spawn(supervise(supervisor.clone(), None, stopping.clone(), shutdown_timeout));
+        }
+        (Input::File(file), Some(Arc::new(checkpoint)))
+    };
+    let mut reader = BufReader::new(input);
 
     // Initialize a double-ended queue with the specified capacity.
-    let mut messages: VecDeque<SBS1Message> = VecDeque::with_capacity(batch_size);
+    let mut messages: VecDeque<SBS1Message> = VecDeque::with_capacity(batch_size.max(batch_size_max));
+    // Parse failures, with the offending line, waiting to be forwarded as diagnostic events.
+    let mut diagnostics: VecDeque<(ParseError, String)> = VecDeque::new();
+    // When the oldest message or failure still in the queues was queued.
+    let mut oldest: Option<Instant> = None;
+    // Critical messages read since the last were sent on the priority lane.
+    let mut urgent: Vec<SBS1Message> = Vec::new();
+    // Where the line of the earliest of them starts in the capture file being replayed.
+    let mut urgent_from: Option<u64> = None;
+    let mut stats = Stats::default();
+    let mut raw = Vec::new();
+    let mut decoder = match receiver {
+        Some((lat, lon)) => Decoder::with_receiver(lat, lon),
+        None => Decoder::new(),
+    };
+    decoder.set_meteorological(decode_met);
+    let beast_listen = get_argument_or_env("BEAST_LISTEN", Some(""));
+    let beast_output = if beast_listen.is_empty() {
+        None
+    } else if input_format == InputFormat::Sbs {
+        eprintln!("BEAST_LISTEN is ignored: SBS input has no frames to re-serve");
+        None
+    } else {
+        Some(sink::serve(&beast_listen, "Beast")?)
+    };
     
-    // Iterate over each line from the TCP stream.
-    for line in reader.lines() {
-        if let Ok(msg) = line {
-            // Parse the line into an SBS1Message.
-            if let Some(parsed) = parse(&msg) {
-                messages.push_back(parsed);
-                
-                // Send the collected messages when the queue reaches the batch size.
-                if messages.len() >= batch_size {
-                    send_to_service(messages.drain(..).collect(), &dataset_api_write_token, &collector).await?;
+    // Batches are sent in the background, so that reading goes on while sinks are slow.
+    let sender = tokio::spawn(send_batches(buffer.clone(), sinks.clone(), latency, checkpoint.clone(), breakers.clone(), supervisor.clone()));
+    // Critical messages go through a buffer and task of their own, so that they are not
+    // held up behind routine batches.
+    let urgent_buffer = Buffer::new(10_000, 0, DropPolicy::Block);
+    let urgent_buffer = Arc::new(match &budget {
+        Some(budget) => urgent_buffer.with_budget(budget.clone()),
+        None => urgent_buffer,
+    });
+    let urgent_sender = priority_lane.then(|| tokio::spawn(send_batches(urgent_buffer.clone(), sinks.clone(), Arc::new(Latency::default()), checkpoint.clone(), breakers.clone(), None)));
+
+    // Iterate over each line (or Beast frame) from the TCP stream. Lines are read as bytes
+    // so that invalid UTF-8 is counted and skipped instead of ending the stream.
+    if let Some(supervisor) = &supervisor {
+        supervisor.busy(Stage::Reader);
+    }
+    loop {
+        // A file is read without waiting, so a signal is noticed here rather than by ending
+        // the input.
+        if stopping.load(Ordering::Relaxed) {
+            break;
+        }
+
+        // Before waiting for more input, make sure to wake up when the queued messages are
+        // due or a coalescing window passes, so that a quiet feed does not hold them back.
+        // Only whole lines and frames are left unread when the wait times out.
+        let deadline = flush_interval.zip(oldest).map(|(interval, queued)| queued + interval);
+        let deadline = deadline.into_iter().chain(coalescer.as_ref().and_then(Coalescer::next_expiry)).chain(priority.next_check()).chain(coverage.as_ref().map(Coverage::next_due)).min();
+        let mut idle = false;
+        if let Some(deadline) = deadline {
+            if reader.buffer().is_empty() {
+                let wait = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+                reader.get_ref().set_read_timeout(Some(wait))?;
+                let ready = reader.fill_buf().map(|_| ());
+                reader.get_ref().set_read_timeout(None)?;
+                match ready {
+                    Ok(()) => {}
+                    Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => idle = true,
+                    Err(err) => {
+                        eprintln!("Read error: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Where the line (or frame) about to be read starts, to keep the replay checkpoint
+        // behind it while its message is held back.
+        let line_start = checkpoint.as_ref().filter(|_| !idle).and_then(|_| reader.stream_position().ok());
+        let parsed = if idle {
+            None
+        } else if input_format == InputFormat::Beast {
+            match beast::read_frame(&mut reader) {
+                Ok(Some(frame)) => {
+                    stats.frames_read += 1;
+                    if let Some(supervisor) = &supervisor {
+                        supervisor.progressed(Stage::Reader);
+                    }
+                    forward_frame(&beast_output, &frame);
+                    decoder.decode(&frame)
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    eprintln!("Read error: {}", err);
+                    break;
+                }
+            }
+        } else {
+            raw.clear();
+            match reader.read_until(b'\n', &mut raw) {
+                Ok(0) => break,
+                Ok(_) => {
+                    stats.lines_read += 1;
+                    if let Some(supervisor) = &supervisor {
+                        supervisor.progressed(Stage::Reader);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Read error: {}", err);
+                    break;
+                }
+            }
+
+            let msg = match clean_line(&raw) {
+                RawLine::Clean(text) => text,
+                RawLine::Repaired(text) => {
+                    stats.lines_repaired += 1;
+                    text
                 }
+                RawLine::Garbled(_) => {
+                    stats.lines_garbled += 1;
+                    continue;
+                }
+                RawLine::Truncated(_) => {
+                    stats.lines_truncated += 1;
+                    continue;
+                }
+            };
+
+            if input_format == InputFormat::Raw {
+                // Decode the AVR-formatted line as a Mode S frame.
+                avr::parse_frame(&msg).and_then(|frame| {
+                    stats.frames_read += 1;
+                    forward_frame(&beast_output, &frame);
+                    decoder.decode(&frame)
+                })
+            } else if strict_parsing {
+                // Parse the line into an SBS1Message.
+                match parse(&msg) {
+                    Ok(parsed) => Some(parsed),
+                    Err(err) => {
+                        eprintln!("Parse error: {} in line {:?}", err, msg);
+                        if forward_parse_errors {
+                            diagnostics.push_back((err, msg));
+                        }
+                        None
+                    }
+                }
+            } else {
+                parse_opt(&msg)
+            }
+        };
+        if parsed.is_none() && !idle {
+            if input_format == InputFormat::Sbs {
+                stats.lines_skipped += 1;
+            } else {
+                stats.frames_skipped += 1;
+            }
+        }
+        // The parser is stalled while what is read keeps failing to make messages.
+        if let (Some(supervisor), false) = (&supervisor, idle) {
+            if parsed.is_some() {
+                supervisor.done(Stage::Parser);
+            } else {
+                supervisor.busy(Stage::Parser);
             }
         }
+
+        if let Some(mut parsed) = parsed {
+            stats.messages_parsed += 1;
+
+            // Clear or drop physically impossible values before they reach DataSet.
+            let valid = match validation {
+                ValidationMode::Off => true,
+                ValidationMode::Sanitize => {
+                    if validate::sanitize(&mut parsed) {
+                        stats.messages_sanitized += 1;
+                    }
+                    true
+                }
+                ValidationMode::Reject => {
+                    if validate::check(&parsed).is_empty() {
+                        true
+                    } else {
+                        stats.messages_rejected += 1;
+                        false
+                    }
+                }
+            };
+
+            // Track the aircraft's state, then drop the traffic outside the geofence or the
+            // altitude and speed bands, judging messages without a position, altitude or speed
+            // by their aircraft's last known one.
+            if valid {
+                tracker.update(&parsed);
+            }
+            let filtered = valid && (geofence.is_some() || bands.is_some()) && {
+                let last = parsed.icao24.and_then(|icao24| tracker.get(icao24)).unwrap_or_default();
+                let position = parsed.lon.zip(parsed.lat).or(last.position());
+                geofence.as_ref().is_some_and(|geofence| !geofence.allows(position))
+                    || bands.is_some_and(|bands| !bands.allows(parsed.altitude_baro.or(last.altitude), parsed.ground_speed.or(last.ground_speed)))
+            };
+            if filtered {
+                stats.messages_filtered += 1;
+            }
+
+            // Add what else is known of the aircraft: what its earlier messages reported, its
+            // registration and its country, type, airline and route, whether it is military,
+            // and its distance and bearing from the receiver, which also counts towards the
+            // coverage. Then leave messages out of the sample and drop repeats of what the
+            // aircraft sent moments before. Critical messages skip batching.
+            if valid && !filtered {
+                if enrich {
+                    tracker.enrich(&mut parsed);
+                }
+                if let Some(registry) = &mut registry {
+                    registry.enrich(&mut parsed);
+                }
+                countries.enrich(&mut parsed);
+                if let Some(aircraft_db) = &aircraft_db {
+                    aircraft_db.enrich(&mut parsed);
+                }
+                if let Some(operators) = &operators {
+                    operators.enrich(&mut parsed);
+                }
+                if let Some(routes) = &routes {
+                    routes.enrich(&mut parsed);
+                }
+                if let Some(military) = &military {
+                    military.enrich(&mut parsed);
+                }
+                if let (Some(receiver), Some(lat), Some(lon)) = (receiver, parsed.lat, parsed.lon) {
+                    // Rounded to a tenth, well within the precision of the positions.
+                    parsed.distance_nm = Some((geo::distance_nm(receiver, (lat, lon)) * 10.0).round() / 10.0);
+                    parsed.bearing_deg = Some((geo::bearing_deg(receiver, (lat, lon)) * 10.0).round() / 10.0 % 360.0);
+                    if let Some(coverage) = &mut coverage {
+                        coverage.record(&parsed);
+                    }
+                }
+                if sampler.as_ref().is_some_and(|sampler| !sampler.keep(&parsed)) {
+                    stats.messages_sampled_out += 1;
+                } else if dedup.as_mut().is_some_and(|dedup| dedup.is_duplicate(&parsed)) {
+                    stats.messages_deduplicated += 1;
+                } else if priority_lane && priority.is_critical(&parsed) {
+                    urgent_from = urgent_from.or(line_start);
+                    urgent.push(parsed);
+                } else if let Some(coalescer) = &mut coalescer {
+                    messages.extend(coalescer.add(parsed, line_start));
+                } else {
+                    messages.push_back(parsed);
+                }
+            }
+        }
+        if let Some(coalescer) = &mut coalescer {
+            messages.extend(coalescer.expired());
+        }
+        let mut lost = priority.lost(&tracker);
+        lost.iter_mut().for_each(|message| countries.enrich(message));
+        if priority_lane {
+            urgent.extend(lost);
+        } else {
+            messages.extend(lost);
+        }
+        messages.extend(coverage.as_mut().and_then(Coverage::summary));
+        if !urgent.is_empty() {
+            stats.messages_prioritized += urgent.len() as u64;
+            let batch = Batch::new(std::mem::take(&mut urgent), Vec::new());
+            // The replay checkpoint must not pass these lines before the batch is sent.
+            if let (Some(checkpoint), Some(offset)) = (&checkpoint, urgent_from.take()) {
+                checkpoint.hold(batch.id, offset);
+            }
+            if !urgent_buffer.push(batch).await {
+                break;
+            }
+        }
+
+        if oldest.is_none() && !(messages.is_empty() && diagnostics.is_empty()) {
+            oldest = Some(Instant::now());
+        }
+
+        // Send the collected messages when the queue reaches the batch size or the oldest of
+        // them has waited for the flush interval.
+        let due = flush_interval.zip(oldest).is_some_and(|(interval, queued)| queued.elapsed() >= interval);
+        let size = sizer.as_ref().map_or(batch_size, BatchSizer::size);
+        if messages.len() + diagnostics.len() >= size || due {
+            if let Some(sizer) = &mut sizer {
+                sizer.taken(messages.len() + diagnostics.len());
+            }
+            let mut batch = take_batch(&mut messages, &mut diagnostics, &mut rate_limit, &mut stats);
+            // Lines whose messages the coalescer still holds are read again after a restart.
+            let held_from = coalescer.as_ref().and_then(Coalescer::held_from);
+            batch.offset = reader.stream_position().ok().map(|position| held_from.map_or(position, |held| held.min(position)));
+            // Pushing only fails once sending has stopped with an error, reported below.
+            if !buffer.push(batch).await {
+                break;
+            }
+            print_stats(&stats, &buffer, budget.as_deref(), &sinks);
+            if let Some(sizer) = &sizer {
+                println!("Batching: {}", sizer);
+            }
+            oldest = None;
+        }
     }
     
-    // Send any remaining messages if there are any left in the queue.
-    if !messages.is_empty() {
-        send_to_service(messages.drain(..).collect(), &dataset_api_write_token, &collector).await?;
+    // Reading has ended, so only sending is left to watch.
+    if let Some(supervisor) = &supervisor {
+        supervisor.done(Stage::Reader);
+        supervisor.done(Stage::Parser);
+    }
+
+    // Send any remaining messages if there are any left in the queue, and wait for the
+    // buffered batches to be sent.
+    if let Some(coalescer) = &mut coalescer {
+        messages.extend(coalescer.drain());
+    }
+    let mut batch = take_batch(&mut messages, &mut diagnostics, &mut rate_limit, &mut stats);
+    batch.offset = reader.stream_position().ok();
+    let end = batch.offset;
+    let flush = async {
+        if !batch.is_empty() {
+            buffer.push(batch).await;
+        }
+        buffer.close();
+        urgent_buffer.close();
+        sender.await??;
+        // Everything read has been sent, even if the last lines made no batch.
+        if let (Some(checkpoint), Some(end)) = (&checkpoint, end) {
+            checkpoint.sent(end);
+            checkpoint.save();
+        }
+        if let Some(urgent_sender) = urgent_sender {
+            urgent_sender.await??;
+        }
+        for sink in sinks.iter() {
+            sink.close().await?;
+        }
+        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+    };
+    let restarting = supervisor.as_ref().is_some_and(|supervisor| supervisor.restarting());
+    if stopping.load(Ordering::Relaxed) {
+        // Batches still queued on disk are replayed on the next start.
+        if tokio::time::timeout(shutdown_timeout, flush).await.is_err() {
+            print_stats(&stats, &buffer, budget.as_deref(), &sinks);
+            if restarting {
+                return Err(supervisor::restart().into());
+            }
+            eprintln!("Timed out after {:?} sending what was read; exiting", shutdown_timeout);
+            std::process::exit(1);
+        }
+    } else {
+        flush.await?;
+    }
+    print_stats(&stats, &buffer, budget.as_deref(), &sinks);
+    if restarting {
+        println!("Restarting the pipeline");
+        return Err(supervisor::restart().into());
     }
 
     Ok(())
 }
 
-/// Send a batch of parsed messages to the DataSet web service.
-///
-/// This function constructs the payload for the DataSet web service, sends it, 
-/// and logs the response.
+/// Checks the stages of the pipeline for stalls, and restarts it when one has stalled: like
+/// on a signal, reading stops and what has been read is sent, and the program is then run
+/// again in place of this process. If sending does not finish within the shutdown timeout,
+/// e.g. because the sender is what stalled, the program is run again regardless.
 ///
 /// # Arguments
 ///
-/// * `messages` - A vector of parsed SBS1 messages to send to the DataSet web service.
-/// * `dataset_api_write_token` - The API write token for the DataSet web service.
-/// * `collector` - The collector (or source) identifier.
+/// * `supervisor` - The progress of the stages.
+/// * `stream` - A handle to the feed connection, unless a file is replayed.
+/// * `stopping` - Set once a stall has been found, to stop reading.
+/// * `shutdown_timeout` - How long sending what has been read may take.
+async fn supervise(supervisor: Arc<Supervisor>, stream: Option<TcpStream>, stopping: Arc<AtomicBool>, shutdown_timeout: Duration) {
+    let period = (supervisor.timeout() / 10).clamp(Duration::from_millis(100), Duration::from_secs(5));
+    let (stage, stalled) = loop {
+        tokio::time::sleep(period).await;
+        if stopping.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(stall) = supervisor.stalled() {
+            break stall;
+        }
+    };
+    eprintln!("Pipeline stalled: the {} has made no progress for {:.0?}; restarting", stage, stalled);
+    supervisor.set_restarting();
+    stopping.store(true, Ordering::Relaxed);
+    if let Some(Err(err)) = stream.map(|stream| stream.shutdown(std::net::Shutdown::Read)) {
+        eprintln!("Error closing the feed connection: {}", err);
+    }
+    tokio::time::sleep(shutdown_timeout).await;
+    eprintln!("Timed out after {:?} sending what was read; restarting anyway", shutdown_timeout);
+    eprintln!("Error restarting: {}", supervisor::restart());
+    std::process::exit(1);
+}
+
+/// Waits for SIGINT or SIGTERM, then stops reading from the feed by shutting down the
+/// reading half of the connection, so that the main loop ends as if the feed had closed. A
+/// second signal exits at once, without sending what is left.
 ///
-/// # Returns
+/// # Arguments
 ///
-/// A Result indicating the success or failure of the operation.
-async fn send_to_service(messages: Vec<SBS1Message>, dataset_api_write_token: &str, collector: &str) -> Result<(), reqwest::Error> {
-    // Construct the event payload for each message.
-    let events: Vec<Value> = messages.into_iter().map(|message| {
-        json!({
-            "parser": "adsb",
-            "ts": message.timestamp,
-            "source": collector,
-            "collector": "imichaelmoore/adsb-rust-dataset",
-            "sev": 3,
-            "attrs": {"message": message}
-        })
-    }).collect();
-
-    // Construct the final payload to be sent to the DataSet web service.
-    let payload = json!({
-        "session": Uuid::new_v4(),
-        "sessionInfo": {
-            "source": collector,
-            "collector": "imichaelmoore/adsb-rust-dataset",
-        },
-        "events": events,
-        "threads": []
-    });
+/// * `stream` - A handle to the feed connection, unless a file is replayed.
+/// * `stopping` - Set once a signal has been received.
+async fn stop_on_signal(stream: Option<TcpStream>, stopping: Arc<AtomicBool>) {
+    shutdown_signal().await;
+    println!("Shutting down: sending what has been read (signal again to exit at once)");
+    stopping.store(true, Ordering::Relaxed);
+    if let Some(Err(err)) = stream.map(|stream| stream.shutdown(std::net::Shutdown::Read)) {
+        eprintln!("Error closing the feed connection: {}", err);
+    }
+    shutdown_signal().await;
+    eprintln!("Exiting without sending what is left");
+    std::process::exit(130);
+}
 
-    // println!("{}", serde_json::to_string_pretty(&payload).unwrap());
+/// Returns when the process receives SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("a SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
 
+/// Takes the queued messages and parse failures as a batch, dropping the messages over the
+/// per-aircraft rate limit if there is one.
+///
+/// # Arguments
+///
+/// * `messages` - The queued messages.
+/// * `diagnostics` - The queued parse failures and their lines.
+/// * `rate_limit` - The per-aircraft rate limit, if any.
+/// * `stats` - The counters, to count the messages dropped.
+fn take_batch(
+    messages: &mut VecDeque<SBS1Message>,
+    diagnostics: &mut VecDeque<(ParseError, String)>,
+    rate_limit: &mut Option<AircraftRateLimit>,
+    stats: &mut Stats,
+) -> Batch {
+    let mut batch = Batch::new(messages.drain(..).collect(), diagnostics.drain(..).collect());
+    if let Some(rate_limit) = rate_limit {
+        let (kept, dropped) = rate_limit.apply(batch.messages);
+        batch.messages = kept;
+        stats.messages_rate_limited += dropped as u64;
+    }
+    batch
+}
 
-    // Send the payload to the DataSet web service.
-    let client = reqwest::Client::new();
-    let res = client.post("https://app.scalyr.com/api/addEvents")
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", dataset_api_write_token))
-        .json(&payload)
-        .send()
-        .await?;
+/// Re-serves a frame in the Beast format to the clients of BEAST_LISTEN, if it is set.
+///
+/// # Arguments
+///
+/// * `output` - The channel to the Beast clients, if any.
+/// * `frame` - The frame as read from the feed.
+fn forward_frame(output: &Option<broadcast::Sender<Arc<[u8]>>>, frame: &Frame) {
+    if let (Some(output), Some(encoded)) = (output, beast::encode_frame(frame)) {
+        // Sending only fails when no client is connected, which is fine.
+        let _ = output.send(encoded.into());
+    }
+}
 
-    // Log the response from the DataSet web service.
-    println!("Response: {:?}", res.text().await?);
+/// Prints the forwarder's counters, the state of the buffer and memory budget, and the
+/// queue of each isolated sink and what each sink that keeps count has uploaded.
+///
+/// # Arguments
+///
+/// * `stats` - The forwarder's counters.
+/// * `buffer` - The buffer of batches waiting to be sent.
+/// * `budget` - The memory budget of the buffered batches, if any.
+/// * `sinks` - The sinks.
+fn print_stats(stats: &Stats, buffer: &Buffer, budget: Option<&MemoryBudget>, sinks: &[Box<dyn Sink>]) {
+    println!("Stats: {}", stats);
+    println!("Buffer: {}", buffer);
+    if let Some(budget) = budget {
+        println!("Memory: {}", budget);
+    }
+    for sink in sinks {
+        if let Some(queue) = sink.queue() {
+            println!("Queue ({}): {}", sink.name(), queue);
+        }
+        if let Some(upload_stats) = sink.upload_stats() {
+            println!("Upload stats ({}): {}", sink.name(), upload_stats);
+        }
+    }
+}
 
+/// Sends the batches pushed to the buffer until it is closed and empty. If sending fails,
+/// the buffer is closed so that reading stops.
+///
+/// # Arguments
+///
+/// * `buffer` - The buffer to take batches from.
+/// * `sinks` - The sinks to send to.
+/// * `latency` - Records how long each batch takes to send, for adaptive batch sizing.
+/// * `checkpoint` - The replay checkpoint, moved past each batch sent and released from
+///   each batch held back, if a file is replayed.
+/// * `breakers` - The circuit breakers of the sinks, in the same order, if failed batches
+///   are held and retried rather than stopping sending.
+/// * `supervisor` - Watches sending for stalls, if enabled.
+///
+/// # Returns
+///
+/// A Result indicating the success or failure of sending.
+async fn send_batches(
+    buffer: Arc<Buffer>,
+    sinks: Arc<Vec<Box<dyn Sink>>>,
+    latency: Arc<Latency>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    breakers: Option<Arc<Mutex<Vec<CircuitBreaker>>>>,
+    supervisor: Option<Arc<Supervisor>>,
+) -> Result<(), SinkError> {
+    let mut result = Ok(());
+    while let Some(batch) = buffer.pop().await {
+        let started = Instant::now();
+        if let Some(supervisor) = &supervisor {
+            supervisor.busy(Stage::Sender);
+        }
+        let sent = match &breakers {
+            Some(breakers) => {
+                send_with_breakers(&sinks, breakers, &batch, supervisor.as_deref()).await;
+                Ok(())
+            }
+            None => send_to_sinks(&sinks, &batch).await,
+        };
+        if let Some(supervisor) = &supervisor {
+            supervisor.done(Stage::Sender);
+        }
+        if let Err(err) = sent {
+            buffer.close();
+            result = Err(err);
+            break;
+        }
+        latency.record(started.elapsed());
+        buffer.done();
+        if let Some(checkpoint) = &checkpoint {
+            checkpoint.release(batch.id);
+            if let Some(offset) = batch.offset {
+                checkpoint.sent(offset);
+            }
+        }
+    }
+    // Keep how far the replay got, even if sending failed.
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.save();
+    }
+    result
+}
+
+/// Sends a batch of messages and parse failures to every sink.
+///
+/// # Arguments
+///
+/// * `sinks` - The sinks to send to.
+/// * `batch` - The parsed messages, and parse failures for sinks that forward them.
+///
+/// # Returns
+///
+/// A Result indicating the success or failure of the operation.
+async fn send_to_sinks(sinks: &[Box<dyn Sink>], batch: &Batch) -> Result<(), SinkError> {
+    for sink in sinks {
+        sink.send_batch(batch).await?;
+    }
     Ok(())
 }
+
+/// Sends a batch to every sink, holding it for the sinks that fail until their circuit
+/// breaker lets a retry through after a backoff or its cooldown, so that it is neither lost
+/// nor sent twice to a sink that took it. Meanwhile, further batches wait in the buffer.
+///
+/// # Arguments
+///
+/// * `sinks` - The sinks to send to.
+/// * `breakers` - The circuit breakers of the sinks, in the same order.
+/// * `batch` - The parsed messages, and parse failures for sinks that forward them.
+/// * `supervisor` - Watches sending for stalls, if enabled. Waiting for a breaker to let a
+///   retry through does not count as a stall.
+async fn send_with_breakers(sinks: &[Box<dyn Sink>], breakers: &Mutex<Vec<CircuitBreaker>>, batch: &Batch, supervisor: Option<&Supervisor>) {
+    let mut pending: Vec<usize> = (0..sinks.len()).collect();
+    loop {
+        let mut events = Vec::new();
+        let mut failed = Vec::new();
+        let mut next: Option<Instant> = None;
+        for index in pending {
+            let (until, event) = breakers.lock().unwrap()[index].ready();
+            events.extend(event);
+            if let Some(until) = until {
+                next = Some(next.map_or(until, |next| next.min(until)));
+                failed.push(index);
+                continue;
+            }
+            match sinks[index].send_batch(batch).await {
+                Ok(()) => events.extend(breakers.lock().unwrap()[index].succeeded()),
+                Err(err) => {
+                    eprintln!("Error sending to sink {}: {}", sinks[index].name(), err);
+                    // Wait before retrying, even while the breaker is still closed, rather
+                    // than send again at once to an endpoint that is down.
+                    let (until, event) = breakers.lock().unwrap()[index].failed();
+                    events.extend(event);
+                    next = Some(next.map_or(until, |next| next.min(until)));
+                    failed.push(index);
+                }
+            }
+        }
+        announce(sinks, breakers, &events).await;
+        if failed.is_empty() {
+            return;
+        }
+        pending = failed;
+        if let Some(next) = next {
+            if let Some(supervisor) = supervisor {
+                supervisor.done(Stage::Sender);
+            }
+            tokio::time::sleep_until(next.into()).await;
+            if let Some(supervisor) = supervisor {
+                supervisor.busy(Stage::Sender);
+            }
+        }
+    }
+}
+
+/// Sends circuit breaker state changes to the sinks whose breaker is closed. Failures are
+/// only logged, as they count against the sink when the batch is sent.
+///
+/// # Arguments
+///
+/// * `sinks` - The sinks.
+/// * `breakers` - The circuit breakers of the sinks, in the same order.
+/// * `events` - The state changes, as `STA` messages.
+async fn announce(sinks: &[Box<dyn Sink>], breakers: &Mutex<Vec<CircuitBreaker>>, events: &[SBS1Message]) {
+    if events.is_empty() {
+        return;
+    }
+    for (index, sink) in sinks.iter().enumerate() {
+        if breakers.lock().unwrap()[index].state() != breaker::State::Closed {
+            continue;
+        }
+        if let Err(err) = sink.send(events).await {
+            eprintln!("Error reporting a circuit breaker change to sink {}: {}", sink.name(), err);
+        }
+    }
+}
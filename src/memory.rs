@@ -0,0 +1,106 @@
+//! This module keeps count of the memory taken by the batches queued in memory, so that
+//! buffering through a long sink outage stays within a budget instead of growing until the
+//! OOM killer steps in, e.g. on a single-board computer with 256MB.
+//!
+//! Sizes are estimated from the messages' fields rather than measured, and leave out the
+//! allocator's overhead, so the budget should leave some room below the memory available.
+
+use crate::buffer::Batch;
+use crate::parse::{ParseError, SBS1Message};
+use std::fmt;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The estimated overhead of each entry in a `BTreeMap`, beyond its key and value.
+const MAP_ENTRY_OVERHEAD: usize = 32;
+
+/// The memory that queued batches may take, shared by the buffers that hold them.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    /// The most bytes the batches may take.
+    max: usize,
+    /// The bytes the queued batches take.
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Creates a memory budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The most bytes the queued batches may take.
+    pub fn new(max: usize) -> Self {
+        MemoryBudget { max, used: AtomicUsize::new(0) }
+    }
+
+    /// Returns whether a batch of the given size fits in the budget next to those queued.
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.used.load(Ordering::Relaxed) + bytes <= self.max
+    }
+
+    /// Records that a batch of the given size was queued.
+    pub fn add(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Records that a batch of the given size left the queue.
+    pub fn remove(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for MemoryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let used = self.used.load(Ordering::Relaxed);
+        write!(f, "used_bytes={} max_bytes={} used_pct={:.1}", used, self.max, used as f64 * 100.0 / self.max.max(1) as f64)
+    }
+}
+
+/// Estimates the memory a batch takes: the batch itself, and its messages and parse
+/// failures with what they hold on the heap.
+pub fn batch_size(batch: &Batch) -> usize {
+    let messages: usize = batch.messages.iter().map(message_heap_size).sum();
+    let diagnostics: usize = batch.diagnostics.iter().map(|(_, line)| line.capacity()).sum();
+    size_of::<Batch>()
+        + batch.messages.capacity() * size_of::<SBS1Message>()
+        + messages
+        + batch.diagnostics.capacity() * size_of::<(ParseError, String)>()
+        + diagnostics
+}
+
+/// Estimates the memory a message holds on the heap, beyond the message itself.
+fn message_heap_size(message: &SBS1Message) -> usize {
+    let strings = [
+        &message.message_type,
+        &message.session_id,
+        &message.aircraft_id,
+        &message.flight_id,
+        &message.callsign,
+        &message.source_type,
+        &message.category_code,
+        &message.category,
+        &message.registration,
+        &message.registration_country,
+        &message.type_code,
+        &message.owner,
+        &message.manufacturer,
+        &message.model,
+        &message.operator,
+        &message.operator_country,
+        &message.military_force,
+        &message.origin,
+        &message.destination,
+        &message.event,
+    ];
+    let extras: usize = message.extras.iter().map(|(name, value)| name.capacity() + value.capacity() + MAP_ENTRY_OVERHEAD).sum();
+    let invalid_fields: usize = message.invalid_fields.iter().map(|field| size_of::<String>() + field.capacity()).sum();
+    let enriched: usize = message.enriched.iter().map(|field| size_of::<String>() + field.capacity()).sum();
+    message.timestamp.capacity()
+        + strings.iter().filter_map(|string| string.as_ref()).map(String::capacity).sum::<usize>()
+        + extras
+        + invalid_fields
+        + enriched
+        + message.ehs.as_ref().map_or(0, |ehs| ehs.bds.capacity())
+        + message.target_state.as_ref().and_then(|state| state.selected_altitude_source.as_ref()).map_or(0, String::capacity)
+        + message.coverage.as_ref().map_or(0, |coverage| coverage.max_range_nm.capacity() * size_of::<Option<f64>>())
+}
@@ -0,0 +1,55 @@
+//! This module tags military and government aircraft by their ICAO 24-bit address, many of
+//! which fall in blocks their states set aside for them, so that they can drive alerts of
+//! their own.
+//!
+//! A table of blocks known to be used by air forces is built in, labelled by country, and a
+//! CSV file with the columns `start`, `end` and `label` can add to it, e.g. to label a block
+//! by the force flying it.
+
+use crate::blocks::AddressBlocks;
+use crate::parse::{Icao24, SBS1Message};
+use std::error::Error;
+
+/// The built-in table of military blocks.
+const BUILTIN: &str = include_str!("military.csv");
+
+/// The blocks of addresses used by military and government aircraft.
+pub struct Military {
+    blocks: AddressBlocks,
+}
+
+impl Military {
+    /// Creates the built-in table of military blocks.
+    pub fn builtin() -> Self {
+        let mut blocks = AddressBlocks::default();
+        blocks.read(BUILTIN.as_bytes()).expect("the built-in table of military blocks is valid");
+        Military { blocks }
+    }
+
+    /// Creates the built-in table of military blocks, with those in a CSV file added to it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the table, or an error if the file could not be read.
+    pub fn with_file(path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut military = Self::builtin();
+        military.blocks.read_file(path)?;
+        Ok(military)
+    }
+
+    /// Returns the label of the military block an address is in, if it is in one. Where
+    /// blocks overlap, the narrowest wins.
+    pub fn lookup(&self, icao24: Icao24) -> Option<&str> {
+        self.blocks.find(icao24)
+    }
+
+    /// Tags a message from an aircraft with a military address as military, with the force
+    /// or country its block is labelled by.
+    pub fn enrich(&self, message: &mut SBS1Message) {
+        let Some(label) = message.icao_address().and_then(|icao24| self.lookup(icao24)) else {
+            return;
+        };
+        message.military = Some(true);
+        message.military_force = Some(label.to_string()).filter(|label| !label.is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+//! This module parses frames in the raw AVR text format (dump1090 port 30002).
+//!
+//! Frames look like `*8D4840D6202CC371C32CE0576098;`, or `@` followed by a 12-digit
+//! hex timestamp and the frame when timestamps are enabled.
+
+use super::Frame;
+
+/// Parses one AVR line into a `Frame`.
+///
+/// # Arguments
+///
+/// * `line` - A string slice containing one AVR-formatted frame.
+///
+/// # Returns
+///
+/// An `Option` containing the `Frame`, or `None` if the line is not a well-formed frame.
+pub fn parse_frame(line: &str) -> Option<Frame> {
+    let body = line.trim().strip_suffix(';')?;
+    let (mlat_timestamp, hex) = if let Some(hex) = body.strip_prefix('*') {
+        (None, hex)
+    } else if let Some(rest) = body.strip_prefix('@') {
+        let timestamp = u64::from_str_radix(rest.get(..12)?, 16).ok()?;
+        (Some(timestamp), rest.get(12..)?)
+    } else {
+        return None;
+    };
+
+    if !matches!(hex.len(), 4 | 14 | 28) {
+        return None;
+    }
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(Frame { data, mlat_timestamp, signal: None })
+}
@@ -0,0 +1,121 @@
+//! This module reads frames in the binary Beast format (dump1090 port 30005).
+//!
+//! Each frame starts with `0x1a`, followed by a type byte, a 6-byte 12 MHz timestamp, a
+//! signal level byte, and the message itself. Any `0x1a` inside the frame is doubled.
+//! Frames can also be encoded again, to re-serve them to other Beast consumers.
+
+use super::Frame;
+use std::io::{self, Read};
+
+/// Marks the start of a frame; doubled when it occurs inside one.
+const ESCAPE: u8 = 0x1a;
+
+/// Reads the next Mode S or Mode A/C frame, skipping other frame types and resynchronizing
+/// after corrupt data.
+///
+/// # Arguments
+///
+/// * `reader` - The Beast byte stream.
+///
+/// # Returns
+///
+/// The next `Frame`, or `Ok(None)` once the stream ends.
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Frame>> {
+    // Set when an unescaped 0x1a cut a frame short, holding the type byte of the next one.
+    let mut interrupted_by = None;
+
+    loop {
+        let kind = match interrupted_by.take() {
+            Some(kind) => kind,
+            None => {
+                match read_byte(reader)? {
+                    None => return Ok(None),
+                    Some(ESCAPE) => {}
+                    Some(_) => continue,
+                }
+                match read_byte(reader)? {
+                    None => return Ok(None),
+                    Some(kind) => kind,
+                }
+            }
+        };
+
+        let message_len = match kind {
+            b'1' => 2,
+            b'2' => 7,
+            b'3' => 14,
+            _ => continue,
+        };
+
+        // Six timestamp bytes and one signal byte precede the message.
+        let mut body = Vec::with_capacity(7 + message_len);
+        while body.len() < 7 + message_len {
+            match read_byte(reader)? {
+                None => return Ok(None),
+                Some(ESCAPE) => match read_byte(reader)? {
+                    None => return Ok(None),
+                    Some(ESCAPE) => body.push(ESCAPE),
+                    Some(next) => {
+                        interrupted_by = Some(next);
+                        break;
+                    }
+                },
+                Some(byte) => body.push(byte),
+            }
+        }
+        if interrupted_by.is_some() {
+            continue;
+        }
+
+        let mlat_timestamp = body[..6].iter().fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+        return Ok(Some(Frame {
+            data: body[7..].to_vec(),
+            mlat_timestamp: Some(mlat_timestamp),
+            signal: Some(body[6]),
+        }));
+    }
+}
+
+/// Encodes a frame in the Beast format.
+///
+/// Frames without a timestamp or signal level (from AVR input) get zeros, which consumers
+/// treat as unknown.
+///
+/// # Arguments
+///
+/// * `frame` - The frame to encode.
+///
+/// # Returns
+///
+/// The encoded frame, or `None` if the frame has an invalid length.
+pub fn encode_frame(frame: &Frame) -> Option<Vec<u8>> {
+    let kind = match frame.data.len() {
+        2 => b'1',
+        7 => b'2',
+        14 => b'3',
+        _ => return None,
+    };
+
+    let mut body = frame.mlat_timestamp.unwrap_or_default().to_be_bytes()[2..].to_vec();
+    body.push(frame.signal.unwrap_or_default());
+    body.extend_from_slice(&frame.data);
+
+    let mut encoded = vec![ESCAPE, kind];
+    for byte in body {
+        encoded.push(byte);
+        if byte == ESCAPE {
+            encoded.push(ESCAPE);
+        }
+    }
+    Some(encoded)
+}
+
+/// Reads a single byte, returning `None` at the end of the stream.
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match reader.read_exact(&mut byte) {
+        Ok(()) => Ok(Some(byte[0])),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
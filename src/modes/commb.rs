@@ -0,0 +1,310 @@
+//! This module decodes Comm-B (DF20/21) replies carrying Mode S enhanced surveillance
+//! registers.
+//!
+//! Replies do not say which register they carry, so each supported register is checked for
+//! internal consistency (reserved bits, status bits, plausible ranges) and a reply is only
+//! decoded when exactly one register matches.
+
+use super::bits;
+use serde_derive::{Deserialize, Serialize};
+
+/// Values decoded from BDS 4,0 (selected vertical intention), 4,4 (meteorological routine
+/// air report), 5,0 (track and turn), and 6,0 (heading and speed) registers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnhancedSurveillance {
+    /// The register the values came from, e.g. `"4,0"`.
+    pub bds: String,
+    /// MCP/FCU selected altitude in feet (BDS 4,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_altitude_mcp: Option<i32>,
+    /// FMS selected altitude in feet (BDS 4,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_altitude_fms: Option<i32>,
+    /// Barometric pressure setting in hPa (BDS 4,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baro_setting: Option<f64>,
+    /// Wind speed in knots (BDS 4,4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wind_speed: Option<i32>,
+    /// Direction the wind blows from, in degrees true (BDS 4,4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wind_direction: Option<f64>,
+    /// Static air temperature in °C (BDS 4,4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub static_temperature: Option<f64>,
+    /// Static air pressure in hPa (BDS 4,4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub static_pressure: Option<i32>,
+    /// Turbulence level from 0 (nil) to 3 (severe) (BDS 4,4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turbulence: Option<u8>,
+    /// Relative humidity in percent (BDS 4,4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<f64>,
+    /// Roll angle in degrees, positive for right wing down (BDS 5,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll_angle: Option<f64>,
+    /// True track angle in degrees (BDS 5,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub true_track: Option<f64>,
+    /// Ground speed in knots (BDS 5,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ground_speed: Option<i32>,
+    /// Track angle rate in degrees per second (BDS 5,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_rate: Option<f64>,
+    /// True airspeed in knots (BDS 5,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub true_airspeed: Option<i32>,
+    /// Magnetic heading in degrees (BDS 6,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub magnetic_heading: Option<f64>,
+    /// Indicated airspeed in knots (BDS 6,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indicated_airspeed: Option<i32>,
+    /// Mach number (BDS 6,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mach: Option<f64>,
+    /// Barometric altitude rate in feet per minute (BDS 6,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baro_vertical_rate: Option<i32>,
+    /// Inertial vertical velocity in feet per minute (BDS 6,0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inertial_vertical_rate: Option<i32>,
+}
+
+/// Decodes the 56-bit MB field of a Comm-B reply.
+///
+/// # Arguments
+///
+/// * `mb` - The MB field, right-aligned.
+/// * `meteorological` - Whether to also consider BDS 4,4. Its weak structure makes false
+///   matches more likely, so it is only checked when asked for.
+///
+/// # Returns
+///
+/// An `Option` with the decoded register, or `None` if no supported register (or more
+/// than one) matches.
+pub fn decode(mb: u64, meteorological: bool) -> Option<EnhancedSurveillance> {
+    let bds44 = if meteorological { bds44(mb) } else { None };
+    let mut candidates = [bds40(mb), bds44, bds50(mb), bds60(mb)].into_iter().flatten();
+    match (candidates.next(), candidates.next()) {
+        (Some(register), None) => Some(register),
+        _ => None,
+    }
+}
+
+/// Extracts a status bit and the value following it, requiring the value (and sign, when
+/// `signed`) to be zero if the status bit is clear.
+///
+/// # Returns
+///
+/// `Err(())` if the field is inconsistent, `Ok(None)` if it is not available, or the value,
+/// which is two's complement when `signed`.
+fn field(mb: u64, status: u32, len: u32, signed: bool) -> Result<Option<i64>, ()> {
+    let width = len + signed as u32;
+    let raw = bits(mb, status + 1, width) as i64;
+    if bits(mb, status, 1) == 0 {
+        return if raw == 0 { Ok(None) } else { Err(()) };
+    }
+    if signed && raw >= 1 << len {
+        Ok(Some(raw - (1 << width)))
+    } else {
+        Ok(Some(raw))
+    }
+}
+
+/// Decodes BDS 4,0 (selected vertical intention).
+fn bds40(mb: u64) -> Option<EnhancedSurveillance> {
+    if bits(mb, 40, 8) != 0 || bits(mb, 52, 2) != 0 {
+        return None;
+    }
+    let mcp = field(mb, 1, 12, false).ok()?.map(|alt| alt as i32 * 16);
+    let fms = field(mb, 14, 12, false).ok()?.map(|alt| alt as i32 * 16);
+    let baro = field(mb, 27, 12, false).ok()?.map(|setting| setting as f64 * 0.1 + 800.0);
+    if (bits(mb, 48, 1) == 0 && bits(mb, 49, 3) != 0) || (bits(mb, 54, 1) == 0 && bits(mb, 55, 2) != 0) {
+        return None;
+    }
+
+    if mcp.is_none() && fms.is_none() && baro.is_none() {
+        return None;
+    }
+    if mcp.is_some_and(|alt| alt == 0 || alt > 50_000)
+        || fms.is_some_and(|alt| alt == 0 || alt > 50_000)
+        || baro.is_some_and(|setting| !(900.0..=1_100.0).contains(&setting))
+    {
+        return None;
+    }
+
+    Some(EnhancedSurveillance {
+        bds: "4,0".to_string(),
+        selected_altitude_mcp: mcp,
+        selected_altitude_fms: fms,
+        baro_setting: baro,
+        ..Default::default()
+    })
+}
+
+/// Decodes BDS 4,4 (meteorological routine air report).
+fn bds44(mb: u64) -> Option<EnhancedSurveillance> {
+    // Figure of merit/source: values above 4 are reserved.
+    if bits(mb, 1, 4) > 4 {
+        return None;
+    }
+    // Every routine report carries wind; one without is more likely another register.
+    let wind = field(mb, 5, 18, false).ok()??;
+    let wind_speed = (wind >> 9) as i32;
+    let wind_direction = (wind & 0x1FF) as f64 * 180.0 / 256.0;
+    let temperature = bits(mb, 24, 11) as i64;
+    let temperature = if temperature >= 1 << 10 { temperature - (1 << 11) } else { temperature } as f64 * 0.25;
+    let pressure = field(mb, 35, 11, false).ok()?.map(|pressure| pressure as i32);
+    let turbulence = field(mb, 47, 2, false).ok()?.map(|level| level as u8);
+    let humidity = field(mb, 50, 6, false).ok()?.map(|humidity| humidity as f64 * 100.0 / 64.0);
+
+    if wind_speed > 250
+        || !(-80.0..=60.0).contains(&temperature)
+        || pressure.is_some_and(|pressure| !(100..=1_100).contains(&pressure))
+    {
+        return None;
+    }
+
+    Some(EnhancedSurveillance {
+        bds: "4,4".to_string(),
+        wind_speed: Some(wind_speed),
+        wind_direction: Some(wind_direction),
+        static_temperature: Some(temperature),
+        static_pressure: pressure,
+        turbulence,
+        humidity,
+        ..Default::default()
+    })
+}
+
+/// Decodes BDS 5,0 (track and turn report).
+fn bds50(mb: u64) -> Option<EnhancedSurveillance> {
+    let roll = field(mb, 1, 9, true).ok()?.map(|roll| roll as f64 * 45.0 / 256.0);
+    let track = field(mb, 12, 10, true).ok()?.map(|track| (track as f64 * 90.0 / 512.0).rem_euclid(360.0));
+    let ground_speed = field(mb, 24, 10, false).ok()?.map(|speed| speed as i32 * 2);
+    let track_rate = field(mb, 35, 9, true).ok()?.map(|rate| rate as f64 * 8.0 / 256.0);
+    let true_airspeed = field(mb, 46, 10, false).ok()?.map(|speed| speed as i32 * 2);
+
+    if roll.is_none() && track.is_none() && ground_speed.is_none() && track_rate.is_none() && true_airspeed.is_none() {
+        return None;
+    }
+    if roll.is_some_and(|roll| roll.abs() > 50.0)
+        || ground_speed.is_some_and(|speed| speed > 600)
+        || true_airspeed.is_some_and(|speed| speed > 500)
+    {
+        return None;
+    }
+    if let (Some(ground_speed), Some(true_airspeed)) = (ground_speed, true_airspeed) {
+        if (ground_speed - true_airspeed).abs() > 200 {
+            return None;
+        }
+    }
+
+    Some(EnhancedSurveillance {
+        bds: "5,0".to_string(),
+        roll_angle: roll,
+        true_track: track,
+        ground_speed,
+        track_rate,
+        true_airspeed,
+        ..Default::default()
+    })
+}
+
+/// Decodes BDS 6,0 (heading and speed report).
+fn bds60(mb: u64) -> Option<EnhancedSurveillance> {
+    let heading = field(mb, 1, 10, true).ok()?.map(|heading| (heading as f64 * 90.0 / 512.0).rem_euclid(360.0));
+    let ias = field(mb, 13, 10, false).ok()?.map(|speed| speed as i32);
+    let mach = field(mb, 24, 10, false).ok()?.map(|mach| mach as f64 * 2.048 / 512.0);
+    let baro_rate = field(mb, 35, 9, true).ok()?.map(|rate| rate as i32 * 32);
+    let inertial_rate = field(mb, 46, 9, true).ok()?.map(|rate| rate as i32 * 32);
+
+    if heading.is_none() && ias.is_none() && mach.is_none() && baro_rate.is_none() && inertial_rate.is_none() {
+        return None;
+    }
+    if ias.is_some_and(|ias| ias == 0 || ias > 500)
+        || mach.is_some_and(|mach| mach == 0.0 || mach > 1.0)
+        || baro_rate.is_some_and(|rate| rate.abs() > 6_000)
+        || inertial_rate.is_some_and(|rate| rate.abs() > 6_000)
+    {
+        return None;
+    }
+    // The speed of sound puts indicated airspeed per Mach roughly between 250 kt (high
+    // altitude) and 700 kt (sea level).
+    if let (Some(ias), Some(mach)) = (ias, mach) {
+        if !(250.0..=700.0).contains(&(ias as f64 / mach)) {
+            return None;
+        }
+    }
+
+    Some(EnhancedSurveillance {
+        bds: "6,0".to_string(),
+        magnetic_heading: heading,
+        indicated_airspeed: ias,
+        mach,
+        baro_vertical_rate: baro_rate,
+        inertial_vertical_rate: inertial_rate,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::{avr, payload};
+
+    /// Returns the MB field of a Comm-B reply in AVR format.
+    fn mb(line: &str) -> u64 {
+        payload(&avr::parse_frame(line).unwrap().data)
+    }
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        assert!(actual.is_some_and(|actual| (actual - expected).abs() < 1e-3), "{:?} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn decodes_selected_vertical_intention() {
+        let register = decode(mb("*A000029C85E42F313000007047D3;"), false).unwrap();
+        assert_eq!(register.bds, "4,0");
+        assert_eq!(register.selected_altitude_mcp, Some(3_008));
+        assert_eq!(register.selected_altitude_fms, Some(3_008));
+        assert_near(register.baro_setting, 1_020.0);
+    }
+
+    #[test]
+    fn decodes_track_and_turn() {
+        let register = decode(mb("*A000139381951536E024D4CCF6B5;"), false).unwrap();
+        assert_eq!(register.bds, "5,0");
+        assert_near(register.roll_angle, 2.109);
+        assert_near(register.true_track, 114.258);
+        assert_eq!(register.ground_speed, Some(438));
+        assert_near(register.track_rate, 0.125);
+        assert_eq!(register.true_airspeed, Some(424));
+    }
+
+    #[test]
+    fn decodes_heading_and_speed() {
+        let register = decode(mb("*A00004128F39F91A7E27C46ADC21;"), false).unwrap();
+        assert_eq!(register.bds, "6,0");
+        assert_near(register.magnetic_heading, 42.715);
+        assert_eq!(register.indicated_airspeed, Some(252));
+        assert_near(register.mach, 0.42);
+        assert_eq!(register.baro_vertical_rate, Some(-1_920));
+        assert_eq!(register.inertial_vertical_rate, Some(-1_920));
+    }
+
+    #[test]
+    fn leaves_ambiguous_replies_undecoded() {
+        // Status bits set for the roll angle (0°) or heading (0°), the ground speed (200 kt)
+        // or Mach (0.4), and the true airspeed (200 kt) or inertial vertical rate (3,200
+        // ft/min), which share their positions in BDS 5,0 and 6,0.
+        let ambiguous = (1 << 55) | (1 << 32) | (100 << 22) | (1 << 10) | 100;
+        assert!(bds50(ambiguous).is_some() && bds60(ambiguous).is_some());
+        assert_eq!(decode(ambiguous, false), None);
+        // Nothing at all is no register either.
+        assert_eq!(decode(0, true), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! This module resolves Compact Position Reporting (CPR) coordinates into latitude and
+//! longitude.
+
+use std::f64::consts::PI;
+
+/// CPR coordinates are 17-bit fractions of a zone.
+const CPR_MAX: f64 = 131_072.0;
+
+/// Returns the number of longitude zones at the given latitude.
+pub fn nl(lat: f64) -> u32 {
+    let lat = lat.abs();
+    if lat == 0.0 {
+        return 59;
+    }
+    if lat == 87.0 {
+        return 2;
+    }
+    if lat > 87.0 {
+        return 1;
+    }
+
+    let a = 1.0 - (PI / 30.0).cos();
+    let b = (PI / 180.0 * lat).cos().powi(2);
+    (2.0 * PI / (1.0 - a / b).acos()).floor() as u32
+}
+
+/// Returns `x` modulo `y`, always non-negative for positive `y`.
+fn modulo(x: f64, y: f64) -> f64 {
+    x - y * (x / y).floor()
+}
+
+/// Resolves an airborne position from an even and an odd CPR frame.
+///
+/// # Arguments
+///
+/// * `even` - The (latitude, longitude) CPR values of the even frame.
+/// * `odd` - The (latitude, longitude) CPR values of the odd frame.
+/// * `odd_is_latest` - Whether the odd frame was received last.
+///
+/// # Returns
+///
+/// An `Option` with (latitude, longitude) in degrees, or `None` if the two frames straddle a
+/// latitude zone boundary.
+pub fn global_airborne(even: (u32, u32), odd: (u32, u32), odd_is_latest: bool) -> Option<(f64, f64)> {
+    let (lat_even, lon_even) = (even.0 as f64 / CPR_MAX, even.1 as f64 / CPR_MAX);
+    let (lat_odd, lon_odd) = (odd.0 as f64 / CPR_MAX, odd.1 as f64 / CPR_MAX);
+
+    let j = (59.0 * lat_even - 60.0 * lat_odd + 0.5).floor();
+    let mut rlat_even = 360.0 / 60.0 * (modulo(j, 60.0) + lat_even);
+    let mut rlat_odd = 360.0 / 59.0 * (modulo(j, 59.0) + lat_odd);
+    if rlat_even >= 270.0 {
+        rlat_even -= 360.0;
+    }
+    if rlat_odd >= 270.0 {
+        rlat_odd -= 360.0;
+    }
+    if !(-90.0..=90.0).contains(&rlat_even) || !(-90.0..=90.0).contains(&rlat_odd) || nl(rlat_even) != nl(rlat_odd) {
+        return None;
+    }
+
+    let (lat, lon_cpr, i) = if odd_is_latest { (rlat_odd, lon_odd, 1) } else { (rlat_even, lon_even, 0) };
+    let zones = nl(lat) as f64;
+    let ni = (zones - i as f64).max(1.0);
+    let m = (lon_even * (zones - 1.0) - lon_odd * zones + 0.5).floor();
+    let mut lon = 360.0 / ni * (modulo(m, ni) + lon_cpr);
+    if lon >= 180.0 {
+        lon -= 360.0;
+    }
+    Some((lat, lon))
+}
+
+/// Resolves an airborne position from a single CPR frame using a nearby reference position.
+///
+/// The result is only correct if the reference is within about 180 NM of the aircraft.
+///
+/// # Arguments
+///
+/// * `reference` - A known (latitude, longitude) near the aircraft.
+/// * `cpr` - The (latitude, longitude) CPR values of the frame.
+/// * `odd` - Whether the frame is an odd frame.
+///
+/// # Returns
+///
+/// The (latitude, longitude) in degrees.
+pub fn local_airborne(reference: (f64, f64), cpr: (u32, u32), odd: bool) -> (f64, f64) {
+    local(reference, cpr, odd, 360.0)
+}
+
+/// Resolves a surface position from a single CPR frame using a nearby reference position.
+///
+/// The result is only correct if the reference is within about 45 NM of the aircraft.
+///
+/// # Arguments
+///
+/// * `reference` - A known (latitude, longitude) near the aircraft, such as the receiver.
+/// * `cpr` - The (latitude, longitude) CPR values of the frame.
+/// * `odd` - Whether the frame is an odd frame.
+///
+/// # Returns
+///
+/// The (latitude, longitude) in degrees.
+pub fn local_surface(reference: (f64, f64), cpr: (u32, u32), odd: bool) -> (f64, f64) {
+    local(reference, cpr, odd, 90.0)
+}
+
+/// Resolves a surface position from an even and an odd CPR frame.
+///
+/// Surface CPR zones are four times smaller than airborne ones, so the pair only fixes the
+/// position within a quadrant; the candidate closest to `reference` is chosen.
+///
+/// # Arguments
+///
+/// * `even` - The (latitude, longitude) CPR values of the even frame.
+/// * `odd` - The (latitude, longitude) CPR values of the odd frame.
+/// * `odd_is_latest` - Whether the odd frame was received last.
+/// * `reference` - A (latitude, longitude) in the same quadrant, such as the receiver.
+///
+/// # Returns
+///
+/// An `Option` with (latitude, longitude) in degrees, or `None` if the two frames straddle a
+/// latitude zone boundary.
+pub fn global_surface(even: (u32, u32), odd: (u32, u32), odd_is_latest: bool, reference: (f64, f64)) -> Option<(f64, f64)> {
+    let (lat_even, lon_even) = (even.0 as f64 / CPR_MAX, even.1 as f64 / CPR_MAX);
+    let (lat_odd, lon_odd) = (odd.0 as f64 / CPR_MAX, odd.1 as f64 / CPR_MAX);
+
+    let j = (59.0 * lat_even - 60.0 * lat_odd + 0.5).floor();
+    let mut rlat_even = 90.0 / 60.0 * (modulo(j, 60.0) + lat_even);
+    let mut rlat_odd = 90.0 / 59.0 * (modulo(j, 59.0) + lat_odd);
+    // Both latitudes are northern-hemisphere solutions; the southern ones are 90 degrees less.
+    let latest = if odd_is_latest { rlat_odd } else { rlat_even };
+    if (latest - 90.0 - reference.0).abs() < (latest - reference.0).abs() {
+        rlat_even -= 90.0;
+        rlat_odd -= 90.0;
+    }
+    if nl(rlat_even) != nl(rlat_odd) {
+        return None;
+    }
+
+    let (lat, lon_cpr, i) = if odd_is_latest { (rlat_odd, lon_odd, 1) } else { (rlat_even, lon_even, 0) };
+    let zones = nl(lat) as f64;
+    let ni = (zones - i as f64).max(1.0);
+    let m = (lon_even * (zones - 1.0) - lon_odd * zones + 0.5).floor();
+    let lon = 90.0 / ni * (modulo(m, ni) + lon_cpr);
+
+    // The longitude is only known modulo 90 degrees.
+    let lon = (0..4)
+        .map(|quadrant| modulo(lon + 90.0 * quadrant as f64 + 180.0, 360.0) - 180.0)
+        .min_by(|a, b| angular_distance(*a, reference.1).total_cmp(&angular_distance(*b, reference.1)))
+        .unwrap();
+    Some((lat, lon))
+}
+
+/// Returns the absolute difference between two longitudes, accounting for wrap-around.
+fn angular_distance(a: f64, b: f64) -> f64 {
+    (modulo(a - b + 180.0, 360.0) - 180.0).abs()
+}
+
+/// Resolves a single CPR frame against a reference, for zones spanning `span` degrees.
+fn local(reference: (f64, f64), cpr: (u32, u32), odd: bool, span: f64) -> (f64, f64) {
+    let i = if odd { 1.0 } else { 0.0 };
+    let (lat_cpr, lon_cpr) = (cpr.0 as f64 / CPR_MAX, cpr.1 as f64 / CPR_MAX);
+
+    let dlat = span / (60.0 - i);
+    let j = (reference.0 / dlat).floor() + (0.5 + modulo(reference.0, dlat) / dlat - lat_cpr).floor();
+    let lat = dlat * (j + lat_cpr);
+
+    let dlon = span / (nl(lat) as f64 - i).max(1.0);
+    let m = (reference.1 / dlon).floor() + (0.5 + modulo(reference.1, dlon) / dlon - lon_cpr).floor();
+    let lon = dlon * (m + lon_cpr);
+    (lat, lon)
+}
@@ -0,0 +1,34 @@
+//! This module computes the Mode S parity (CRC-24) used to validate frames.
+
+/// The Mode S generator polynomial, including the x^24 term.
+const GENERATOR: u32 = 0x1FF_F409;
+
+/// Computes the parity of a frame's payload and XORs it with the frame's parity field.
+///
+/// For DF17/18 frames a valid frame yields zero and for DF11 at most the interrogator code
+/// (low 7 bits). For address/parity frames (DF0/4/5/16/20/21) the result is the aircraft
+/// address.
+///
+/// # Arguments
+///
+/// * `data` - A complete 7- or 14-byte frame.
+///
+/// # Returns
+///
+/// The 24-bit residual.
+pub fn residual(data: &[u8]) -> u32 {
+    let payload_len = data.len() - 3;
+    let mut crc: u32 = 0;
+    for &byte in &data[..payload_len] {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= GENERATOR;
+            }
+        }
+    }
+
+    let parity = ((data[payload_len] as u32) << 16) | ((data[payload_len + 1] as u32) << 8) | data[payload_len + 2] as u32;
+    crc ^ parity
+}
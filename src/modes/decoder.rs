@@ -0,0 +1,669 @@
+//! This module turns Mode S frames into `SBS1Message`s, keeping the per-aircraft state
+//! needed to validate addresses and resolve positions.
+
+use super::{bits, commb, cpr, crc, payload, target, Frame};
+use crate::parse::{Icao24, SBS1Message};
+use chrono::Local;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long an address stays trusted for address/parity frames after a DF11/17 frame.
+const ADDRESS_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum time between an even and an odd frame for a global position decode.
+const CPR_PAIR_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// Maximum age of a barometric altitude or GNSS/baro difference combined with a later message.
+const ALTITUDE_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// Maximum age of a previous position used as the reference for a local position decode.
+const LOCAL_REFERENCE_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Aircraft not heard from for this long are forgotten.
+const AIRCRAFT_TTL: Duration = Duration::from_secs(300);
+
+/// Number of frames between sweeps for aircraft to forget.
+const PRUNE_INTERVAL: u32 = 1024;
+
+/// The timestamp mlat-client puts on Beast frames carrying its own position results
+/// (`0xFF` `0x00` then "MLAT").
+const MLAT_TIMESTAMP: u64 = 0xFF00_4D4C_4154;
+
+/// Characters of the 6-bit identification alphabet; `#` marks unused codes.
+const CALLSIGN_CHARS: &[u8; 64] = b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+/// An even or odd CPR position frame.
+#[derive(Debug, Clone, Copy)]
+struct CprFrame {
+    lat: u32,
+    lon: u32,
+    /// Whether the frame came from a surface rather than an airborne position message.
+    surface: bool,
+    received: Instant,
+}
+
+/// What the decoder remembers about one aircraft.
+#[derive(Debug)]
+struct AircraftState {
+    /// When any frame from the aircraft was last decoded.
+    seen: Instant,
+    /// When a frame with verifiable parity from the aircraft's own transponder (DF11/17)
+    /// was last received.
+    verified: Option<Instant>,
+    even: Option<CprFrame>,
+    odd: Option<CprFrame>,
+    /// The last resolved position and when it was resolved.
+    position: Option<(f64, f64, Instant)>,
+    /// The last barometric altitude from an airborne position and when it was received.
+    altitude_baro: Option<(i32, Instant)>,
+    /// The last difference between GNSS and barometric altitude and when it was received.
+    geom_delta: Option<(i32, Instant)>,
+    /// ADS-B version from the last operational status message.
+    version: Option<u8>,
+    /// NIC supplement-A from the last operational status message.
+    nic_a: bool,
+    /// Navigation accuracy category for position from the last operational status message.
+    nac_p: Option<u8>,
+    /// Source integrity level from the last operational status message.
+    sil: Option<u8>,
+}
+
+/// Decodes Mode S frames, remembering recently seen aircraft between calls.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    aircraft: HashMap<Icao24, AircraftState>,
+    frames_since_prune: u32,
+    /// The receiver's (latitude, longitude), used as the reference for surface positions.
+    receiver: Option<(f64, f64)>,
+    /// Whether Comm-B replies are also checked for meteorological reports (BDS 4,4).
+    meteorological: bool,
+}
+
+impl Decoder {
+    /// Creates a decoder with no known aircraft.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a decoder that knows the receiver's position.
+    ///
+    /// Surface positions can only be resolved relative to a nearby reference, so without
+    /// the receiver position they are only resolved for aircraft with a recent airborne
+    /// position.
+    pub fn with_receiver(lat: f64, lon: f64) -> Self {
+        Decoder { receiver: Some((lat, lon)), ..Self::default() }
+    }
+
+    /// Enables or disables decoding of meteorological routine air reports (BDS 4,4) from
+    /// Comm-B replies. It is off by default.
+    pub fn set_meteorological(&mut self, enabled: bool) {
+        self.meteorological = enabled;
+    }
+
+    /// Decodes one frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to decode.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the decoded message, or `None` if the frame failed its parity check,
+    /// came from an unknown address, or carries nothing this decoder emits.
+    pub fn decode(&mut self, frame: &Frame) -> Option<SBS1Message> {
+        let now = Instant::now();
+        self.prune(now);
+
+        let data = &frame.data;
+        let df = data.first().map(|&byte| (byte >> 3).min(24))?;
+        let expected_len = if df >= 16 { 14 } else { 7 };
+        if data.len() != expected_len {
+            return None;
+        }
+
+        let residual = crc::residual(data);
+        let icao24 = match df {
+            11 | 17 => {
+                let valid = if df == 11 { residual & !0x7F == 0 } else { residual == 0 };
+                if !valid {
+                    return None;
+                }
+                let icao24 = address_announced(data);
+                self.verify(icao24, now);
+                icao24
+            }
+            18 => {
+                // Only control fields using the standard extended squitter layout are decoded;
+                // coarse TIS-B (3), management messages (4), and reserved values are not.
+                if residual != 0 || !matches!(data[0] & 0x07, 0 | 1 | 2 | 5 | 6) {
+                    return None;
+                }
+                // Rebroadcast and non-transponder traffic does not vouch for the address. An
+                // address that is not an ICAO one is marked, as readsb does with `~`, so that
+                // it is kept apart from the aircraft whose ICAO address has the same digits.
+                let announced = address_announced(data);
+                let icao24 = if extended_squitter_source(data).ends_with("_other") {
+                    Icao24::non_icao(announced.as_u32())?
+                } else {
+                    announced
+                };
+                self.see(icao24, now);
+                icao24
+            }
+            0 | 4 | 5 | 16 | 20 | 21 => {
+                let icao24 = Icao24::new(residual)?;
+                if !self.is_known(icao24, now) {
+                    return None;
+                }
+                self.see(icao24, now);
+                icao24
+            }
+            _ => return None,
+        };
+
+        let mut msg = SBS1Message::new();
+        msg.message_type = Some("MSG".to_string());
+        msg.icao24 = Some(icao24);
+        msg.mlat = frame.mlat_timestamp == Some(MLAT_TIMESTAMP);
+        // Beast signal levels are an amplitude scaled to 0-255; readsb reports them in dBFS.
+        msg.signal = frame.signal.filter(|&level| level > 0).map(|level| 20.0 * (level as f64 / 255.0).log10());
+        let received = Local::now().naive_local();
+        msg.generated_date = Some(received);
+        msg.logged_date = Some(received);
+
+        match df {
+            11 => {
+                // All-call replies only say the aircraft is in range.
+                msg.transmission_type = Some(8);
+            }
+            0 | 16 => {
+                msg.transmission_type = Some(7);
+                msg.altitude_baro = decode_ac13(data);
+                self.combine_altitudes(&mut msg, icao24, now);
+            }
+            4 | 20 => {
+                msg.transmission_type = Some(5);
+                msg.altitude_baro = decode_ac13(data);
+                self.combine_altitudes(&mut msg, icao24, now);
+                set_flight_status(&mut msg, data[0] & 0x07);
+            }
+            5 | 21 => {
+                msg.transmission_type = Some(6);
+                msg.squawk = Some(decode_id13(data));
+                msg.emergency = msg.squawk.map(|squawk| matches!(squawk, 7500 | 7600 | 7700));
+                set_flight_status(&mut msg, data[0] & 0x07);
+            }
+            17 | 18 => {
+                self.decode_extended_squitter(&mut msg, icao24, data, now)?;
+                msg.source_type = Some(extended_squitter_source(data).to_string());
+            }
+            _ => return None,
+        }
+        if msg.source_type.is_none() {
+            msg.source_type = Some("mode_s".to_string());
+        }
+
+        if df == 20 || df == 21 {
+            msg.ehs = commb::decode(payload(data), self.meteorological);
+        }
+
+        Some(msg)
+    }
+
+    /// Fills `msg` from the ME field of a DF17 extended squitter.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the type code is not one this decoder emits.
+    fn decode_extended_squitter(&mut self, msg: &mut SBS1Message, icao24: Icao24, data: &[u8], now: Instant) -> Option<()> {
+        let type_code = data[4] >> 3;
+        match type_code {
+            1..=4 => {
+                msg.transmission_type = Some(1);
+                msg.callsign = Some(decode_callsign(&data[5..11]));
+                if let Some((code, description)) = emitter_category(type_code, data[4] & 0x07) {
+                    msg.category_code = Some(code);
+                    msg.category = Some(description.to_string());
+                }
+            }
+            5..=8 => {
+                msg.transmission_type = Some(2);
+                msg.on_ground = Some(true);
+                let me = payload(data);
+                msg.surface_speed = surface_speed(bits(me, 6, 7));
+                if bits(me, 13, 1) == 1 {
+                    msg.track = Some((bits(me, 14, 7) as f64 * 360.0 / 128.0) as f32);
+                }
+                self.set_position(msg, icao24, data, true, now);
+                self.set_accuracy(msg, icao24, type_code, false);
+            }
+            9..=18 | 20..=22 => {
+                msg.transmission_type = Some(3);
+                msg.on_ground = Some(false);
+                if type_code <= 18 {
+                    msg.altitude_baro = decode_ac12(data);
+                } else {
+                    msg.altitude_geom = decode_ac12(data);
+                }
+                self.combine_altitudes(msg, icao24, now);
+                self.set_position(msg, icao24, data, false, now);
+                // TIS-B and ADS-R reuse this bit as the IMF flag rather than NIC supplement-B.
+                let nic_b = !is_rebroadcast(data) && bits(payload(data), 8, 1) == 1;
+                self.set_accuracy(msg, icao24, type_code, nic_b);
+            }
+            19 => {
+                msg.transmission_type = Some(4);
+                let me = payload(data);
+                decode_velocity(msg, me)?;
+                // The GNSS/baro difference is 0 when unavailable and otherwise value + 1, in
+                // 25 ft steps, negative when GNSS altitude is below barometric altitude.
+                let delta = bits(me, 50, 7) as i32;
+                if delta != 0 {
+                    let sign = if bits(me, 49, 1) == 1 { -1 } else { 1 };
+                    if let Some(state) = self.aircraft.get_mut(&icao24) {
+                        state.geom_delta = Some(((delta - 1) * 25 * sign, now));
+                    }
+                    self.combine_altitudes(msg, icao24, now);
+                }
+            }
+            29 => {
+                // Target state has no SBS-1 transmission type, so only the extra field is set.
+                msg.target_state = Some(target::decode(payload(data))?);
+            }
+            31 => {
+                // Operational status is not forwarded itself; it qualifies later positions.
+                self.record_operational_status(icao24, payload(data));
+                return None;
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Sets the position of `msg` from the CPR coordinates of an airborne or surface position
+    /// message, if it can be resolved.
+    fn set_position(&mut self, msg: &mut SBS1Message, icao24: Icao24, data: &[u8], surface: bool, received: Instant) {
+        let odd = data[6] & 0x04 != 0;
+        let lat = ((data[6] as u32 & 0x03) << 15) | ((data[7] as u32) << 7) | (data[8] as u32 >> 1);
+        let lon = ((data[8] as u32 & 0x01) << 16) | ((data[9] as u32) << 8) | data[10] as u32;
+        if let Some((lat, lon)) = self.resolve_position(icao24, CprFrame { lat, lon, surface, received }, odd) {
+            msg.lat = Some(lat);
+            msg.lon = Some(lon);
+        }
+    }
+
+    /// Records a CPR frame for `icao24` and resolves a position from it if possible.
+    ///
+    /// A global decode is used when an even and odd frame of the same kind arrived close
+    /// together; otherwise the aircraft's own recent position serves as the reference for a
+    /// local decode. Surface positions additionally need a reference to pick the quadrant,
+    /// falling back to the receiver position.
+    fn resolve_position(&mut self, icao24: Icao24, frame: CprFrame, odd: bool) -> Option<(f64, f64)> {
+        let receiver = self.receiver;
+        let state = self.aircraft.get_mut(&icao24)?;
+        if odd {
+            state.odd = Some(frame);
+        } else {
+            state.even = Some(frame);
+        }
+
+        let pair = match (state.even, state.odd) {
+            (Some(even), Some(odd_frame))
+                if even.surface == frame.surface
+                    && odd_frame.surface == frame.surface
+                    && even.received.max(odd_frame.received) - even.received.min(odd_frame.received) <= CPR_PAIR_MAX_AGE =>
+            {
+                Some(((even.lat, even.lon), (odd_frame.lat, odd_frame.lon)))
+            }
+            _ => None,
+        };
+        let reference = state
+            .position
+            .filter(|&(_, _, resolved)| frame.received - resolved <= LOCAL_REFERENCE_MAX_AGE)
+            .map(|(lat, lon, _)| (lat, lon));
+
+        let position = if frame.surface {
+            match (pair, reference.or(receiver)) {
+                (Some((even, odd_cpr)), Some(reference)) => cpr::global_surface(even, odd_cpr, odd, reference),
+                (None, Some(reference)) => Some(cpr::local_surface(reference, (frame.lat, frame.lon), odd)),
+                _ => None,
+            }
+        } else {
+            match pair {
+                Some((even, odd_cpr)) => cpr::global_airborne(even, odd_cpr, odd),
+                None => reference.map(|reference| cpr::local_airborne(reference, (frame.lat, frame.lon), odd)),
+            }
+        }?;
+
+        state.position = Some((position.0, position.1, frame.received));
+        Some(position)
+    }
+
+    /// Remembers a barometric altitude from `msg` and fills in `altitude_geom` from recent
+    /// barometric altitudes and GNSS/baro differences where `msg` lacks it.
+    fn combine_altitudes(&mut self, msg: &mut SBS1Message, icao24: Icao24, now: Instant) {
+        let Some(state) = self.aircraft.get_mut(&icao24) else { return };
+        if let Some(altitude) = msg.altitude_baro {
+            state.altitude_baro = Some((altitude, now));
+        }
+
+        let recent = |value: Option<(i32, Instant)>| value.filter(|&(_, at)| now - at <= ALTITUDE_MAX_AGE).map(|(value, _)| value);
+        if msg.altitude_geom.is_none() {
+            if let (Some(altitude), Some(delta)) = (recent(state.altitude_baro), recent(state.geom_delta)) {
+                msg.altitude_geom = Some(altitude + delta);
+            }
+        }
+    }
+
+    /// Remembers the version and accuracy fields of an operational status message.
+    fn record_operational_status(&mut self, icao24: Icao24, me: u64) {
+        let Some(state) = self.aircraft.get_mut(&icao24) else { return };
+        // Subtypes 0 (airborne) and 1 (surface) share the fields used here; version 0
+        // transponders use a different layout without them.
+        if bits(me, 6, 3) > 1 {
+            return;
+        }
+        let version = bits(me, 41, 3) as u8;
+        state.version = Some(version);
+        if version >= 1 {
+            state.nic_a = bits(me, 44, 1) == 1;
+            state.nac_p = Some(bits(me, 45, 4) as u8);
+            state.sil = Some(bits(me, 51, 2) as u8);
+        }
+    }
+
+    /// Sets the integrity and accuracy categories of a position message, once the
+    /// aircraft's ADS-B version is known.
+    fn set_accuracy(&self, msg: &mut SBS1Message, icao24: Icao24, type_code: u8, nic_b: bool) {
+        let Some(state) = self.aircraft.get(&icao24) else { return };
+        let Some(version) = state.version.filter(|&version| version >= 1) else { return };
+        msg.nic = navigation_integrity(type_code, version, state.nic_a, nic_b);
+        msg.nac_p = state.nac_p;
+        msg.sil = state.sil;
+    }
+
+    /// Marks `icao24` as heard on a frame with verifiable parity from its own transponder.
+    fn verify(&mut self, icao24: Icao24, now: Instant) {
+        self.see(icao24, now).verified = Some(now);
+    }
+
+    /// Marks `icao24` as heard, without vouching for its address, and returns its state.
+    fn see(&mut self, icao24: Icao24, now: Instant) -> &mut AircraftState {
+        let state = self.aircraft.entry(icao24).or_insert(AircraftState {
+            seen: now,
+            verified: None,
+            even: None,
+            odd: None,
+            position: None,
+            altitude_baro: None,
+            geom_delta: None,
+            version: None,
+            nic_a: false,
+            nac_p: None,
+            sil: None,
+        });
+        state.seen = now;
+        state
+    }
+
+    /// Returns whether `icao24` was recently heard on a frame with verifiable parity.
+    fn is_known(&self, icao24: Icao24, now: Instant) -> bool {
+        self.aircraft
+            .get(&icao24)
+            .and_then(|state| state.verified)
+            .is_some_and(|verified| now - verified <= ADDRESS_TTL)
+    }
+
+    /// Periodically forgets aircraft that have not been heard from recently.
+    fn prune(&mut self, now: Instant) {
+        self.frames_since_prune += 1;
+        if self.frames_since_prune >= PRUNE_INTERVAL {
+            self.frames_since_prune = 0;
+            self.aircraft.retain(|_, state| now - state.seen <= AIRCRAFT_TTL);
+        }
+    }
+}
+
+/// Returns whether an extended squitter is TIS-B or ADS-R traffic relayed by a ground station.
+fn is_rebroadcast(data: &[u8]) -> bool {
+    data[0] >> 3 == 18 && data[0] & 0x07 >= 2
+}
+
+/// Classifies where an extended squitter came from, using readsb's naming.
+///
+/// DF17 is ADS-B from a transponder. DF18 is split by control field into non-transponder
+/// ADS-B, TIS-B (ground radar tracks), and ADS-R (rebroadcast UAT traffic), each with an ICAO
+/// or other (anonymous/non-ICAO) address as given by the IMF bit.
+fn extended_squitter_source(data: &[u8]) -> &'static str {
+    if data[0] >> 3 == 17 {
+        return "adsb_icao";
+    }
+    let me = payload(data);
+    let imf = match data[4] >> 3 {
+        5..=18 | 20..=22 => bits(me, 8, 1) == 1,
+        19 => bits(me, 9, 1) == 1,
+        _ => false,
+    };
+    match (data[0] & 0x07, imf) {
+        (0, _) => "adsb_icao_nt",
+        (1, _) => "adsb_other",
+        (2, false) => "tisb_icao",
+        (6, false) => "adsr_icao",
+        (6, true) => "adsr_other",
+        _ => "tisb_other",
+    }
+}
+
+/// Reads the address announced in the AA field of a DF11/17/18 frame.
+fn address_announced(data: &[u8]) -> Icao24 {
+    let address = ((data[1] as u32) << 16) | ((data[2] as u32) << 8) | data[3] as u32;
+    Icao24::new(address).unwrap()
+}
+
+/// Sets the alert, SPI, and on-ground flags from a DF4/5/20/21 flight status field.
+fn set_flight_status(msg: &mut SBS1Message, flight_status: u8) {
+    msg.alert = Some(matches!(flight_status, 2..=4));
+    msg.spi = Some(matches!(flight_status, 4 | 5));
+    msg.on_ground = match flight_status {
+        0 | 2 => Some(false),
+        1 | 3 => Some(true),
+        _ => None,
+    };
+}
+
+/// Decodes the 13-bit altitude code of a DF0/4/16/20 frame, in feet.
+fn decode_ac13(data: &[u8]) -> Option<i32> {
+    let ac13 = ((data[2] as u32 & 0x1F) << 8) | data[3] as u32;
+    // Altitudes in metres (M bit) are not decoded.
+    if ac13 == 0 || ac13 & 0x40 != 0 {
+        return None;
+    }
+    decode_altitude(ac13)
+}
+
+/// Decodes the 12-bit altitude code of a DF17 airborne position, in feet.
+///
+/// Type codes 9-18 carry barometric altitude and 20-22 GNSS height, with the same encoding.
+fn decode_ac12(data: &[u8]) -> Option<i32> {
+    let ac12 = ((data[5] as u32) << 4) | (data[6] as u32 >> 4);
+    if ac12 == 0 {
+        return None;
+    }
+    // The 12-bit code is the 13-bit one without the M bit.
+    decode_altitude(((ac12 & 0x0FC0) << 1) | (ac12 & 0x3F))
+}
+
+/// Decodes a 13-bit altitude code (with the M bit clear), in feet.
+///
+/// With the Q bit set the altitude is a binary count of 25 ft steps from -1,000 ft; without
+/// it the transponder only has a Mode C (Gillham) encoder and the altitude is in 100 ft
+/// steps from -1,200 ft. Both can be negative at airports below sea level.
+fn decode_altitude(code: u32) -> Option<i32> {
+    if code & 0x10 != 0 {
+        let n = ((code & 0x1F80) >> 2) | ((code & 0x20) >> 1) | (code & 0x0F);
+        return Some(n as i32 * 25 - 1000);
+    }
+
+    // Gillham codes use the identity bit layout: C1 A1 C2 A2 C4 A4 M B1 Q B2 D2 B4 D4. The
+    // 500 ft digits (D2 D4 A1 A2 A4 B1 B2 B4) and 100 ft digits (C1 C2 C4) are Gray codes.
+    let bit = |n: u32| (code >> n) & 1;
+    let gray500 = [2, 0, 11, 9, 7, 5, 3, 1].iter().fold(0, |acc, &n| (acc << 1) | bit(n));
+    let gray100 = [12, 10, 8].iter().fold(0, |acc, &n| (acc << 1) | bit(n));
+    let n500 = gray_to_binary(gray500) as i32;
+    let mut n100 = match gray_to_binary(gray100) {
+        0 | 5 | 6 => return None,
+        7 => 5,
+        n => n as i32,
+    };
+    // The 100 ft digits count down in odd 500 ft bands.
+    if n500 % 2 == 1 {
+        n100 = 6 - n100;
+    }
+    Some(n500 * 500 + n100 * 100 - 1300)
+}
+
+/// Converts a reflected binary (Gray) code to binary.
+fn gray_to_binary(gray: u32) -> u32 {
+    let mut value = gray;
+    let mut shifted = gray >> 1;
+    while shifted != 0 {
+        value ^= shifted;
+        shifted >>= 1;
+    }
+    value
+}
+
+/// Decodes the 13-bit identity (squawk) code of a DF5/21 frame as its four octal digits.
+fn decode_id13(data: &[u8]) -> i32 {
+    let id13 = ((data[2] as u32 & 0x1F) << 8) | data[3] as u32;
+    let bit = |n: u32| (id13 >> n) & 1;
+    let a = bit(11) | bit(9) << 1 | bit(7) << 2;
+    let b = bit(5) | bit(3) << 1 | bit(1) << 2;
+    let c = bit(12) | bit(10) << 1 | bit(8) << 2;
+    let d = bit(4) | bit(2) << 1 | bit(0) << 2;
+    (a * 1000 + b * 100 + c * 10 + d) as i32
+}
+
+/// Fills ground speed and track (subtypes 1-2) or magnetic heading and airspeed
+/// (subtypes 3-4), plus vertical rate, from an airborne velocity message.
+///
+/// # Returns
+///
+/// `None` for reserved subtypes.
+fn decode_velocity(msg: &mut SBS1Message, me: u64) -> Option<()> {
+    let subtype = bits(me, 6, 3);
+    // Supersonic subtypes report speeds in units of 4 kt.
+    let scale = if subtype == 2 || subtype == 4 { 4 } else { 1 };
+    // Speed and rate fields encode "not available" as 0 and otherwise value + 1.
+    let available = |raw: u64| if raw == 0 { None } else { Some((raw - 1) as i32) };
+
+    match subtype {
+        1 | 2 => {
+            let east_west = available(bits(me, 15, 10));
+            let north_south = available(bits(me, 26, 10));
+            if let (Some(east_west), Some(north_south)) = (east_west, north_south) {
+                let east = (east_west * scale) as f64 * if bits(me, 14, 1) == 1 { -1.0 } else { 1.0 };
+                let north = (north_south * scale) as f64 * if bits(me, 25, 1) == 1 { -1.0 } else { 1.0 };
+                msg.ground_speed = Some(east.hypot(north) as f32);
+                msg.track = Some(east.atan2(north).to_degrees().rem_euclid(360.0) as f32);
+            }
+        }
+        3 | 4 => {
+            if bits(me, 14, 1) == 1 {
+                msg.magnetic_heading = Some(bits(me, 15, 10) as f64 * 360.0 / 1024.0);
+            }
+            let airspeed = available(bits(me, 26, 10)).map(|speed| speed * scale);
+            if bits(me, 25, 1) == 1 {
+                msg.true_airspeed = airspeed;
+            } else {
+                msg.indicated_airspeed = airspeed;
+            }
+        }
+        _ => return None,
+    }
+
+    msg.vertical_rate = available(bits(me, 38, 9)).map(|rate| rate * 64 * if bits(me, 37, 1) == 1 { -1 } else { 1 });
+    Some(())
+}
+
+/// Decodes the movement field of a surface position message, in knots.
+///
+/// The encoding is non-linear, with finer steps at taxiing speeds.
+fn surface_speed(movement: u64) -> Option<f32> {
+    let (base_movement, base_speed, step) = match movement {
+        1 => return Some(0.0),
+        2..=8 => (2, 0.125, 0.125),
+        9..=12 => (9, 1.0, 0.25),
+        13..=38 => (13, 2.0, 0.5),
+        39..=93 => (39, 15.0, 1.0),
+        94..=108 => (94, 70.0, 2.0),
+        109..=123 => (109, 100.0, 5.0),
+        124 => return Some(175.0),
+        _ => return None,
+    };
+    Some(base_speed + (movement - base_movement) as f32 * step)
+}
+
+/// Derives the navigation integrity category from a position message's type code and the
+/// NIC supplement bits.
+fn navigation_integrity(type_code: u8, version: u8, nic_a: bool, nic_b: bool) -> Option<u8> {
+    // Version 1 only has supplement-A; version 2 needs both supplements for the higher value.
+    let supplemented = if version >= 2 { nic_a && nic_b } else { nic_a };
+    let nic = match type_code {
+        5 | 9 | 20 => 11,
+        6 | 10 | 21 => 10,
+        7 => if nic_a { 9 } else { 8 },
+        8 => 0,
+        11 => if supplemented { 9 } else { 8 },
+        12 => 7,
+        13 => 6,
+        14 => 5,
+        15 => 4,
+        16 => if supplemented { 3 } else { 2 },
+        17 => 1,
+        18 | 22 => 0,
+        _ => return None,
+    };
+    Some(nic)
+}
+
+/// Maps the type code (1-4) and emitter category field of an identification message to a
+/// category code such as `A3` and its description.
+///
+/// # Returns
+///
+/// `None` when the aircraft reports no category information or a reserved value.
+fn emitter_category(type_code: u8, category: u8) -> Option<(String, &'static str)> {
+    let (set, description) = match (type_code, category) {
+        (4, 1) => ('A', "Light"),
+        (4, 2) => ('A', "Small"),
+        (4, 3) => ('A', "Large"),
+        (4, 4) => ('A', "High vortex large"),
+        (4, 5) => ('A', "Heavy"),
+        (4, 6) => ('A', "High performance"),
+        (4, 7) => ('A', "Rotorcraft"),
+        (3, 1) => ('B', "Glider"),
+        (3, 2) => ('B', "Lighter-than-air"),
+        (3, 3) => ('B', "Parachutist"),
+        (3, 4) => ('B', "Ultralight"),
+        (3, 6) => ('B', "UAV"),
+        (3, 7) => ('B', "Space vehicle"),
+        (2, 1) => ('C', "Surface emergency vehicle"),
+        (2, 2) => ('C', "Surface service vehicle"),
+        (2, 3) => ('C', "Point obstacle"),
+        (2, 4) => ('C', "Cluster obstacle"),
+        (2, 5) => ('C', "Line obstacle"),
+        _ => return None,
+    };
+    Some((format!("{}{}", set, category), description))
+}
+
+/// Decodes the eight 6-bit characters of an identification message.
+fn decode_callsign(chars: &[u8]) -> String {
+    let bits = chars.iter().fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+    (0..8)
+        .map(|i| CALLSIGN_CHARS[((bits >> (42 - 6 * i)) & 0x3F) as usize] as char)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,36 @@
+//! This module decodes Mode S frames received in the raw (AVR) or Beast formats.
+//!
+//! Decoded frames are turned into `SBS1Message`s, using the same transmission types
+//! dump1090 uses for its SBS-1 output, so the rest of the pipeline treats both inputs alike.
+
+pub mod avr;
+pub mod beast;
+pub mod commb;
+pub mod cpr;
+pub mod crc;
+pub mod target;
+mod decoder;
+
+pub use decoder::Decoder;
+
+/// A Mode S or Mode A/C frame as received from the feed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The frame bytes: 2 for Mode A/C, 7 for short and 14 for long Mode S frames.
+    pub data: Vec<u8>,
+    /// The receiver's 12 MHz timestamp counter, if the input format carries one.
+    pub mlat_timestamp: Option<u64>,
+    /// The raw signal level byte, if the input format carries one.
+    pub signal: Option<u8>,
+}
+
+/// Extracts `len` bits of a 56-bit ME or MB field starting at 1-based bit `start`, counting
+/// from the left as the specifications do.
+pub(crate) fn bits(field: u64, start: u32, len: u32) -> u64 {
+    (field >> (57 - start - len)) & ((1 << len) - 1)
+}
+
+/// Reads the 56-bit ME/MB field of a long frame (bits 33-88).
+pub(crate) fn payload(data: &[u8]) -> u64 {
+    data[4..11].iter().fold(0u64, |acc, &byte| (acc << 8) | byte as u64)
+}
@@ -0,0 +1,89 @@
+//! This module decodes target state and status messages (DF17/18 type code 29), which
+//! report what the crew has selected on the autopilot rather than what the aircraft is doing.
+//!
+//! Only the ADS-B version 2 layout (subtype 1) is decoded; the version 1 layout is rare
+//! and inconsistently implemented by transponders.
+
+use super::bits;
+use serde_derive::{Deserialize, Serialize};
+
+/// Autopilot targets and modes from a target state and status message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TargetState {
+    /// Selected altitude in feet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_altitude: Option<i32>,
+    /// Where the selected altitude comes from: `"mcp"` (MCP/FCU) or `"fms"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_altitude_source: Option<String>,
+    /// Barometric pressure setting (QNH) in hPa.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baro_setting: Option<f64>,
+    /// Selected heading in degrees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_heading: Option<f64>,
+    /// Whether the autopilot is engaged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autopilot: Option<bool>,
+    /// Whether vertical navigation mode is active.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vnav: Option<bool>,
+    /// Whether altitude hold mode is active.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude_hold: Option<bool>,
+    /// Whether approach mode is active.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approach: Option<bool>,
+    /// Whether lateral navigation mode is active.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lnav: Option<bool>,
+    /// Whether TCAS/ACAS is operational.
+    pub tcas_operational: bool,
+}
+
+/// Decodes the 56-bit ME field of a target state and status message.
+///
+/// # Arguments
+///
+/// * `me` - The ME field, right-aligned.
+///
+/// # Returns
+///
+/// An `Option` with the decoded targets, or `None` if the message is not the version 2
+/// layout or carries no targets at all.
+pub fn decode(me: u64) -> Option<TargetState> {
+    if bits(me, 1, 5) != 29 || bits(me, 6, 2) != 1 {
+        return None;
+    }
+
+    // Selected altitude and pressure setting are 0 when unavailable and otherwise value + 1.
+    let altitude = bits(me, 10, 11);
+    let selected_altitude = (altitude != 0).then(|| (altitude as i32 - 1) * 32);
+    let selected_altitude_source = selected_altitude.map(|_| {
+        if bits(me, 9, 1) == 1 { "fms" } else { "mcp" }.to_string()
+    });
+    let baro = bits(me, 21, 9);
+    let baro_setting = (baro != 0).then(|| (baro - 1) as f64 * 0.8 + 800.0);
+    let selected_heading = (bits(me, 30, 1) == 1).then(|| bits(me, 31, 9) as f64 * 180.0 / 256.0);
+
+    if selected_altitude.is_none() && baro_setting.is_none() && selected_heading.is_none() {
+        return None;
+    }
+
+    // The mode bits are only meaningful when the status bit says they are populated.
+    let modes = bits(me, 47, 1) == 1;
+    let mode = |bit| modes.then(|| bits(me, bit, 1) == 1);
+
+    Some(TargetState {
+        selected_altitude,
+        selected_altitude_source,
+        baro_setting,
+        selected_heading,
+        autopilot: mode(48),
+        vnav: mode(49),
+        altitude_hold: mode(50),
+        approach: mode(52),
+        lnav: mode(54),
+        tcas_operational: bits(me, 53, 1) == 1,
+    })
+}
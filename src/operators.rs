@@ -0,0 +1,90 @@
+//! This module resolves the airline operating a flight from the three-letter ICAO prefix of
+//! its callsign (e.g. `DLH` in `DLH4AB`), so that traffic can be broken down by carrier.
+//!
+//! A table of major airlines is built in, and a CSV file with the columns `prefix`, `name`
+//! and `country` can add to it or override it.
+
+use crate::parse::SBS1Message;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+/// The built-in table of airlines.
+const BUILTIN: &str = include_str!("operators.csv");
+
+/// An airline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operator {
+    /// The airline's name, e.g. `Lufthansa`.
+    pub name: String,
+    /// The country the airline is based in, e.g. `Germany`.
+    pub country: Option<String>,
+}
+
+/// The airlines, by callsign prefix.
+pub struct Operators {
+    airlines: HashMap<String, Operator>,
+}
+
+impl Operators {
+    /// Creates the built-in table of airlines.
+    pub fn builtin() -> Self {
+        let mut operators = Operators { airlines: HashMap::new() };
+        operators.read(BUILTIN.as_bytes()).expect("the built-in table of airlines is valid");
+        operators
+    }
+
+    /// Creates the built-in table of airlines, with those in a CSV file added to it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the table, or an error if the file could not be read.
+    pub fn with_file(path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut operators = Self::builtin();
+        operators.read(std::fs::File::open(path)?)?;
+        Ok(operators)
+    }
+
+    /// Reads airlines from CSV with a header row, replacing those with the same prefix.
+    fn read(&mut self, csv: impl Read) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut reader = csv::Reader::from_reader(csv);
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+        let (Some(prefix_column), Some(name_column)) = (column("prefix"), column("name")) else {
+            return Err("the airline table needs a prefix and a name column".into());
+        };
+        let country_column = column("country");
+        for record in reader.records() {
+            let record = record?;
+            let (Some(prefix), Some(name)) = (record.get(prefix_column).map(str::trim), record.get(name_column).map(str::trim)) else {
+                continue;
+            };
+            if prefix.len() != 3 || name.is_empty() {
+                continue;
+            }
+            let country = country_column.and_then(|column| record.get(column)).map(str::trim).filter(|country| !country.is_empty()).map(str::to_string);
+            self.airlines.insert(prefix.to_uppercase(), Operator { name: name.to_string(), country });
+        }
+        Ok(())
+    }
+
+    /// Returns the airline flying under a callsign, if its prefix is known. Only callsigns of
+    /// three letters followed by a digit count, so that registrations used as callsigns
+    /// (e.g. `GABCD`) are not taken for airlines.
+    pub fn lookup(&self, callsign: &str) -> Option<&Operator> {
+        let callsign = callsign.trim().as_bytes();
+        if callsign.len() < 4 || !callsign[..3].iter().all(u8::is_ascii_alphabetic) || !callsign[3].is_ascii_digit() {
+            return None;
+        }
+        self.airlines.get(&String::from_utf8_lossy(&callsign[..3]).to_uppercase())
+    }
+
+    /// Adds the airline and its country to a message with a callsign of a known airline.
+    pub fn enrich(&self, message: &mut SBS1Message) {
+        let Some(operator) = message.callsign.as_deref().and_then(|callsign| self.lookup(callsign)) else {
+            return;
+        };
+        message.operator = Some(operator.name.clone());
+        message.operator_country = operator.country.clone();
+    }
+}
@@ -3,39 +3,237 @@
 extern crate chrono;
 extern crate serde_derive;
 
+use crate::coverage::CoverageSummary;
+use crate::modes::commb::EnhancedSurveillance;
+use crate::modes::target::TargetState;
 use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::str::FromStr;
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
+
+/// Number of comma-separated columns in a complete `MSG` line.
+pub const MSG_FIELD_COUNT: usize = 22;
+
+/// Set on addresses that are not ICAO addresses, above the 24 bits of the address.
+const NON_ICAO: u32 = 1 << 24;
+
+/// A 24-bit ICAO aircraft address, displayed as six lowercase hex digits.
+///
+/// mlat-client and readsb mark addresses that are not ICAO addresses (e.g. anonymous
+/// addresses or track numbers) with a `~` prefix. The marker is kept, so that such an address
+/// is never taken for the ICAO address with the same digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Icao24(u32);
+
+impl Icao24 {
+    /// Creates an address from its numeric value, or `None` if it does not fit in 24 bits.
+    pub fn new(address: u32) -> Option<Self> {
+        if address <= 0xFF_FFFF { Some(Icao24(address)) } else { None }
+    }
+
+    /// Creates a non-ICAO address from its numeric value, or `None` if it does not fit in 24
+    /// bits.
+    pub fn non_icao(address: u32) -> Option<Self> {
+        Self::new(address).map(|icao24| Icao24(icao24.0 | NON_ICAO))
+    }
+
+    /// Returns whether the address is an ICAO address, rather than one marked with `~`.
+    pub fn is_icao(&self) -> bool {
+        self.0 & NON_ICAO == 0
+    }
+
+    /// Returns the numeric value of the address, without the non-ICAO marker.
+    pub fn as_u32(&self) -> u32 {
+        self.0 & 0xFF_FFFF
+    }
+}
+
+impl fmt::Display for Icao24 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_icao() {
+            write!(f, "~")?;
+        }
+        write!(f, "{:06x}", self.as_u32())
+    }
+}
+
+impl FromStr for Icao24 {
+    type Err = String;
+
+    /// Parses six hex digits in either case, ignoring surrounding whitespace, with an optional
+    /// `~` marking a non-ICAO address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (digits, marker) = match s.strip_prefix('~') {
+            Some(digits) => (digits, NON_ICAO),
+            None => (s, 0),
+        };
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid ICAO address {:?}", s));
+        }
+        u32::from_str_radix(digits, 16).map(|address| Icao24(address | marker)).map_err(|e| e.to_string())
+    }
+}
+
+impl serde::Serialize for Icao24 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Icao24 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s: String = serde::Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
 
 /// Represents a decoded SBS1 message with various aviation-related fields.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SBS1Message {
     pub timestamp: String, // Nanoseconds since the UNIX epoch
-    message_type: Option<String>,
-    transmission_type: Option<i32>,
-    session_id: Option<String>,
-    aircraft_id: Option<String>,
-    icao24: Option<String>,
-    flight_id: Option<String>,
-    generated_date: Option<NaiveDateTime>,
-    logged_date: Option<NaiveDateTime>,
-    callsign: Option<String>,
-    altitude: Option<i32>,
-    ground_speed: Option<f32>,
-    track: Option<f32>,
-    lat: Option<f32>,
-    lon: Option<f32>,
-    vertical_rate: Option<i32>,
-    squawk: Option<i32>,
-    alert: Option<bool>,
-    emergency: Option<bool>,
-    spi: Option<bool>,
-    on_ground: Option<bool>
+    pub message_type: Option<String>,
+    pub transmission_type: Option<i32>,
+    pub session_id: Option<String>,
+    pub aircraft_id: Option<String>,
+    pub icao24: Option<Icao24>,
+    pub flight_id: Option<String>,
+    pub generated_date: Option<NaiveDateTime>,
+    pub logged_date: Option<NaiveDateTime>,
+    pub callsign: Option<String>,
+    /// Barometric (pressure) altitude in feet.
+    pub altitude_baro: Option<i32>,
+    /// Geometric (GNSS) altitude in feet (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude_geom: Option<i32>,
+    pub ground_speed: Option<f32>,
+    pub track: Option<f32>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub vertical_rate: Option<i32>,
+    pub squawk: Option<i32>,
+    pub alert: Option<bool>,
+    pub emergency: Option<bool>,
+    pub spi: Option<bool>,
+    pub on_ground: Option<bool>,
+    /// Whether the position was computed by multilateration rather than reported by the
+    /// aircraft, as marked by mlat-client (`MLAT` message class or column, or the Beast MLAT
+    /// timestamp).
+    pub mlat: bool,
+    /// Signal level in dBFS (Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<f64>,
+    /// Ground speed in knots from a surface position message (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub surface_speed: Option<f32>,
+    /// Where the message came from, using readsb's naming: `adsb_icao` for DF17, `mode_s` for
+    /// other transponder replies, and `adsb_icao_nt`, `adsb_other`, `tisb_icao`, `tisb_other`,
+    /// `adsr_icao` or `adsr_other` for DF18 (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_type: Option<String>,
+    /// Magnetic heading in degrees from an airspeed-subtype velocity message, as opposed to
+    /// `track`, which is the true track over ground (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub magnetic_heading: Option<f64>,
+    /// Indicated airspeed in knots from an airspeed-subtype velocity message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indicated_airspeed: Option<i32>,
+    /// True airspeed in knots from an airspeed-subtype velocity message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub true_airspeed: Option<i32>,
+    /// Navigation integrity category of a position (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nic: Option<u8>,
+    /// Navigation accuracy category for position, from the aircraft's operational status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nac_p: Option<u8>,
+    /// Source integrity level, from the aircraft's operational status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sil: Option<u8>,
+    /// Emitter category code from an identification message, e.g. `A3` (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_code: Option<String>,
+    /// Human-readable emitter category, e.g. `Large`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// The aircraft's registration, from the registration database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration: Option<String>,
+    /// The country the aircraft's address is allocated to, e.g. `Germany`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_country: Option<String>,
+    /// The aircraft's ICAO type designator, e.g. `A319`, from the registration database or
+    /// else the aircraft database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_code: Option<String>,
+    /// The aircraft's registered owner or operator, from the registration database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// The aircraft's manufacturer, from the aircraft database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    /// The aircraft's model, from the aircraft database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The airline flying under the callsign, from its prefix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    /// The country of the airline flying under the callsign.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator_country: Option<String>,
+    /// Whether the aircraft's address is in a block used by military aircraft; only ever set
+    /// to true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub military: Option<bool>,
+    /// The force or country the military block of the aircraft's address belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub military_force: Option<String>,
+    /// The ICAO code of the airport the flight departed from, from the route API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// The ICAO code of the airport the flight is bound for, from the route API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// The great-circle distance from the receiver in nautical miles, for messages with a
+    /// position when the receiver position is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_nm: Option<f64>,
+    /// The bearing from the receiver in degrees clockwise from true north.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearing_deg: Option<f64>,
+    /// Enhanced surveillance registers decoded from a Comm-B reply (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ehs: Option<EnhancedSurveillance>,
+    /// Autopilot targets from a target state and status message (raw and Beast input only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_state: Option<TargetState>,
+    /// The receiver's coverage, on `coverage` events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<CoverageSummary>,
+    /// Non-empty columns after `on_ground`, as appended by VRS and some dump1090 forks
+    /// (e.g. signal level), keyed by column position (`col22`, `col23`, ...), or the
+    /// details of a `STA` event.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extras: BTreeMap<String, String>,
+    /// Names of fields cleared because they held impossible values.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub invalid_fields: Vec<String>,
+    /// Names of fields filled in from what the aircraft reported earlier, when enriching.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enriched: Vec<String>,
+    /// How many messages were merged into this one, when coalescing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coalesced: Option<u32>,
+    /// What a `STA` status message reports instead of a reception, e.g. `lost` when an
+    /// aircraft has not been heard from for a while.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>
 }
 
 impl SBS1Message {
     /// Creates a new `SBS1Message` with the current timestamp and all other fields set to `None`.
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         let now = std::time::SystemTime::now();
         let since_the_epoch = now.duration_since(std::time::UNIX_EPOCH).unwrap();
         let timestamp_in_nanos = since_the_epoch.as_secs() * 1_000_000_000 + since_the_epoch.subsec_nanos() as u64;
@@ -53,7 +251,8 @@ impl SBS1Message {
             generated_date: None,
             logged_date: None,
             callsign: None,
-            altitude: None,
+            altitude_baro: None,
+            altitude_geom: None,
             ground_speed: None,
             track: None,
             lat: None,
@@ -63,12 +262,179 @@ impl SBS1Message {
             alert: None,
             emergency: None,
             spi: None,
-            on_ground: None
+            on_ground: None,
+            mlat: false,
+            signal: None,
+            surface_speed: None,
+            source_type: None,
+            magnetic_heading: None,
+            indicated_airspeed: None,
+            true_airspeed: None,
+            nic: None,
+            nac_p: None,
+            sil: None,
+            category_code: None,
+            category: None,
+            registration: None,
+            registration_country: None,
+            type_code: None,
+            owner: None,
+            manufacturer: None,
+            model: None,
+            operator: None,
+            operator_country: None,
+            military: None,
+            military_force: None,
+            origin: None,
+            destination: None,
+            distance_nm: None,
+            bearing_deg: None,
+            ehs: None,
+            target_state: None,
+            coverage: None,
+            extras: BTreeMap::new(),
+            invalid_fields: Vec::new(),
+            enriched: Vec::new(),
+            coalesced: None,
+            event: None
+        }
+    }
+
+    /// Returns whether the message reports an emergency, by its flag or a squawk of 7500
+    /// (hijack), 7600 (radio failure) or 7700 (general emergency).
+    pub fn is_emergency(&self) -> bool {
+        self.emergency == Some(true) || matches!(self.squawk, Some(7500 | 7600 | 7700))
+    }
+
+    /// Returns the aircraft's address if it is an ICAO address, whose block tells who it was
+    /// allocated to, rather than a non-ICAO one marked with `~`.
+    pub fn icao_address(&self) -> Option<Icao24> {
+        self.icao24.filter(Icao24::is_icao)
+    }
+
+    /// Formats the message as an SBS-1 (BaseStation) `MSG` line, without a line terminator.
+    ///
+    /// Missing dates are filled in from the receipt timestamp and missing session, aircraft
+    /// and flight IDs are set to 1, as dump1090 does.
+    ///
+    /// # Returns
+    ///
+    /// The line, or `None` if the message has no SBS-1 transmission type (e.g. target state
+    /// messages).
+    pub fn to_sbs1(&self) -> Option<String> {
+        let transmission_type = self.transmission_type?;
+        let received = chrono::DateTime::from_timestamp_nanos(self.timestamp.parse().unwrap_or_default()).naive_utc();
+        let generated = self.generated_date.unwrap_or(received);
+        let logged = self.logged_date.unwrap_or(received);
+        let id = |value: &Option<String>| value.clone().unwrap_or_else(|| "1".to_string());
+        let number = |value: Option<String>| value.unwrap_or_default();
+        let flag = |value: Option<bool>| value.map(|value| if value { "-1" } else { "0" }).unwrap_or_default();
+
+        Some(format!(
+            "MSG,{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            transmission_type,
+            id(&self.session_id),
+            id(&self.aircraft_id),
+            self.icao24.map(|icao24| icao24.to_string().to_uppercase()).unwrap_or_default(),
+            id(&self.flight_id),
+            generated.format("%Y/%m/%d"),
+            generated.format("%H:%M:%S%.3f"),
+            logged.format("%Y/%m/%d"),
+            logged.format("%H:%M:%S%.3f"),
+            self.callsign.clone().unwrap_or_default(),
+            number(self.altitude_baro.map(|value| value.to_string())),
+            number(self.ground_speed.map(|value| value.to_string())),
+            number(self.track.map(|value| value.to_string())),
+            number(self.lat.map(|value| format!("{:.5}", value))),
+            number(self.lon.map(|value| format!("{:.5}", value))),
+            number(self.vertical_rate.map(|value| value.to_string())),
+            number(self.squawk.map(|value| format!("{:04}", value))),
+            flag(self.alert),
+            flag(self.emergency),
+            flag(self.spi),
+            flag(self.on_ground),
+        ))
+    }
+}
+
+/// Describes why a line could not be parsed into an `SBS1Message`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParseError {
+    /// The first column is not a message class this parser understands (e.g. `SEL`, `AIR`);
+    /// `MSG` and mlat-client's `MLAT` are understood.
+    UnknownMessageClass(String),
+    /// The line has fewer columns than a complete `MSG` line.
+    FieldCount { expected: usize, found: usize },
+    /// A numeric or flag column holds something that is not a number.
+    BadNumber { column: usize, value: String },
+    /// A date/time column pair could not be parsed.
+    BadDate { column: usize, value: String },
+    /// The aircraft address column is not six hex digits.
+    BadIcao24 { column: usize, value: String },
+}
+
+impl ParseError {
+    /// Returns the zero-based index of the offending column, if the error relates to one.
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            ParseError::UnknownMessageClass(_) => Some(0),
+            ParseError::FieldCount { .. } => None,
+            ParseError::BadNumber { column, .. }
+            | ParseError::BadDate { column, .. }
+            | ParseError::BadIcao24 { column, .. } => Some(*column),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownMessageClass(class) => write!(f, "unknown message class {:?}", class),
+            ParseError::FieldCount { expected, found } => write!(f, "expected {} fields, found {}", expected, found),
+            ParseError::BadNumber { column, value } => write!(f, "invalid number {:?} in column {}", value, column),
+            ParseError::BadDate { column, value } => write!(f, "invalid date/time {:?} in column {}", value, column),
+            ParseError::BadIcao24 { column, value } => write!(f, "invalid ICAO address {:?} in column {}", value, column),
         }
     }
 }
 
-/// Parses an SBS1 message string and returns an `Option<SBS1Message>`.
+impl std::error::Error for ParseError {}
+
+/// Parses an SBS1 message string, rejecting anything that is not a well-formed `MSG` line.
+///
+/// The full set of columns must be present and every non-empty numeric, flag, and date
+/// column must be valid.
+///
+/// # Arguments
+///
+/// * `msg` - A string slice containing an SBS1 message.
+///
+/// # Returns
+///
+/// A `Result` containing the parsed `SBS1Message`, or a `ParseError` describing the first
+/// problem found.
+pub fn parse(msg: &str) -> Result<SBS1Message, ParseError> {
+    parse_fields(msg, true)
+}
+
+/// Parses an SBS1 message string, tolerating missing or unparseable columns.
+///
+/// Only lines that are not `MSG` lines or carry an invalid aircraft address are rejected;
+/// any other column that is absent or cannot be parsed is set to `None`.
+///
+/// # Arguments
+///
+/// * `msg` - A string slice containing an SBS1 message.
+///
+/// # Returns
+///
+/// A `Result` containing the parsed `SBS1Message`, or `ParseError::UnknownMessageClass` or
+/// `ParseError::BadIcao24`.
+pub fn parse_lenient(msg: &str) -> Result<SBS1Message, ParseError> {
+    parse_fields(msg, false)
+}
+
+/// Parses an SBS1 message string leniently and returns an `Option<SBS1Message>`.
 ///
 /// # Arguments
 ///
@@ -77,36 +443,58 @@ impl SBS1Message {
 /// # Returns
 ///
 /// An `Option` that contains a parsed `SBS1Message` if successful or `None` otherwise.
-pub fn parse(msg: &str) -> Option<SBS1Message> {
-    let mut sbs1 = SBS1Message::new();
+pub fn parse_opt(msg: &str) -> Option<SBS1Message> {
+    parse_lenient(msg).ok()
+}
+
+/// Splits `msg` into columns and fills an `SBS1Message`, failing on bad columns only if `strict`.
+fn parse_fields(msg: &str, strict: bool) -> Result<SBS1Message, ParseError> {
     let parts: Vec<&str> = msg.trim().split(',').collect();
 
-    match parts.get(0) {
-        Some(&"MSG") => {
-            sbs1.message_type = Some("MSG".to_string());
-            sbs1.transmission_type = parse_int(parts.get(1));
-            sbs1.session_id = parse_string(parts.get(2));
-            sbs1.aircraft_id = parse_string(parts.get(3));
-            sbs1.icao24 = parse_string(parts.get(4));
-            sbs1.flight_id = parse_string(parts.get(5));
-            sbs1.generated_date = parse_date_time(parts.get(6), parts.get(7));
-            sbs1.logged_date = parse_date_time(parts.get(8), parts.get(9));
-            sbs1.callsign = if parts[10].is_empty() { None } else { Some(String::from(parts[10].trim())) };
-            sbs1.altitude = parse_int(parts.get(11));
-            sbs1.ground_speed = parse_float(parts.get(12));
-            sbs1.track = parse_float(parts.get(13));
-            sbs1.lat = parse_float(parts.get(14));
-            sbs1.lon = parse_float(parts.get(15));
-            sbs1.vertical_rate = parse_int(parts.get(16));
-            sbs1.squawk = parse_int(parts.get(17));
-            sbs1.alert = parse_bool(parts.get(18));
-            sbs1.emergency = parse_bool(parts.get(19));
-            sbs1.spi = parse_bool(parts.get(20));
-            sbs1.on_ground = parse_bool(parts.get(21));
-            Some(sbs1)
-        },
-        _ => None
+    // mlat-client's SBS output uses its own message class for otherwise ordinary MSG lines.
+    let mlat = match parts[0] {
+        "MSG" => false,
+        "MLAT" => true,
+        class => return Err(ParseError::UnknownMessageClass(class.to_string())),
+    };
+    if strict && parts.len() < MSG_FIELD_COUNT {
+        return Err(ParseError::FieldCount { expected: MSG_FIELD_COUNT, found: parts.len() });
+    }
+
+    let mut sbs1 = SBS1Message::new();
+    sbs1.message_type = Some("MSG".to_string());
+    sbs1.mlat = mlat;
+    sbs1.transmission_type = parse_int(&parts, 1, strict)?;
+    sbs1.session_id = parse_string(parts.get(2));
+    sbs1.aircraft_id = parse_string(parts.get(3));
+    sbs1.icao24 = parse_icao24(&parts, 4)?;
+    sbs1.flight_id = parse_string(parts.get(5));
+    sbs1.generated_date = parse_date_time(&parts, 6, 7, strict)?;
+    sbs1.logged_date = parse_date_time(&parts, 8, 9, strict)?;
+    sbs1.callsign = parse_callsign(parts.get(10));
+    sbs1.altitude_baro = parse_int(&parts, 11, strict)?;
+    sbs1.ground_speed = parse_float(&parts, 12, strict)?;
+    sbs1.track = parse_float(&parts, 13, strict)?;
+    sbs1.lat = parse_coordinate(&parts, 14, strict)?;
+    sbs1.lon = parse_coordinate(&parts, 15, strict)?;
+    sbs1.vertical_rate = parse_int(&parts, 16, strict)?;
+    sbs1.squawk = parse_int(&parts, 17, strict)?;
+    sbs1.alert = parse_bool(&parts, 18, strict)?;
+    sbs1.emergency = parse_bool(&parts, 19, strict)?;
+    sbs1.spi = parse_bool(&parts, 20, strict)?;
+    sbs1.on_ground = parse_bool(&parts, 21, strict)?;
+    // Some feeds flag multilaterated positions with an extra `MLAT` column instead.
+    if parts.iter().skip(MSG_FIELD_COUNT).any(|value| value.trim().eq_ignore_ascii_case("MLAT")) {
+        sbs1.mlat = true;
     }
+    sbs1.extras = parts
+        .iter()
+        .enumerate()
+        .skip(MSG_FIELD_COUNT)
+        .filter(|(_, value)| !value.is_empty() && !value.trim().eq_ignore_ascii_case("MLAT"))
+        .map(|(column, value)| (format!("col{}", column), value.to_string()))
+        .collect();
+    Ok(sbs1)
 }
 
 /// Converts an `Option<&&str>` into an `Option<String>`.
@@ -114,39 +502,83 @@ fn parse_string(opt: Option<&&str>) -> Option<String> {
     opt.map(|&s| s.to_string())
 }
 
-/// Parses a string representation of a boolean (by integer) into an `Option<bool>`.
-fn parse_bool(opt: Option<&&str>) -> Option<bool> {
-    match opt {
-        Some(s) => i32::from_str(s).ok().map(|num| num != 0),
-        None => None,
+/// Trims a callsign column, treating an empty column or a placeholder of hyphens (as used
+/// for aircraft without a callsign by some MLAT feeds) as `None`.
+fn parse_callsign(opt: Option<&&str>) -> Option<String> {
+    opt.map(|s| s.trim())
+        .filter(|s| !s.is_empty() && !s.chars().all(|c| c == '-'))
+        .map(|s| s.to_string())
+}
+
+/// Parses and normalizes an aircraft address column, treating an empty or missing column as `None`.
+///
+/// mlat-client prefixes non-ICAO addresses with `~`; the marker is kept. An invalid
+/// address is an error even when not parsing strictly, since a message that cannot be told
+/// apart from other aircraft's is of no use.
+fn parse_icao24(parts: &[&str], column: usize) -> Result<Option<Icao24>, ParseError> {
+    match parts.get(column).filter(|s| !s.trim().is_empty()) {
+        None => Ok(None),
+        Some(s) => Icao24::from_str(s).map(Some).map_err(|_| ParseError::BadIcao24 { column, value: s.to_string() }),
+    }
+}
+
+/// Parses a numeric column, treating an empty or missing column as `None`.
+///
+/// An unparseable value is an error if `strict` is set and `None` otherwise.
+fn parse_number<T: FromStr>(parts: &[&str], column: usize, strict: bool) -> Result<Option<T>, ParseError> {
+    match parts.get(column).filter(|s| !s.is_empty()) {
+        None => Ok(None),
+        Some(s) => match T::from_str(s) {
+            Ok(value) => Ok(Some(value)),
+            Err(_) if strict => Err(ParseError::BadNumber { column, value: s.to_string() }),
+            Err(_) => Ok(None),
+        },
     }
 }
 
-/// Converts an `Option<&&str>` into an `Option<i32>`.
-fn parse_int(opt: Option<&&str>) -> Option<i32> {
-    opt.and_then(|&s| i32::from_str(s).ok())
+/// Parses a string representation of a boolean (by integer) into an `Option<bool>`.
+fn parse_bool(parts: &[&str], column: usize, strict: bool) -> Result<Option<bool>, ParseError> {
+    Ok(parse_number::<i32>(parts, column, strict)?.map(|num| num != 0))
 }
 
-/// Converts an `Option<&&str>` into an `Option<f32>`.
-fn parse_float(opt: Option<&&str>) -> Option<f32> {
-    opt.and_then(|&s| f32::from_str(s).ok())
+/// Parses a column into an `Option<i32>`.
+fn parse_int(parts: &[&str], column: usize, strict: bool) -> Result<Option<i32>, ParseError> {
+    parse_number(parts, column, strict)
 }
 
-/// Combines date and time string representations into a single `NaiveDateTime`.
+/// Parses a column into an `Option<f32>`.
+fn parse_float(parts: &[&str], column: usize, strict: bool) -> Result<Option<f32>, ParseError> {
+    parse_number(parts, column, strict)
+}
+
+/// Parses a latitude or longitude column into an `Option<f64>`, keeping the feed's full precision.
+fn parse_coordinate(parts: &[&str], column: usize, strict: bool) -> Result<Option<f64>, ParseError> {
+    parse_number(parts, column, strict)
+}
+
+/// Combines date and time columns into a single `NaiveDateTime`.
 ///
 /// # Arguments
 ///
-/// * `opt_date` - An optional string slice representing the date.
-/// * `opt_time` - An optional string slice representing the time.
+/// * `parts` - The columns of the message.
+/// * `date_column` - Index of the column holding the date.
+/// * `time_column` - Index of the column holding the time.
+/// * `strict` - Whether an unparseable date/time is an error rather than `None`.
 ///
 /// # Returns
 ///
-/// An `Option` that contains a `NaiveDateTime` if successful or `None` otherwise.
-fn parse_date_time(opt_date: Option<&&str>, opt_time: Option<&&str>) -> Option<NaiveDateTime> {
-    if let (Some(&date), Some(&time)) = (opt_date, opt_time) {
-        let combined = format!("{} {}", date, time);
-        NaiveDateTime::parse_from_str(&combined, "%Y/%m/%d %H:%M:%S").ok()
-    } else {
-        None
+/// A `Result` containing an `Option<NaiveDateTime>`, which is `None` if both columns are empty.
+fn parse_date_time(parts: &[&str], date_column: usize, time_column: usize, strict: bool) -> Result<Option<NaiveDateTime>, ParseError> {
+    let date = parts.get(date_column).copied().unwrap_or("");
+    let time = parts.get(time_column).copied().unwrap_or("");
+    if date.is_empty() && time.is_empty() {
+        return Ok(None);
+    }
+
+    let combined = format!("{} {}", date, time);
+    match NaiveDateTime::parse_from_str(&combined, "%Y/%m/%d %H:%M:%S%.f") {
+        Ok(date_time) => Ok(Some(date_time)),
+        Err(_) if strict => Err(ParseError::BadDate { column: date_column, value: combined }),
+        Err(_) => Ok(None),
     }
 }
@@ -0,0 +1,97 @@
+//! This module picks out the critical messages sent on the priority lane, ahead of routine
+//! batches: emergencies, messages from aircraft on a watchlist, and reports of aircraft
+//! that have not been heard from for a while.
+
+use crate::parse::{Icao24, SBS1Message};
+use crate::tracker::Tracker;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// How often aircraft are checked for having been lost.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Decides which messages are critical, and reports aircraft that have been lost.
+pub struct Priority {
+    /// The watched aircraft addresses.
+    icao24s: HashSet<Icao24>,
+    /// The watched callsigns, in upper case.
+    callsigns: HashSet<String>,
+    /// How long an aircraft can go unheard before it is reported lost, if at all.
+    lost_after: Option<Duration>,
+    /// The aircraft reported lost that have not been heard from since.
+    reported: HashSet<Icao24>,
+    /// When aircraft were last checked for having been lost.
+    checked: Instant,
+}
+
+impl Priority {
+    /// Creates the rules for the priority lane.
+    ///
+    /// # Arguments
+    ///
+    /// * `watchlist` - Comma-separated aircraft to watch, each an ICAO 24-bit address in hex
+    ///   (e.g. `3c6444`) or a callsign (e.g. `DLH4AB`).
+    /// * `lost_after` - How long an aircraft can go unheard before it is reported lost, if
+    ///   lost aircraft are reported.
+    pub fn new(watchlist: &str, lost_after: Option<Duration>) -> Self {
+        let mut icao24s = HashSet::new();
+        let mut callsigns = HashSet::new();
+        for entry in watchlist.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            // Six hex digits could also be a callsign (e.g. `ABC123`), so they count as both.
+            if let Ok(icao24) = entry.parse::<Icao24>() {
+                icao24s.insert(icao24);
+            }
+            callsigns.insert(entry.to_uppercase());
+        }
+        Priority { icao24s, callsigns, lost_after, reported: HashSet::new(), checked: Instant::now() }
+    }
+
+    /// Returns whether a message is critical: an emergency, or from a watched aircraft.
+    pub fn is_critical(&self, message: &SBS1Message) -> bool {
+        message.is_emergency()
+            || message.icao24.is_some_and(|icao24| self.icao24s.contains(&icao24))
+            || message.callsign.as_ref().is_some_and(|callsign| self.callsigns.contains(&callsign.trim().to_uppercase()))
+    }
+
+    /// Returns when aircraft are next due to be checked for having been lost, if they are.
+    pub fn next_check(&self) -> Option<Instant> {
+        self.lost_after.map(|_| self.checked + CHECK_INTERVAL)
+    }
+
+    /// Reports the aircraft in the tracker that have gone unheard for too long, at most once
+    /// per check interval and once until they are heard from again.
+    ///
+    /// # Returns
+    ///
+    /// A `STA` message with the event `lost` for each, carrying what was last known of it.
+    pub fn lost(&mut self, tracker: &Tracker) -> Vec<SBS1Message> {
+        let Some(lost_after) = self.lost_after else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        if now.duration_since(self.checked) < CHECK_INTERVAL {
+            return Vec::new();
+        }
+        self.checked = now;
+
+        let lost: HashSet<Icao24> = tracker.unheard_for(lost_after).into_iter().collect();
+        let mut newly_lost: Vec<Icao24> = lost.difference(&self.reported).copied().collect();
+        newly_lost.sort();
+        self.reported = lost;
+        newly_lost
+            .into_iter()
+            .filter_map(|icao24| {
+                let last = tracker.get(icao24)?;
+                let mut message = SBS1Message::new();
+                message.message_type = Some("STA".to_string());
+                message.icao24 = Some(icao24);
+                message.callsign = last.callsign;
+                message.altitude_baro = last.altitude;
+                message.lat = last.lat;
+                message.lon = last.lon;
+                message.event = Some("lost".to_string());
+                Some(message)
+            })
+            .collect()
+    }
+}
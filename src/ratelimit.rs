@@ -0,0 +1,176 @@
+//! This module caps how many messages are forwarded per aircraft and second, so that busy
+//! corridors do not blow out ingest budgets. Within each second, the most complete messages
+//! are kept, and the newest of equally complete ones, which preserves the track.
+
+use crate::parse::{Icao24, SBS1Message};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Limits the messages of each aircraft to a number per second of receipt time.
+pub struct AircraftRateLimit {
+    max_rate: usize,
+    /// The latest second each aircraft was forwarded in, and how many of its messages were
+    /// forwarded in it, so that the limit holds across batches.
+    forwarded: HashMap<Icao24, (u64, usize)>,
+}
+
+impl AircraftRateLimit {
+    /// Creates a rate limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_rate` - The most messages to forward per aircraft and second.
+    pub fn new(max_rate: usize) -> Self {
+        AircraftRateLimit { max_rate, forwarded: HashMap::new() }
+    }
+
+    /// Drops the messages over the limit from a batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - The batch, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// The messages kept, in their original order, and the number dropped.
+    pub fn apply(&mut self, messages: Vec<SBS1Message>) -> (Vec<SBS1Message>, usize) {
+        // Group the messages of each aircraft by the second they were received in.
+        let mut groups: BTreeMap<(u64, Icao24), Vec<usize>> = BTreeMap::new();
+        for (index, message) in messages.iter().enumerate() {
+            if let Some(icao24) = message.icao24 {
+                let second = message.timestamp.parse::<u64>().unwrap_or_default() / 1_000_000_000;
+                groups.entry((second, icao24)).or_default().push(index);
+            }
+        }
+
+        let mut dropped = HashSet::new();
+        for ((second, icao24), mut indices) in groups {
+            let forwarded = self.forwarded.entry(icao24).or_insert((second, 0));
+            if forwarded.0 < second {
+                *forwarded = (second, 0);
+            } else if forwarded.0 > second {
+                // Received before the last second forwarded, e.g. across a restart of the
+                // clock; let it through rather than guess.
+                continue;
+            }
+            let allowed = self.max_rate.saturating_sub(forwarded.1);
+            if indices.len() > allowed {
+                // Keep the most complete and, of those, the newest.
+                indices.sort_by_key(|&index| (std::cmp::Reverse(completeness(&messages[index])), std::cmp::Reverse(index)));
+                dropped.extend(indices.drain(allowed..));
+            }
+            forwarded.1 += indices.len();
+        }
+
+        // Forget aircraft that have not been forwarded for a while.
+        if let Some(&(latest, _)) = self.forwarded.values().max() {
+            self.forwarded.retain(|_, (second, _)| *second + 60 >= latest);
+        }
+
+        let count = dropped.len();
+        let kept = messages.into_iter().enumerate().filter(|(index, _)| !dropped.contains(index)).map(|(_, message)| message).collect();
+        (kept, count)
+    }
+}
+
+/// Returns how many of the fields that make up a track a message carries.
+fn completeness(message: &SBS1Message) -> usize {
+    [
+        message.lat.is_some() && message.lon.is_some(),
+        message.altitude_baro.is_some(),
+        message.ground_speed.is_some(),
+        message.track.is_some(),
+        message.vertical_rate.is_some(),
+        message.callsign.is_some(),
+        message.squawk.is_some(),
+    ]
+    .into_iter()
+    .filter(|&set| set)
+    .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a message of an aircraft received at a time in milliseconds, carrying an
+    /// altitude and, if `position`, a position.
+    fn message(icao24: &str, millis: u64, altitude: i32, position: bool) -> SBS1Message {
+        let mut message = SBS1Message::new();
+        message.timestamp = (millis * 1_000_000).to_string();
+        message.icao24 = icao24.parse().ok();
+        message.altitude_baro = Some(altitude);
+        if position {
+            message.lat = Some(52.2572);
+            message.lon = Some(3.9194);
+        }
+        message
+    }
+
+    fn altitudes(messages: &[SBS1Message]) -> Vec<i32> {
+        messages.iter().filter_map(|message| message.altitude_baro).collect()
+    }
+
+    #[test]
+    fn keeps_the_most_complete_and_newest_messages() {
+        let mut limit = AircraftRateLimit::new(2);
+        let batch = vec![
+            message("40621d", 1_000, 1, true),
+            message("40621d", 1_100, 2, false),
+            message("40621d", 1_200, 3, false),
+            message("40621d", 1_300, 4, true),
+            message("40621d", 1_400, 5, false),
+        ];
+        let (kept, dropped) = limit.apply(batch);
+        assert_eq!((altitudes(&kept), dropped), (vec![1, 4], 3));
+
+        let mut limit = AircraftRateLimit::new(2);
+        let batch = (0..4).map(|step| message("40621d", 1_000 + step * 100, step as i32, false)).collect();
+        let (kept, dropped) = limit.apply(batch);
+        assert_eq!((altitudes(&kept), dropped), (vec![2, 3], 2));
+    }
+
+    #[test]
+    fn limits_each_aircraft_and_second_separately() {
+        let mut limit = AircraftRateLimit::new(1);
+        let batch = vec![
+            message("40621d", 1_000, 1, false),
+            message("4ca7b4", 1_100, 2, false),
+            message("40621d", 1_200, 3, false),
+            message("40621d", 2_000, 4, false),
+            message("4ca7b4", 2_500, 5, false),
+        ];
+        let (kept, dropped) = limit.apply(batch);
+        assert_eq!((altitudes(&kept), dropped), (vec![2, 3, 4, 5], 1));
+    }
+
+    #[test]
+    fn holds_the_limit_across_batches() {
+        let mut limit = AircraftRateLimit::new(2);
+        assert_eq!(limit.apply(vec![message("40621d", 1_000, 1, false)]).1, 0);
+        let (kept, dropped) = limit.apply(vec![message("40621d", 1_500, 2, false), message("40621d", 1_600, 3, false)]);
+        assert_eq!((altitudes(&kept), dropped), (vec![3], 1));
+        assert_eq!(limit.apply(vec![message("40621d", 1_700, 4, false)]).1, 1);
+        // A new second starts a new count.
+        assert_eq!(limit.apply(vec![message("40621d", 2_000, 5, false), message("40621d", 2_100, 6, false)]).1, 0);
+        // An earlier second is let through.
+        assert_eq!(limit.apply(vec![message("40621d", 1_800, 7, false)]).1, 0);
+    }
+
+    #[test]
+    fn lets_messages_of_no_aircraft_through() {
+        let mut limit = AircraftRateLimit::new(1);
+        let batch = (0..3).map(|step| message("", 1_000, step, false)).collect();
+        assert_eq!(limit.apply(batch).1, 0);
+    }
+
+    #[test]
+    fn forgets_aircraft_not_seen_for_a_minute() {
+        let mut limit = AircraftRateLimit::new(1);
+        limit.apply(vec![message("40621d", 1_000, 1, false)]);
+        limit.apply(vec![message("4ca7b4", 30_000, 2, false)]);
+        assert_eq!(limit.forwarded.len(), 2);
+        limit.apply(vec![message("4ca7b4", 62_000, 3, false)]);
+        assert!(!limit.forwarded.contains_key(&"40621d".parse().unwrap()));
+        assert_eq!(limit.forwarded.len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! This module looks up the registration, ICAO type code and registered owner of aircraft by
+//! their address, from a local database, so that events say which aircraft they are from and
+//! not just its transponder address.
+//!
+//! The database is either a BaseStation.sqb file, as kept by Virtual Radar Server and
+//! BaseStation (requires the `sqlite` feature), or a CSV file with the columns `icao24`,
+//! `registration`, `type_code` and `owner`. A CSV file is read into memory whole; a
+//! BaseStation database is queried as aircraft are first heard, with the answers kept in an
+//! LRU cache.
+
+use crate::parse::{Icao24, SBS1Message};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// What the database knows about an aircraft.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Registration {
+    /// The aircraft's registration (tail number), e.g. `D-AIBL`.
+    pub registration: Option<String>,
+    /// The ICAO type designator, e.g. `A319`.
+    pub type_code: Option<String>,
+    /// The registered owner or operator, e.g. `Lufthansa`.
+    pub owner: Option<String>,
+}
+
+/// Where registrations are looked up.
+enum Source {
+    Csv(HashMap<Icao24, Registration>),
+    #[cfg(feature = "sqlite")]
+    BaseStation {
+        connection: rusqlite::Connection,
+        /// The registrations looked up recently, including the aircraft not in the database.
+        cache: lru::LruCache<Icao24, Option<Registration>>,
+    },
+}
+
+/// Looks up aircraft registrations in a local database.
+pub struct Registry {
+    source: Source,
+}
+
+impl Registry {
+    /// Opens a registration database: a CSV file if the path ends in `.csv`, and a
+    /// BaseStation database otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The database file.
+    /// * `cache_size` - How many aircraft a BaseStation database's cache holds.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the registry, or an error if the file could not be read, or is
+    /// a BaseStation database without the `sqlite` feature.
+    pub fn open(path: &str, cache_size: usize) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if path.to_lowercase().ends_with(".csv") {
+            return Ok(Registry { source: Source::Csv(read_csv(path)?) });
+        }
+        Self::open_basestation(path, cache_size)
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn open_basestation(path: &str, cache_size: usize) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let connection = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        // Fail now rather than on the first lookup if this is not a BaseStation database.
+        connection.prepare_cached("SELECT Registration, ICAOTypeCode, RegisteredOwners FROM Aircraft WHERE ModeS = ?1")?;
+        let cache_size = std::num::NonZeroUsize::new(cache_size).unwrap_or(std::num::NonZeroUsize::MIN);
+        Ok(Registry { source: Source::BaseStation { connection, cache: lru::LruCache::new(cache_size) } })
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn open_basestation(path: &str, _cache_size: usize) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Err(format!("reading the BaseStation database {} requires the `sqlite` feature", path).into())
+    }
+
+    /// Looks up an aircraft.
+    ///
+    /// # Returns
+    ///
+    /// What the database knows about the aircraft, or `None` if it is not in the database.
+    pub fn lookup(&mut self, icao24: Icao24) -> Option<Registration> {
+        match &mut self.source {
+            Source::Csv(registrations) => registrations.get(&icao24).cloned(),
+            #[cfg(feature = "sqlite")]
+            Source::BaseStation { connection, cache } => cache
+                .get_or_insert(icao24, || match query(connection, icao24) {
+                    Ok(registration) => registration,
+                    Err(err) => {
+                        eprintln!("Error looking up aircraft {} in the BaseStation database: {}", icao24, err);
+                        None
+                    }
+                })
+                .clone(),
+        }
+    }
+
+    /// Adds the registration, type code and owner of the aircraft a message is from, if the
+    /// database knows them.
+    pub fn enrich(&mut self, message: &mut SBS1Message) {
+        let Some(registration) = message.icao24.and_then(|icao24| self.lookup(icao24)) else {
+            return;
+        };
+        message.registration = registration.registration;
+        message.type_code = registration.type_code;
+        message.owner = registration.owner;
+    }
+}
+
+/// Queries a BaseStation database for an aircraft, whose address it keeps in upper case.
+#[cfg(feature = "sqlite")]
+fn query(connection: &rusqlite::Connection, icao24: Icao24) -> rusqlite::Result<Option<Registration>> {
+    use rusqlite::OptionalExtension;
+
+    let mut statement = connection.prepare_cached("SELECT Registration, ICAOTypeCode, RegisteredOwners FROM Aircraft WHERE ModeS = ?1")?;
+    statement
+        .query_row([icao24.to_string().to_uppercase()], |row| {
+            Ok(Registration { registration: non_empty(row.get(0)?), type_code: non_empty(row.get(1)?), owner: non_empty(row.get(2)?) })
+        })
+        .optional()
+}
+
+/// Reads the registrations in a CSV file with a header row. Rows with an invalid address are
+/// skipped.
+fn read_csv(path: &str) -> Result<HashMap<Icao24, Registration>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+    let Some(icao24_column) = column("icao24") else {
+        return Err(format!("{} has no icao24 column", path).into());
+    };
+    let (registration_column, type_code_column, owner_column) = (column("registration"), column("type_code"), column("owner"));
+
+    let mut registrations = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let Some(icao24) = record.get(icao24_column).and_then(|icao24| icao24.parse().ok()) else {
+            continue;
+        };
+        let field = |column: Option<usize>| non_empty(column.and_then(|column| record.get(column)).map(str::to_string));
+        registrations.insert(icao24, Registration { registration: field(registration_column), type_code: field(type_code_column), owner: field(owner_column) });
+    }
+    Ok(registrations)
+}
+
+/// Trims a field, treating one that is missing or blank as unknown.
+fn non_empty(field: Option<String>) -> Option<String> {
+    field.map(|field| field.trim().to_string()).filter(|field| !field.is_empty())
+}
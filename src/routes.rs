@@ -0,0 +1,185 @@
+//! This module adds the origin and destination airports of flights, looked up by callsign
+//! from a route API, so that traffic can be broken down by route.
+//!
+//! Lookups never hold up the messages: the first message with a new callsign starts a lookup
+//! in the background and goes out without a route, and later messages with that callsign
+//! carry the route once it is known. Routes (and callsigns without one) are cached.
+
+use crate::parse::SBS1Message;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// How long to wait before looking up a callsign again after a lookup failed.
+const RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How long to wait for the route API to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The route APIs that can be queried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteApi {
+    /// adsbdb.com: `GET {url}/{callsign}`.
+    Adsbdb,
+    /// adsb.lol: `POST {url}` with the callsign in a `routeset` request.
+    AdsbLol,
+}
+
+impl RouteApi {
+    /// Returns where the API is served by default.
+    pub fn default_url(&self) -> &'static str {
+        match self {
+            RouteApi::Adsbdb => "https://api.adsbdb.com/v0/callsign",
+            RouteApi::AdsbLol => "https://api.adsb.lol/api/0/routeset",
+        }
+    }
+}
+
+impl FromStr for RouteApi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "adsbdb" => Ok(RouteApi::Adsbdb),
+            "adsblol" | "adsb.lol" => Ok(RouteApi::AdsbLol),
+            other => Err(format!("unknown route API {:?} (expected adsbdb or adsblol)", other)),
+        }
+    }
+}
+
+/// The airports a flight flies between, as ICAO codes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Route {
+    pub origin: Option<String>,
+    pub destination: Option<String>,
+}
+
+/// What is known of a callsign's route.
+enum Entry {
+    /// Being looked up.
+    Pending,
+    /// Looked up, with or without a route, until it is looked up again.
+    Known { route: Option<Route>, expires: Instant },
+}
+
+/// The API and the cache, shared with the lookups running in the background.
+struct Inner {
+    api: RouteApi,
+    url: String,
+    client: reqwest::Client,
+    /// How long a route is cached.
+    ttl: Duration,
+    cache: Mutex<HashMap<String, Entry>>,
+    /// Limits the lookups running at the same time.
+    permits: Semaphore,
+}
+
+/// Looks up the routes of flights in the background, and caches them.
+pub struct Routes {
+    inner: Arc<Inner>,
+}
+
+impl Routes {
+    /// Creates a route lookup.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - The route API to query.
+    /// * `url` - Where the API is served, e.g. `RouteApi::default_url`.
+    /// * `ttl` - How long a route is cached.
+    /// * `max_in_flight` - How many lookups may run at the same time, at least 1.
+    pub fn new(api: RouteApi, url: &str, ttl: Duration, max_in_flight: usize) -> Self {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default();
+        let inner = Inner {
+            api,
+            url: url.trim_end_matches('/').to_string(),
+            client,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+            permits: Semaphore::new(max_in_flight.max(1)),
+        };
+        Routes { inner: Arc::new(inner) }
+    }
+
+    /// Returns the cached route of a callsign, starting a lookup in the background if it is
+    /// not cached or has expired.
+    pub fn lookup(&self, callsign: &str) -> Option<Route> {
+        let callsign = callsign.trim().to_uppercase();
+        if callsign.is_empty() {
+            return None;
+        }
+        let mut cache = self.inner.cache.lock().unwrap();
+        let now = Instant::now();
+        match cache.get(&callsign) {
+            Some(Entry::Pending) => return None,
+            Some(Entry::Known { route, expires }) if *expires > now => return route.clone(),
+            _ => {}
+        }
+        // Forget the expired routes, rather than let callsigns no longer heard pile up.
+        cache.retain(|_, entry| !matches!(entry, Entry::Known { expires, .. } if *expires <= now));
+        cache.insert(callsign.clone(), Entry::Pending);
+        tokio::spawn(fetch(self.inner.clone(), callsign));
+        None
+    }
+
+    /// Adds the origin and destination to a message with a callsign whose route is known.
+    pub fn enrich(&self, message: &mut SBS1Message) {
+        let Some(route) = message.callsign.as_deref().and_then(|callsign| self.lookup(callsign)) else {
+            return;
+        };
+        message.origin = route.origin;
+        message.destination = route.destination;
+    }
+}
+
+/// Looks up the route of a callsign and caches what was found. A failed lookup is logged and
+/// retried after a while.
+async fn fetch(inner: Arc<Inner>, callsign: String) {
+    let (route, ttl) = {
+        let Ok(_permit) = inner.permits.acquire().await else {
+            return;
+        };
+        match query(&inner, &callsign).await {
+            Ok(route) => (route, inner.ttl),
+            Err(err) => {
+                eprintln!("Error looking up the route of {}: {}", callsign, err);
+                (None, RETRY_AFTER)
+            }
+        }
+    };
+    inner.cache.lock().unwrap().insert(callsign, Entry::Known { route, expires: Instant::now() + ttl });
+}
+
+/// Queries the route API for a callsign.
+///
+/// # Returns
+///
+/// A `Result` containing the route, or `None` if the API does not know it, or an error if
+/// the API could not be queried.
+async fn query(inner: &Inner, callsign: &str) -> Result<Option<Route>, Box<dyn Error + Send + Sync>> {
+    match inner.api {
+        RouteApi::Adsbdb => {
+            let response = inner.client.get(format!("{}/{}", inner.url, callsign)).send().await?;
+            // adsbdb answers 404 for callsigns it has no route for.
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let body: Value = response.error_for_status()?.json().await?;
+            let route = &body["response"]["flightroute"];
+            let airport = |end: &str| route[end]["icao_code"].as_str().map(str::to_string);
+            Ok(route.is_object().then(|| Route { origin: airport("origin"), destination: airport("destination") }))
+        }
+        RouteApi::AdsbLol => {
+            // adsb.lol takes a position with each callsign, which is not known here.
+            let request = json!({"planes": [{"callsign": callsign, "lat": 0, "lng": 0}]});
+            let body: Value = inner.client.post(&inner.url).json(&request).send().await?.error_for_status()?.json().await?;
+            // Multi-leg routes list every airport, e.g. `EDDF-KORD-KLAX`.
+            let airports: Vec<&str> = body[0]["airport_codes"].as_str().unwrap_or_default().split('-').filter(|code| !code.is_empty() && *code != "unknown").collect();
+            Ok((airports.len() >= 2).then(|| Route { origin: airports.first().map(|code| code.to_string()), destination: airports.last().map(|code| code.to_string()) }))
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! This module forwards a random sample of messages, at rates that can differ by
+//! transmission type, for users who only need statistical coverage rather than every frame.
+//! Emergencies are always forwarded.
+
+use crate::parse::SBS1Message;
+use std::collections::HashMap;
+
+/// Decides which messages are forwarded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampler {
+    /// The share of messages forwarded, from 0 to 1, unless their type has its own.
+    default: f64,
+    /// The share forwarded of each transmission type.
+    rates: HashMap<i32, f64>,
+}
+
+impl Sampler {
+    /// Creates a sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The share of messages forwarded, as a number from 0 to 1 (e.g. `0.1`)
+    ///   or as `1/N`.
+    /// * `rates` - Comma-separated `type=rate` pairs, e.g. `8=1/10,3=1`, giving the share
+    ///   forwarded of each transmission type.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sampler, or an error if a rate is invalid.
+    pub fn parse(default: &str, rates: &str) -> Result<Self, String> {
+        let rates = rates
+            .split(',')
+            .filter(|pair| !pair.trim().is_empty())
+            .map(|pair| {
+                let (kind, rate) = pair.split_once('=').ok_or_else(|| format!("invalid sample rate {:?} (expected type=rate)", pair))?;
+                let kind = kind.trim().parse().map_err(|_| format!("invalid transmission type {:?}", kind.trim()))?;
+                Ok((kind, parse_rate(rate)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Sampler { default: parse_rate(default)?, rates })
+    }
+
+    /// Returns whether every message is forwarded, so that sampling can be skipped.
+    pub fn is_all(&self) -> bool {
+        self.default >= 1.0 && self.rates.values().all(|&rate| rate >= 1.0)
+    }
+
+    /// Returns whether to forward a message.
+    pub fn keep(&self, message: &SBS1Message) -> bool {
+        if message.is_emergency() {
+            return true;
+        }
+        let rate = message.transmission_type.and_then(|kind| self.rates.get(&kind)).copied().unwrap_or(self.default);
+        rate >= 1.0 || rand::random::<f64>() < rate
+    }
+}
+
+/// Parses a rate given as a number from 0 to 1 or as `1/N`.
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    let rate = rate.trim();
+    let value = match rate.split_once('/') {
+        Some((numerator, denominator)) => numerator.trim().parse::<f64>().ok().zip(denominator.trim().parse::<f64>().ok()).map(|(numerator, denominator)| numerator / denominator),
+        None => rate.parse().ok(),
+    };
+    value.filter(|value| (0.0..=1.0).contains(value)).ok_or_else(|| format!("invalid sample rate {:?} (expected a number from 0 to 1, or 1/N)", rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(transmission_type: i32) -> SBS1Message {
+        let mut message = SBS1Message::new();
+        message.transmission_type = Some(transmission_type);
+        message
+    }
+
+    fn kept(sampler: &Sampler, message: &SBS1Message) -> usize {
+        (0..10_000).filter(|_| sampler.keep(message)).count()
+    }
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("0.25"), Ok(0.25));
+        assert_eq!(parse_rate(" 1/4 "), Ok(0.25));
+        assert_eq!(parse_rate("1 / 10"), Ok(0.1));
+        assert_eq!(parse_rate("0"), Ok(0.0));
+        assert_eq!(parse_rate("1"), Ok(1.0));
+        assert_eq!(parse_rate("1/1"), Ok(1.0));
+        for rate in ["1.5", "-0.1", "2/1", "1/0", "-1/4", "1/", "/4", "1/x", "half", "", "NaN"] {
+            assert!(parse_rate(rate).is_err(), "{:?} should be rejected", rate);
+        }
+    }
+
+    #[test]
+    fn parses_rates_by_transmission_type() {
+        let sampler = Sampler::parse("1/10", "8=0, 3=1").unwrap();
+        assert_eq!(sampler.default, 0.1);
+        assert_eq!(sampler.rates, HashMap::from([(8, 0.0), (3, 1.0)]));
+        assert!(!sampler.is_all());
+        assert!(Sampler::parse("1", "3=1").unwrap().is_all());
+        assert!(Sampler::parse("1", "8").is_err());
+        assert!(Sampler::parse("1", "x=1").is_err());
+        assert!(Sampler::parse("1", "8=2").is_err());
+    }
+
+    #[test]
+    fn forwards_the_share_of_each_type() {
+        let sampler = Sampler::parse("1/2", "8=0,3=1").unwrap();
+        assert_eq!(kept(&sampler, &message(8)), 0);
+        assert_eq!(kept(&sampler, &message(3)), 10_000);
+        assert!((4_000..6_000).contains(&kept(&sampler, &message(4))));
+        assert!((4_000..6_000).contains(&kept(&sampler, &SBS1Message::new())));
+    }
+
+    #[test]
+    fn always_forwards_emergencies() {
+        let sampler = Sampler::parse("0", "").unwrap();
+        assert_eq!(kept(&sampler, &message(4)), 0);
+        let mut flagged = message(4);
+        flagged.emergency = Some(true);
+        assert_eq!(kept(&sampler, &flagged), 10_000);
+        for squawk in [7500, 7600, 7700] {
+            let mut squawking = message(6);
+            squawking.squawk = Some(squawk);
+            assert_eq!(kept(&sampler, &squawking), 10_000);
+        }
+        let mut squawking = message(6);
+        squawking.squawk = Some(7000);
+        assert_eq!(kept(&sampler, &squawking), 0);
+    }
+}
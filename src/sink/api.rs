@@ -0,0 +1,110 @@
+//! This module serves the current state of every aircraft in view over HTTP (requires the
+//! `api` feature), so other services can ask what is overhead right now.
+//!
+//! `GET /api/aircraft` returns all aircraft and `GET /api/aircraft/{icao24}` a single one.
+//! The state is kept by the tracker, which is updated from every parsed message, so it
+//! also takes in messages that are sampled out or dropped as repeats before batching.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::{Icao24, SBS1Message};
+use crate::tracker::{self, Aircraft, Tracker};
+use async_trait::async_trait;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Describes an aircraft as JSON, with ages in seconds relative to `now`.
+fn to_json(icao24: Icao24, aircraft: &Aircraft, now: i64) -> Value {
+    let age = |time: i64| (now - time).max(0) as f64 / 1e9;
+    json!({
+        "icao24": icao24,
+        "callsign": aircraft.callsign,
+        "lat": aircraft.lat,
+        "lon": aircraft.lon,
+        "altitude": aircraft.altitude,
+        "ground_speed": aircraft.ground_speed,
+        "track": aircraft.track,
+        "vertical_rate": aircraft.vertical_rate,
+        "squawk": aircraft.squawk,
+        "on_ground": aircraft.on_ground,
+        "messages": aircraft.messages,
+        "seen": age(aircraft.last_seen),
+        "seen_pos": aircraft.last_position.map(age),
+    })
+}
+
+/// Serves the latest state of each aircraft, as kept by the tracker, over HTTP.
+pub struct ApiSink;
+
+impl ApiSink {
+    /// Starts the HTTP server and creates an API sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on, e.g. `0.0.0.0:8080`.
+    /// * `tracker` - The tracker that keeps the state of each aircraft.
+    /// * `expire` - How long an aircraft is served after it was last heard.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the address could not be bound.
+    pub fn new(address: &str, tracker: Arc<Tracker>, expire: Duration) -> Result<Self, SinkError> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        println!("Serving the aircraft API on http://{}/api/aircraft", address);
+
+        let router = Router::new()
+            .route("/api/aircraft", get(list))
+            .route("/api/aircraft/{icao24}", get(single))
+            .with_state((tracker, expire));
+        tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, router).await {
+                eprintln!("Aircraft API server failed: {}", err);
+            }
+        });
+        Ok(ApiSink)
+    }
+
+    /// Creates an API sink from API_LISTEN and API_EXPIRE_SECONDS.
+    pub fn from_settings(settings: Settings, tracker: Arc<Tracker>) -> Result<Self, SinkError> {
+        Self::new(
+            &settings("API_LISTEN", Some("0.0.0.0:8080")),
+            tracker,
+            Duration::from_secs(settings("API_EXPIRE_SECONDS", Some("300")).parse()?),
+        )
+    }
+}
+
+/// Handles `GET /api/aircraft`.
+async fn list(State((tracker, expire)): State<(Arc<Tracker>, Duration)>) -> Json<Value> {
+    let now = tracker::now();
+    let list: Vec<Value> = tracker.seen_within(expire).iter().map(|(icao24, aircraft)| to_json(*icao24, aircraft, now)).collect();
+    Json(json!({"now": now as f64 / 1e9, "aircraft": list}))
+}
+
+/// Handles `GET /api/aircraft/{icao24}`.
+async fn single(State((tracker, expire)): State<(Arc<Tracker>, Duration)>, Path(icao24): Path<String>) -> Result<Json<Value>, StatusCode> {
+    let now = tracker::now();
+    let icao24: Icao24 = icao24.parse().map_err(|_| StatusCode::NOT_FOUND)?;
+    match tracker.get(icao24) {
+        Some(aircraft) if aircraft.last_seen >= now - expire.as_nanos() as i64 => Ok(Json(to_json(icao24, &aircraft, now))),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+#[async_trait]
+impl Sink for ApiSink {
+    fn name(&self) -> &'static str {
+        "api"
+    }
+
+    /// Does nothing, as the tracker is updated from every message as it is parsed.
+    async fn send(&self, _batch: &[SBS1Message]) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+//! This module lets each sink send in batches of its own size and at its own interval, and
+//! retry failed sends, whatever size the batches are taken at, e.g. DataSet in batches of
+//! 500 while MQTT publishes each message as it comes.
+//!
+//! A sink that holds messages for its own batches counts them as sent once it holds them,
+//! so with DELIVERY=at-least-once they are only safe from crashes once actually sent. A
+//! batch of its own that fails stays held and is retried with the next.
+
+use super::{key_values, Settings, Sink, SinkError};
+use crate::buffer::{Batch, Buffer};
+use crate::parse::{ParseError, SBS1Message};
+use crate::stats::UploadStats;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// The longest delay between retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How one sink regroups and retries the batches sent to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SinkBatching {
+    /// The number of messages and parse failures the sink sends at a time, or 0 to send
+    /// batches as they are taken.
+    pub batch_size: usize,
+    /// How long the sink holds messages before sending them in a smaller batch, if at all.
+    pub flush_interval: Option<Duration>,
+    /// How many times a batch is tried before its failure is reported, at least 1.
+    pub max_attempts: u32,
+    /// The delay before the first retry; it doubles with each retry.
+    pub retry_backoff: Duration,
+}
+
+impl Default for SinkBatching {
+    fn default() -> Self {
+        SinkBatching { batch_size: 0, flush_interval: None, max_attempts: 1, retry_backoff: Duration::from_millis(500) }
+    }
+}
+
+impl SinkBatching {
+    /// Reads the batching of each sink from SINK_BATCH_SIZES, SINK_FLUSH_INTERVALS (in
+    /// seconds), SINK_MAX_ATTEMPTS and SINK_RETRY_BACKOFF_MS, each a comma-separated
+    /// `sink=value` list, e.g. `dataset=500,mqtt=1`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the batching of each sink named in any of the lists, or an
+    /// error if a value is not a number.
+    pub fn from_settings(settings: Settings) -> Result<HashMap<String, Self>, SinkError> {
+        let mut batching: HashMap<String, Self> = HashMap::new();
+        for (name, size) in key_values(&settings("SINK_BATCH_SIZES", Some(""))) {
+            batching.entry(name.to_lowercase()).or_default().batch_size = size.parse()?;
+        }
+        for (name, secs) in key_values(&settings("SINK_FLUSH_INTERVALS", Some(""))) {
+            let interval = Duration::from_secs_f64(secs.parse()?);
+            batching.entry(name.to_lowercase()).or_default().flush_interval = Some(interval).filter(|interval| !interval.is_zero());
+        }
+        for (name, attempts) in key_values(&settings("SINK_MAX_ATTEMPTS", Some(""))) {
+            batching.entry(name.to_lowercase()).or_default().max_attempts = attempts.parse::<u32>()?.max(1);
+        }
+        for (name, millis) in key_values(&settings("SINK_RETRY_BACKOFF_MS", Some(""))) {
+            batching.entry(name.to_lowercase()).or_default().retry_backoff = Duration::from_millis(millis.parse()?);
+        }
+        Ok(batching)
+    }
+
+    /// Returns whether batches are regrouped rather than sent as they are taken.
+    fn regroups(&self) -> bool {
+        self.batch_size > 0 || self.flush_interval.is_some()
+    }
+}
+
+/// What the sink holds until it sends its next batch.
+#[derive(Default)]
+struct Pending {
+    messages: Vec<SBS1Message>,
+    diagnostics: Vec<(ParseError, String)>,
+    /// When the oldest message or failure held arrived.
+    oldest: Option<Instant>,
+    /// The last batch taken in, so that a batch sent again after a failure, e.g. by a
+    /// circuit breaker, is not held twice.
+    last: Option<Uuid>,
+}
+
+impl Pending {
+    fn len(&self) -> usize {
+        self.messages.len() + self.diagnostics.len()
+    }
+
+    /// Takes up to `size` messages and failures, oldest first, or all of them for 0.
+    fn take(&mut self, size: usize) -> Batch {
+        let size = if size == 0 { self.len() } else { size };
+        let messages: Vec<SBS1Message> = self.messages.drain(..size.min(self.messages.len())).collect();
+        let diagnostics = self.diagnostics.drain(..(size - messages.len()).min(self.diagnostics.len())).collect();
+        if self.len() == 0 {
+            self.oldest = None;
+        }
+        Batch::new(messages, diagnostics)
+    }
+
+    /// Puts a batch that could not be sent back in front of what is held.
+    fn restore(&mut self, batch: Batch) {
+        self.messages.splice(0..0, batch.messages);
+        self.diagnostics.splice(0..0, batch.diagnostics);
+        self.oldest.get_or_insert_with(Instant::now);
+    }
+}
+
+/// The sink and what it holds, shared with the task that flushes it.
+struct Inner {
+    sink: Box<dyn Sink>,
+    batching: SinkBatching,
+    /// Also held while sending, so that batches go out in order.
+    pending: Mutex<Pending>,
+}
+
+impl Inner {
+    /// Sends a batch, retrying it with exponential backoff.
+    async fn send(&self, batch: &Batch) -> Result<(), SinkError> {
+        let mut delay = self.batching.retry_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.sink.send_batch(batch).await {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt < self.batching.max_attempts => {
+                    eprintln!("Sink {} failed (attempt {} of {}): {}; retrying in {:?}", self.sink.name(), attempt, self.batching.max_attempts, err, delay);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Sends what is held in batches of the sink's size, and the rest too if `all`. A
+    /// batch that fails stays held.
+    async fn flush(&self, pending: &mut Pending, all: bool) -> Result<(), SinkError> {
+        let size = self.batching.batch_size;
+        while (size > 0 && pending.len() >= size) || (all && pending.len() > 0) {
+            let batch = pending.take(size);
+            if let Err(err) = self.send(&batch).await {
+                pending.restore(batch);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a sink to send in batches of its own size and at its own interval, and to retry
+/// what fails.
+pub struct BatchedSink {
+    inner: Arc<Inner>,
+}
+
+impl BatchedSink {
+    /// Wraps a sink. If it has a flush interval, a task sends what it holds once the oldest
+    /// has waited that long.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to wrap.
+    /// * `batching` - How the sink regroups and retries batches.
+    pub fn new(sink: Box<dyn Sink>, batching: SinkBatching) -> Self {
+        let flush_interval = batching.flush_interval;
+        let inner = Arc::new(Inner { sink, batching, pending: Mutex::new(Pending::default()) });
+        if let Some(interval) = flush_interval {
+            tokio::spawn(flush_when_due(Arc::downgrade(&inner), interval));
+        }
+        BatchedSink { inner }
+    }
+}
+
+/// Sends what a sink holds once the oldest has waited for the flush interval, until the sink
+/// is dropped. Failures are logged, as no caller is waiting, and what failed is tried again
+/// at the next flush.
+async fn flush_when_due(inner: Weak<Inner>, interval: Duration) {
+    let period = (interval / 10).max(Duration::from_millis(100));
+    loop {
+        tokio::time::sleep(period).await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        let mut pending = inner.pending.lock().await;
+        if pending.oldest.is_some_and(|oldest| oldest.elapsed() >= interval) {
+            if let Err(err) = inner.flush(&mut pending, true).await {
+                eprintln!("Error sending the {} messages held for sink {}: {}; retrying at the next flush", pending.len(), inner.sink.name(), err);
+                pending.oldest = Some(Instant::now());
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for BatchedSink {
+    fn name(&self) -> &'static str {
+        self.inner.sink.name()
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        self.send_batch(&Batch::new(batch.to_vec(), Vec::new())).await
+    }
+
+    async fn send_diagnostics(&self, diagnostics: &[(ParseError, String)]) -> Result<(), SinkError> {
+        self.send_batch(&Batch::new(Vec::new(), diagnostics.to_vec())).await
+    }
+
+    async fn send_batch(&self, batch: &Batch) -> Result<(), SinkError> {
+        if !self.inner.batching.regroups() {
+            return self.inner.send(batch).await;
+        }
+        let mut pending = self.inner.pending.lock().await;
+        if pending.last != Some(batch.id) {
+            pending.last = Some(batch.id);
+            pending.messages.extend(batch.messages.iter().cloned());
+            pending.diagnostics.extend(batch.diagnostics.iter().cloned());
+            if pending.len() > 0 {
+                pending.oldest.get_or_insert_with(Instant::now);
+            }
+        }
+        self.inner.flush(&mut pending, false).await
+    }
+
+    fn upload_stats(&self) -> Option<&UploadStats> {
+        self.inner.sink.upload_stats()
+    }
+
+    fn queue(&self) -> Option<&Buffer> {
+        self.inner.sink.queue()
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        let mut pending = self.inner.pending.lock().await;
+        self.inner.flush(&mut pending, true).await?;
+        self.inner.sink.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as SyncMutex;
+
+    /// A sink that records the altitudes of the batches it is sent, failing while it has
+    /// failures left.
+    #[derive(Default)]
+    struct Recorder {
+        sent: Arc<SyncMutex<Vec<Vec<i32>>>>,
+        failures: SyncMutex<u32>,
+    }
+
+    #[async_trait]
+    impl Sink for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        async fn send(&self, _batch: &[SBS1Message]) -> Result<(), SinkError> {
+            unreachable!("batches are sent whole")
+        }
+
+        async fn send_batch(&self, batch: &Batch) -> Result<(), SinkError> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err("unavailable".into());
+            }
+            self.sent.lock().unwrap().push(altitudes(&batch.messages));
+            Ok(())
+        }
+    }
+
+    fn messages(altitudes: impl IntoIterator<Item = i32>) -> Vec<SBS1Message> {
+        altitudes
+            .into_iter()
+            .map(|altitude| {
+                let mut message = SBS1Message::new();
+                message.altitude_baro = Some(altitude);
+                message
+            })
+            .collect()
+    }
+
+    fn altitudes(messages: &[SBS1Message]) -> Vec<i32> {
+        messages.iter().filter_map(|message| message.altitude_baro).collect()
+    }
+
+    fn diagnostic(line: &str) -> (ParseError, String) {
+        (ParseError::FieldCount { expected: 22, found: 1 }, line.to_string())
+    }
+
+    /// Wraps a recorder that fails its first `failures` sends.
+    fn batched(batching: SinkBatching, failures: u32) -> (BatchedSink, Arc<SyncMutex<Vec<Vec<i32>>>>) {
+        let recorder = Recorder { failures: SyncMutex::new(failures), ..Recorder::default() };
+        let sent = recorder.sent.clone();
+        (BatchedSink::new(Box::new(recorder), batching), sent)
+    }
+
+    #[test]
+    fn takes_messages_then_failures_oldest_first() {
+        let mut pending = Pending { messages: messages(1..=3), diagnostics: vec![diagnostic("a"), diagnostic("b")], oldest: Some(Instant::now()), last: None };
+        let batch = pending.take(2);
+        assert_eq!((altitudes(&batch.messages), batch.diagnostics.len()), (vec![1, 2], 0));
+        let batch = pending.take(2);
+        assert_eq!(altitudes(&batch.messages), [3]);
+        assert_eq!(batch.diagnostics[0].1, "a");
+        assert!(pending.oldest.is_some());
+        let batch = pending.take(0);
+        assert_eq!((batch.messages.len(), batch.diagnostics[0].1.as_str()), (0, "b"));
+        assert_eq!((pending.len(), pending.oldest), (0, None));
+    }
+
+    #[test]
+    fn restores_a_failed_batch_in_front() {
+        let mut pending = Pending { messages: messages(1..=4), diagnostics: vec![diagnostic("a")], ..Pending::default() };
+        let batch = pending.take(2);
+        pending.messages.extend(messages([5]));
+        pending.diagnostics.push(diagnostic("b"));
+        pending.restore(batch);
+        assert_eq!(altitudes(&pending.messages), [1, 2, 3, 4, 5]);
+        assert_eq!(pending.diagnostics.iter().map(|(_, line)| line.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(pending.oldest.is_some());
+    }
+
+    #[tokio::test]
+    async fn resends_a_failed_batch_before_what_came_after() {
+        let (sink, sent) = batched(SinkBatching { batch_size: 2, ..SinkBatching::default() }, 1);
+        assert!(sink.send_batch(&Batch::new(messages(1..=3), Vec::new())).await.is_err());
+        assert!(sent.lock().unwrap().is_empty());
+        sink.send_batch(&Batch::new(messages([4, 5]), Vec::new())).await.unwrap();
+        sink.close().await.unwrap();
+        assert_eq!(*sent.lock().unwrap(), [vec![1, 2], vec![3, 4], vec![5]]);
+    }
+
+    #[tokio::test]
+    async fn holds_a_batch_sent_again_only_once() {
+        let (sink, sent) = batched(SinkBatching { batch_size: 2, ..SinkBatching::default() }, 1);
+        let batch = Batch::new(messages(1..=3), Vec::new());
+        assert!(sink.send_batch(&batch).await.is_err());
+        // Sent again after the failure, e.g. by a circuit breaker.
+        sink.send_batch(&batch).await.unwrap();
+        sink.send_batch(&Batch::new(messages([4]), Vec::new())).await.unwrap();
+        sink.close().await.unwrap();
+        assert_eq!(*sent.lock().unwrap(), [vec![1, 2], vec![3, 4]]);
+    }
+
+    #[tokio::test]
+    async fn retries_up_to_the_attempts_allowed() {
+        let batching = SinkBatching { max_attempts: 3, retry_backoff: Duration::from_millis(1), ..SinkBatching::default() };
+        let (sink, sent) = batched(batching.clone(), 2);
+        sink.send_batch(&Batch::new(messages([1]), Vec::new())).await.unwrap();
+        assert_eq!(*sent.lock().unwrap(), [vec![1]]);
+
+        let (sink, sent) = batched(batching, 3);
+        assert!(sink.send_batch(&Batch::new(messages([1]), Vec::new())).await.is_err());
+        assert!(sent.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! This module gives a sink a queue and task of its own, so that a slow or failing sink
+//! (e.g. a dead webhook) only holds up and drops its own batches, while the other sinks keep
+//! up with the feed.
+//!
+//! A batch counts as sent once it is queued for each sink, so DELIVERY=at-least-once does
+//! not see what a sink then fails to send. Without a circuit breaker, that is logged and
+//! dropped. With one, the batch is held and retried through the breaker, while the batches
+//! behind it wait in the sink's queue and the other sinks carry on.
+
+use super::{Sink, SinkError};
+use crate::breaker::CircuitBreaker;
+use crate::buffer::{Batch, Buffer};
+use crate::parse::{ParseError, SBS1Message};
+use crate::stats::UploadStats;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+/// Wraps a sink to send from a queue of its own.
+pub struct Bulkhead {
+    name: &'static str,
+    sink: Arc<dyn Sink>,
+    buffer: Arc<Buffer>,
+    /// The task that sends from the queue, until it is closed.
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Bulkhead {
+    /// Wraps a sink and starts the task that sends from its queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to wrap.
+    /// * `buffer` - The sink's queue.
+    pub fn new(sink: Box<dyn Sink>, buffer: Buffer) -> Self {
+        Self::start(sink, buffer, None)
+    }
+
+    /// Wraps a sink behind a circuit breaker and starts the task that sends from its queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to wrap.
+    /// * `buffer` - The sink's queue.
+    /// * `breaker` - The sink's circuit breaker.
+    /// * `events` - Where the breaker's state changes are queued as batches, for every sink,
+    ///   e.g. the main buffer.
+    pub fn with_breaker(sink: Box<dyn Sink>, buffer: Buffer, breaker: CircuitBreaker, events: Arc<Buffer>) -> Self {
+        Self::start(sink, buffer, Some((breaker, events)))
+    }
+
+    fn start(sink: Box<dyn Sink>, buffer: Buffer, breaker: Option<(CircuitBreaker, Arc<Buffer>)>) -> Self {
+        let name = sink.name();
+        let sink: Arc<dyn Sink> = Arc::from(sink);
+        let buffer = Arc::new(buffer);
+        let task = tokio::spawn(send_from_queue(sink.clone(), buffer.clone(), breaker));
+        Bulkhead { name, sink, buffer, task: Mutex::new(Some(task)) }
+    }
+}
+
+/// Sends the batches queued for a sink until its queue is closed and empty.
+async fn send_from_queue(sink: Arc<dyn Sink>, buffer: Arc<Buffer>, mut breaker: Option<(CircuitBreaker, Arc<Buffer>)>) {
+    while let Some(batch) = buffer.pop().await {
+        match &mut breaker {
+            Some((breaker, events)) => send_through_breaker(&*sink, breaker, events, &batch).await,
+            None => {
+                if let Err(err) = sink.send_batch(&batch).await {
+                    eprintln!("Dropped a batch of {} messages for sink {}: {}", batch.len(), sink.name(), err);
+                }
+            }
+        }
+        buffer.done();
+    }
+}
+
+/// Sends a batch to a sink, retrying it once the breaker lets a retry through after a
+/// backoff or its cooldown, until it has been sent.
+async fn send_through_breaker(sink: &dyn Sink, breaker: &mut CircuitBreaker, events: &Arc<Buffer>, batch: &Batch) {
+    loop {
+        let (until, event) = breaker.ready();
+        announce(events, event);
+        if let Some(until) = until {
+            tokio::time::sleep_until(until.into()).await;
+            continue;
+        }
+        match sink.send_batch(batch).await {
+            Ok(()) => {
+                announce(events, breaker.succeeded());
+                return;
+            }
+            Err(err) => {
+                eprintln!("Error sending to sink {}: {}", sink.name(), err);
+                announce(events, breaker.failed().1);
+            }
+        }
+    }
+}
+
+/// Queues a circuit breaker state change for every sink. A sink whose breaker is open gets
+/// it once it is working again. The sink's task does not wait for room, which could take
+/// until the sink itself has caught up.
+fn announce(events: &Arc<Buffer>, event: Option<SBS1Message>) {
+    if let Some(event) = event {
+        let events = events.clone();
+        tokio::spawn(async move { events.push(Batch::new(vec![event], Vec::new())).await });
+    }
+}
+
+#[async_trait]
+impl Sink for Bulkhead {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        self.send_batch(&Batch::new(batch.to_vec(), Vec::new())).await
+    }
+
+    async fn send_diagnostics(&self, diagnostics: &[(ParseError, String)]) -> Result<(), SinkError> {
+        self.send_batch(&Batch::new(Vec::new(), diagnostics.to_vec())).await
+    }
+
+    async fn send_batch(&self, batch: &Batch) -> Result<(), SinkError> {
+        if !self.buffer.push(batch.clone()).await {
+            return Err(format!("the queue of sink {} is closed", self.name).into());
+        }
+        Ok(())
+    }
+
+    fn upload_stats(&self) -> Option<&UploadStats> {
+        self.sink.upload_stats()
+    }
+
+    fn queue(&self) -> Option<&Buffer> {
+        Some(&self.buffer)
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        self.buffer.close();
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            task.await?;
+        }
+        self.sink.close().await
+    }
+}
@@ -0,0 +1,143 @@
+//! This module bulk-inserts messages into a ClickHouse table over the HTTP interface.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The table the sink writes to, created if it does not exist. `{table}` is replaced by the
+/// qualified table name.
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS {table} (
+    time DateTime64(9, 'UTC'),
+    collector LowCardinality(String),
+    icao24 String,
+    transmission_type Nullable(UInt8),
+    callsign Nullable(String),
+    altitude_baro Nullable(Int32),
+    ground_speed Nullable(Float32),
+    track Nullable(Float32),
+    lat Nullable(Float64),
+    lon Nullable(Float64),
+    vertical_rate Nullable(Int32),
+    squawk Nullable(UInt16),
+    on_ground Nullable(Bool),
+    message String
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(time)
+ORDER BY (icao24, time)
+";
+
+/// Inserts each batch with one `INSERT ... FORMAT JSONEachRow` request.
+pub struct ClickHouseSink {
+    url: String,
+    /// The qualified, quoted table name, e.g. `` `default`.`adsb_messages` ``.
+    table: String,
+    user: String,
+    password: String,
+    collector: String,
+    /// Whether the table still has to be created before the first insert.
+    create_table: AtomicBool,
+}
+
+impl ClickHouseSink {
+    /// Creates a ClickHouse sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The HTTP interface URL, e.g. `http://localhost:8123`.
+    /// * `database` - The database holding the table.
+    /// * `table` - The table to insert into.
+    /// * `user` - The user to authenticate as.
+    /// * `password` - The user's password.
+    /// * `collector` - The collector name, stored with every row.
+    /// * `create_table` - Whether to create the table with `SCHEMA` if it does not exist.
+    pub fn new(url: &str, database: &str, table: &str, user: &str, password: &str, collector: &str, create_table: bool) -> Self {
+        ClickHouseSink {
+            url: url.trim_end_matches('/').to_string(),
+            table: format!("`{}`.`{}`", database.replace('`', ""), table.replace('`', "")),
+            user: user.to_string(),
+            password: password.to_string(),
+            collector: collector.to_string(),
+            create_table: AtomicBool::new(create_table),
+        }
+    }
+
+    /// Creates a ClickHouse sink from CLICKHOUSE_URL, CLICKHOUSE_DATABASE, CLICKHOUSE_TABLE,
+    /// CLICKHOUSE_USER, CLICKHOUSE_PASSWORD, CLICKHOUSE_CREATE_TABLE and 1090_COLLECTOR.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Ok(Self::new(
+            &settings("CLICKHOUSE_URL", Some("http://localhost:8123")),
+            &settings("CLICKHOUSE_DATABASE", Some("default")),
+            &settings("CLICKHOUSE_TABLE", Some("adsb_messages")),
+            &settings("CLICKHOUSE_USER", Some("default")),
+            &settings("CLICKHOUSE_PASSWORD", Some("")),
+            &settings("1090_COLLECTOR", Some("dump1090")),
+            settings("CLICKHOUSE_CREATE_TABLE", Some("true")).parse()?,
+        ))
+    }
+
+    /// Runs a query with the given body appended to it.
+    async fn query(&self, client: &reqwest::Client, query: &str, body: Vec<u8>) -> Result<(), SinkError> {
+        let response = client
+            .post(&self.url)
+            .query(&[("query", query)])
+            .header("X-ClickHouse-User", &self.user)
+            .header("X-ClickHouse-Key", &self.password)
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            // ClickHouse explains what went wrong in the body.
+            return Err(format!("ClickHouse returned {}: {}", response.status(), response.text().await?.trim()).into());
+        }
+        Ok(())
+    }
+
+    /// Converts a message to a JSONEachRow row.
+    fn row(&self, message: &SBS1Message) -> Result<String, SinkError> {
+        let time = DateTime::from_timestamp_nanos(message.timestamp.parse().unwrap_or_default());
+        Ok(json!({
+            "time": time.format("%Y-%m-%d %H:%M:%S%.9f").to_string(),
+            "collector": self.collector,
+            "icao24": message.icao24.map(|icao24| icao24.to_string()).unwrap_or_default(),
+            "transmission_type": message.transmission_type,
+            "callsign": message.callsign,
+            "altitude_baro": message.altitude_baro,
+            "ground_speed": message.ground_speed,
+            "track": message.track,
+            "lat": message.lat,
+            "lon": message.lon,
+            "vertical_rate": message.vertical_rate,
+            "squawk": message.squawk,
+            "on_ground": message.on_ground,
+            "message": serde_json::to_string(message)?,
+        })
+        .to_string())
+    }
+}
+
+#[async_trait]
+impl Sink for ClickHouseSink {
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let client = reqwest::Client::new();
+        if self.create_table.load(Ordering::Relaxed) {
+            self.query(&client, &SCHEMA.replace("{table}", &self.table), Vec::new()).await?;
+            self.create_table.store(false, Ordering::Relaxed);
+        }
+
+        let mut rows = String::new();
+        for message in batch {
+            rows.push_str(&self.row(message)?);
+            rows.push('\n');
+        }
+        self.query(&client, &format!("INSERT INTO {} FORMAT JSONEachRow", self.table), rows.into_bytes()).await
+    }
+}
@@ -0,0 +1,94 @@
+//! This module writes messages to a local CSV file, for spreadsheets and pandas.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::sync::Mutex;
+
+/// The default columns, in the classic BaseStation order.
+pub const BASESTATION_COLUMNS: [&str; 22] = [
+    "message_type", "transmission_type", "session_id", "aircraft_id", "icao24", "flight_id",
+    "generated_date", "generated_time", "logged_date", "logged_time", "callsign", "altitude_baro",
+    "ground_speed", "track", "lat", "lon", "vertical_rate", "squawk", "alert", "emergency", "spi",
+    "on_ground",
+];
+
+/// Appends one row per message with a fixed set of columns, writing a header row when the
+/// file is new.
+pub struct CsvSink {
+    columns: Vec<String>,
+    writer: Mutex<csv::Writer<File>>,
+}
+
+impl CsvSink {
+    /// Opens (or creates) the file and creates a CSV sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to append to.
+    /// * `columns` - The columns to write. Any message field can be a column; the date
+    ///   fields can also be split into `*_date` and `*_time` columns as in BaseStation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the file could not be opened.
+    pub fn new(path: &str, columns: Vec<String>) -> Result<Self, SinkError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = csv::Writer::from_writer(file);
+        if is_new {
+            writer.write_record(&columns)?;
+            writer.flush()?;
+        }
+        Ok(CsvSink { columns, writer: Mutex::new(writer) })
+    }
+
+    /// Creates a CSV sink from CSV_PATH and CSV_COLUMNS (comma-separated, defaulting to
+    /// `BASESTATION_COLUMNS`).
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let columns = settings("CSV_COLUMNS", Some(&BASESTATION_COLUMNS.join(",")))
+            .split(',')
+            .map(|column| column.trim().to_string())
+            .filter(|column| !column.is_empty())
+            .collect();
+        Self::new(&settings("CSV_PATH", Some("adsb.csv")), columns)
+    }
+}
+
+/// Formats a column of a message serialized to JSON.
+fn cell(fields: &Value, column: &str) -> String {
+    // BaseStation splits each date-time into separate date and time columns.
+    let (field, part) = match column.strip_suffix("_time") {
+        Some(prefix) => (format!("{}_date", prefix), Some(1)),
+        None if column.ends_with("_date") => (column.to_string(), Some(0)),
+        None => (column.to_string(), None),
+    };
+    match (&fields[&field], part) {
+        (Value::Null, _) => String::new(),
+        (Value::String(date_time), Some(part)) => {
+            let date_time = date_time.replacen('T', " ", 1).replacen('-', "/", 2);
+            date_time.split(' ').nth(part).unwrap_or_default().to_string()
+        }
+        (Value::String(value), None) => value.clone(),
+        (value, _) => value.to_string(),
+    }
+}
+
+#[async_trait]
+impl Sink for CsvSink {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut writer = self.writer.lock().unwrap();
+        for message in batch {
+            let fields = serde_json::to_value(message)?;
+            writer.write_record(self.columns.iter().map(|column| cell(&fields, column)))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1139 @@
+//! This module sends messages to SentinelOne's DataSet (formerly Scalyr) through its
+//! `addEvents` API.
+
+use super::{key_values, Delivery, Settings, Sink, SinkError};
+use crate::buffer::Batch;
+use crate::parse::{ParseError, SBS1Message};
+use crate::stats::UploadStats;
+use async_trait::async_trait;
+use chrono::Local;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+use rand::Rng;
+use serde_derive::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+/// The most bytes of serialized events sent in one request. DataSet rejects request bodies
+/// over 6 MB (before compression); this leaves room for the rest of the payload.
+const MAX_EVENTS_BYTES: usize = 5_500_000;
+
+/// How request bodies are compressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Uncompressed JSON.
+    Identity,
+    /// Gzip, the default; ADS-B JSON is repetitive and compresses well.
+    Gzip,
+    /// Raw deflate.
+    Deflate,
+}
+
+impl Encoding {
+    /// Parses an encoding name: `gzip`, `deflate` or `none`.
+    pub fn parse(name: &str) -> Result<Self, SinkError> {
+        match name.to_lowercase().as_str() {
+            "gzip" => Ok(Encoding::Gzip),
+            "deflate" => Ok(Encoding::Deflate),
+            "none" | "identity" => Ok(Encoding::Identity),
+            other => Err(format!("invalid DataSet compression {:?} (expected gzip, deflate or none)", other).into()),
+        }
+    }
+
+    /// Compresses a request body.
+    fn encode(self, body: Vec<u8>) -> Result<Vec<u8>, SinkError> {
+        Ok(match self {
+            Encoding::Identity => body,
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&body)?;
+                encoder.finish()?
+            }
+            Encoding::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&body)?;
+                encoder.finish()?
+            }
+        })
+    }
+
+    /// Returns the `Content-Encoding` header value, if any.
+    fn header(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Deflate => Some("deflate"),
+        }
+    }
+}
+
+/// How failed requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times a request is attempted before its batch is given up on; at least 1.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled for each further one.
+    pub backoff: Duration,
+    /// The longest delay between attempts.
+    pub max_backoff: Duration,
+    /// Whether delays are randomized, so that many collectors recovering from the same
+    /// outage do not retry in lockstep.
+    pub jitter: bool,
+    /// Whether a batch given up on is dropped, or fails sending so that it is not lost.
+    pub delivery: Delivery,
+}
+
+impl RetryPolicy {
+    /// Creates a retry policy from DATASET_MAX_ATTEMPTS, DATASET_RETRY_BACKOFF_MS,
+    /// DATASET_RETRY_MAX_BACKOFF_MS, DATASET_RETRY_JITTER and DELIVERY.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Ok(RetryPolicy {
+            max_attempts: settings("DATASET_MAX_ATTEMPTS", Some("5")).parse::<u32>()?.max(1),
+            backoff: Duration::from_millis(settings("DATASET_RETRY_BACKOFF_MS", Some("500")).parse()?),
+            max_backoff: Duration::from_millis(settings("DATASET_RETRY_MAX_BACKOFF_MS", Some("30000")).parse()?),
+            jitter: settings("DATASET_RETRY_JITTER", Some("true")).parse()?,
+            delivery: settings("DELIVERY", Some("at-most-once")).parse()?,
+        })
+    }
+
+    /// Returns the delay after a failed attempt, counted from 1.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.backoff.saturating_mul(1 << (attempt - 1).min(16)).min(self.max_backoff);
+        if !self.jitter {
+            return delay;
+        }
+        // Wait between half and all of the delay.
+        let half = delay.as_millis() as u64 / 2;
+        Duration::from_millis(half + rand::thread_rng().gen_range(0..=half))
+    }
+}
+
+/// What a message or event must be for a severity rule to apply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// An emergency squawk (7500, 7600 or 7700) or the emergency flag.
+    Emergency,
+    /// A particular squawk code, written `squawk:7700`.
+    Squawk(i32),
+    /// The squawk-change alert flag.
+    Alert,
+    /// The ident (SPI) flag.
+    Spi,
+    /// A multilaterated position.
+    Mlat,
+    /// An aircraft on the ground.
+    OnGround,
+    /// An all-call reply, sent as a presence event.
+    Seen,
+    /// A meteorological report.
+    Met,
+    /// A parse failure.
+    Diagnostic,
+}
+
+impl Condition {
+    /// Parses a condition name, e.g. `emergency` or `squawk:7700`.
+    fn parse(name: &str) -> Result<Self, SinkError> {
+        Ok(match name.to_lowercase().as_str() {
+            "emergency" => Condition::Emergency,
+            "alert" => Condition::Alert,
+            "spi" => Condition::Spi,
+            "mlat" => Condition::Mlat,
+            "on_ground" => Condition::OnGround,
+            "seen" => Condition::Seen,
+            "met" => Condition::Met,
+            "diagnostic" => Condition::Diagnostic,
+            other => match other.strip_prefix("squawk:").map(str::parse) {
+                Some(Ok(squawk)) => Condition::Squawk(squawk),
+                _ => return Err(format!("invalid severity condition {:?}", name).into()),
+            },
+        })
+    }
+
+    /// Returns whether a message matches the condition. Messages never match `Diagnostic`.
+    fn matches(self, message: &SBS1Message) -> bool {
+        match self {
+            Condition::Emergency => message.is_emergency(),
+            Condition::Squawk(squawk) => message.squawk == Some(squawk),
+            Condition::Alert => message.alert == Some(true),
+            Condition::Spi => message.spi == Some(true),
+            Condition::Mlat => message.mlat,
+            Condition::OnGround => message.on_ground == Some(true),
+            Condition::Seen => message.transmission_type == Some(8),
+            Condition::Met => message.ehs.as_ref().is_some_and(|ehs| ehs.bds == "4,4"),
+            Condition::Diagnostic => false,
+        }
+    }
+}
+
+/// Assigns each event a DataSet severity (0 to 6) by the first rule it matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeverityRules {
+    rules: Vec<(Condition, u8)>,
+    /// The severity of events that match no rule.
+    default: u8,
+}
+
+impl SeverityRules {
+    /// Parses severity rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - Comma-separated `condition=severity` pairs, tried in order, e.g.
+    ///   `emergency=5,mlat=2,diagnostic=1`.
+    /// * `default` - The severity of events that match no rule.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the rules, or an error naming an invalid condition or severity.
+    pub fn parse(rules: &str, default: u8) -> Result<Self, SinkError> {
+        let severity = |value: &str| match value.trim().parse::<u8>() {
+            Ok(severity) if severity <= 6 => Ok(severity),
+            _ => Err(format!("invalid DataSet severity {:?} (expected 0 to 6)", value)),
+        };
+        let rules = rules
+            .split(',')
+            .filter(|rule| !rule.trim().is_empty())
+            .map(|rule| {
+                let (condition, value) = rule.split_once('=').ok_or_else(|| format!("invalid severity rule {:?} (expected condition=severity)", rule))?;
+                Ok((Condition::parse(condition.trim())?, severity(value)?))
+            })
+            .collect::<Result<_, SinkError>>()?;
+        Ok(SeverityRules { rules, default: severity(&default.to_string())? })
+    }
+
+    /// Returns the severity of a message.
+    pub fn message(&self, message: &SBS1Message) -> u8 {
+        self.rules.iter().find(|(condition, _)| condition.matches(message)).map_or(self.default, |&(_, severity)| severity)
+    }
+
+    /// Returns the severity of parse failures.
+    pub fn diagnostic(&self) -> u8 {
+        self.rules.iter().find(|(condition, _)| *condition == Condition::Diagnostic).map_or(self.default, |&(_, severity)| severity)
+    }
+}
+
+/// How message fields are laid out in event attributes. By default the whole message is
+/// nested under `attrs.message`; flattened, each field that is set is placed directly in
+/// `attrs`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AttrsMapping {
+    /// Fields also placed at the top level of the attributes, e.g. `icao24`.
+    pub promote: Vec<String>,
+    /// New names of fields, wherever they are placed.
+    pub rename: HashMap<String, String>,
+    /// Fields left out of `attrs.message`, e.g. to save on ingestion. Promoted fields stay at
+    /// the top level, so promoting and dropping a field moves it.
+    pub drop: HashSet<String>,
+    /// The prefix of fields placed directly in `attrs` instead of under `message`, or `None`
+    /// to nest them.
+    pub flatten: Option<String>,
+}
+
+impl AttrsMapping {
+    /// Creates a mapping from DATASET_PROMOTE_FIELDS and DATASET_DROP_FIELDS (comma-separated
+    /// field names), DATASET_RENAME_FIELDS (a comma-separated `field=name` list),
+    /// FLATTEN_ATTRS and FLATTEN_ATTRS_PREFIX.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let names = |list: String| -> Vec<String> { list.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect() };
+        let flatten = settings("FLATTEN_ATTRS", Some("false")).parse::<bool>()?.then(|| settings("FLATTEN_ATTRS_PREFIX", Some("")));
+        Ok(AttrsMapping {
+            promote: names(settings("DATASET_PROMOTE_FIELDS", Some(""))),
+            rename: key_values(&settings("DATASET_RENAME_FIELDS", Some(""))).into_iter().collect(),
+            drop: names(settings("DATASET_DROP_FIELDS", Some(""))).into_iter().collect(),
+            flatten,
+        })
+    }
+
+    /// Returns the name a field is sent under.
+    fn name(&self, field: &str) -> String {
+        self.rename.get(field).cloned().unwrap_or_else(|| field.to_string())
+    }
+
+    /// Lays out fields as event attributes.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The fields, by their original names.
+    /// * `nest` - Whether the fields go under `message` (and only promoted ones at the top
+    ///   level) or all at the top level, as for presence events. Flattening overrides this.
+    fn attrs(&self, fields: Map<String, Value>, nest: bool) -> Value {
+        let mut attrs = Map::new();
+        if let Some(prefix) = &self.flatten {
+            for field in &self.promote {
+                if let Some(value) = fields.get(field).filter(|value| !value.is_null()) {
+                    attrs.insert(self.name(field), value.clone());
+                }
+            }
+            for (field, value) in fields {
+                if !value.is_null() && !self.drop.contains(&field) && !self.promote.contains(&field) {
+                    attrs.insert(format!("{}{}", prefix, self.name(&field)), value);
+                }
+            }
+            return Value::Object(attrs);
+        }
+        if nest {
+            for field in &self.promote {
+                if let Some(value) = fields.get(field) {
+                    attrs.insert(self.name(field), value.clone());
+                }
+            }
+        }
+        let kept: Map<String, Value> = fields.into_iter().filter(|(field, _)| !self.drop.contains(field)).map(|(field, value)| (self.name(&field), value)).collect();
+        if nest {
+            attrs.insert("message".to_string(), Value::Object(kept));
+        } else {
+            attrs.extend(kept);
+        }
+        Value::Object(attrs)
+    }
+}
+
+/// The `parser` and `logfile` attributes of one stream of events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogAttrs {
+    /// The parser DataSet applies to the events.
+    pub parser: String,
+    /// The `logfile` attribute of the events, or `None` to leave it unset.
+    pub logfile: Option<String>,
+}
+
+/// The streams events are sent in. Each is a DataSet log with its own attributes, so that
+/// DataSet's parser pipeline can treat the kinds of events differently.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogStreams {
+    /// Decoded messages.
+    pub adsb: LogAttrs,
+    /// Presence events from all-call replies.
+    pub seen: LogAttrs,
+    /// Meteorological reports.
+    pub met: LogAttrs,
+    /// Parse failures.
+    pub diagnostic: LogAttrs,
+}
+
+impl LogStreams {
+    /// Creates the streams from DATASET_LOGFILE and, for each stream, DATASET_<STREAM>_PARSER
+    /// and DATASET_<STREAM>_LOGFILE, where the stream is `ADSB`, `SEEN`, `MET` or
+    /// `DIAGNOSTIC`. Streams without a logfile of their own use DATASET_LOGFILE.
+    pub fn from_settings(settings: Settings) -> Self {
+        let logfile = settings("DATASET_LOGFILE", Some(""));
+        let stream = |name: &str, parser: &str| {
+            let name = name.to_uppercase();
+            let logfile = settings(&format!("DATASET_{}_LOGFILE", name), Some(&logfile));
+            LogAttrs {
+                parser: settings(&format!("DATASET_{}_PARSER", name), Some(parser)),
+                logfile: (!logfile.is_empty()).then_some(logfile),
+            }
+        };
+        LogStreams {
+            adsb: stream("adsb", "adsb"),
+            seen: stream("seen", "adsb-seen"),
+            met: stream("met", "modes-met"),
+            diagnostic: stream("diagnostic", "adsb-diagnostic"),
+        }
+    }
+
+    /// Returns the attributes of the log with the given id.
+    fn attrs(&self, id: &str) -> Value {
+        let log = match id {
+            "seen" => &self.seen,
+            "met" => &self.met,
+            "diagnostic" => &self.diagnostic,
+            _ => &self.adsb,
+        };
+        let mut attrs = json!({"parser": log.parser});
+        if let Some(logfile) = &log.logfile {
+            attrs["logfile"] = json!(logfile);
+        }
+        attrs
+    }
+}
+
+/// Where the API write token comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// The token itself.
+    Fixed(String),
+    /// A file holding the token, such as a mounted Kubernetes secret. It is re-read when it
+    /// changes and when DataSet rejects the token, so the token can be rotated without a
+    /// restart.
+    File(PathBuf),
+}
+
+/// Reads a token file, ignoring surrounding whitespace.
+fn read_token(path: &Path) -> Result<(String, Option<SystemTime>), SinkError> {
+    let unreadable = |err: std::io::Error| format!("cannot read the DataSet API token file {}: {}", path.display(), err);
+    let modified = std::fs::metadata(path).map_err(unreadable)?.modified().ok();
+    let token = std::fs::read_to_string(path).map_err(unreadable)?.trim().to_string();
+    if token.is_empty() {
+        return Err(format!("the DataSet API token file {} is empty", path.display()).into());
+    }
+    Ok((token, modified))
+}
+
+/// How the HTTP client reaches DataSet. One client is shared by all requests, so
+/// connections are kept alive and reused.
+#[derive(Debug)]
+pub struct HttpOptions {
+    /// The proxy to send requests through, or `None` to use the proxy given by the
+    /// `HTTPS_PROXY` environment variable, if any.
+    pub proxy: Option<reqwest::Proxy>,
+    /// Further certificate authorities to trust, e.g. that of a TLS-intercepting proxy.
+    pub ca_certs: Vec<reqwest::Certificate>,
+    /// Whether to trust only `ca_certs` rather than also the system's, which pins the server
+    /// to those authorities (or to its own self-signed certificate).
+    pub pin: bool,
+    /// How long connecting may take.
+    pub connect_timeout: Duration,
+    /// How long a whole request may take, so that a hung request cannot stall sending.
+    pub request_timeout: Duration,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            proxy: None,
+            ca_certs: Vec::new(),
+            pin: false,
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Creates HTTP options from DATASET_PROXY (e.g. `http://proxy.example.com:3128`),
+    /// DATASET_PROXY_USER, DATASET_PROXY_PASSWORD, DATASET_CA_CERT (a PEM file),
+    /// DATASET_TLS_PIN, DATASET_CONNECT_TIMEOUT_SECONDS and DATASET_REQUEST_TIMEOUT_SECONDS.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let mut options = HttpOptions::default();
+        let proxy = settings("DATASET_PROXY", Some(""));
+        if !proxy.is_empty() {
+            let mut proxy = reqwest::Proxy::all(&proxy).map_err(|err| format!("invalid DataSet proxy {:?}: {}", proxy, err))?;
+            let user = settings("DATASET_PROXY_USER", Some(""));
+            if !user.is_empty() {
+                proxy = proxy.basic_auth(&user, &settings("DATASET_PROXY_PASSWORD", Some("")));
+            }
+            options.proxy = Some(proxy);
+        }
+
+        let ca_cert = settings("DATASET_CA_CERT", Some(""));
+        if !ca_cert.is_empty() {
+            let pem = std::fs::read(&ca_cert).map_err(|err| format!("cannot read DataSet CA certificate {}: {}", ca_cert, err))?;
+            options.ca_certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|err| format!("invalid DataSet CA certificate {}: {}", ca_cert, err))?;
+        }
+        options.pin = settings("DATASET_TLS_PIN", Some("false")).parse()?;
+        options.connect_timeout = Duration::from_secs_f64(settings("DATASET_CONNECT_TIMEOUT_SECONDS", Some("10")).parse()?);
+        options.request_timeout = Duration::from_secs_f64(settings("DATASET_REQUEST_TIMEOUT_SECONDS", Some("60")).parse()?);
+        if options.pin && options.ca_certs.is_empty() {
+            return Err("DATASET_TLS_PIN requires the certificates to pin in DATASET_CA_CERT".into());
+        }
+        Ok(options)
+    }
+
+    /// Builds an HTTP client with these options.
+    fn client(self) -> Result<reqwest::Client, SinkError> {
+        let mut builder = reqwest::Client::builder()
+            .tls_built_in_root_certs(!self.pin)
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout);
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(proxy);
+        }
+        for cert in self.ca_certs {
+            builder = builder.add_root_certificate(cert);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Why a request failed.
+enum Failure {
+    /// A network error or server-side problem that may go away, so the request is retried,
+    /// after the delay the server asked for if it gave one.
+    Transient(SinkError, Option<Duration>),
+    /// DataSet rejected the API write token.
+    Unauthorized(SinkError),
+    /// A problem with the request itself, which retrying will not fix.
+    Permanent(SinkError),
+}
+
+/// The body of an `addEvents` response.
+#[derive(Debug, Default, Deserialize)]
+struct Response {
+    /// `success`, or an error such as `error/client/badParam` or `error/server/backoff`.
+    #[serde(default)]
+    status: String,
+    /// What went wrong, for errors.
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl Response {
+    /// Classifies a response, from its HTTP status and body, as a success or a failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_status` - The HTTP status of the response.
+    /// * `body` - The response body, which DataSet sends as JSON.
+    /// * `retry_after` - The delay from the `Retry-After` header, if any.
+    fn check(http_status: reqwest::StatusCode, body: &str, retry_after: Option<Duration>) -> Result<(), Failure> {
+        let response: Response = serde_json::from_str(body).unwrap_or_default();
+        if http_status.is_success() && response.status == "success" {
+            return Ok(());
+        }
+        let detail = match (&response.message, response.status.is_empty()) {
+            (Some(message), _) => format!("{} ({})", message, response.status),
+            (None, false) => response.status.clone(),
+            (None, true) => format!("unexpected response {:?}", body),
+        };
+
+        if http_status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || response.status.contains("backoff")
+            || response.status.contains("serverTooBusy")
+        {
+            Err(Failure::Transient(format!("DataSet is too busy: {}", detail).into(), retry_after))
+        } else if http_status == reqwest::StatusCode::UNAUTHORIZED
+            || http_status == reqwest::StatusCode::FORBIDDEN
+            || response.status.contains("noPermission")
+            || response.status.contains("authentication")
+        {
+            Err(Failure::Unauthorized(format!("DataSet rejected the API write token: {}", detail).into()))
+        } else if http_status == reqwest::StatusCode::PAYLOAD_TOO_LARGE || response.status.contains("tooLarge") {
+            Err(Failure::Permanent(format!("DataSet rejected the batch as too large; lower BATCH_SIZE: {}", detail).into()))
+        } else if http_status.is_server_error() || response.status.starts_with("error/server") {
+            Err(Failure::Transient(format!("DataSet server error, HTTP {}: {}", http_status, detail).into(), retry_after))
+        } else if http_status == reqwest::StatusCode::REQUEST_TIMEOUT {
+            Err(Failure::Transient(format!("DataSet timed out waiting for the request: {}", detail).into(), retry_after))
+        } else if http_status.is_success() && response.status.is_empty() {
+            // Not DataSet's answer, e.g. a page from an intercepting proxy, so the batch may
+            // yet get through.
+            Err(Failure::Transient(format!("DataSet could not be reached, HTTP {}: {}", http_status, detail).into(), retry_after))
+        } else {
+            Err(Failure::Permanent(format!("DataSet error, HTTP {}: {}", http_status, detail).into()))
+        }
+    }
+}
+
+/// A DataSet session. Requests within a session are sent one at a time, so that its event
+/// timestamps never decrease.
+struct Session {
+    id: Uuid,
+    /// The latest event timestamp sent in the session.
+    last_ts: i64,
+}
+
+/// Sends events to the DataSet web service, shared with the tasks that send requests.
+struct Client {
+    /// The HTTP client, set up with the proxy and certificates if there are any.
+    http: reqwest::Client,
+    /// The `addEvents` endpoint of the DataSet web service.
+    url: reqwest::Url,
+    /// Where the API write token for the DataSet web service comes from.
+    token: Token,
+    /// The token last read from the token file and when the file was modified.
+    file_token: Mutex<(String, Option<SystemTime>)>,
+    /// The collector (or source) identifier.
+    collector: String,
+    /// Further `sessionInfo` attributes, e.g. `serverHost`, to tell receiver sites apart.
+    session_info: Vec<(String, String)>,
+    /// The attributes of the logs events are sent in.
+    logs: LogStreams,
+    /// How request bodies are compressed.
+    encoding: Encoding,
+    /// How failed requests are retried.
+    retry: RetryPolicy,
+    /// What has been uploaded so far.
+    stats: UploadStats,
+    /// The sessions not in use by a request, one per request that may be in flight. They
+    /// last for the whole run so that the collector shows up as long-lived sessions.
+    sessions: Mutex<Vec<Session>>,
+}
+
+/// Sends messages to DataSet as events.
+pub struct DataSetSink {
+    client: Arc<Client>,
+    /// How severities are assigned to events.
+    severity: SeverityRules,
+    /// How message fields are laid out in event attributes.
+    attrs: AttrsMapping,
+    /// Limits how many batches are sent at once.
+    in_flight: Arc<Semaphore>,
+    max_in_flight: u32,
+}
+
+impl DataSetSink {
+    /// Creates a DataSet sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_url` - The base URL of the DataSet web service, e.g. `https://app.scalyr.com`
+    ///   or `https://app.eu.scalyr.com`.
+    /// * `token` - Where the API write token for the DataSet web service comes from.
+    /// * `collector` - The collector (or source) identifier.
+    /// * `session_info` - Further `sessionInfo` attributes, e.g. `serverHost`.
+    /// * `logs` - The `parser` and `logfile` attributes of each stream of events.
+    /// * `severity` - How severities are assigned to events.
+    /// * `attrs` - How message fields are laid out in event attributes.
+    /// * `encoding` - How request bodies are compressed.
+    /// * `retry` - How failed requests are retried.
+    /// * `max_in_flight` - How many batches may be sent at once; at least 1. Each gets its
+    ///   own session.
+    /// * `http` - How the HTTP client reaches DataSet.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the URL is not a valid HTTP(S) URL or
+    /// the token file cannot be read.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        api_url: &str,
+        token: Token,
+        collector: &str,
+        session_info: Vec<(String, String)>,
+        logs: LogStreams,
+        severity: SeverityRules,
+        attrs: AttrsMapping,
+        encoding: Encoding,
+        retry: RetryPolicy,
+        max_in_flight: u32,
+        http: HttpOptions,
+    ) -> Result<Self, SinkError> {
+        let url = reqwest::Url::parse(api_url).map_err(|err| format!("invalid DataSet API URL {:?}: {}", api_url, err))?;
+        if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+            return Err(format!("invalid DataSet API URL {:?} (expected an http or https URL)", api_url).into());
+        }
+        // Accept the base URL with or without a path, e.g. behind a proxy.
+        let url = url.join(&format!("{}/api/addEvents", url.path().trim_end_matches('/')))?;
+        // Read the token file now, so that a missing file is reported at startup.
+        let file_token = match &token {
+            Token::Fixed(_) => (String::new(), None),
+            Token::File(path) => read_token(path)?,
+        };
+        let max_in_flight = max_in_flight.max(1);
+        let sessions = (0..max_in_flight).map(|_| Session { id: Uuid::new_v4(), last_ts: 0 }).collect();
+        let client = Client {
+            http: http.client()?,
+            url,
+            token,
+            file_token: Mutex::new(file_token),
+            collector: collector.to_string(),
+            session_info,
+            logs,
+            encoding,
+            retry,
+            stats: UploadStats::default(),
+            sessions: Mutex::new(sessions),
+        };
+        Ok(DataSetSink {
+            client: Arc::new(client),
+            severity,
+            attrs,
+            in_flight: Arc::new(Semaphore::new(max_in_flight as usize)),
+            max_in_flight,
+        })
+    }
+
+    /// Creates a DataSet sink from DATASET_API_URL, DATASET_API_WRITE_TOKEN_FILE or else
+    /// DATASET_API_WRITE_TOKEN, 1090_COLLECTOR,
+    /// the session settings (DATASET_SERVER_HOST, DATASET_REGION, DATASET_SITE and the
+    /// comma-separated `key=value` list DATASET_SESSION_ATTRIBUTES), the log settings,
+    /// DATASET_SEVERITY_RULES,
+    /// DATASET_DEFAULT_SEVERITY, the attribute mapping settings, DATASET_COMPRESSION, the
+    /// retry settings, DATASET_MAX_IN_FLIGHT and the HTTP settings.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let mut session_info = Vec::new();
+        for (attribute, name) in [("serverHost", "DATASET_SERVER_HOST"), ("region", "DATASET_REGION"), ("site", "DATASET_SITE")] {
+            let value = settings(name, Some(""));
+            if !value.is_empty() {
+                session_info.push((attribute.to_string(), value));
+            }
+        }
+        session_info.extend(key_values(&settings("DATASET_SESSION_ATTRIBUTES", Some(""))));
+        let token_file = settings("DATASET_API_WRITE_TOKEN_FILE", Some(""));
+        let token = if token_file.is_empty() {
+            Token::Fixed(settings("DATASET_API_WRITE_TOKEN", None))
+        } else {
+            Token::File(PathBuf::from(token_file))
+        };
+        Self::new(
+            &settings("DATASET_API_URL", Some("https://app.scalyr.com")),
+            token,
+            &settings("1090_COLLECTOR", Some("dump1090")),
+            session_info,
+            LogStreams::from_settings(settings),
+            SeverityRules::parse(
+                &settings("DATASET_SEVERITY_RULES", Some("emergency=5,mlat=2,diagnostic=1")),
+                settings("DATASET_DEFAULT_SEVERITY", Some("3")).parse()?,
+            )?,
+            AttrsMapping::from_settings(settings)?,
+            Encoding::parse(&settings("DATASET_COMPRESSION", Some("gzip")))?,
+            RetryPolicy::from_settings(settings)?,
+            settings("DATASET_MAX_IN_FLIGHT", Some("1")).parse()?,
+            HttpOptions::from_settings(settings)?,
+        )
+    }
+
+    /// Turns messages into events.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The messages.
+    /// * `threads` - The names of the threads of the events, added to.
+    fn message_events(&self, batch: &[SBS1Message], threads: &mut BTreeMap<String, String>) -> Result<Vec<Value>, SinkError> {
+        // Events are grouped into a thread per aircraft, named with its callsign once known.
+        let mut events = Vec::with_capacity(batch.len());
+        for message in batch {
+            let thread = message.icao24.map_or_else(|| self.client.collector.clone(), |icao24| icao24.to_string());
+            let name = threads.entry(thread.clone()).or_insert_with(|| thread.clone());
+            if let Some(callsign) = &message.callsign {
+                *name = format!("{} ({})", thread, callsign.trim());
+            }
+
+            // All-call replies (MSG,8) only show an aircraft is in range, so they are sent as
+            // small presence events instead of full messages.
+            if message.transmission_type == Some(8) {
+                let fields = Map::from_iter([("icao24".to_string(), json!(message.icao24)), ("signal".to_string(), json!(message.signal))]);
+                events.push(json!({
+                    "thread": thread,
+                    "log": "seen",
+                    "ts": event_time(message),
+                    "source": self.client.collector,
+                    "collector": "imichaelmoore/adsb-rust-dataset",
+                    "sev": self.severity.message(message),
+                    "attrs": self.attrs.attrs(fields, false)
+                }));
+                continue;
+            }
+
+            // Meteorological reports get their own log so weather analytics can query them
+            // separately.
+            let log = if message.ehs.as_ref().is_some_and(|ehs| ehs.bds == "4,4") { "met" } else { "adsb" };
+            let Value::Object(fields) = serde_json::to_value(message)? else {
+                continue;
+            };
+            events.push(json!({
+                "thread": thread,
+                "log": log,
+                "ts": event_time(message),
+                "source": self.client.collector,
+                "collector": "imichaelmoore/adsb-rust-dataset",
+                "sev": self.severity.message(message),
+                "attrs": self.attrs.attrs(fields, true)
+            }));
+        }
+        Ok(events)
+    }
+
+    /// Turns parse failures into events.
+    ///
+    /// # Arguments
+    ///
+    /// * `diagnostics` - The parse failures and their lines.
+    /// * `threads` - The names of the threads of the events, added to.
+    fn diagnostic_events(&self, diagnostics: &[(ParseError, String)], threads: &mut BTreeMap<String, String>) -> Vec<Value> {
+        // Parse failures are forwarded in their own log, by default with the lowest severity.
+        // They belong to the receiver's thread, named after the collector, and are given the
+        // time of the messages around them when they are sent.
+        let events: Vec<Value> = diagnostics.iter().map(|(error, line)| {
+            json!({
+                "thread": self.client.collector,
+                "log": "diagnostic",
+                "source": self.client.collector,
+                "collector": "imichaelmoore/adsb-rust-dataset",
+                "sev": self.severity.diagnostic(),
+                "attrs": {"column": error.column(), "reason": error.to_string(), "line": line}
+            })
+        }).collect();
+
+        if !events.is_empty() {
+            threads.entry(self.client.collector.clone()).or_insert_with(|| self.client.collector.clone());
+        }
+        events
+    }
+
+    /// Sends events in the background once fewer than the maximum number of batches are in
+    /// flight, so that reading can go on while DataSet responds.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events, each with its `thread` set.
+    /// * `threads` - The name of each thread.
+    async fn add_events(&self, events: Vec<Value>, threads: BTreeMap<String, String>) -> Result<(), SinkError> {
+        let permit = match self.in_flight.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            // Every request slot is busy, which holds up the batches behind this one.
+            Err(_) => {
+                let started = std::time::Instant::now();
+                let permit = self.in_flight.clone().acquire_owned().await?;
+                self.client.stats.waited(started.elapsed());
+                permit
+            }
+        };
+        let client = self.client.clone();
+        if client.retry.delivery == Delivery::AtLeastOnce {
+            // The batch only counts as sent once DataSet has accepted it.
+            let result = client.add_events(events, &threads).await;
+            drop(permit);
+            return result;
+        }
+        tokio::spawn(async move {
+            if let Err(err) = client.add_events(events, &threads).await {
+                eprintln!("Error sending to DataSet: {}", err);
+            }
+            drop(permit);
+        });
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Sends events to the DataSet web service in a free session.
+    async fn add_events(&self, events: Vec<Value>, threads: &BTreeMap<String, String>) -> Result<(), SinkError> {
+        // There are as many sessions as requests allowed in flight, so one is always free.
+        let mut session = self.sessions.lock().unwrap().pop().expect("a session for each request in flight");
+        let result = self.add_session_events(&mut session, events, threads).await;
+        self.sessions.lock().unwrap().push(session);
+        result
+    }
+
+    /// Sends events to the DataSet web service, split into as many requests as needed to
+    /// stay under its size limit.
+    async fn add_session_events(&self, session: &mut Session, mut events: Vec<Value>, threads: &BTreeMap<String, String>) -> Result<(), SinkError> {
+        // Messages can arrive slightly out of order, e.g. when replaying a recording, so
+        // timestamps that would go backwards are moved up to the latest one sent.
+        let event_ts = |event: &Value| event["ts"].as_str().and_then(|ts| ts.parse::<i64>().ok());
+        // Diagnostics have no time of their own. They take that of the latest message, or
+        // the next one, and only fall back to the current time without moving the latest
+        // timestamp: otherwise one garbled line would move every later message of a replay
+        // up to the time it was read.
+        let next_message_ts = events.iter().find_map(event_ts);
+        for event in &mut events {
+            match event_ts(event) {
+                Some(ts) => {
+                    let ts = ts.max(session.last_ts);
+                    event["ts"] = json!(ts.to_string());
+                    session.last_ts = ts;
+                }
+                None => {
+                    let ts = Some(session.last_ts).filter(|ts| *ts > 0).or(next_message_ts).unwrap_or_else(crate::tracker::now);
+                    event["ts"] = json!(ts.to_string());
+                }
+            }
+        }
+
+        // Split the events where the payload would grow too large.
+        let mut chunk = Vec::new();
+        let mut chunk_bytes = 0;
+        for event in events {
+            let bytes = serde_json::to_vec(&event)?.len() + 1;
+            if !chunk.is_empty() && chunk_bytes + bytes > MAX_EVENTS_BYTES {
+                self.add_chunk(session, std::mem::take(&mut chunk), threads).await?;
+                chunk_bytes = 0;
+            }
+            chunk.push(event);
+            chunk_bytes += bytes;
+        }
+        if !chunk.is_empty() {
+            self.add_chunk(session, chunk, threads).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends events in one request, retrying transient failures. Events that still fail are
+    /// dropped and logged rather than stopping the collector, unless delivery is at least
+    /// once.
+    async fn add_chunk(&self, session: &Session, events: Vec<Value>, threads: &BTreeMap<String, String>) -> Result<(), SinkError> {
+        // Construct the final payload to be sent to the DataSet web service.
+        let count = events.len();
+        let mut session_info = json!({
+            "source": self.collector,
+            "collector": "imichaelmoore/adsb-rust-dataset",
+        });
+        for (name, value) in &self.session_info {
+            session_info[name] = json!(value);
+        }
+        // Name the threads and describe the logs of the events in the request.
+        let ids: BTreeSet<&str> = events.iter().filter_map(|event| event["thread"].as_str()).collect();
+        let threads: Vec<Value> = ids.into_iter().map(|id| json!({"id": id, "name": threads.get(id).map_or(id, String::as_str)})).collect();
+        let ids: BTreeSet<&str> = events.iter().filter_map(|event| event["log"].as_str()).collect();
+        let logs: Vec<Value> = ids.into_iter().map(|id| json!({"id": id, "attrs": self.logs.attrs(id)})).collect();
+        let payload = json!({
+            "session": session.id,
+            "sessionInfo": session_info,
+            "events": events,
+            "threads": threads,
+            "logs": logs
+        });
+        let json = serde_json::to_vec(&payload)?;
+        let json_bytes = json.len();
+        let body = self.encoding.encode(json)?;
+        let body_bytes = body.len();
+
+        let mut attempt = 1;
+        let mut reloaded = false;
+        let err = loop {
+            match self.post(body.clone()).await {
+                Ok(()) => {
+                    self.stats.sent(count, json_bytes, body_bytes);
+                    return Ok(());
+                }
+                // The token may have been rotated since the file was last read.
+                Err(Failure::Unauthorized(err)) if !reloaded && self.reload_token() => {
+                    eprintln!("{}; retrying with the new token from the token file", err);
+                    reloaded = true;
+                }
+                Err(Failure::Transient(err, retry_after)) if attempt < self.retry.max_attempts => {
+                    let delay = retry_after.unwrap_or_else(|| self.retry.delay(attempt));
+                    eprintln!("DataSet request failed (attempt {} of {}): {}; retrying in {:?}", attempt, self.retry.max_attempts, err, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(Failure::Transient(err, _)) | Err(Failure::Unauthorized(err)) | Err(Failure::Permanent(err)) => break err,
+            }
+        };
+
+        if self.retry.delivery == Delivery::AtLeastOnce {
+            return Err(format!("DataSet did not accept a batch of {} events after {} attempt(s): {}", count, attempt, err).into());
+        }
+        let dropped = self.stats.failed(count);
+        eprintln!("Dropped a batch of {} DataSet events after {} attempt(s) ({} batches dropped so far): {}", count, attempt, dropped, err);
+        Ok(())
+    }
+
+    /// Returns the API write token, re-reading the token file if it has changed.
+    fn token(&self) -> Result<String, SinkError> {
+        let path = match &self.token {
+            Token::Fixed(token) => return Ok(token.clone()),
+            Token::File(path) => path,
+        };
+        let mut file_token = self.file_token.lock().unwrap();
+        if std::fs::metadata(path)?.modified().ok() != file_token.1 {
+            *file_token = read_token(path)?;
+        }
+        Ok(file_token.0.clone())
+    }
+
+    /// Re-reads the token file, even if it seems unchanged.
+    ///
+    /// # Returns
+    ///
+    /// Whether the token changed.
+    fn reload_token(&self) -> bool {
+        let Token::File(path) = &self.token else {
+            return false;
+        };
+        let mut file_token = self.file_token.lock().unwrap();
+        match read_token(path) {
+            Ok(new_token) if new_token.0 != file_token.0 => {
+                *file_token = new_token;
+                true
+            }
+            Ok(_) => false,
+            Err(err) => {
+                eprintln!("Error reading the DataSet API token file: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Posts a request body to the DataSet web service once and checks the response.
+    async fn post(&self, body: Vec<u8>) -> Result<(), Failure> {
+        // A token file being replaced may be missing for a moment.
+        let token = self.token().map_err(|err| Failure::Transient(err, None))?;
+        let mut request = self.http.post(self.url.clone())
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", token));
+        if let Some(encoding) = self.encoding.header() {
+            request = request.header("Content-Encoding", encoding);
+        }
+        let res = request.body(body).send().await.map_err(|err| Failure::Transient(err.into(), None))?;
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let text = res.text().await.map_err(|err| Failure::Transient(err.into(), None))?;
+        Response::check(status, &text, retry_after)
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a delay in seconds or an HTTP date to wait
+/// until (RFC 9110, section 10.2.3). A date that has passed is no delay.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    // The preferred `Sun, 06 Nov 1994 08:49:37 GMT`, then the obsolete RFC 850 and asctime
+    // forms, which are always in GMT.
+    let date = chrono::DateTime::parse_from_rfc2822(value)
+        .map(|date| date.to_utc())
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%A, %d-%b-%y %H:%M:%S GMT").map(|date| date.and_utc()))
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%a %b %e %H:%M:%S %Y").map(|date| date.and_utc()))
+        .ok()?;
+    Some((date - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Returns when a message was generated, in nanoseconds since the UNIX epoch, falling back
+/// to when it was received. Generated dates are in the receiver's local time, as written by
+/// dump1090.
+fn event_time(message: &SBS1Message) -> String {
+    message
+        .generated_date
+        .and_then(|generated| generated.and_local_timezone(Local).earliest())
+        .and_then(|generated| generated.timestamp_nanos_opt())
+        .map(|nanos| nanos.to_string())
+        .unwrap_or_else(|| message.timestamp.clone())
+}
+
+#[async_trait]
+impl Sink for DataSetSink {
+    fn name(&self) -> &'static str {
+        "dataset"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut threads = BTreeMap::new();
+        let events = self.message_events(batch, &mut threads)?;
+        self.add_events(events, threads).await
+    }
+
+    async fn send_diagnostics(&self, diagnostics: &[(ParseError, String)]) -> Result<(), SinkError> {
+        let mut threads = BTreeMap::new();
+        let events = self.diagnostic_events(diagnostics, &mut threads);
+        self.add_events(events, threads).await
+    }
+
+    async fn send_batch(&self, batch: &Batch) -> Result<(), SinkError> {
+        let mut threads = BTreeMap::new();
+        let mut events = self.message_events(&batch.messages, &mut threads)?;
+        events.extend(self.diagnostic_events(&batch.diagnostics, &mut threads));
+        // DataSet discards events whose sequence number it has already received for their
+        // sequence ID, so a batch sent again, e.g. after a restart, is not stored twice.
+        // The batch is also named in the attributes, for systems reading the events from
+        // DataSet to do the same.
+        let id = batch.id.to_string();
+        for (number, event) in events.iter_mut().enumerate() {
+            event["si"] = json!(id);
+            event["sn"] = json!(number + 1);
+            if !self.attrs.drop.contains("batch_id") {
+                event["attrs"]["batch_id"] = json!(id);
+            }
+            if !self.attrs.drop.contains("batch_seq") {
+                event["attrs"]["batch_seq"] = json!(batch.seq);
+            }
+        }
+        self.add_events(events, threads).await
+    }
+
+    fn upload_stats(&self) -> Option<&UploadStats> {
+        Some(&self.client.stats)
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        // Wait for the batches still in flight.
+        let _all = self.in_flight.acquire_many(self.max_in_flight).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    /// Classifies a response, returning `ok`, `transient`, `unauthorized` or `permanent`.
+    fn check(http_status: u16, body: &str) -> &'static str {
+        match Response::check(StatusCode::from_u16(http_status).unwrap(), body, None) {
+            Ok(()) => "ok",
+            Err(Failure::Transient(..)) => "transient",
+            Err(Failure::Unauthorized(_)) => "unauthorized",
+            Err(Failure::Permanent(_)) => "permanent",
+        }
+    }
+
+    #[test]
+    fn classifies_responses() {
+        for (http_status, body, expected) in [
+            (200, r#"{"status":"success"}"#, "ok"),
+            (429, "", "transient"),
+            (200, r#"{"status":"error/server/backoff","message":"slow down"}"#, "transient"),
+            (503, r#"{"status":"error/server/serverTooBusy"}"#, "transient"),
+            (401, r#"{"status":"error/client/noPermission"}"#, "unauthorized"),
+            (403, "", "unauthorized"),
+            (200, r#"{"status":"error/client/noPermission/authentication"}"#, "unauthorized"),
+            (413, "", "permanent"),
+            (400, r#"{"status":"error/client/tooLarge"}"#, "permanent"),
+            (500, "Internal Server Error", "transient"),
+            (502, "<html>Bad Gateway</html>", "transient"),
+            (200, r#"{"status":"error/server"}"#, "transient"),
+            (408, "", "transient"),
+            (200, "<html><body>Sign in to continue</body></html>", "transient"),
+            (204, "", "transient"),
+            (400, r#"{"status":"error/client/badParam","message":"bad ts"}"#, "permanent"),
+            (404, "Not Found", "permanent"),
+        ] {
+            assert_eq!(check(http_status, body), expected, "HTTP {} {}", http_status, body);
+        }
+    }
+
+    #[test]
+    fn passes_retry_after_on() {
+        let retry_after = Some(Duration::from_secs(7));
+        let Err(Failure::Transient(_, delay)) = Response::check(StatusCode::TOO_MANY_REQUESTS, "", retry_after) else {
+            panic!("rate limiting should be transient");
+        };
+        assert_eq!(delay, retry_after);
+    }
+
+    /// Formats a time a minute from now, and checks that it parses as a delay of about a
+    /// minute.
+    fn assert_a_minute_from_now(format: &str) {
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(60)).format(format).to_string();
+        let delay = parse_retry_after(&date).unwrap_or_else(|| panic!("{:?} should parse", date));
+        assert!((Duration::from_secs(58)..=Duration::from_secs(60)).contains(&delay), "{:?} gave {:?}", date, delay);
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+        // The IMF-fixdate, RFC 850 and asctime forms.
+        assert_a_minute_from_now("%a, %d %b %Y %H:%M:%S GMT");
+        assert_a_minute_from_now("%A, %d-%b-%y %H:%M:%S GMT");
+        assert_a_minute_from_now("%a %b %e %H:%M:%S %Y");
+        // A date that has passed is no delay.
+        assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Sunday, 06-Nov-94 08:49:37 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Sun Nov  6 08:49:37 1994"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("-5"), None);
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn randomizes_retry_delays_between_half_and_all_of_the_backoff() {
+        let mut policy = RetryPolicy {
+            max_attempts: 5,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_millis(3000),
+            jitter: false,
+            delivery: Delivery::AtMostOnce,
+        };
+        let delays: Vec<Duration> = (1..=4).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(delays, [500, 1000, 2000, 3000].map(Duration::from_millis));
+
+        policy.jitter = true;
+        let delays: HashSet<Duration> = (0..100).map(|_| policy.delay(4)).collect();
+        assert!(delays.iter().all(|delay| (Duration::from_millis(1500)..=Duration::from_millis(3000)).contains(delay)));
+        assert!(delays.len() > 10);
+    }
+}
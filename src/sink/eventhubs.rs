@@ -0,0 +1,175 @@
+//! This module sends messages to an Azure Event Hub through its REST API (requires the
+//! `eventhubs` feature).
+//!
+//! Requests are authorized with a shared access signature computed from the connection
+//! string's key, as described in
+//! <https://learn.microsoft.com/rest/api/eventhub/generate-sas-token>.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long each shared access signature is valid for, in seconds.
+const SAS_LIFETIME_SECONDS: u64 = 3600;
+
+/// The largest request body to send; Event Hubs rejects batches over 1 MB.
+const MAX_REQUEST_BYTES: usize = 1_000_000;
+
+/// The parts of an Event Hubs connection string that are used.
+#[derive(Debug, PartialEq)]
+pub struct ConnectionString {
+    /// The namespace host, e.g. `example.servicebus.windows.net`.
+    pub host: String,
+    pub key_name: String,
+    pub key: String,
+    /// The event hub, if the connection string is scoped to one.
+    pub entity_path: Option<String>,
+}
+
+impl ConnectionString {
+    /// Parses a connection string such as
+    /// `Endpoint=sb://example.servicebus.windows.net/;SharedAccessKeyName=send;SharedAccessKey=...;EntityPath=adsb`.
+    pub fn parse(connection_string: &str) -> Result<Self, SinkError> {
+        let mut host = None;
+        let mut key_name = None;
+        let mut key = None;
+        let mut entity_path = None;
+        for part in connection_string.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(|| format!("invalid connection string part {:?}", part))?;
+            match name.trim() {
+                "Endpoint" => {
+                    let value = value.trim();
+                    host = Some(value.trim_start_matches("sb://").trim_start_matches("https://").trim_end_matches('/').to_string());
+                }
+                "SharedAccessKeyName" => key_name = Some(value.trim().to_string()),
+                "SharedAccessKey" => key = Some(value.trim().to_string()),
+                "EntityPath" => entity_path = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+        Ok(ConnectionString {
+            host: host.ok_or("connection string has no Endpoint")?,
+            key_name: key_name.ok_or("connection string has no SharedAccessKeyName")?,
+            key: key.ok_or("connection string has no SharedAccessKey")?,
+            entity_path,
+        })
+    }
+}
+
+/// Sends each batch as one batch of events, with each event's partition key set to its
+/// ICAO address so an aircraft's events stay in order within a partition.
+pub struct EventHubsSink {
+    connection: ConnectionString,
+    /// The event hub to send to.
+    hub: String,
+    /// Whether to set partition keys.
+    partition_by_icao24: bool,
+}
+
+impl EventHubsSink {
+    /// Creates an Event Hubs sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The parsed connection string.
+    /// * `hub` - The event hub to send to.
+    /// * `partition_by_icao24` - Whether to set each event's partition key to its ICAO address.
+    pub fn new(connection: ConnectionString, hub: &str, partition_by_icao24: bool) -> Self {
+        EventHubsSink { connection, hub: hub.to_string(), partition_by_icao24 }
+    }
+
+    /// Creates an Event Hubs sink from EVENTHUBS_CONNECTION_STRING, EVENTHUBS_NAME (needed if
+    /// the connection string has no EntityPath) and EVENTHUBS_PARTITION_KEY.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let connection = ConnectionString::parse(&settings("EVENTHUBS_CONNECTION_STRING", None))?;
+        let hub = match settings("EVENTHUBS_NAME", Some("")) {
+            hub if !hub.is_empty() => hub,
+            _ => connection.entity_path.clone().ok_or("set EVENTHUBS_NAME or EntityPath in the connection string")?,
+        };
+        let partition_by_icao24 = settings("EVENTHUBS_PARTITION_KEY", Some("true")).parse()?;
+        Ok(Self::new(connection, &hub, partition_by_icao24))
+    }
+
+    /// Computes a shared access signature for a resource URI.
+    fn sas_token(&self, uri: &str) -> Result<String, SinkError> {
+        let expiry = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + SAS_LIFETIME_SECONDS;
+        let resource = percent_encode(uri);
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.connection.key.as_bytes())?;
+        mac.update(format!("{}\n{}", resource, expiry).as_bytes());
+        let signature = base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+        Ok(format!(
+            "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+            resource,
+            percent_encode(&signature),
+            expiry,
+            percent_encode(&self.connection.key_name),
+        ))
+    }
+
+    /// Converts a message to an event in the REST batch format.
+    fn event(&self, message: &SBS1Message) -> Result<Value, SinkError> {
+        let mut event = json!({"Body": serde_json::to_string(message)?});
+        if let (true, Some(icao24)) = (self.partition_by_icao24, message.icao24) {
+            event["BrokerProperties"] = json!({"PartitionKey": icao24.to_string()});
+        }
+        Ok(event)
+    }
+}
+
+/// Percent-encodes everything but unreserved characters.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Sink for EventHubsSink {
+    fn name(&self) -> &'static str {
+        "eventhubs"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let uri = format!("https://{}/{}", self.connection.host, self.hub);
+
+        // Split the batch so that no request exceeds the size limit.
+        let mut bodies = Vec::new();
+        let mut body = Vec::new();
+        for message in batch {
+            let event = serde_json::to_vec(&self.event(message)?)?;
+            if !body.is_empty() && body.len() + event.len() + 2 > MAX_REQUEST_BYTES {
+                body.push(b']');
+                bodies.push(std::mem::take(&mut body));
+            }
+            body.push(if body.is_empty() { b'[' } else { b',' });
+            body.extend_from_slice(&event);
+        }
+        if !body.is_empty() {
+            body.push(b']');
+            bodies.push(body);
+        }
+
+        let client = reqwest::Client::new();
+        for body in bodies {
+            client
+                .post(format!("{}/messages", uri))
+                .header("Authorization", self.sas_token(&uri)?)
+                .header("Content-Type", "application/vnd.microsoft.servicebus.json")
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
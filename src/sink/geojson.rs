@@ -0,0 +1,191 @@
+//! This module keeps the latest position of every aircraft in view and periodically writes
+//! them to a file as a GeoJSON FeatureCollection, which web maps such as Leaflet or Mapbox
+//! can load directly.
+//!
+//! The file is replaced atomically, so a web server can serve it while it is rewritten.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What is known about an aircraft.
+#[derive(Default)]
+struct Aircraft {
+    callsign: Option<String>,
+    altitude: Option<i32>,
+    ground_speed: Option<f32>,
+    track: Option<f32>,
+    vertical_rate: Option<i32>,
+    squawk: Option<i32>,
+    on_ground: Option<bool>,
+    /// The latest position as (lon, lat).
+    position: Option<(f64, f64)>,
+    /// Recent positions as (lon, lat), oldest first.
+    path: VecDeque<(f64, f64)>,
+    /// When the aircraft was last heard, in nanoseconds since the UNIX epoch.
+    last_seen: i64,
+}
+
+/// The aircraft in view and when the file was last written.
+struct State {
+    aircraft: HashMap<String, Aircraft>,
+    written: Option<Instant>,
+}
+
+/// Writes the positions of the aircraft in view as GeoJSON Point features, optionally with
+/// a LineString feature for each aircraft's recent track.
+pub struct GeoJsonSink {
+    path: String,
+    /// How often the file is rewritten.
+    interval: Duration,
+    /// How long an aircraft stays on the map after it was last heard.
+    expire: Duration,
+    /// How many positions to keep per track, or 0 to not write tracks.
+    track_points: usize,
+    state: Mutex<State>,
+}
+
+impl GeoJsonSink {
+    /// Creates a GeoJSON sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write.
+    /// * `interval` - How often the file is rewritten.
+    /// * `expire` - How long an aircraft stays on the map after it was last heard.
+    /// * `track_points` - How many positions to keep per track, or 0 to not write tracks.
+    pub fn new(path: &str, interval: Duration, expire: Duration, track_points: usize) -> Self {
+        GeoJsonSink {
+            path: path.to_string(),
+            interval,
+            expire,
+            track_points,
+            state: Mutex::new(State { aircraft: HashMap::new(), written: None }),
+        }
+    }
+
+    /// Creates a GeoJSON sink from GEOJSON_PATH, GEOJSON_INTERVAL_SECONDS,
+    /// GEOJSON_EXPIRE_SECONDS and GEOJSON_TRACK_POINTS.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Ok(Self::new(
+            &settings("GEOJSON_PATH", Some("aircraft.geojson")),
+            Duration::from_secs(settings("GEOJSON_INTERVAL_SECONDS", Some("5")).parse()?),
+            Duration::from_secs(settings("GEOJSON_EXPIRE_SECONDS", Some("60")).parse()?),
+            settings("GEOJSON_TRACK_POINTS", Some("0")).parse()?,
+        ))
+    }
+
+    /// Merges a message into the state of its aircraft.
+    fn update(&self, state: &mut State, message: &SBS1Message) {
+        let Some(icao24) = message.icao24 else {
+            return;
+        };
+        let aircraft = state.aircraft.entry(icao24.to_string()).or_default();
+        aircraft.last_seen = aircraft.last_seen.max(message.timestamp.parse().unwrap_or_default());
+        if message.callsign.is_some() {
+            aircraft.callsign = message.callsign.clone();
+        }
+        if message.altitude_baro.is_some() {
+            aircraft.altitude = message.altitude_baro;
+        }
+        if message.ground_speed.is_some() {
+            aircraft.ground_speed = message.ground_speed;
+        }
+        if message.track.is_some() {
+            aircraft.track = message.track;
+        }
+        if message.vertical_rate.is_some() {
+            aircraft.vertical_rate = message.vertical_rate;
+        }
+        if message.squawk.is_some() {
+            aircraft.squawk = message.squawk;
+        }
+        if message.on_ground.is_some() {
+            aircraft.on_ground = message.on_ground;
+        }
+        if let (Some(lat), Some(lon)) = (message.lat, message.lon) {
+            aircraft.position = Some((lon, lat));
+            if self.track_points > 0 {
+                aircraft.path.push_back((lon, lat));
+                if aircraft.path.len() > self.track_points {
+                    aircraft.path.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Builds the FeatureCollection of the aircraft with a known position.
+    fn feature_collection(&self, state: &State) -> Value {
+        let mut features = Vec::new();
+        for (icao24, aircraft) in &state.aircraft {
+            let Some((lon, lat)) = aircraft.position else {
+                continue;
+            };
+            features.push(json!({
+                "type": "Feature",
+                "id": icao24,
+                "geometry": {"type": "Point", "coordinates": [lon, lat]},
+                "properties": {
+                    "icao24": icao24,
+                    "callsign": aircraft.callsign,
+                    "altitude": aircraft.altitude,
+                    "ground_speed": aircraft.ground_speed,
+                    "track": aircraft.track,
+                    "vertical_rate": aircraft.vertical_rate,
+                    "squawk": aircraft.squawk,
+                    "on_ground": aircraft.on_ground,
+                    "last_seen": aircraft.last_seen / 1_000_000_000,
+                },
+            }));
+            if aircraft.path.len() >= 2 {
+                let coordinates: Vec<[f64; 2]> = aircraft.path.iter().map(|&(lon, lat)| [lon, lat]).collect();
+                features.push(json!({
+                    "type": "Feature",
+                    "id": format!("{}-track", icao24),
+                    "geometry": {"type": "LineString", "coordinates": coordinates},
+                    "properties": {"icao24": icao24, "callsign": aircraft.callsign},
+                }));
+            }
+        }
+        json!({"type": "FeatureCollection", "features": features})
+    }
+
+    /// Drops aircraft that have not been heard for a while and replaces the file.
+    fn write(&self, state: &mut State) -> Result<(), SinkError> {
+        let newest = state.aircraft.values().map(|aircraft| aircraft.last_seen).max().unwrap_or_default();
+        let oldest = newest - self.expire.as_nanos() as i64;
+        state.aircraft.retain(|_, aircraft| aircraft.last_seen >= oldest);
+
+        let temporary = format!("{}.tmp", self.path);
+        std::fs::write(&temporary, serde_json::to_vec(&self.feature_collection(state))?)?;
+        std::fs::rename(&temporary, &self.path)?;
+        state.written = Some(Instant::now());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for GeoJsonSink {
+    fn name(&self) -> &'static str {
+        "geojson"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut state = self.state.lock().unwrap();
+        for message in batch {
+            self.update(&mut state, message);
+        }
+        if state.written.is_none_or(|written| written.elapsed() >= self.interval) {
+            self.write(&mut state)?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        self.write(&mut self.state.lock().unwrap())
+    }
+}
@@ -0,0 +1,121 @@
+//! This module writes messages to InfluxDB v2 in line protocol.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+
+/// Writes each message with at least one numeric field as an `adsb` point.
+pub struct InfluxDbSink {
+    /// The base URL of the InfluxDB server, e.g. `http://localhost:8086`.
+    url: String,
+    org: String,
+    bucket: String,
+    token: String,
+    /// The collector (or source) identifier, written as a tag.
+    collector: String,
+}
+
+impl InfluxDbSink {
+    /// Creates an InfluxDB sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The base URL of the InfluxDB server.
+    /// * `org` - The organization that owns the bucket.
+    /// * `bucket` - The bucket to write to.
+    /// * `token` - An API token with write access to the bucket.
+    /// * `collector` - The collector (or source) identifier.
+    pub fn new(url: &str, org: &str, bucket: &str, token: &str, collector: &str) -> Self {
+        InfluxDbSink {
+            url: url.trim_end_matches('/').to_string(),
+            org: org.to_string(),
+            bucket: bucket.to_string(),
+            token: token.to_string(),
+            collector: collector.to_string(),
+        }
+    }
+
+    /// Creates an InfluxDB sink from INFLUXDB_URL, INFLUXDB_ORG, INFLUXDB_BUCKET,
+    /// INFLUXDB_TOKEN and 1090_COLLECTOR.
+    pub fn from_settings(settings: Settings) -> Self {
+        Self::new(
+            &settings("INFLUXDB_URL", None),
+            &settings("INFLUXDB_ORG", None),
+            &settings("INFLUXDB_BUCKET", Some("adsb")),
+            &settings("INFLUXDB_TOKEN", None),
+            &settings("1090_COLLECTOR", Some("dump1090")),
+        )
+    }
+}
+
+/// Converts a message to a line protocol point.
+///
+/// # Arguments
+///
+/// * `message` - The message to convert.
+/// * `collector` - The collector (or source) identifier.
+///
+/// # Returns
+///
+/// An `Option` containing the line, or `None` if the message has none of the fields.
+pub fn to_line(message: &SBS1Message, collector: &str) -> Option<String> {
+    let mut fields = Vec::new();
+    if let Some(altitude) = message.altitude_baro {
+        fields.push(format!("altitude={}i", altitude));
+    }
+    if let Some(speed) = message.ground_speed {
+        fields.push(format!("speed={}", speed));
+    }
+    if let Some(lat) = message.lat {
+        fields.push(format!("lat={}", lat));
+    }
+    if let Some(lon) = message.lon {
+        fields.push(format!("lon={}", lon));
+    }
+    if let Some(vertical_rate) = message.vertical_rate {
+        fields.push(format!("vertical_rate={}i", vertical_rate));
+    }
+    if fields.is_empty() {
+        return None;
+    }
+
+    let mut line = format!("adsb,collector={}", escape_tag(collector));
+    if let Some(icao24) = message.icao24 {
+        line.push_str(&format!(",icao24={}", icao24));
+    }
+    if let Some(callsign) = message.callsign.as_deref().filter(|callsign| !callsign.is_empty()) {
+        line.push_str(&format!(",callsign={}", escape_tag(callsign)));
+    }
+    Some(format!("{} {} {}", line, fields.join(","), message.timestamp))
+}
+
+/// Escapes the characters line protocol treats specially in tag values.
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+#[async_trait]
+impl Sink for InfluxDbSink {
+    fn name(&self) -> &'static str {
+        "influxdb"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let lines: Vec<String> = batch.iter().filter_map(|message| to_line(message, &self.collector)).collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let client = reqwest::Client::new();
+        client.post(format!("{}/api/v2/write", self.url))
+            .query(&[("org", self.org.as_str()), ("bucket", self.bucket.as_str()), ("precision", "ns")])
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(lines.join("\n"))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
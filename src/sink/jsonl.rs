@@ -0,0 +1,124 @@
+//! This module writes messages to a local file as JSON Lines, rotating it by size and age.
+//!
+//! Rotated files are renamed with a timestamp suffix and, optionally, gzipped in the
+//! background.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use chrono::Local;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The file currently being written.
+struct Output {
+    writer: BufWriter<File>,
+    /// The size of the file, including buffered writes.
+    bytes: u64,
+    opened: Instant,
+}
+
+/// Appends one JSON object per line for each message.
+pub struct JsonLinesSink {
+    path: String,
+    /// The size in bytes after which the file is rotated, or 0 to never rotate by size.
+    max_bytes: u64,
+    /// The age after which the file is rotated, or `None` to never rotate by age.
+    max_age: Option<Duration>,
+    /// Whether rotated files are gzipped.
+    gzip: bool,
+    output: Mutex<Output>,
+}
+
+impl JsonLinesSink {
+    /// Opens (or creates) the file and creates a JSON Lines sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to append to.
+    /// * `max_bytes` - The size in bytes after which the file is rotated, or 0 to never
+    ///   rotate by size.
+    /// * `max_age` - The age after which the file is rotated, or `None`.
+    /// * `gzip` - Whether rotated files are gzipped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the file could not be opened.
+    pub fn new(path: &str, max_bytes: u64, max_age: Option<Duration>, gzip: bool) -> Result<Self, SinkError> {
+        let output = open(path)?;
+        Ok(JsonLinesSink { path: path.to_string(), max_bytes, max_age, gzip, output: Mutex::new(output) })
+    }
+
+    /// Creates a JSON Lines sink from JSONL_PATH, JSONL_MAX_BYTES, JSONL_ROTATE_SECONDS and
+    /// JSONL_GZIP.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let max_bytes: u64 = settings("JSONL_MAX_BYTES", Some("104857600")).parse()?;
+        let rotate_seconds: u64 = settings("JSONL_ROTATE_SECONDS", Some("3600")).parse()?;
+        let max_age = Some(Duration::from_secs(rotate_seconds)).filter(|age| !age.is_zero());
+        let gzip: bool = settings("JSONL_GZIP", Some("false")).parse()?;
+        Self::new(&settings("JSONL_PATH", Some("adsb.jsonl")), max_bytes, max_age, gzip)
+    }
+
+    /// Rotates the file if it is too large or too old.
+    fn rotate(&self, output: &mut Output) -> Result<(), SinkError> {
+        let too_large = self.max_bytes > 0 && output.bytes >= self.max_bytes;
+        let too_old = self.max_age.is_some_and(|age| output.opened.elapsed() >= age);
+        if !too_large && !too_old {
+            return Ok(());
+        }
+
+        output.writer.flush()?;
+        let rotated = format!("{}.{}", self.path, Local::now().format("%Y%m%d%H%M%S%3f"));
+        std::fs::rename(&self.path, &rotated)?;
+        *output = open(&self.path)?;
+        if self.gzip {
+            // Compressing a large file takes a while, so it happens off the batching loop.
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = gzip(&rotated) {
+                    eprintln!("Failed to gzip {}: {}", rotated, err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Opens `path` for appending.
+fn open(path: &str) -> io::Result<Output> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let bytes = file.metadata()?.len();
+    Ok(Output { writer: BufWriter::new(file), bytes, opened: Instant::now() })
+}
+
+/// Compresses `path` to `path.gz` and removes the original.
+fn gzip(path: &str) -> io::Result<()> {
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(format!("{}.gz", path))?), Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    std::fs::remove_file(path)
+}
+
+#[async_trait]
+impl Sink for JsonLinesSink {
+    fn name(&self) -> &'static str {
+        "jsonl"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut output = self.output.lock().unwrap();
+        for message in batch {
+            let mut line = serde_json::to_vec(message)?;
+            line.push(b'\n');
+            output.writer.write_all(&line)?;
+            output.bytes += line.len() as u64;
+        }
+        output.writer.flush()?;
+
+        self.rotate(&mut output)
+    }
+}
@@ -0,0 +1,76 @@
+//! This module publishes messages to a Kafka topic (requires the `kafka` feature).
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use std::time::Duration;
+
+/// Publishes each message as JSON, keyed by its ICAO address so that all messages of an
+/// aircraft land in the same partition, in order.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaSink {
+    /// Creates a Kafka sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `brokers` - Comma-separated `host:port` bootstrap servers.
+    /// * `topic` - The topic to publish to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the producer could not be created.
+    pub fn new(brokers: &str, topic: &str) -> Result<Self, SinkError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("client.id", "adsb-rust-dataset")
+            .create()?;
+        Ok(KafkaSink { producer, topic: topic.to_string() })
+    }
+
+    /// Creates a Kafka sink from KAFKA_BROKERS and KAFKA_TOPIC.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Self::new(&settings("KAFKA_BROKERS", None), &settings("KAFKA_TOPIC", Some("adsb")))
+    }
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        // Queue the whole batch before waiting for deliveries, so the producer can batch
+        // them into as few requests as possible.
+        let mut deliveries = Vec::with_capacity(batch.len());
+        for message in batch {
+            let payload = serde_json::to_string(message)?;
+            let key = message.icao24.map(|icao24| icao24.to_string());
+            let mut record: FutureRecord<String, String> = FutureRecord::to(&self.topic).payload(&payload);
+            if let Some(key) = key.as_ref() {
+                record = record.key(key);
+            }
+            match self.producer.send_result(record) {
+                Ok(delivery) => deliveries.push(delivery),
+                Err((err, _)) => return Err(err.into()),
+            }
+        }
+
+        for delivery in deliveries {
+            match delivery.await {
+                Ok(Ok(_)) => {}
+                Ok(Err((err, _))) => return Err(err.into()),
+                Err(_) => return Err("Kafka delivery was cancelled".into()),
+            }
+        }
+        self.producer.poll(Duration::ZERO);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! This module puts messages into an AWS Kinesis data stream (requires the `kinesis` feature).
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use aws_sdk_kinesis::primitives::Blob;
+use aws_sdk_kinesis::types::PutRecordsRequestEntry;
+use aws_sdk_kinesis::Client;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// The most records Kinesis accepts in one PutRecords request.
+const MAX_RECORDS_PER_REQUEST: usize = 500;
+
+/// The delay before the first retry of failed records; it doubles with each retry.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Puts each message as a JSON record, partitioned by its ICAO address so each aircraft's
+/// records stay in order within a shard.
+pub struct KinesisSink {
+    stream: String,
+    /// How many times to retry records that failed, e.g. because a shard was throttled.
+    max_retries: u32,
+    /// The client, created from the AWS environment when the first batch is sent.
+    client: Mutex<Option<Client>>,
+}
+
+impl KinesisSink {
+    /// Creates a Kinesis sink. Credentials and region come from the standard AWS environment
+    /// variables, profiles or instance metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream name.
+    /// * `max_retries` - How many times to retry records that failed.
+    pub fn new(stream: &str, max_retries: u32) -> Self {
+        KinesisSink { stream: stream.to_string(), max_retries, client: Mutex::new(None) }
+    }
+
+    /// Creates a Kinesis sink from KINESIS_STREAM and KINESIS_MAX_RETRIES.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Ok(Self::new(&settings("KINESIS_STREAM", None), settings("KINESIS_MAX_RETRIES", Some("3")).parse()?))
+    }
+
+    /// Puts records, retrying those that fail with exponential backoff.
+    async fn put_records(&self, client: &Client, mut records: Vec<PutRecordsRequestEntry>) -> Result<(), SinkError> {
+        let mut delay = RETRY_DELAY;
+        for attempt in 0..=self.max_retries {
+            let output = client
+                .put_records()
+                .stream_name(&self.stream)
+                .set_records(Some(records.clone()))
+                .send()
+                .await?;
+            if output.failed_record_count().unwrap_or_default() == 0 {
+                return Ok(());
+            }
+
+            // Results are in the same order as the records; keep those that failed.
+            let mut first_error = None;
+            records = records
+                .into_iter()
+                .zip(output.records())
+                .filter(|(_, result)| result.error_code().is_some())
+                .map(|(record, result)| {
+                    first_error.get_or_insert_with(|| format!("{}: {}", result.error_code().unwrap_or_default(), result.error_message().unwrap_or_default()));
+                    record
+                })
+                .collect();
+            if attempt == self.max_retries {
+                return Err(format!("{} Kinesis records failed, e.g. {}", records.len(), first_error.unwrap_or_default()).into());
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for KinesisSink {
+    fn name(&self) -> &'static str {
+        "kinesis"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let client = {
+            let mut client = self.client.lock().await;
+            if client.is_none() {
+                *client = Some(Client::new(&aws_config::load_from_env().await));
+            }
+            client.as_ref().unwrap().clone()
+        };
+
+        for chunk in batch.chunks(MAX_RECORDS_PER_REQUEST) {
+            let mut records = Vec::with_capacity(chunk.len());
+            for message in chunk {
+                let partition_key = message.icao24.map(|icao24| icao24.to_string()).unwrap_or_else(|| "unknown".to_string());
+                records.push(
+                    PutRecordsRequestEntry::builder()
+                        .data(Blob::new(serde_json::to_vec(message)?))
+                        .partition_key(partition_key)
+                        .build()?,
+                );
+            }
+            self.put_records(&client, records).await?;
+        }
+        Ok(())
+    }
+}
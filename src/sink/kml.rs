@@ -0,0 +1,212 @@
+//! This module assembles the positions of each flight into a track and, once the flight is
+//! over, writes it to a KML or KMZ file for review in Google Earth.
+//!
+//! A flight is considered over once its aircraft has not been heard for a while, or when
+//! the input ends.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use chrono::DateTime;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Feet per metre, as KML altitudes are in metres.
+const FEET_PER_METRE: f64 = 3.280_84;
+
+/// A flight being assembled.
+#[derive(Default)]
+struct Flight {
+    callsign: Option<String>,
+    /// The latest barometric altitude in feet, applied to the following positions.
+    altitude: Option<i32>,
+    /// The positions as (lon, lat, altitude in feet).
+    points: Vec<(f64, f64, Option<i32>)>,
+    /// When the aircraft was first and last heard, in nanoseconds since the UNIX epoch.
+    first_seen: i64,
+    last_seen: i64,
+}
+
+/// Writes one KML or KMZ file per completed flight.
+pub struct KmlSink {
+    directory: PathBuf,
+    /// Whether to write zipped KMZ files instead of KML.
+    kmz: bool,
+    /// How long an aircraft must be silent for its flight to be considered over.
+    timeout: Duration,
+    /// Flights with fewer positions are discarded.
+    min_points: usize,
+    flights: Mutex<HashMap<String, Flight>>,
+}
+
+impl KmlSink {
+    /// Creates the directory and a KML sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to write files to.
+    /// * `kmz` - Whether to write zipped KMZ files instead of KML.
+    /// * `timeout` - How long an aircraft must be silent for its flight to be considered over.
+    /// * `min_points` - Flights with fewer positions are discarded.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the directory could not be created.
+    pub fn new(directory: &str, kmz: bool, timeout: Duration, min_points: usize) -> Result<Self, SinkError> {
+        std::fs::create_dir_all(directory)?;
+        Ok(KmlSink { directory: PathBuf::from(directory), kmz, timeout, min_points, flights: Mutex::new(HashMap::new()) })
+    }
+
+    /// Creates a KML sink from KML_DIR, KML_FORMAT (`kml` or `kmz`), KML_FLIGHT_TIMEOUT_SECONDS
+    /// and KML_MIN_POINTS.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let kmz = match settings("KML_FORMAT", Some("kml")).to_lowercase().as_str() {
+            "kml" => false,
+            "kmz" => true,
+            other => return Err(format!("invalid KML format {:?} (expected kml or kmz)", other).into()),
+        };
+        Self::new(
+            &settings("KML_DIR", Some("tracks")),
+            kmz,
+            Duration::from_secs(settings("KML_FLIGHT_TIMEOUT_SECONDS", Some("600")).parse()?),
+            settings("KML_MIN_POINTS", Some("2")).parse()?,
+        )
+    }
+
+    /// Writes a completed flight, unless it has too few positions.
+    fn finish(&self, icao24: &str, flight: Flight) -> Result<(), SinkError> {
+        if flight.points.len() < self.min_points.max(1) {
+            return Ok(());
+        }
+
+        let start = DateTime::from_timestamp_nanos(flight.first_seen);
+        let name = match &flight.callsign {
+            Some(callsign) => format!("{}-{}", icao24, callsign.replace(|c: char| !c.is_ascii_alphanumeric(), "")),
+            None => icao24.to_string(),
+        };
+        let stem = format!("{}-{}", start.format("%Y%m%dT%H%M%SZ"), name);
+        let document = document(icao24, &flight);
+
+        if self.kmz {
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(self.directory.join(format!("{}.kmz", stem)))?);
+            zip.start_file("doc.kml", zip::write::SimpleFileOptions::default())?;
+            zip.write_all(document.as_bytes())?;
+            zip.finish()?;
+        } else {
+            std::fs::write(self.directory.join(format!("{}.kml", stem)), document)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a flight as a KML document with one placemark holding its track.
+fn document(icao24: &str, flight: &Flight) -> String {
+    let title = match &flight.callsign {
+        Some(callsign) => format!("{} ({})", callsign, icao24),
+        None => icao24.to_string(),
+    };
+    let begin = DateTime::from_timestamp_nanos(flight.first_seen).format("%Y-%m-%dT%H:%M:%SZ");
+    let end = DateTime::from_timestamp_nanos(flight.last_seen).format("%Y-%m-%dT%H:%M:%SZ");
+
+    // Altitudes are only absolute if every position has one; otherwise clamp to the ground.
+    let absolute = flight.points.iter().all(|(_, _, altitude)| altitude.is_some());
+    let coordinates: Vec<String> = flight
+        .points
+        .iter()
+        .map(|&(lon, lat, altitude)| match altitude {
+            Some(altitude) if absolute => format!("{:.6},{:.6},{:.1}", lon, lat, altitude as f64 / FEET_PER_METRE),
+            _ => format!("{:.6},{:.6}", lon, lat),
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+<Document>
+<name>{title}</name>
+<Style id="track"><LineStyle><color>ff00aaff</color><width>3</width></LineStyle></Style>
+<Placemark>
+<name>{title}</name>
+<description>{icao24} from {begin} to {end}</description>
+<TimeSpan><begin>{begin}</begin><end>{end}</end></TimeSpan>
+<styleUrl>#track</styleUrl>
+<LineString>
+<altitudeMode>{mode}</altitudeMode>
+<coordinates>{coordinates}</coordinates>
+</LineString>
+</Placemark>
+</Document>
+</kml>
+"#,
+        title = escape(&title),
+        icao24 = escape(icao24),
+        begin = begin,
+        end = end,
+        mode = if absolute { "absolute" } else { "clampToGround" },
+        coordinates = coordinates.join(" "),
+    )
+}
+
+/// Escapes text for XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[async_trait]
+impl Sink for KmlSink {
+    fn name(&self) -> &'static str {
+        "kml"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let timeout = self.timeout.as_nanos() as i64;
+        let mut flights = self.flights.lock().unwrap();
+        let mut newest = 0;
+
+        for message in batch {
+            let Some(icao24) = message.icao24.map(|icao24| icao24.to_string()) else {
+                continue;
+            };
+            let timestamp: i64 = message.timestamp.parse().unwrap_or_default();
+            newest = newest.max(timestamp);
+
+            // An aircraft heard again after the timeout is on a new flight.
+            if flights.get(&icao24).is_some_and(|flight| timestamp - flight.last_seen > timeout) {
+                let flight = flights.remove(&icao24).unwrap();
+                self.finish(&icao24, flight)?;
+            }
+            let flight = flights.entry(icao24).or_insert_with(|| Flight { first_seen: timestamp, ..Default::default() });
+            flight.last_seen = flight.last_seen.max(timestamp);
+            if message.callsign.is_some() {
+                flight.callsign = message.callsign.clone();
+            }
+            if message.altitude_baro.is_some() {
+                flight.altitude = message.altitude_baro;
+            }
+            if let (Some(lat), Some(lon)) = (message.lat, message.lon) {
+                flight.points.push((lon, lat, flight.altitude));
+            }
+        }
+
+        let finished: Vec<String> = flights
+            .iter()
+            .filter(|(_, flight)| newest - flight.last_seen > timeout)
+            .map(|(icao24, _)| icao24.clone())
+            .collect();
+        for icao24 in finished {
+            let flight = flights.remove(&icao24).unwrap();
+            self.finish(&icao24, flight)?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        for (icao24, flight) in self.flights.lock().unwrap().drain() {
+            self.finish(&icao24, flight)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+//! This module defines sinks: destinations that batches of parsed messages are sent to.
+//!
+//! Each sink reads its own settings when it is created, so settings of sinks that are not
+//! selected are never required. Sinks are selected by name with `create`. Sinks with heavy
+//! dependencies are behind cargo features of the same name.
+
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod batched;
+pub mod bulkhead;
+pub mod clickhouse;
+pub mod csv;
+pub mod dataset;
+#[cfg(feature = "eventhubs")]
+pub mod eventhubs;
+pub mod geojson;
+pub mod influxdb;
+pub mod jsonl;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "kinesis")]
+pub mod kinesis;
+pub mod kml;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod otlp;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "pubsub")]
+pub mod pubsub;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sbs;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stdout;
+pub mod syslog;
+pub mod udp;
+#[cfg(unix)]
+pub mod unix;
+pub mod webhook;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "zeromq")]
+pub mod zeromq;
+
+use crate::buffer::{Batch, Buffer};
+use crate::parse::{ParseError, SBS1Message};
+use crate::stats::UploadStats;
+use crate::tracker::Tracker;
+use async_trait::async_trait;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// The error type returned by sinks.
+pub type SinkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Looks up a setting by name from the command line or environment, falling back to the
+/// default if one is given and exiting with an error otherwise.
+pub type Settings = fn(&str, Option<&str>) -> String;
+
+/// When a batch counts as sent, which decides whether a failure can lose it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Once it has been handed to the sinks. Sinks that send in the background drop what
+    /// still fails after retrying, so that an outage does not stop the collector. The
+    /// default.
+    AtMostOnce,
+    /// Once every sink has confirmed accepting it. A batch that still fails after retrying
+    /// stops sending, so that it is sent again (from QUEUE_DIR, after a restart) rather than
+    /// lost; destinations that cannot discard repeats may then receive it twice.
+    AtLeastOnce,
+}
+
+impl FromStr for Delivery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "at-most-once" => Ok(Delivery::AtMostOnce),
+            "at-least-once" => Ok(Delivery::AtLeastOnce),
+            _ => Err(format!("unknown delivery {:?} (expected at-most-once or at-least-once)", s)),
+        }
+    }
+}
+
+/// A destination for batches of parsed messages.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Returns the name the sink is selected by, for log messages.
+    fn name(&self) -> &'static str;
+
+    /// Sends a batch of messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The messages to send, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating the success or failure of the operation.
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError>;
+
+    /// Sends parse failures and their lines. Sinks that have no use for them ignore them.
+    async fn send_diagnostics(&self, _diagnostics: &[(ParseError, String)]) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Sends a batch of messages and parse failures with `send` and `send_diagnostics`.
+    /// Sinks that can discard what they have already received override it to key what they
+    /// send with the batch id, which stays the same when the batch is sent again.
+    async fn send_batch(&self, batch: &Batch) -> Result<(), SinkError> {
+        if !batch.messages.is_empty() {
+            self.send(&batch.messages).await?;
+        }
+        if !batch.diagnostics.is_empty() {
+            self.send_diagnostics(&batch.diagnostics).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns what the sink has uploaded so far, for sinks that keep count.
+    fn upload_stats(&self) -> Option<&UploadStats> {
+        None
+    }
+
+    /// Returns the queue the sink sends from, for sinks with a queue of their own.
+    fn queue(&self) -> Option<&Buffer> {
+        None
+    }
+
+    /// Flushes and closes any open output once the input has ended. Sinks that send each
+    /// batch completely have nothing to do.
+    async fn close(&self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// Creates the sink with the given name.
+///
+/// # Arguments
+///
+/// * `name` - The sink name, e.g. `dataset`.
+/// * `settings` - Looks up the sink's settings.
+/// * `tracker` - The tracker that keeps the state of each aircraft, for the sinks that
+///   serve it.
+///
+/// # Returns
+///
+/// A `Result` containing the sink, or an error if the name is unknown (or its feature is
+/// not enabled) or the sink could not be set up.
+// Without the `api` and `websocket` features, no sink needs the tracker.
+#[cfg_attr(not(any(feature = "api", feature = "websocket")), allow(unused_variables))]
+pub fn create(name: &str, settings: Settings, tracker: &Arc<Tracker>) -> Result<Box<dyn Sink>, SinkError> {
+    match name.trim().to_lowercase().as_str() {
+        #[cfg(feature = "amqp")]
+        "amqp" => Ok(Box::new(amqp::AmqpSink::from_settings(settings)?)),
+        #[cfg(feature = "api")]
+        "api" => Ok(Box::new(api::ApiSink::from_settings(settings, tracker.clone())?)),
+        "clickhouse" => Ok(Box::new(clickhouse::ClickHouseSink::from_settings(settings)?)),
+        "csv" => Ok(Box::new(csv::CsvSink::from_settings(settings)?)),
+        "dataset" => Ok(Box::new(dataset::DataSetSink::from_settings(settings)?)),
+        #[cfg(feature = "eventhubs")]
+        "eventhubs" => Ok(Box::new(eventhubs::EventHubsSink::from_settings(settings)?)),
+        "geojson" => Ok(Box::new(geojson::GeoJsonSink::from_settings(settings)?)),
+        "influxdb" => Ok(Box::new(influxdb::InfluxDbSink::from_settings(settings))),
+        "jsonl" => Ok(Box::new(jsonl::JsonLinesSink::from_settings(settings)?)),
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Box::new(kafka::KafkaSink::from_settings(settings)?)),
+        #[cfg(feature = "kinesis")]
+        "kinesis" => Ok(Box::new(kinesis::KinesisSink::from_settings(settings)?)),
+        "kml" => Ok(Box::new(kml::KmlSink::from_settings(settings)?)),
+        #[cfg(feature = "mqtt")]
+        "mqtt" => Ok(Box::new(mqtt::MqttSink::from_settings(settings)?)),
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Box::new(nats::NatsSink::from_settings(settings)?)),
+        "otlp" => Ok(Box::new(otlp::OtlpSink::from_settings(settings))),
+        #[cfg(feature = "parquet")]
+        "parquet" => Ok(Box::new(parquet::ParquetSink::from_settings(settings)?)),
+        #[cfg(feature = "prometheus")]
+        "prometheus" => Ok(Box::new(prometheus::PrometheusSink::from_settings(settings)?)),
+        #[cfg(feature = "pubsub")]
+        "pubsub" => Ok(Box::new(pubsub::PubSubSink::from_settings(settings)?)),
+        #[cfg(feature = "redis")]
+        "redis" => Ok(Box::new(redis::RedisSink::from_settings(settings)?)),
+        #[cfg(feature = "s3")]
+        "s3" => Ok(Box::new(s3::S3Sink::from_settings(settings)?)),
+        "sbs" => Ok(Box::new(sbs::SbsServerSink::from_settings(settings)?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Box::new(sqlite::SqliteSink::from_settings(settings)?)),
+        "stdout" => Ok(Box::new(stdout::StdoutSink::from_settings(settings)?)),
+        "syslog" => Ok(Box::new(syslog::SyslogSink::from_settings(settings)?)),
+        "udp" => Ok(Box::new(udp::UdpSink::from_settings(settings)?)),
+        #[cfg(unix)]
+        "unix" => Ok(Box::new(unix::UnixSocketSink::from_settings(settings)?)),
+        "webhook" => Ok(Box::new(webhook::WebhookSink::from_settings(settings)?)),
+        #[cfg(feature = "websocket")]
+        "websocket" => Ok(Box::new(websocket::WebSocketSink::from_settings(settings, tracker.clone())?)),
+        #[cfg(feature = "zeromq")]
+        "zeromq" => Ok(Box::new(zeromq::ZeroMqSink::from_settings(settings)?)),
+        other => Err(format!("unknown sink {:?} (or its cargo feature is not enabled)", other).into()),
+    }
+}
+
+/// Parses a comma-separated `key=value` list, as used for request headers and by the
+/// OTEL_* environment variables.
+pub(crate) fn key_values(list: &str) -> Vec<(String, String)> {
+    list.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+/// Listens for TCP clients and forwards everything sent on the returned channel to each of
+/// them, for sinks and outputs that serve a feed to other tools.
+///
+/// A client that falls too far behind skips the data it missed rather than slowing down
+/// the others.
+///
+/// # Arguments
+///
+/// * `address` - The address to listen on, e.g. `0.0.0.0:30103`.
+/// * `feed` - What is served, for log messages.
+///
+/// # Returns
+///
+/// A `Result` containing the sending half of the channel, or an error if the address could
+/// not be bound.
+pub fn serve(address: &str, feed: &'static str) -> Result<broadcast::Sender<Arc<[u8]>>, SinkError> {
+    let listener = std::net::TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    println!("Serving {} on {}", feed, address);
+
+    let (sender, _) = broadcast::channel(1024);
+    let clients = sender.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => forward_to_client(stream, clients.subscribe(), feed, peer.to_string()),
+                Err(err) => eprintln!("Error accepting {} client: {}", feed, err),
+            }
+        }
+    });
+    Ok(sender)
+}
+
+/// Like `serve`, but listens on a Unix domain socket, replacing a stale socket file left
+/// behind by an earlier run.
+///
+/// # Arguments
+///
+/// * `path` - The socket path, e.g. `/run/adsb/adsb.sock`.
+/// * `feed` - What is served, for log messages.
+///
+/// # Returns
+///
+/// A `Result` containing the sending half of the channel, or an error if the socket could
+/// not be bound.
+#[cfg(unix)]
+pub fn serve_unix(path: &str, feed: &'static str) -> Result<broadcast::Sender<Arc<[u8]>>, SinkError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::UnixListener::from_std(listener)?;
+    println!("Serving {} on {}", feed, path);
+
+    let (sender, _) = broadcast::channel(1024);
+    let clients = sender.clone();
+    let path = path.to_string();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => forward_to_client(stream, clients.subscribe(), feed, path.clone()),
+                Err(err) => eprintln!("Error accepting {} client: {}", feed, err),
+            }
+        }
+    });
+    Ok(sender)
+}
+
+/// Writes everything received on the channel to a client until it disconnects.
+fn forward_to_client<W>(mut stream: W, mut receiver: broadcast::Receiver<Arc<[u8]>>, feed: &'static str, peer: String)
+where
+    W: tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::broadcast::error::RecvError;
+
+    println!("{} client connected: {}", feed, peer);
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(data) => {
+                    if stream.write_all(&data).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => eprintln!("{} client {} fell behind; skipped {} writes", feed, peer, skipped),
+                Err(RecvError::Closed) => break,
+            }
+        }
+        println!("{} client disconnected: {}", feed, peer);
+    });
+}
@@ -0,0 +1,79 @@
+//! This module publishes messages to an MQTT broker (requires the `mqtt` feature).
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use std::time::Duration;
+
+/// Publishes each message as JSON to a per-aircraft topic, `{prefix}/{icao24}`.
+pub struct MqttSink {
+    client: AsyncClient,
+    topic_prefix: String,
+    qos: QoS,
+    retain: bool,
+}
+
+impl MqttSink {
+    /// Creates an MQTT sink and starts its connection in the background.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The broker address, client id and credentials.
+    /// * `topic_prefix` - The prefix of the per-aircraft topics, e.g. `adsb`.
+    /// * `qos` - The quality of service to publish with.
+    /// * `retain` - Whether the broker keeps the latest message of each aircraft for new
+    ///   subscribers.
+    pub fn new(options: MqttOptions, topic_prefix: &str, qos: QoS, retain: bool) -> Self {
+        let (client, mut eventloop) = AsyncClient::new(options, 1024);
+        // The event loop performs the actual network I/O and reconnects when polled again
+        // after an error.
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = eventloop.poll().await {
+                    eprintln!("MQTT connection error: {}", err);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        });
+        MqttSink { client, topic_prefix: topic_prefix.trim_end_matches('/').to_string(), qos, retain }
+    }
+
+    /// Creates an MQTT sink from MQTT_HOST, MQTT_PORT, MQTT_CLIENT_ID, MQTT_USERNAME,
+    /// MQTT_PASSWORD, MQTT_TOPIC_PREFIX, MQTT_QOS and MQTT_RETAIN.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let port: u16 = settings("MQTT_PORT", Some("1883")).parse()?;
+        let mut options = MqttOptions::new(settings("MQTT_CLIENT_ID", Some("adsb-rust-dataset")), settings("MQTT_HOST", None), port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let username = settings("MQTT_USERNAME", Some(""));
+        if !username.is_empty() {
+            options.set_credentials(username, settings("MQTT_PASSWORD", Some("")));
+        }
+        let qos = match settings("MQTT_QOS", Some("0")).as_str() {
+            "0" => QoS::AtMostOnce,
+            "1" => QoS::AtLeastOnce,
+            "2" => QoS::ExactlyOnce,
+            other => return Err(format!("invalid MQTT QoS {:?} (expected 0, 1 or 2)", other).into()),
+        };
+        let retain: bool = settings("MQTT_RETAIN", Some("false")).parse()?;
+        Ok(Self::new(options, &settings("MQTT_TOPIC_PREFIX", Some("adsb")), qos, retain))
+    }
+}
+
+#[async_trait]
+impl Sink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        // Messages without an address have no topic to go to.
+        for message in batch {
+            if let Some(icao24) = message.icao24 {
+                let topic = format!("{}/{}", self.topic_prefix, icao24);
+                self.client.publish(topic, self.qos, self.retain, serde_json::to_vec(message)?).await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! This module publishes messages to NATS, optionally persisting them with JetStream
+//! (requires the `nats` feature).
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_nats::{jetstream, Client, ConnectOptions};
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+/// How the sink authenticates to the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    None,
+    /// A `.creds` file holding a user JWT and NKey seed.
+    File(String),
+    Token(String),
+    UserPassword(String, String),
+}
+
+/// Publishes each message as JSON to a subject derived from the aircraft.
+pub struct NatsSink {
+    url: String,
+    /// The subject, in which `{icao24}` and `{collector}` are filled in.
+    subject: String,
+    collector: String,
+    credentials: Credentials,
+    /// Whether to publish through JetStream and wait for the server to store each message.
+    jetstream: bool,
+    /// The JetStream stream to create, if it does not exist, covering the subject.
+    stream: Option<String>,
+    /// The client, created when the first batch is sent. It reconnects by itself.
+    client: Mutex<Option<Client>>,
+}
+
+impl NatsSink {
+    /// Creates a NATS sink. The connection is opened when the first batch is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The server URL, e.g. `nats://localhost:4222`.
+    /// * `subject` - The subject, in which `{icao24}` and `{collector}` are filled in.
+    /// * `collector` - The collector name for the subject.
+    /// * `credentials` - How to authenticate.
+    /// * `jetstream` - Whether to publish through JetStream and wait for acknowledgements.
+    /// * `stream` - The JetStream stream to create for the subject, if any.
+    pub fn new(url: &str, subject: &str, collector: &str, credentials: Credentials, jetstream: bool, stream: Option<String>) -> Self {
+        NatsSink {
+            url: url.to_string(),
+            subject: subject.to_string(),
+            collector: collector.to_string(),
+            credentials,
+            jetstream,
+            stream,
+            client: Mutex::new(None),
+        }
+    }
+
+    /// Creates a NATS sink from NATS_URL, NATS_SUBJECT, NATS_CREDS_FILE, NATS_TOKEN,
+    /// NATS_USER, NATS_PASSWORD, NATS_JETSTREAM, NATS_STREAM and 1090_COLLECTOR.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let creds_file = settings("NATS_CREDS_FILE", Some(""));
+        let token = settings("NATS_TOKEN", Some(""));
+        let user = settings("NATS_USER", Some(""));
+        let credentials = if !creds_file.is_empty() {
+            Credentials::File(creds_file)
+        } else if !token.is_empty() {
+            Credentials::Token(token)
+        } else if !user.is_empty() {
+            Credentials::UserPassword(user, settings("NATS_PASSWORD", Some("")))
+        } else {
+            Credentials::None
+        };
+        let stream = settings("NATS_STREAM", Some(""));
+        let jetstream = settings("NATS_JETSTREAM", Some("false")).parse::<bool>()? || !stream.is_empty();
+
+        Ok(Self::new(
+            &settings("NATS_URL", Some("nats://localhost:4222")),
+            &settings("NATS_SUBJECT", Some("adsb.{icao24}")),
+            &settings("1090_COLLECTOR", Some("dump1090")),
+            credentials,
+            jetstream,
+            Some(stream).filter(|stream| !stream.is_empty()),
+        ))
+    }
+
+    /// Fills in the subject for a message.
+    fn subject(&self, message: &SBS1Message) -> String {
+        let icao24 = message.icao24.map(|icao24| icao24.to_string()).unwrap_or_else(|| "unknown".to_string());
+        self.subject.replace("{icao24}", &icao24).replace("{collector}", &self.collector)
+    }
+
+    /// Connects to the server and, if configured, creates the JetStream stream.
+    async fn connect(&self) -> Result<Client, SinkError> {
+        let mut options = ConnectOptions::new().name("adsb-rust-dataset");
+        options = match &self.credentials {
+            Credentials::None => options,
+            Credentials::File(path) => options.credentials_file(path).await?,
+            Credentials::Token(token) => options.token(token.clone()),
+            Credentials::UserPassword(user, password) => options.user_and_password(user.clone(), password.clone()),
+        };
+        let client = options.connect(&self.url).await?;
+
+        if let Some(stream) = &self.stream {
+            // Capture every subject the template can produce.
+            let subject = self.subject.replace("{icao24}", "*").replace("{collector}", "*");
+            jetstream::new(client.clone())
+                .get_or_create_stream(jetstream::stream::Config {
+                    name: stream.clone(),
+                    subjects: vec![subject],
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Ok(client)
+    }
+}
+
+#[async_trait]
+impl Sink for NatsSink {
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let client = {
+            let mut client = self.client.lock().await;
+            if client.is_none() {
+                *client = Some(self.connect().await?);
+            }
+            client.as_ref().unwrap().clone()
+        };
+
+        if self.jetstream {
+            // Publish the whole batch before waiting, so acknowledgements arrive together.
+            let context = jetstream::new(client);
+            let mut acks = Vec::with_capacity(batch.len());
+            for message in batch {
+                acks.push(context.publish(self.subject(message), serde_json::to_vec(message)?.into()).await?);
+            }
+            for ack in acks {
+                ack.await?;
+            }
+        } else {
+            for message in batch {
+                client.publish(self.subject(message), serde_json::to_vec(message)?.into()).await?;
+            }
+            client.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        if let Some(client) = self.client.lock().await.take() {
+            client.flush().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! This module exports messages as OpenTelemetry log records over OTLP/HTTP with JSON
+//! encoding, which any OpenTelemetry collector and most observability backends accept.
+
+use super::{key_values, Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use serde_json::{json, Map, Value};
+
+/// The OTLP severity number for INFO.
+const SEVERITY_INFO: u8 = 9;
+
+/// Sends each batch as one OTLP logs export request.
+pub struct OtlpSink {
+    /// The full logs endpoint, e.g. `http://localhost:4318/v1/logs`.
+    url: String,
+    /// Extra request headers, e.g. for authentication.
+    headers: Vec<(String, String)>,
+    /// Resource attributes describing the receiver, in OTLP JSON form.
+    resource: Vec<Value>,
+}
+
+impl OtlpSink {
+    /// Creates an OTLP logs sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The OTLP/HTTP base endpoint, e.g. `http://localhost:4318`.
+    /// * `headers` - Extra request headers.
+    /// * `resource` - Resource attributes as (key, value) pairs.
+    pub fn new(endpoint: &str, headers: Vec<(String, String)>, resource: Vec<(String, String)>) -> Self {
+        OtlpSink {
+            url: format!("{}/v1/logs", endpoint.trim_end_matches('/')),
+            headers,
+            resource: resource.into_iter().map(|(key, value)| attribute(&key, Value::String(value))).collect(),
+        }
+    }
+
+    /// Creates an OTLP sink from OTLP_ENDPOINT, OTLP_HEADERS and OTEL_RESOURCE_ATTRIBUTES
+    /// (both comma-separated `key=value` lists), 1090_COLLECTOR, RECEIVER_LAT and
+    /// RECEIVER_LON.
+    pub fn from_settings(settings: Settings) -> Self {
+        let mut resource = vec![
+            ("service.name".to_string(), "adsb-rust-dataset".to_string()),
+            ("adsb.collector".to_string(), settings("1090_COLLECTOR", Some("dump1090"))),
+        ];
+        let receiver_lat = settings("RECEIVER_LAT", Some(""));
+        let receiver_lon = settings("RECEIVER_LON", Some(""));
+        if !receiver_lat.is_empty() && !receiver_lon.is_empty() {
+            resource.push(("adsb.receiver.lat".to_string(), receiver_lat));
+            resource.push(("adsb.receiver.lon".to_string(), receiver_lon));
+        }
+        resource.extend(key_values(&settings("OTEL_RESOURCE_ATTRIBUTES", Some(""))));
+        Self::new(&settings("OTLP_ENDPOINT", Some("http://localhost:4318")), key_values(&settings("OTLP_HEADERS", Some(""))), resource)
+    }
+
+    /// Converts a message to an OTLP log record with the message fields as its body.
+    fn log_record(&self, message: &SBS1Message) -> Value {
+        let body = match serde_json::to_value(message) {
+            Ok(Value::Object(fields)) => any_value(Value::Object(fields)),
+            _ => json!({"stringValue": ""}),
+        };
+        let mut attributes = Vec::new();
+        if let Some(icao24) = message.icao24 {
+            attributes.push(attribute("adsb.icao24", Value::String(icao24.to_string())));
+        }
+        json!({
+            "timeUnixNano": message.timestamp,
+            "observedTimeUnixNano": message.timestamp,
+            "severityNumber": SEVERITY_INFO,
+            "severityText": "INFO",
+            "body": body,
+            "attributes": attributes,
+        })
+    }
+}
+
+/// Builds an OTLP `KeyValue`.
+fn attribute(key: &str, value: Value) -> Value {
+    json!({"key": key, "value": any_value(value)})
+}
+
+/// Converts a JSON value to an OTLP `AnyValue`.
+fn any_value(value: Value) -> Value {
+    match value {
+        Value::Null => json!({}),
+        Value::Bool(value) => json!({"boolValue": value}),
+        // 64-bit integers are strings in OTLP JSON.
+        Value::Number(number) if number.is_i64() => json!({"intValue": number.to_string()}),
+        Value::Number(number) => json!({"doubleValue": number.as_f64()}),
+        Value::String(value) => json!({"stringValue": value}),
+        Value::Array(values) => json!({"arrayValue": {"values": values.into_iter().map(any_value).collect::<Vec<_>>()}}),
+        Value::Object(fields) => {
+            let values: Vec<Value> = fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| attribute(&key, value))
+                .collect();
+            json!({"kvlistValue": {"values": values}})
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for OtlpSink {
+    fn name(&self) -> &'static str {
+        "otlp"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut scope = Map::new();
+        scope.insert("scope".to_string(), json!({"name": "adsb-rust-dataset", "version": env!("CARGO_PKG_VERSION")}));
+        scope.insert("logRecords".to_string(), Value::Array(batch.iter().map(|message| self.log_record(message)).collect()));
+        let payload = json!({
+            "resourceLogs": [{
+                "resource": {"attributes": self.resource},
+                "scopeLogs": [scope],
+            }]
+        });
+
+        let client = reqwest::Client::new();
+        let mut request = client.post(&self.url).json(&payload);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
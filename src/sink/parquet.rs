@@ -0,0 +1,197 @@
+//! This module writes messages to Parquet files partitioned by hour or day (requires the
+//! `parquet` feature).
+//!
+//! Files are laid out Hive-style, e.g. `{dir}/date=2024-01-31/hour=13/part-...parquet`, so
+//! DuckDB and Athena can prune partitions. A file is only complete, and readable, once it
+//! is closed: when messages for a later partition arrive or when the sink is closed.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use arrow_array::builder::{BooleanBuilder, Float32Builder, Float64Builder, Int32Builder, StringBuilder, TimestampNanosecondBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// The partition file currently being written.
+struct Partition {
+    /// The partition directory relative to the output directory, e.g. `date=2024-01-31/hour=13`.
+    key: String,
+    writer: ArrowWriter<File>,
+}
+
+/// Buffers messages into row groups and writes them to partitioned Parquet files.
+pub struct ParquetSink {
+    dir: PathBuf,
+    /// Whether files are partitioned by hour as well as day.
+    hourly: bool,
+    schema: SchemaRef,
+    properties: WriterProperties,
+    current: Mutex<Option<Partition>>,
+}
+
+impl ParquetSink {
+    /// Creates a Parquet sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory the partitions are created in.
+    /// * `hourly` - Whether to partition by hour as well as day.
+    /// * `row_group_size` - The number of rows buffered per row group.
+    pub fn new(dir: &str, hourly: bool, row_group_size: usize) -> Self {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false),
+            Field::new("icao24", DataType::Utf8, true),
+            Field::new("transmission_type", DataType::Int32, true),
+            Field::new("callsign", DataType::Utf8, true),
+            Field::new("altitude_baro", DataType::Int32, true),
+            Field::new("altitude_geom", DataType::Int32, true),
+            Field::new("ground_speed", DataType::Float32, true),
+            Field::new("track", DataType::Float32, true),
+            Field::new("lat", DataType::Float64, true),
+            Field::new("lon", DataType::Float64, true),
+            Field::new("vertical_rate", DataType::Int32, true),
+            Field::new("squawk", DataType::Int32, true),
+            Field::new("on_ground", DataType::Boolean, true),
+            Field::new("source_type", DataType::Utf8, true),
+            Field::new("mlat", DataType::Boolean, false),
+        ]));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(row_group_size)
+            .build();
+        ParquetSink { dir: PathBuf::from(dir), hourly, schema, properties, current: Mutex::new(None) }
+    }
+
+    /// Creates a Parquet sink from PARQUET_DIR, PARQUET_PARTITION (`hour` or `day`) and
+    /// PARQUET_ROW_GROUP_SIZE.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let hourly = match settings("PARQUET_PARTITION", Some("hour")).as_str() {
+            "hour" => true,
+            "day" => false,
+            other => return Err(format!("invalid Parquet partitioning {:?} (expected hour or day)", other).into()),
+        };
+        let row_group_size: usize = settings("PARQUET_ROW_GROUP_SIZE", Some("100000")).parse()?;
+        Ok(Self::new(&settings("PARQUET_DIR", Some("parquet")), hourly, row_group_size))
+    }
+
+    /// Returns the partition a message belongs to, by its timestamp in UTC.
+    fn partition_key(&self, message: &SBS1Message) -> String {
+        let time = DateTime::from_timestamp_nanos(message.timestamp.parse().unwrap_or_default());
+        if self.hourly {
+            time.format("date=%Y-%m-%d/hour=%H").to_string()
+        } else {
+            time.format("date=%Y-%m-%d").to_string()
+        }
+    }
+
+    /// Opens a new file in the partition `key`.
+    fn open(&self, key: &str) -> Result<Partition, SinkError> {
+        let dir = self.dir.join(key);
+        std::fs::create_dir_all(&dir)?;
+        let file = File::create(dir.join(format!("part-{}.parquet", Local::now().format("%Y%m%d%H%M%S%3f"))))?;
+        let writer = ArrowWriter::try_new(file, self.schema.clone(), Some(self.properties.clone()))?;
+        Ok(Partition { key: key.to_string(), writer })
+    }
+
+    /// Converts messages to a record batch.
+    fn record_batch(&self, messages: &[&SBS1Message]) -> Result<RecordBatch, SinkError> {
+        let mut timestamp = TimestampNanosecondBuilder::new().with_timezone("UTC");
+        let mut icao24 = StringBuilder::new();
+        let mut transmission_type = Int32Builder::new();
+        let mut callsign = StringBuilder::new();
+        let mut altitude_baro = Int32Builder::new();
+        let mut altitude_geom = Int32Builder::new();
+        let mut ground_speed = Float32Builder::new();
+        let mut track = Float32Builder::new();
+        let mut lat = Float64Builder::new();
+        let mut lon = Float64Builder::new();
+        let mut vertical_rate = Int32Builder::new();
+        let mut squawk = Int32Builder::new();
+        let mut on_ground = BooleanBuilder::new();
+        let mut source_type = StringBuilder::new();
+        let mut mlat = BooleanBuilder::new();
+        for message in messages {
+            timestamp.append_value(message.timestamp.parse().unwrap_or_default());
+            icao24.append_option(message.icao24.map(|icao24| icao24.to_string()));
+            transmission_type.append_option(message.transmission_type);
+            callsign.append_option(message.callsign.as_deref());
+            altitude_baro.append_option(message.altitude_baro);
+            altitude_geom.append_option(message.altitude_geom);
+            ground_speed.append_option(message.ground_speed);
+            track.append_option(message.track);
+            lat.append_option(message.lat);
+            lon.append_option(message.lon);
+            vertical_rate.append_option(message.vertical_rate);
+            squawk.append_option(message.squawk);
+            on_ground.append_option(message.on_ground);
+            source_type.append_option(message.source_type.as_deref());
+            mlat.append_value(message.mlat);
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(timestamp.finish()),
+            Arc::new(icao24.finish()),
+            Arc::new(transmission_type.finish()),
+            Arc::new(callsign.finish()),
+            Arc::new(altitude_baro.finish()),
+            Arc::new(altitude_geom.finish()),
+            Arc::new(ground_speed.finish()),
+            Arc::new(track.finish()),
+            Arc::new(lat.finish()),
+            Arc::new(lon.finish()),
+            Arc::new(vertical_rate.finish()),
+            Arc::new(squawk.finish()),
+            Arc::new(on_ground.finish()),
+            Arc::new(source_type.finish()),
+            Arc::new(mlat.finish()),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    fn name(&self) -> &'static str {
+        "parquet"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut current = self.current.lock().unwrap();
+        // Write runs of consecutive messages in the same partition, switching files when
+        // the partition changes.
+        let mut start = 0;
+        while start < batch.len() {
+            let key = self.partition_key(&batch[start]);
+            let end = batch[start..]
+                .iter()
+                .position(|message| self.partition_key(message) != key)
+                .map_or(batch.len(), |offset| start + offset);
+
+            if current.as_ref().is_some_and(|partition| partition.key != key) {
+                current.take().unwrap().writer.close()?;
+            }
+            if current.is_none() {
+                *current = Some(self.open(&key)?);
+            }
+            let rows: Vec<&SBS1Message> = batch[start..end].iter().collect();
+            let record_batch = self.record_batch(&rows)?;
+            current.as_mut().unwrap().writer.write(&record_batch)?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        if let Some(partition) = self.current.lock().unwrap().take() {
+            partition.writer.close()?;
+        }
+        Ok(())
+    }
+}
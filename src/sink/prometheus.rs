@@ -0,0 +1,165 @@
+//! This module pushes aggregated airspace metrics to a Prometheus remote-write endpoint
+//! (requires the `prometheus` feature).
+//!
+//! Unlike the other sinks it does not forward messages, only a few time series summarizing
+//! each batch: aircraft in view, message rate, and maximum range from the receiver.
+
+use super::{Settings, Sink, SinkError};
+use crate::geo::distance_nm;
+use crate::parse::{Icao24, SBS1Message};
+use async_trait::async_trait;
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How recently an aircraft must have been heard to count as in view.
+const IN_VIEW_WINDOW: Duration = Duration::from_secs(60);
+
+/// The remote-write `WriteRequest` protobuf message.
+#[derive(Clone, PartialEq, prost::Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds since the UNIX epoch.
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// What is remembered between batches.
+struct Aggregate {
+    /// When each aircraft was last heard.
+    last_seen: HashMap<Icao24, Instant>,
+    /// When the previous batch was pushed.
+    last_push: Instant,
+}
+
+/// Pushes `adsb_aircraft_in_view`, `adsb_messages_per_second` and, with a receiver
+/// position, `adsb_max_range_nm` after every batch.
+pub struct PrometheusSink {
+    url: String,
+    bearer_token: Option<String>,
+    collector: String,
+    /// The receiver's (latitude, longitude), needed for the range metric.
+    receiver: Option<(f64, f64)>,
+    state: Mutex<Aggregate>,
+}
+
+impl PrometheusSink {
+    /// Creates a Prometheus remote-write sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The remote-write endpoint, e.g. `http://localhost:9090/api/v1/write`.
+    /// * `bearer_token` - A token to authenticate with, if the endpoint needs one.
+    /// * `collector` - The collector (or source) identifier, added as a label.
+    /// * `receiver` - The receiver's (latitude, longitude), if known.
+    pub fn new(url: &str, bearer_token: Option<String>, collector: &str, receiver: Option<(f64, f64)>) -> Self {
+        PrometheusSink {
+            url: url.to_string(),
+            bearer_token,
+            collector: collector.to_string(),
+            receiver,
+            state: Mutex::new(Aggregate { last_seen: HashMap::new(), last_push: Instant::now() }),
+        }
+    }
+
+    /// Creates a Prometheus sink from PROMETHEUS_REMOTE_WRITE_URL,
+    /// PROMETHEUS_BEARER_TOKEN, 1090_COLLECTOR, RECEIVER_LAT and RECEIVER_LON.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let bearer_token = Some(settings("PROMETHEUS_BEARER_TOKEN", Some(""))).filter(|token| !token.is_empty());
+        let receiver_lat = settings("RECEIVER_LAT", Some(""));
+        let receiver_lon = settings("RECEIVER_LON", Some(""));
+        let receiver = if receiver_lat.is_empty() || receiver_lon.is_empty() {
+            None
+        } else {
+            Some((receiver_lat.parse()?, receiver_lon.parse()?))
+        };
+        Ok(Self::new(&settings("PROMETHEUS_REMOTE_WRITE_URL", None), bearer_token, &settings("1090_COLLECTOR", Some("dump1090")), receiver))
+    }
+
+    /// Builds a single-sample series labelled with the collector.
+    fn series(&self, name: &str, value: f64, timestamp: i64) -> TimeSeries {
+        // Labels must be sorted by name.
+        TimeSeries {
+            labels: vec![
+                Label { name: "__name__".to_string(), value: name.to_string() },
+                Label { name: "collector".to_string(), value: self.collector.clone() },
+            ],
+            samples: vec![Sample { value, timestamp }],
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for PrometheusSink {
+    fn name(&self) -> &'static str {
+        "prometheus"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let now = Instant::now();
+        let (in_view, elapsed) = {
+            let mut state = self.state.lock().unwrap();
+            for icao24 in batch.iter().filter_map(|message| message.icao24) {
+                state.last_seen.insert(icao24, now);
+            }
+            state.last_seen.retain(|_, seen| now - *seen <= IN_VIEW_WINDOW);
+            let elapsed = now - state.last_push;
+            state.last_push = now;
+            (state.last_seen.len(), elapsed)
+        };
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        let mut timeseries = vec![
+            self.series("adsb_aircraft_in_view", in_view as f64, timestamp),
+            self.series("adsb_messages_per_second", batch.len() as f64 / elapsed.as_secs_f64().max(0.001), timestamp),
+        ];
+        if let Some(receiver) = self.receiver {
+            let max_range = batch
+                .iter()
+                .filter_map(|message| Some((message.lat?, message.lon?)))
+                .map(|position| distance_nm(receiver, position))
+                .fold(None, |max: Option<f64>, range| Some(max.map_or(range, |max| max.max(range))));
+            if let Some(max_range) = max_range {
+                timeseries.push(self.series("adsb_max_range_nm", max_range, timestamp));
+            }
+        }
+
+        let body = snap::raw::Encoder::new().compress_vec(&WriteRequest { timeseries }.encode_to_vec())?;
+        let client = reqwest::Client::new();
+        let mut request = client.post(&self.url)
+            .header("Content-Type", "application/x-protobuf")
+            .header("Content-Encoding", "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body);
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! This module publishes messages to a Google Cloud Pub/Sub topic through its REST API
+//! (requires the `pubsub` feature).
+//!
+//! Requests are authenticated with an OAuth access token obtained by signing a JWT with a
+//! service account key. When PUBSUB_EMULATOR_HOST is set, as for Google's own client
+//! libraries, messages go to the emulator without authentication.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The OAuth scope needed to publish.
+const SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
+
+/// The most messages Pub/Sub accepts in one publish request.
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// The fields used from a service account key file.
+#[derive(Deserialize)]
+struct ServiceAccount {
+    client_email: String,
+    private_key: String,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+/// The claims of the JWT exchanged for an access token.
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+/// The token endpoint's response.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Publishes each message as JSON, in requests of up to `batch_size` messages.
+pub struct PubSubSink {
+    /// The topic's publish URL.
+    url: String,
+    /// The service account to authenticate as; `None` for the emulator.
+    account: Option<ServiceAccount>,
+    /// Whether to set each message's ordering key to its ICAO address.
+    ordering: bool,
+    batch_size: usize,
+    collector: String,
+    /// The current access token and when it should be renewed.
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl PubSubSink {
+    /// Creates a Pub/Sub sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The API endpoint, e.g. `https://pubsub.googleapis.com`.
+    /// * `project` - The project ID.
+    /// * `topic` - The topic ID.
+    /// * `credentials` - The path of a service account key file, or `None` for the emulator.
+    /// * `ordering` - Whether to set each message's ordering key to its ICAO address.
+    /// * `batch_size` - The most messages to send in one request (at most 1000).
+    /// * `collector` - The collector name, sent as the `collector` attribute.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the key file could not be read.
+    pub fn new(
+        endpoint: &str,
+        project: &str,
+        topic: &str,
+        credentials: Option<&str>,
+        ordering: bool,
+        batch_size: usize,
+        collector: &str,
+    ) -> Result<Self, SinkError> {
+        let account = match credentials {
+            Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+            None => None,
+        };
+        Ok(PubSubSink {
+            url: format!("{}/v1/projects/{}/topics/{}:publish", endpoint.trim_end_matches('/'), project, topic),
+            account,
+            ordering,
+            batch_size: batch_size.clamp(1, MAX_BATCH_SIZE),
+            collector: collector.to_string(),
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Creates a Pub/Sub sink from PUBSUB_PROJECT, PUBSUB_TOPIC, PUBSUB_ORDERING,
+    /// PUBSUB_BATCH_SIZE, GOOGLE_APPLICATION_CREDENTIALS, PUBSUB_EMULATOR_HOST and
+    /// 1090_COLLECTOR.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let emulator = settings("PUBSUB_EMULATOR_HOST", Some(""));
+        let (endpoint, credentials) = if emulator.is_empty() {
+            ("https://pubsub.googleapis.com".to_string(), Some(settings("GOOGLE_APPLICATION_CREDENTIALS", None)))
+        } else {
+            (format!("http://{}", emulator), None)
+        };
+        Self::new(
+            &endpoint,
+            &settings("PUBSUB_PROJECT", None),
+            &settings("PUBSUB_TOPIC", Some("adsb")),
+            credentials.as_deref(),
+            settings("PUBSUB_ORDERING", Some("true")).parse()?,
+            settings("PUBSUB_BATCH_SIZE", Some("1000")).parse()?,
+            &settings("1090_COLLECTOR", Some("dump1090")),
+        )
+    }
+
+    /// Returns a valid access token, fetching a new one when the current one is about to expire.
+    async fn access_token(&self, client: &reqwest::Client, account: &ServiceAccount) -> Result<String, SinkError> {
+        if let Some((token, renew_at)) = self.token.lock().unwrap().as_ref() {
+            if Instant::now() < *renew_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let claims = Claims { iss: &account.client_email, scope: SCOPE, aud: &account.token_uri, iat: now, exp: now + 3600 };
+        let assertion = jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &EncodingKey::from_rsa_pem(account.private_key.as_bytes())?,
+        )?;
+        let response: TokenResponse = client
+            .post(&account.token_uri)
+            .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", &assertion)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // Renew a minute early so a token never expires mid-request.
+        let renew_at = Instant::now() + Duration::from_secs(response.expires_in.saturating_sub(60));
+        *self.token.lock().unwrap() = Some((response.access_token.clone(), renew_at));
+        Ok(response.access_token)
+    }
+
+    /// Converts a message to a Pub/Sub message with the JSON as its data.
+    fn pubsub_message(&self, message: &SBS1Message) -> Result<Value, SinkError> {
+        let data = base64::engine::general_purpose::STANDARD.encode(serde_json::to_vec(message)?);
+        let mut pubsub_message = json!({"data": data, "attributes": {"collector": self.collector}});
+        if let Some(icao24) = message.icao24 {
+            pubsub_message["attributes"]["icao24"] = json!(icao24.to_string());
+            if self.ordering {
+                pubsub_message["orderingKey"] = json!(icao24.to_string());
+            }
+        }
+        Ok(pubsub_message)
+    }
+}
+
+#[async_trait]
+impl Sink for PubSubSink {
+    fn name(&self) -> &'static str {
+        "pubsub"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let client = reqwest::Client::new();
+        for chunk in batch.chunks(self.batch_size) {
+            let messages = chunk.iter().map(|message| self.pubsub_message(message)).collect::<Result<Vec<_>, _>>()?;
+            let mut request = client.post(&self.url).json(&json!({"messages": messages}));
+            if let Some(account) = &self.account {
+                request = request.bearer_auth(self.access_token(&client, account).await?);
+            }
+            request.send().await?.error_for_status()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! This module appends messages to a Redis stream (requires the `redis` feature).
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+/// Appends each message to a stream with XADD, one stream field per message field.
+pub struct RedisSink {
+    url: String,
+    /// The stream key.
+    key: String,
+    /// The approximate number of entries to keep in the stream; 0 keeps everything.
+    max_len: u64,
+    /// The connection, opened when the first batch is sent and reopened after an error.
+    connection: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisSink {
+    /// Creates a Redis Streams sink. The connection is opened when the first batch is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The server URL, e.g. `redis://localhost:6379`.
+    /// * `key` - The stream key.
+    /// * `max_len` - The approximate number of entries to keep in the stream; 0 keeps everything.
+    pub fn new(url: &str, key: &str, max_len: u64) -> Self {
+        RedisSink { url: url.to_string(), key: key.to_string(), max_len, connection: Mutex::new(None) }
+    }
+
+    /// Creates a Redis Streams sink from REDIS_URL, REDIS_STREAM and REDIS_MAXLEN.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Ok(Self::new(
+            &settings("REDIS_URL", Some("redis://localhost:6379")),
+            &settings("REDIS_STREAM", Some("adsb")),
+            settings("REDIS_MAXLEN", Some("100000")).parse()?,
+        ))
+    }
+
+    /// Builds the XADD commands for a batch as one pipeline.
+    fn pipeline(&self, batch: &[SBS1Message]) -> Result<redis::Pipeline, SinkError> {
+        let mut pipeline = redis::pipe();
+        for message in batch {
+            let command = pipeline.cmd("XADD").arg(&self.key);
+            if self.max_len > 0 {
+                // `~` lets Redis trim whole macro nodes, which is much cheaper than exact trimming.
+                command.arg("MAXLEN").arg("~").arg(self.max_len);
+            }
+            command.arg("*");
+            if let Value::Object(fields) = serde_json::to_value(message)? {
+                for (name, value) in fields {
+                    let value = match value {
+                        Value::Null => continue,
+                        Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    command.arg(name).arg(value);
+                }
+            }
+            command.ignore();
+        }
+        Ok(pipeline)
+    }
+}
+
+#[async_trait]
+impl Sink for RedisSink {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let pipeline = self.pipeline(batch)?;
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            let client = redis::Client::open(self.url.as_str())?;
+            *connection = Some(client.get_multiplexed_async_connection().await?);
+        }
+        let result = pipeline.query_async::<()>(connection.as_mut().unwrap()).await;
+        if result.is_err() {
+            // Reconnect on the next batch.
+            *connection = None;
+        }
+        Ok(result?)
+    }
+}
@@ -0,0 +1,203 @@
+//! This module archives messages as gzipped JSON Lines objects in S3 or an S3-compatible
+//! store such as MinIO (requires the `s3` feature).
+//!
+//! Batches are accumulated into one compressed object until it reaches a size or age limit,
+//! so the store is not flooded with small objects. The age is checked as batches arrive, so
+//! on a quiet feed an object waits for the next batch or for shutdown. A finished object is
+//! kept until it has been uploaded, and one whose upload failed is retried, under the same
+//! key, with the next batch.
+
+use super::{Settings, Sink, SinkError};
+use crate::buffer::Batch;
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, WriteMultipart};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Objects larger than this are uploaded in parts.
+const MULTIPART_THRESHOLD: usize = 8 * 1024 * 1024;
+
+/// The object being accumulated.
+struct Pending {
+    encoder: GzEncoder<Vec<u8>>,
+    /// How many messages the object holds.
+    messages: usize,
+    started: Instant,
+}
+
+impl Pending {
+    fn new() -> Self {
+        Pending { encoder: GzEncoder::new(Vec::new(), Compression::default()), messages: 0, started: Instant::now() }
+    }
+}
+
+/// A finished object waiting to be uploaded.
+struct Object {
+    key: Path,
+    body: Vec<u8>,
+    /// How many messages the object holds.
+    messages: usize,
+}
+
+/// The object being accumulated and the finished ones not yet uploaded.
+struct State {
+    pending: Pending,
+    /// Finished objects, oldest first. Each stays until its upload succeeds.
+    unsent: VecDeque<Object>,
+    /// The id of the last batch added, so that a batch sent again after a failed upload is
+    /// not added twice.
+    last: Option<Uuid>,
+}
+
+/// Uploads accumulated messages as `{prefix}{time}-{uuid}.jsonl.gz` objects.
+pub struct S3Sink {
+    store: Box<dyn ObjectStore>,
+    /// The key prefix, with `{date}`, `{hour}` and `{receiver}` placeholders.
+    prefix: String,
+    receiver: String,
+    /// The compressed size after which an object is uploaded.
+    max_bytes: usize,
+    /// The age after which an object is uploaded.
+    max_age: Duration,
+    state: Mutex<State>,
+}
+
+impl S3Sink {
+    /// Creates an S3 sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The bucket to upload to.
+    /// * `prefix` - The key prefix, with `{date}`, `{hour}` and `{receiver}` placeholders.
+    /// * `receiver` - The value of the `{receiver}` placeholder.
+    /// * `max_bytes` - The compressed size after which an object is uploaded.
+    /// * `max_age` - The age after which an object is uploaded.
+    pub fn new(store: Box<dyn ObjectStore>, prefix: &str, receiver: &str, max_bytes: usize, max_age: Duration) -> Self {
+        S3Sink {
+            store,
+            prefix: prefix.to_string(),
+            receiver: receiver.to_string(),
+            max_bytes,
+            max_age,
+            state: Mutex::new(State { pending: Pending::new(), unsent: VecDeque::new(), last: None }),
+        }
+    }
+
+    /// Creates an S3 sink from S3_BUCKET, S3_PREFIX, S3_REGION, S3_ENDPOINT,
+    /// S3_OBJECT_BYTES, S3_OBJECT_SECONDS and 1090_COLLECTOR. Credentials are read from the
+    /// usual AWS_* environment variables.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(settings("S3_BUCKET", None))
+            .with_region(settings("S3_REGION", Some("us-east-1")));
+        let endpoint = settings("S3_ENDPOINT", Some(""));
+        if !endpoint.is_empty() {
+            // MinIO and most other S3-compatible stores are addressed by path, often over HTTP.
+            builder = builder.with_endpoint(&endpoint).with_allow_http(true).with_virtual_hosted_style_request(false);
+        }
+        let max_bytes: usize = settings("S3_OBJECT_BYTES", Some("16777216")).parse()?;
+        let max_seconds: u64 = settings("S3_OBJECT_SECONDS", Some("300")).parse()?;
+        Ok(Self::new(
+            Box::new(builder.build()?),
+            &settings("S3_PREFIX", Some("adsb/{date}/{receiver}/")),
+            &settings("1090_COLLECTOR", Some("dump1090")),
+            max_bytes,
+            Duration::from_secs(max_seconds),
+        ))
+    }
+
+    /// Adds messages to the pending object, and finishes it if it has reached its size or
+    /// age limit.
+    fn add(&self, state: &mut State, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        for message in batch {
+            serde_json::to_writer(&mut state.pending.encoder, message)?;
+            state.pending.encoder.write_all(b"\n")?;
+        }
+        state.pending.messages += batch.len();
+        if state.pending.encoder.get_ref().len() >= self.max_bytes || state.pending.started.elapsed() >= self.max_age {
+            self.finish(state)?;
+        }
+        Ok(())
+    }
+
+    /// Compresses the pending object, if it holds anything, and queues it for upload under
+    /// its key.
+    fn finish(&self, state: &mut State) -> Result<(), SinkError> {
+        let pending = std::mem::replace(&mut state.pending, Pending::new());
+        if pending.messages == 0 {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let prefix = self.prefix
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{hour}", &now.format("%H").to_string())
+            .replace("{receiver}", &self.receiver);
+        let key = Path::from(format!("{}{}-{}.jsonl.gz", prefix, now.format("%Y%m%dT%H%M%SZ"), Uuid::new_v4()));
+        state.unsent.push_back(Object { key, body: pending.encoder.finish()?, messages: pending.messages });
+        Ok(())
+    }
+
+    /// Uploads the finished objects, oldest first, stopping at the first that fails, which
+    /// is kept to be retried.
+    async fn upload_unsent(&self) -> Result<(), SinkError> {
+        loop {
+            let Some(object) = self.state.lock().unwrap().unsent.pop_front() else {
+                return Ok(());
+            };
+            if let Err(err) = self.upload(&object).await {
+                self.state.lock().unwrap().unsent.push_front(object);
+                return Err(err);
+            }
+        }
+    }
+
+    /// Uploads an object, in parts if it is large.
+    async fn upload(&self, object: &Object) -> Result<(), SinkError> {
+        if object.body.len() > MULTIPART_THRESHOLD {
+            let mut upload = WriteMultipart::new(self.store.put_multipart(&object.key).await?);
+            upload.write(&object.body);
+            upload.finish().await?;
+        } else {
+            self.store.put(&object.key, object.body.clone().into()).await?;
+        }
+        println!("Uploaded {} messages to {}", object.messages, object.key);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for S3Sink {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        self.add(&mut self.state.lock().unwrap(), batch)?;
+        self.upload_unsent().await
+    }
+
+    async fn send_batch(&self, batch: &Batch) -> Result<(), SinkError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.last != Some(batch.id) {
+                state.last = Some(batch.id);
+                self.add(&mut state, &batch.messages)?;
+            }
+        }
+        self.upload_unsent().await
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        self.finish(&mut self.state.lock().unwrap())?;
+        self.upload_unsent().await
+    }
+}
@@ -0,0 +1,54 @@
+//! This module serves messages to other tools, such as Virtual Radar Server, as an SBS-1
+//! (BaseStation) feed on a local TCP port, so they can chain off this collector instead of
+//! opening another connection to dump1090.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Re-emits each message as an SBS-1 `MSG` line to every connected client.
+pub struct SbsServerSink {
+    clients: broadcast::Sender<Arc<[u8]>>,
+}
+
+impl SbsServerSink {
+    /// Starts listening and creates an SBS-1 server sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on, e.g. `0.0.0.0:30103`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the address could not be bound.
+    pub fn new(address: &str) -> Result<Self, SinkError> {
+        Ok(SbsServerSink { clients: super::serve(address, "SBS-1")? })
+    }
+
+    /// Creates an SBS-1 server sink from SBS_LISTEN.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Self::new(&settings("SBS_LISTEN", Some("0.0.0.0:30103")))
+    }
+}
+
+#[async_trait]
+impl Sink for SbsServerSink {
+    fn name(&self) -> &'static str {
+        "sbs"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut lines = String::new();
+        for line in batch.iter().filter_map(SBS1Message::to_sbs1) {
+            lines.push_str(&line);
+            lines.push_str("\r\n");
+        }
+        // Sending only fails when no client is connected, which is fine.
+        if !lines.is_empty() {
+            let _ = self.clients.send(lines.into_bytes().into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! This module archives messages in a local SQLite database (requires the `sqlite`
+//! feature).
+//!
+//! The database uses WAL mode so it can be queried while messages are appended. Once the
+//! file grows past a size limit it is renamed with a timestamp suffix and a new one is
+//! started, so edge devices with limited storage can delete old archives.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use chrono::Local;
+use rusqlite::{params, Connection};
+use std::sync::Mutex;
+
+/// Creates the table the messages are appended to.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS messages (
+        timestamp INTEGER NOT NULL,
+        icao24 TEXT,
+        transmission_type INTEGER,
+        callsign TEXT,
+        altitude_baro INTEGER,
+        ground_speed REAL,
+        track REAL,
+        lat REAL,
+        lon REAL,
+        vertical_rate INTEGER,
+        squawk INTEGER,
+        on_ground INTEGER,
+        message TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS messages_icao24_timestamp ON messages (icao24, timestamp);
+";
+
+/// Appends every message to a SQLite database file.
+pub struct SqliteSink {
+    path: String,
+    /// The size in bytes after which the file is rolled over, or 0 to never roll over.
+    max_bytes: u64,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database and creates a SQLite sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The database file.
+    /// * `max_bytes` - The size in bytes after which the file is rolled over, or 0 to never
+    ///   roll over.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the database could not be opened.
+    pub fn new(path: &str, max_bytes: u64) -> Result<Self, SinkError> {
+        Ok(SqliteSink { path: path.to_string(), max_bytes, connection: Mutex::new(open(path)?) })
+    }
+
+    /// Creates a SQLite sink from SQLITE_PATH and SQLITE_MAX_BYTES.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let max_bytes: u64 = settings("SQLITE_MAX_BYTES", Some("1073741824")).parse()?;
+        Self::new(&settings("SQLITE_PATH", Some("adsb.sqlite")), max_bytes)
+    }
+
+    /// Renames the database with a timestamp suffix if it has grown past `max_bytes` and
+    /// starts a new one in its place.
+    fn roll_over(&self, connection: &mut Connection) -> Result<(), SinkError> {
+        if self.max_bytes == 0 || std::fs::metadata(&self.path)?.len() < self.max_bytes {
+            return Ok(());
+        }
+        // Fold the write-ahead log into the database file before it is moved.
+        connection.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        let archive = format!("{}.{}", self.path, Local::now().format("%Y%m%d%H%M%S%3f"));
+        *connection = Connection::open_in_memory()?;
+        std::fs::rename(&self.path, &archive)?;
+        let _ = std::fs::remove_file(format!("{}-wal", self.path));
+        let _ = std::fs::remove_file(format!("{}-shm", self.path));
+        *connection = open(&self.path)?;
+        println!("Rolled SQLite archive over to {}", archive);
+        Ok(())
+    }
+}
+
+/// Opens the database in WAL mode and creates the schema if needed.
+fn open(path: &str) -> Result<Connection, SinkError> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "NORMAL")?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(connection)
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO messages (timestamp, icao24, transmission_type, callsign, altitude_baro, ground_speed, track, lat, lon, vertical_rate, squawk, on_ground, message)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?;
+            for message in batch {
+                insert.execute(params![
+                    message.timestamp.parse::<i64>().unwrap_or_default(),
+                    message.icao24.map(|icao24| icao24.to_string()),
+                    message.transmission_type,
+                    message.callsign,
+                    message.altitude_baro,
+                    message.ground_speed,
+                    message.track,
+                    message.lat,
+                    message.lon,
+                    message.vertical_rate,
+                    message.squawk,
+                    message.on_ground,
+                    serde_json::to_string(message)?,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+
+        self.roll_over(&mut connection)
+    }
+}
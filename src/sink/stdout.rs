@@ -0,0 +1,72 @@
+//! This module prints messages to standard output as JSON, for checking parsing locally
+//! without sending anything over the network.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use std::io::Write;
+
+/// Prints each message, or each batch, as JSON.
+pub struct StdoutSink {
+    /// Whether to indent the JSON over several lines.
+    pretty: bool,
+    /// Whether to print each batch as one JSON array instead of one document per message.
+    per_batch: bool,
+}
+
+impl StdoutSink {
+    /// Creates a stdout sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `pretty` - Whether to indent the JSON over several lines.
+    /// * `per_batch` - Whether to print each batch as one JSON array.
+    pub fn new(pretty: bool, per_batch: bool) -> Self {
+        StdoutSink { pretty, per_batch }
+    }
+
+    /// Creates a stdout sink from STDOUT_FORMAT (`compact` or `pretty`) and STDOUT_MODE
+    /// (`message` or `batch`).
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let pretty = match settings("STDOUT_FORMAT", Some("compact")).to_lowercase().as_str() {
+            "compact" => false,
+            "pretty" => true,
+            other => return Err(format!("invalid stdout format {:?} (expected compact or pretty)", other).into()),
+        };
+        let per_batch = match settings("STDOUT_MODE", Some("message")).to_lowercase().as_str() {
+            "message" => false,
+            "batch" => true,
+            other => return Err(format!("invalid stdout mode {:?} (expected message or batch)", other).into()),
+        };
+        Ok(Self::new(pretty, per_batch))
+    }
+
+    /// Formats a value as JSON in the configured style.
+    fn to_json<T: serde::Serialize + ?Sized>(&self, value: &T) -> serde_json::Result<String> {
+        if self.pretty {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut out = std::io::stdout().lock();
+        if self.per_batch {
+            writeln!(out, "{}", self.to_json(batch)?)?;
+        } else {
+            for message in batch {
+                writeln!(out, "{}", self.to_json(message)?)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! This module sends messages as RFC 5424 syslog messages over UDP, TCP or TLS, with the
+//! message fields as structured data.
+//!
+//! TCP and TLS use octet-counting framing (RFC 6587 and RFC 5425), which rsyslog and
+//! syslog-ng understand.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde_json::Value;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::Mutex;
+
+/// The structured data ID the fields are sent under; 32473 is the private enterprise
+/// number reserved for examples and documentation.
+const SD_ID: &str = "adsb@32473";
+
+/// How messages reach the syslog server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+/// Sends one syslog message per message.
+pub struct SyslogSink {
+    host: String,
+    port: u16,
+    transport: Transport,
+    /// The syslog facility code, e.g. 16 for local0.
+    facility: u8,
+    /// The HOSTNAME field: the collector (or source) identifier.
+    hostname: String,
+    /// The open TCP or TLS connection, reopened after an error.
+    stream: Mutex<Option<Box<dyn AsyncWrite + Send + Unpin>>>,
+}
+
+impl SyslogSink {
+    /// Creates a syslog sink. Connections are opened when the first batch is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The syslog server.
+    /// * `port` - The syslog server port.
+    /// * `transport` - UDP, TCP or TLS.
+    /// * `facility` - The syslog facility code.
+    /// * `hostname` - The HOSTNAME field, e.g. the collector name.
+    pub fn new(host: &str, port: u16, transport: Transport, facility: u8, hostname: &str) -> Self {
+        SyslogSink {
+            host: host.to_string(),
+            port,
+            transport,
+            facility,
+            hostname: hostname.to_string(),
+            stream: Mutex::new(None),
+        }
+    }
+
+    /// Creates a syslog sink from SYSLOG_HOST, SYSLOG_PORT, SYSLOG_TRANSPORT (`udp`, `tcp` or
+    /// `tls`), SYSLOG_FACILITY and 1090_COLLECTOR.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let (transport, default_port) = match settings("SYSLOG_TRANSPORT", Some("udp")).to_lowercase().as_str() {
+            "udp" => (Transport::Udp, "514"),
+            "tcp" => (Transport::Tcp, "514"),
+            "tls" => (Transport::Tls, "6514"),
+            other => return Err(format!("invalid syslog transport {:?} (expected udp, tcp or tls)", other).into()),
+        };
+        let port: u16 = settings("SYSLOG_PORT", Some(default_port)).parse()?;
+        let facility: u8 = settings("SYSLOG_FACILITY", Some("16")).parse()?;
+        if facility > 23 {
+            return Err(format!("invalid syslog facility {} (expected 0-23)", facility).into());
+        }
+        Ok(Self::new(&settings("SYSLOG_HOST", None), port, transport, facility, &settings("1090_COLLECTOR", Some("dump1090"))))
+    }
+
+    /// Formats a message as an RFC 5424 syslog message with informational severity.
+    pub fn format(&self, message: &SBS1Message) -> String {
+        let time = DateTime::from_timestamp_nanos(message.timestamp.parse().unwrap_or_default());
+        let mut data = format!("[{}", SD_ID);
+        if let Ok(Value::Object(fields)) = serde_json::to_value(message) {
+            for (name, value) in fields {
+                let value = match value {
+                    Value::Null => continue,
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                };
+                data.push_str(&format!(" {}=\"{}\"", name, escape_param(&value)));
+            }
+        }
+        data.push(']');
+
+        format!(
+            "<{}>1 {} {} adsb-rust-dataset {} MSG{} {}",
+            self.facility as u32 * 8 + 6,
+            time.format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+            header_field(&self.hostname),
+            std::process::id(),
+            message.transmission_type.unwrap_or_default(),
+            data,
+        )
+    }
+
+    /// Opens a TCP or TLS connection to the server.
+    async fn connect(&self) -> Result<Box<dyn AsyncWrite + Send + Unpin>, SinkError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        if self.transport == Transport::Tls {
+            let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+            Ok(Box::new(connector.connect(&self.host, stream).await?))
+        } else {
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+/// Escapes the characters RFC 5424 requires escaping in structured data values.
+fn escape_param(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+/// Makes a header field printable ASCII without spaces, or `-` if nothing is left.
+fn header_field(value: &str) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+#[async_trait]
+impl Sink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        if self.transport == Transport::Udp {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect((self.host.as_str(), self.port)).await?;
+            for message in batch {
+                socket.send(self.format(message).as_bytes()).await?;
+            }
+            return Ok(());
+        }
+
+        let mut frames = Vec::new();
+        for message in batch {
+            let line = self.format(message);
+            frames.extend_from_slice(format!("{} {}", line.len(), line).as_bytes());
+        }
+
+        let mut stream = self.stream.lock().await;
+        if stream.is_none() {
+            *stream = Some(self.connect().await?);
+        }
+        let result = async {
+            let writer = stream.as_mut().unwrap();
+            writer.write_all(&frames).await?;
+            writer.flush().await
+        }.await;
+        if result.is_err() {
+            // Reconnect on the next batch.
+            *stream = None;
+        }
+        Ok(result?)
+    }
+}
@@ -0,0 +1,87 @@
+//! This module sends each message as a JSON UDP datagram, for lightweight visualizers and
+//! embedded displays.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::net::UdpSocket;
+
+/// Limits the datagram rate with a token bucket that holds up to one second's worth.
+struct RateLimit {
+    /// Datagrams allowed per second.
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimit {
+    /// Takes up to `wanted` tokens, returning how many were available.
+    fn take(&mut self, wanted: usize) -> usize {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.refilled).as_secs_f64() * self.rate).min(self.rate);
+        self.refilled = now;
+        let taken = (self.tokens.floor() as usize).min(wanted);
+        self.tokens -= taken as f64;
+        taken
+    }
+}
+
+/// Fires one datagram per message at a host and port.
+pub struct UdpSink {
+    host: String,
+    port: u16,
+    /// The rate limit, or `None` to send everything.
+    limit: Option<Mutex<RateLimit>>,
+}
+
+impl UdpSink {
+    /// Creates a UDP sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host to send to; may be a broadcast address.
+    /// * `port` - The port to send to.
+    /// * `max_rate` - The most datagrams to send per second, or 0 for no limit. Messages
+    ///   over the limit are dropped.
+    pub fn new(host: &str, port: u16, max_rate: u32) -> Self {
+        let limit = (max_rate > 0).then(|| Mutex::new(RateLimit { rate: max_rate as f64, tokens: max_rate as f64, refilled: Instant::now() }));
+        UdpSink { host: host.to_string(), port, limit }
+    }
+
+    /// Creates a UDP sink from UDP_HOST, UDP_PORT and UDP_MAX_RATE.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Ok(Self::new(
+            &settings("UDP_HOST", None),
+            settings("UDP_PORT", None).parse()?,
+            settings("UDP_MAX_RATE", Some("0")).parse()?,
+        ))
+    }
+}
+
+#[async_trait]
+impl Sink for UdpSink {
+    fn name(&self) -> &'static str {
+        "udp"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let allowed = match &self.limit {
+            Some(limit) => limit.lock().unwrap().take(batch.len()),
+            None => batch.len(),
+        };
+        if allowed == 0 {
+            return Ok(());
+        }
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.set_broadcast(true)?;
+        socket.connect((self.host.as_str(), self.port)).await?;
+        // Over the limit, keep the newest messages, which matter most to a live display.
+        for message in &batch[batch.len() - allowed..] {
+            socket.send(&serde_json::to_vec(message)?).await?;
+        }
+        Ok(())
+    }
+}
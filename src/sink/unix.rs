@@ -0,0 +1,57 @@
+//! This module serves messages as newline-delimited JSON on a Unix domain socket, so
+//! co-located processes such as a local web UI can consume the stream without TCP ports.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Writes each message as a line of JSON to every process connected to the socket.
+pub struct UnixSocketSink {
+    path: String,
+    clients: broadcast::Sender<Arc<[u8]>>,
+}
+
+impl UnixSocketSink {
+    /// Creates the socket and a Unix socket sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The socket path.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the socket could not be created.
+    pub fn new(path: &str) -> Result<Self, SinkError> {
+        Ok(UnixSocketSink { path: path.to_string(), clients: super::serve_unix(path, "JSON")? })
+    }
+
+    /// Creates a Unix socket sink from UNIX_SOCKET_PATH.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        Self::new(&settings("UNIX_SOCKET_PATH", Some("/tmp/adsb.sock")))
+    }
+}
+
+#[async_trait]
+impl Sink for UnixSocketSink {
+    fn name(&self) -> &'static str {
+        "unix"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut lines = Vec::new();
+        for message in batch {
+            serde_json::to_writer(&mut lines, message)?;
+            lines.push(b'\n');
+        }
+        // Sending only fails when no client is connected, which is fine.
+        let _ = self.clients.send(lines.into());
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), SinkError> {
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
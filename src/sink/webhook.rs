@@ -0,0 +1,104 @@
+//! This module sends messages to an arbitrary HTTP endpoint, with the request body rendered
+//! from a user-supplied minijinja template.
+
+use super::{key_values, Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use minijinja::{context, Environment};
+use reqwest::Method;
+
+/// The default template: the batch (or message) as JSON.
+const DEFAULT_BATCH_TEMPLATE: &str = "{{ messages | tojson }}";
+const DEFAULT_MESSAGE_TEMPLATE: &str = "{{ message | tojson }}";
+
+/// Sends one request per batch, or per message, with a templated body.
+pub struct WebhookSink {
+    url: String,
+    method: Method,
+    headers: Vec<(String, String)>,
+    /// Holds the body template, named `body`.
+    templates: Environment<'static>,
+    /// Whether a request is sent for each message rather than each batch.
+    per_message: bool,
+    /// The collector (or source) identifier, available to the template as `collector`.
+    collector: String,
+}
+
+impl WebhookSink {
+    /// Creates a webhook sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The endpoint to send to.
+    /// * `method` - The HTTP method, e.g. `POST`.
+    /// * `headers` - Request headers, e.g. `Content-Type` and authentication.
+    /// * `template` - The body template. It sees `messages` (or `message` when
+    ///   `per_message`) and `collector`.
+    /// * `per_message` - Whether to send a request for each message instead of each batch.
+    /// * `collector` - The collector (or source) identifier.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the template does not compile.
+    pub fn new(url: &str, method: Method, headers: Vec<(String, String)>, template: String, per_message: bool, collector: &str) -> Result<Self, SinkError> {
+        let mut templates = Environment::new();
+        templates.add_template_owned("body", template)?;
+        Ok(WebhookSink { url: url.to_string(), method, headers, templates, per_message, collector: collector.to_string() })
+    }
+
+    /// Creates a webhook sink from WEBHOOK_URL, WEBHOOK_METHOD, WEBHOOK_HEADERS
+    /// (comma-separated `Name=value` pairs), WEBHOOK_MODE (`batch` or `message`),
+    /// WEBHOOK_TEMPLATE or WEBHOOK_TEMPLATE_FILE, and 1090_COLLECTOR.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let per_message = match settings("WEBHOOK_MODE", Some("batch")).to_lowercase().as_str() {
+            "batch" => false,
+            "message" => true,
+            other => return Err(format!("invalid webhook mode {:?} (expected batch or message)", other).into()),
+        };
+        let template_file = settings("WEBHOOK_TEMPLATE_FILE", Some(""));
+        let template = if template_file.is_empty() {
+            let default = if per_message { DEFAULT_MESSAGE_TEMPLATE } else { DEFAULT_BATCH_TEMPLATE };
+            settings("WEBHOOK_TEMPLATE", Some(default))
+        } else {
+            std::fs::read_to_string(&template_file)?
+        };
+        let method = Method::from_bytes(settings("WEBHOOK_METHOD", Some("POST")).to_uppercase().as_bytes())?;
+        let mut headers = key_values(&settings("WEBHOOK_HEADERS", Some("")));
+        if !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+            headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        }
+        Self::new(&settings("WEBHOOK_URL", None), method, headers, template, per_message, &settings("1090_COLLECTOR", Some("dump1090")))
+    }
+
+    /// Sends one request with the given body.
+    async fn request(&self, client: &reqwest::Client, body: String) -> Result<(), SinkError> {
+        let mut request = client.request(self.method.clone(), &self.url).body(body);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let template = self.templates.get_template("body")?;
+        let client = reqwest::Client::new();
+        if self.per_message {
+            for message in batch {
+                let body = template.render(context! { message => message, collector => &self.collector })?;
+                self.request(&client, body).await?;
+            }
+        } else {
+            let body = template.render(context! { messages => batch, collector => &self.collector })?;
+            self.request(&client, body).await?;
+        }
+        Ok(())
+    }
+}
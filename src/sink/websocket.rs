@@ -0,0 +1,212 @@
+//! This module streams messages as JSON to browsers and other clients over WebSocket
+//! (requires the `websocket` feature), so live web maps can be driven directly by this
+//! collector.
+//!
+//! Each connection can filter what it receives by aircraft and by bounding box, either in
+//! the query string when connecting (`ws://host:8090/?icao24=4ca2d6,3c6444&bbox=-1,51,1,52`)
+//! or later by sending a JSON text message such as
+//! `{"icao24": ["4ca2d6"], "bbox": [-1, 51, 1, 52]}`, which replaces the filter. Bounding
+//! boxes are `min_lon,min_lat,max_lon,max_lat`, as in GeoJSON.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use crate::tracker::Tracker;
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+/// A message ready to be sent, with what filters need to know about it.
+struct Update {
+    icao24: Option<String>,
+    /// The position as (lon, lat): the message's own, or else the aircraft's last known
+    /// one.
+    position: Option<(f64, f64)>,
+    json: String,
+}
+
+/// What a connection wants to receive.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Filter {
+    /// The aircraft to send messages of; empty for all aircraft.
+    #[serde(default)]
+    pub icao24: HashSet<String>,
+    /// Only send messages of aircraft last seen inside `[min_lon, min_lat, max_lon, max_lat]`.
+    #[serde(default)]
+    pub bbox: Option<[f64; 4]>,
+}
+
+impl Filter {
+    /// Parses a filter from a query string such as `icao24=4ca2d6,3c6444&bbox=-1,51,1,52`.
+    pub fn from_query(query: &str) -> Result<Self, String> {
+        let mut filter = Filter::default();
+        for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let value = value.replace("%2C", ",").replace("%2c", ",");
+            match name {
+                "icao24" => {
+                    filter.icao24 = value.split(',').map(|icao24| icao24.trim().to_lowercase()).filter(|icao24| !icao24.is_empty()).collect();
+                }
+                "bbox" => {
+                    let bounds = value.split(',').map(|bound| bound.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>();
+                    match bounds.as_deref() {
+                        Ok(&[min_lon, min_lat, max_lon, max_lat]) => filter.bbox = Some([min_lon, min_lat, max_lon, max_lat]),
+                        _ => return Err(format!("invalid bbox {:?} (expected min_lon,min_lat,max_lon,max_lat)", value)),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Returns whether a position is inside the bounding box.
+    fn contains(&self, (lon, lat): (f64, f64)) -> bool {
+        match self.bbox {
+            Some([min_lon, min_lat, max_lon, max_lat]) => (min_lon..=max_lon).contains(&lon) && (min_lat..=max_lat).contains(&lat),
+            None => true,
+        }
+    }
+}
+
+/// Pushes each message as a JSON text message to every connected client whose filter it
+/// passes.
+pub struct WebSocketSink {
+    updates: broadcast::Sender<Arc<Vec<Update>>>,
+    /// Knows where aircraft were last seen, for messages without a position.
+    tracker: Arc<Tracker>,
+}
+
+impl WebSocketSink {
+    /// Starts listening and creates a WebSocket sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on, e.g. `0.0.0.0:8090`.
+    /// * `tracker` - The tracker that keeps the state of each aircraft.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sink, or an error if the address could not be bound.
+    pub fn new(address: &str, tracker: Arc<Tracker>) -> Result<Self, SinkError> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        println!("Serving WebSocket clients on {}", address);
+
+        let (updates, _) = broadcast::channel(256);
+        tokio::spawn(accept(listener, updates.clone()));
+        Ok(WebSocketSink { updates, tracker })
+    }
+
+    /// Creates a WebSocket sink from WEBSOCKET_LISTEN.
+    pub fn from_settings(settings: Settings, tracker: Arc<Tracker>) -> Result<Self, SinkError> {
+        Self::new(&settings("WEBSOCKET_LISTEN", Some("0.0.0.0:8090")), tracker)
+    }
+}
+
+/// Accepts connections and serves each of them in its own task.
+async fn accept(listener: TcpListener, updates: broadcast::Sender<Arc<Vec<Update>>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let receiver = updates.subscribe();
+                tokio::spawn(async move {
+                    if let Err(err) = serve(stream, receiver).await {
+                        eprintln!("WebSocket client {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => eprintln!("Error accepting WebSocket client: {}", err),
+        }
+    }
+}
+
+/// Serves one connection until the client goes away.
+// The handshake callback has to return tungstenite's large error response type.
+#[allow(clippy::result_large_err)]
+async fn serve(stream: TcpStream, mut updates: broadcast::Receiver<Arc<Vec<Update>>>) -> Result<(), SinkError> {
+    let mut query = String::new();
+    let socket = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response: Response| {
+        query = request.uri().query().unwrap_or_default().to_string();
+        Ok(response)
+    })
+    .await?;
+    let (mut writer, mut reader) = socket.split();
+    let mut filter = match Filter::from_query(&query) {
+        Ok(filter) => filter,
+        Err(err) => {
+            writer.send(Message::Close(None)).await?;
+            return Err(err.into());
+        }
+    };
+
+    loop {
+        tokio::select! {
+            update = updates.recv() => {
+                let batch = match update {
+                    Ok(batch) => batch,
+                    // A slow client misses messages rather than holding up the others.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Ok(()),
+                };
+                for update in batch.iter() {
+                    if passes(&filter, update) {
+                        writer.feed(Message::Text(update.json.clone())).await?;
+                    }
+                }
+                writer.flush().await?;
+            }
+            message = reader.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Filter>(&text) {
+                    Ok(new_filter) => {
+                        filter = Filter { icao24: new_filter.icao24.iter().map(|icao24| icao24.to_lowercase()).collect(), ..new_filter };
+                    }
+                    Err(err) => writer.send(Message::Text(serde_json::json!({"error": err.to_string()}).to_string())).await?,
+                },
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+            },
+        }
+    }
+}
+
+/// Returns whether an update passes a connection's filter.
+fn passes(filter: &Filter, update: &Update) -> bool {
+    let icao24 = update.icao24.as_deref().unwrap_or_default();
+    if !filter.icao24.is_empty() && !filter.icao24.contains(icao24) {
+        return false;
+    }
+    match update.position {
+        Some(position) => filter.contains(position),
+        None => filter.bbox.is_none(),
+    }
+}
+
+#[async_trait]
+impl Sink for WebSocketSink {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        // Serialize once for all clients.
+        let mut updates = Vec::with_capacity(batch.len());
+        for message in batch {
+            updates.push(Update {
+                icao24: message.icao24.map(|icao24| icao24.to_string()),
+                position: message.lon.zip(message.lat).or_else(|| self.tracker.get(message.icao24?)?.position()),
+                json: serde_json::to_string(message)?,
+            });
+        }
+        // Sending only fails when no client is connected, which is fine.
+        let _ = self.updates.send(Arc::new(updates));
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! This module publishes messages on a ZeroMQ PUB socket (requires the `zeromq` feature),
+//! for local consumers that want low latency without running a broker.
+
+use super::{Settings, Sink, SinkError};
+use crate::parse::SBS1Message;
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use zeromq::{PubSocket, Socket, SocketSend, ZmqMessage};
+
+/// What the topic of each message is derived from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topic {
+    /// The aircraft's address, e.g. `adsb.4ca2d6`.
+    Icao24,
+    /// The SBS-1 transmission type, e.g. `adsb.msg3`.
+    MessageType,
+}
+
+/// Publishes each message as a two-frame ZeroMQ message: the topic, then the JSON.
+/// Subscribers filter by topic prefix, e.g. `adsb.4ca2d6` or just `adsb.`.
+pub struct ZeroMqSink {
+    endpoint: String,
+    topic: Topic,
+    prefix: String,
+    /// The socket, bound when the first batch is sent.
+    socket: Mutex<Option<PubSocket>>,
+}
+
+impl ZeroMqSink {
+    /// Creates a ZeroMQ sink. The socket is bound when the first batch is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The endpoint to bind, e.g. `tcp://0.0.0.0:5556` or `ipc:///tmp/adsb`.
+    /// * `topic` - What the topic of each message is derived from.
+    /// * `prefix` - Prepended to each topic.
+    pub fn new(endpoint: &str, topic: Topic, prefix: &str) -> Self {
+        ZeroMqSink { endpoint: endpoint.to_string(), topic, prefix: prefix.to_string(), socket: Mutex::new(None) }
+    }
+
+    /// Creates a ZeroMQ sink from ZMQ_ENDPOINT, ZMQ_TOPIC (`icao24` or `type`) and
+    /// ZMQ_TOPIC_PREFIX.
+    pub fn from_settings(settings: Settings) -> Result<Self, SinkError> {
+        let topic = match settings("ZMQ_TOPIC", Some("icao24")).to_lowercase().as_str() {
+            "icao24" => Topic::Icao24,
+            "type" => Topic::MessageType,
+            other => return Err(format!("invalid ZeroMQ topic {:?} (expected icao24 or type)", other).into()),
+        };
+        Ok(Self::new(&settings("ZMQ_ENDPOINT", Some("tcp://0.0.0.0:5556")), topic, &settings("ZMQ_TOPIC_PREFIX", Some("adsb."))))
+    }
+
+    /// Returns the topic of a message.
+    fn topic(&self, message: &SBS1Message) -> String {
+        let topic = match self.topic {
+            Topic::Icao24 => message.icao24.map(|icao24| icao24.to_string()).unwrap_or_else(|| "unknown".to_string()),
+            Topic::MessageType => match message.transmission_type {
+                Some(transmission_type) => format!("msg{}", transmission_type),
+                None => "other".to_string(),
+            },
+        };
+        format!("{}{}", self.prefix, topic)
+    }
+}
+
+#[async_trait]
+impl Sink for ZeroMqSink {
+    fn name(&self) -> &'static str {
+        "zeromq"
+    }
+
+    async fn send(&self, batch: &[SBS1Message]) -> Result<(), SinkError> {
+        let mut socket = self.socket.lock().await;
+        if socket.is_none() {
+            let mut new_socket = PubSocket::new();
+            new_socket.bind(&self.endpoint).await?;
+            println!("Publishing on ZeroMQ endpoint {}", self.endpoint);
+            *socket = Some(new_socket);
+        }
+        let socket = socket.as_mut().unwrap();
+
+        for message in batch {
+            let mut zmq_message = ZmqMessage::from(self.topic(message));
+            zmq_message.push_back(serde_json::to_vec(message)?.into());
+            socket.send(zmq_message).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+//! This module keeps queued batches on disk, so that they survive restarts and outages that
+//! outlast what memory can hold.
+//!
+//! Batches are appended to segment files, one line each, and synced before they count as
+//! queued. Compressed batches take a line giving the size of their gzip data, followed by
+//! the data and a newline, so that a queue outlasting a long time offline stays small. A
+//! cursor file records where the batches that have been sent end; it is only written after
+//! sending, so a batch is replayed after a crash rather than lost. Segments are deleted
+//! once the cursor has moved past them.
+
+use crate::buffer::Batch;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Where a batch is stored.
+struct Record {
+    segment: u64,
+    offset: u64,
+    /// The length of the line, including its newline.
+    length: u64,
+    /// The number of messages and parse failures in the batch.
+    len: usize,
+}
+
+/// A queue of batches in segment files in a directory.
+pub struct Spool {
+    dir: PathBuf,
+    /// The size at which a new segment is started.
+    segment_bytes: u64,
+    /// Whether batches are appended compressed.
+    compress: bool,
+    /// The batches not yet taken, oldest first.
+    records: VecDeque<Record>,
+    /// The segment being written, its size, and the file.
+    segment: u64,
+    segment_size: u64,
+    file: File,
+    /// Whether a failed append may have left part of a line at the end of the segment.
+    torn: bool,
+}
+
+impl Spool {
+    /// Opens the queue in a directory, creating it if needed, and finds the batches left
+    /// unsent by an earlier run.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory, which holds nothing else.
+    /// * `segment_bytes` - The size at which a new segment file is started.
+    /// * `compress` - Whether batches are appended compressed with gzip. Batches left by an
+    ///   earlier run are read either way.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the queue, or an error if the directory cannot be used.
+    pub fn open(dir: &Path, segment_bytes: u64, compress: bool) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let (cursor_segment, cursor_offset) = match fs::read_to_string(dir.join("cursor")) {
+            Ok(cursor) => parse_cursor(&cursor).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("invalid queue cursor {:?}", cursor)))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (0, 0),
+            Err(err) => return Err(err),
+        };
+
+        let mut records = VecDeque::new();
+        let segments = segments(dir)?;
+        for &segment in &segments {
+            // Segments before the cursor have been sent, and empty ones hold nothing.
+            if segment < cursor_segment || fs::metadata(segment_path(dir, segment))?.len() == 0 {
+                fs::remove_file(segment_path(dir, segment))?;
+                continue;
+            }
+            let mut offset = if segment == cursor_segment { cursor_offset } else { 0 };
+            let mut reader = BufReader::new(File::open(segment_path(dir, segment))?);
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                let mut length = reader.read_until(b'\n', &mut line)? as u64;
+                // A line without its newline was cut short by a crash while it was written,
+                // so it never counted as queued. The same goes for compressed data.
+                if length == 0 || !line.ends_with(b"\n") {
+                    break;
+                }
+                if let Some(size) = compressed_len(&line) {
+                    let mut data = vec![0; size + 1];
+                    if reader.read_exact(&mut data).is_err() || !data.ends_with(b"\n") {
+                        break;
+                    }
+                    length += data.len() as u64;
+                }
+                match record_len(&line) {
+                    Some(len) => records.push_back(Record { segment, offset, length, len }),
+                    None => eprintln!("Skipping a corrupt record in queue segment {}", segment_path(dir, segment).display()),
+                }
+                offset += length;
+            }
+        }
+
+        // Always write to a new segment, after anything an earlier run left behind.
+        let segment = segments.last().map_or(0, |last| last + 1).max(cursor_segment);
+        let file = OpenOptions::new().create(true).append(true).open(segment_path(dir, segment))?;
+        let spool = Spool { dir: dir.to_path_buf(), segment_bytes, compress, records, segment, segment_size: 0, file, torn: false };
+        if !spool.records.is_empty() {
+            println!("Replaying {} queued batches ({} messages) from {}", spool.records.len(), spool.len(), dir.display());
+        }
+        Ok(spool)
+    }
+
+    /// Returns the number of batches queued.
+    pub fn batches(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns the number of messages and parse failures queued.
+    pub fn len(&self) -> usize {
+        self.records.iter().map(|record| record.len).sum()
+    }
+
+    /// Returns whether nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the size of the queued batches on disk.
+    pub fn bytes(&self) -> u64 {
+        self.records.iter().map(|record| record.length).sum()
+    }
+
+    /// Appends a batch and syncs it to disk.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the size of the batch on disk, or an error if it could not be
+    /// written, in which case nothing of it is queued.
+    pub fn append(&mut self, batch: &Batch) -> io::Result<u64> {
+        let mut line = format!("{}\t", batch.len()).into_bytes();
+        if self.compress {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            serde_json::to_writer(&mut encoder, batch)?;
+            let data = encoder.finish()?;
+            line.extend(format!("#{}\n", data.len()).into_bytes());
+            line.extend(data);
+        } else {
+            serde_json::to_writer(&mut line, batch)?;
+        }
+        line.push(b'\n');
+
+        if self.torn || (self.segment_size > 0 && self.segment_size + line.len() as u64 > self.segment_bytes) {
+            self.file = OpenOptions::new().create(true).append(true).open(segment_path(&self.dir, self.segment + 1))?;
+            self.segment += 1;
+            self.segment_size = 0;
+            self.torn = false;
+        }
+        if let Err(err) = self.file.write_all(&line).and_then(|()| self.file.sync_data()) {
+            // Cut off what was written of the line, e.g. when the disk is full, so that it
+            // does not shift the records appended after it. Failing that, the next batch
+            // starts a new segment.
+            self.torn = self.file.set_len(self.segment_size).is_err();
+            return Err(err);
+        }
+        let length = line.len() as u64;
+        self.records.push_back(Record { segment: self.segment, offset: self.segment_size, length, len: batch.len() });
+        self.segment_size += length;
+        Ok(length)
+    }
+
+    /// Takes the oldest batch. It stays on disk until `commit` is called.
+    ///
+    /// # Returns
+    ///
+    /// The batch, the number of messages and parse failures in it, and its size on disk, or
+    /// `None` if nothing is queued. The error of a batch that cannot be read is returned
+    /// instead of it, and the batch is skipped.
+    pub fn take(&mut self) -> Option<(io::Result<Batch>, usize, u64)> {
+        let record = self.records.pop_front()?;
+        Some((self.read(&record), record.len, record.length))
+    }
+
+    /// Skips the oldest batch without reading it.
+    ///
+    /// # Returns
+    ///
+    /// The number of messages and parse failures in it and its size on disk, or `None` if
+    /// nothing is queued.
+    pub fn skip(&mut self) -> Option<(usize, u64)> {
+        self.records.pop_front().map(|record| (record.len, record.length))
+    }
+
+    /// Records that every batch taken or skipped so far has been dealt with, so that it is
+    /// not replayed, and deletes the segments that are no longer needed.
+    pub fn commit(&mut self) -> io::Result<()> {
+        let (segment, offset) = self.records.front().map_or((self.segment, self.segment_size), |record| (record.segment, record.offset));
+        let cursor = self.dir.join("cursor");
+        let temporary = self.dir.join("cursor.tmp");
+        fs::write(&temporary, format!("{} {}\n", segment, offset))?;
+        fs::rename(&temporary, &cursor)?;
+        for old in segments(&self.dir)?.into_iter().filter(|&old| old < segment) {
+            fs::remove_file(segment_path(&self.dir, old))?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, record: &Record) -> io::Result<Batch> {
+        let mut file = File::open(segment_path(&self.dir, record.segment))?;
+        file.seek(SeekFrom::Start(record.offset))?;
+        let mut line = vec![0; record.length as usize];
+        file.read_exact(&mut line)?;
+        let json = line.iter().position(|&byte| byte == b'\t').map_or(&line[..], |tab| &line[tab + 1..]);
+        if compressed_len(&line).is_some() {
+            // Skip the size line, and leave out the final newline.
+            let data = json.iter().position(|&byte| byte == b'\n').map_or(&[][..], |newline| &json[newline + 1..json.len() - 1]);
+            return Ok(serde_json::from_reader(GzDecoder::new(data))?);
+        }
+        Ok(serde_json::from_slice(json)?)
+    }
+}
+
+/// Returns the path of a segment file.
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:020}.segment", segment))
+}
+
+/// Returns the numbers of the segment files in a directory, in order.
+fn segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if let Some(segment) = name.to_str().and_then(|name| name.strip_suffix(".segment")).and_then(|number| number.parse().ok()) {
+            segments.push(segment);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Parses the `segment offset` contents of a cursor file.
+fn parse_cursor(cursor: &str) -> Option<(u64, u64)> {
+    let (segment, offset) = cursor.trim().split_once(' ')?;
+    Some((segment.parse().ok()?, offset.parse().ok()?))
+}
+
+/// Returns the size of the gzip data of a compressed record, given as `#size` after the
+/// number of messages and parse failures, or `None` if the record is plain JSON.
+fn compressed_len(line: &[u8]) -> Option<usize> {
+    let tab = line.iter().position(|&byte| byte == b'\t')?;
+    let size = line[tab + 1..].strip_prefix(b"#")?;
+    let newline = size.iter().position(|&byte| byte == b'\n')?;
+    std::str::from_utf8(&size[..newline]).ok()?.parse().ok()
+}
+
+/// Returns the number of messages and parse failures in a record, which precedes its JSON.
+fn record_len(line: &[u8]) -> Option<usize> {
+    let tab = line.iter().position(|&byte| byte == b'\t')?;
+    std::str::from_utf8(&line[..tab]).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use uuid::Uuid;
+
+    const LINE: &str = "MSG,3,1,1,4CA2D6,1,2023/09/10,12:34:56.789,2023/09/10,12:34:56.790,,35000,,,53.12345,-6.54321,,,0,0,0,0";
+
+    /// Returns a directory of its own under the system's temporary directory.
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("adsb-spool-{}", Uuid::new_v4()))
+    }
+
+    fn batch(messages: usize) -> Batch {
+        Batch::new((0..messages).map(|_| parse(LINE).unwrap()).collect(), Vec::new())
+    }
+
+    /// Takes the oldest batch, which must be readable, and returns its id.
+    fn take_id(spool: &mut Spool) -> Uuid {
+        spool.take().expect("a batch should be queued").0.expect("the batch should be readable").id
+    }
+
+    #[test]
+    fn takes_batches_in_order() {
+        for compress in [false, true] {
+            let dir = temp_dir();
+            let mut spool = Spool::open(&dir, 1 << 20, compress).unwrap();
+            let (first, second) = (batch(2), batch(3));
+            let size = spool.append(&first).unwrap();
+            spool.append(&second).unwrap();
+            assert_eq!((spool.batches(), spool.len()), (2, 5));
+            assert!(spool.bytes() > size);
+
+            let (taken, len, bytes) = spool.take().unwrap();
+            let taken = taken.unwrap();
+            assert_eq!((taken.id, taken.seq, taken.messages.len()), (first.id, first.seq, 2));
+            assert_eq!((len, bytes), (2, size));
+            assert_eq!(take_id(&mut spool), second.id);
+            assert!(spool.take().is_none());
+            assert!(spool.is_empty());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn replays_what_was_not_committed() {
+        let dir = temp_dir();
+        let batches = [batch(1), batch(1), batch(1)];
+        let mut spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        for batch in &batches {
+            spool.append(batch).unwrap();
+        }
+        assert_eq!(take_id(&mut spool), batches[0].id);
+        spool.commit().unwrap();
+        // Taken, but not committed before the restart.
+        assert_eq!(take_id(&mut spool), batches[1].id);
+        drop(spool);
+
+        let mut spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        assert_eq!(spool.batches(), 2);
+        assert_eq!(take_id(&mut spool), batches[1].id);
+        let bytes = spool.bytes();
+        assert_eq!(spool.skip(), Some((1, bytes)));
+        spool.commit().unwrap();
+        drop(spool);
+
+        let spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        assert!(spool.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deletes_segments_behind_the_cursor() {
+        let dir = temp_dir();
+        // Each batch fills a segment of its own.
+        let mut spool = Spool::open(&dir, 1, false).unwrap();
+        for _ in 0..3 {
+            spool.append(&batch(1)).unwrap();
+        }
+        assert_eq!(segments(&dir).unwrap(), [0, 1, 2]);
+        spool.take();
+        spool.take();
+        spool.commit().unwrap();
+        assert_eq!(segments(&dir).unwrap(), [2]);
+        assert_eq!(fs::read_to_string(dir.join("cursor")).unwrap(), "2 0\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_compressed_and_plain_records_together() {
+        let dir = temp_dir();
+        let (plain, compressed) = (batch(1), batch(1));
+        let mut spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        spool.append(&plain).unwrap();
+        drop(spool);
+        let mut spool = Spool::open(&dir, 1 << 20, true).unwrap();
+        spool.append(&compressed).unwrap();
+        drop(spool);
+
+        let mut spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        assert_eq!(take_id(&mut spool), plain.id);
+        assert_eq!(take_id(&mut spool), compressed.id);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_a_torn_last_line() {
+        for compress in [false, true] {
+            let dir = temp_dir();
+            let whole = batch(1);
+            let mut spool = Spool::open(&dir, 1 << 20, compress).unwrap();
+            spool.append(&whole).unwrap();
+            drop(spool);
+            // A crash while the next line was written.
+            let mut file = OpenOptions::new().append(true).open(segment_path(&dir, 0)).unwrap();
+            file.write_all(if compress { b"1\t#100\n\x1f\x8b" } else { b"1\t{\"id\":" }).unwrap();
+            drop(file);
+
+            let mut spool = Spool::open(&dir, 1 << 20, compress).unwrap();
+            assert_eq!(spool.batches(), 1);
+            let after = batch(1);
+            spool.append(&after).unwrap();
+            assert_eq!(take_id(&mut spool), whole.id);
+            assert_eq!(take_id(&mut spool), after.id);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn moves_on_after_a_failed_append() {
+        let dir = temp_dir();
+        let mut spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        let first = batch(1);
+        spool.append(&first).unwrap();
+        // Part of a line written before the disk filled up, on a file that can then neither
+        // be written nor cut back.
+        let mut file = OpenOptions::new().append(true).open(segment_path(&dir, 0)).unwrap();
+        file.write_all(b"1\t{\"id\":").unwrap();
+        spool.file = File::open(segment_path(&dir, 0)).unwrap();
+        assert!(spool.append(&batch(1)).is_err());
+        assert_eq!(spool.batches(), 1);
+
+        let last = batch(1);
+        spool.append(&last).unwrap();
+        assert_eq!(take_id(&mut spool), first.id);
+        assert_eq!(take_id(&mut spool), last.id);
+        drop(spool);
+
+        let mut spool = Spool::open(&dir, 1 << 20, false).unwrap();
+        assert_eq!(take_id(&mut spool), first.id);
+        assert_eq!(take_id(&mut spool), last.id);
+        assert!(spool.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
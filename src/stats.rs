@@ -0,0 +1,150 @@
+//! This module keeps runtime counters for the forwarder.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counters describing what the forwarder has done since startup.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Lines read from the feed.
+    pub lines_read: u64,
+    /// Lines that held NULs or control characters, which were removed before parsing.
+    pub lines_repaired: u64,
+    /// Lines skipped because they were not valid UTF-8.
+    pub lines_garbled: u64,
+    /// Lines skipped because they were cut short.
+    pub lines_truncated: u64,
+    /// Lines skipped because they could not be parsed.
+    pub lines_skipped: u64,
+    /// Raw or Beast frames read from the feed.
+    pub frames_read: u64,
+    /// Frames skipped because they failed their parity check, came from an unknown address,
+    /// or carried nothing that is forwarded.
+    pub frames_skipped: u64,
+    /// Messages successfully parsed.
+    pub messages_parsed: u64,
+    /// Messages forwarded with impossible fields cleared.
+    pub messages_sanitized: u64,
+    /// Messages dropped because they held impossible values.
+    pub messages_rejected: u64,
+    /// Messages dropped because they repeated one forwarded moments before.
+    pub messages_deduplicated: u64,
+    /// Messages dropped because their aircraft was over its rate limit.
+    pub messages_rate_limited: u64,
+    /// Messages dropped because their aircraft was outside the geofence or the altitude and
+    /// speed bands.
+    pub messages_filtered: u64,
+    /// Messages left out of the sample.
+    pub messages_sampled_out: u64,
+    /// Critical messages and events sent on the priority lane instead of in batches.
+    pub messages_prioritized: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lines={} repaired={} garbled={} truncated={} skipped={} frames={} frames_skipped={} parsed={} sanitized={} rejected={} deduplicated={} rate_limited={} filtered={} sampled_out={} prioritized={}",
+            self.lines_read,
+            self.lines_repaired,
+            self.lines_garbled,
+            self.lines_truncated,
+            self.lines_skipped,
+            self.frames_read,
+            self.frames_skipped,
+            self.messages_parsed,
+            self.messages_sanitized,
+            self.messages_rejected,
+            self.messages_deduplicated,
+            self.messages_rate_limited,
+            self.messages_filtered,
+            self.messages_sampled_out,
+            self.messages_prioritized
+        )
+    }
+}
+
+/// Counters describing what a sink has uploaded since startup, so that operators can
+/// predict ingest costs and spot failures that would otherwise go unnoticed.
+#[derive(Debug)]
+pub struct UploadStats {
+    /// Events accepted by the destination.
+    pub events: AtomicU64,
+    /// Bytes of those events before compression.
+    pub bytes: AtomicU64,
+    /// Bytes actually posted, after compression.
+    pub compressed_bytes: AtomicU64,
+    /// Requests that failed for good.
+    pub failures: AtomicU64,
+    /// Events lost in those requests.
+    pub failed_events: AtomicU64,
+    /// Batches that had to wait for the destination to take more, holding up the ones
+    /// behind them, and how long they waited in milliseconds.
+    pub waits: AtomicU64,
+    pub wait_ms: AtomicU64,
+    started: Instant,
+}
+
+impl Default for UploadStats {
+    fn default() -> Self {
+        UploadStats {
+            events: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            compressed_bytes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            failed_events: AtomicU64::new(0),
+            waits: AtomicU64::new(0),
+            wait_ms: AtomicU64::new(0),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl UploadStats {
+    /// Records a successful request.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - How many events it carried.
+    /// * `bytes` - Its size before compression.
+    /// * `compressed_bytes` - Its size as posted.
+    pub fn sent(&self, events: usize, bytes: usize, compressed_bytes: usize) {
+        self.events.fetch_add(events as u64, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.compressed_bytes.fetch_add(compressed_bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records a request that failed for good, returning how many have failed so far.
+    pub fn failed(&self, events: usize) -> u64 {
+        self.failed_events.fetch_add(events as u64, Ordering::Relaxed);
+        self.failures.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Records that a batch had to wait before it could be sent.
+    pub fn waited(&self, wait: Duration) {
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.wait_ms.fetch_add(wait.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for UploadStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        // Extrapolate the volume so far to a day, which is what ingest is usually billed by.
+        let elapsed = self.started.elapsed().as_secs_f64().max(1.0);
+        let per_day = bytes as f64 / elapsed * 86_400.0;
+        write!(
+            f,
+            "events={} bytes={} compressed={} failures={} failed_events={} waits={} wait_secs={:.1} est_per_day={:.1}MB",
+            self.events.load(Ordering::Relaxed),
+            bytes,
+            self.compressed_bytes.load(Ordering::Relaxed),
+            self.failures.load(Ordering::Relaxed),
+            self.failed_events.load(Ordering::Relaxed),
+            self.waits.load(Ordering::Relaxed),
+            self.wait_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+            per_day / 1e6
+        )
+    }
+}
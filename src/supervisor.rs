@@ -0,0 +1,134 @@
+//! This module watches the stages of the pipeline for stalls, so that a feed connection that
+//! has silently died, a decoder that has lost sync, or a sink request that never returns is
+//! noticed and the pipeline restarted, without relying on an external process manager.
+//!
+//! Each stage keeps a clock that runs while it has work it has not made progress on; a
+//! stage whose clock passes the timeout has stalled. The pipeline is restarted by running
+//! the program again in place of the current process, with the same arguments and
+//! environment, so that it reconnects and starts over with fresh state.
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A stage of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reads lines or frames from the feed.
+    Reader,
+    /// Turns what was read into messages.
+    Parser,
+    /// Sends batches to the sinks.
+    Sender,
+}
+
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::Reader, Stage::Parser, Stage::Sender];
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Reader => "reader",
+            Stage::Parser => "parser",
+            Stage::Sender => "sender",
+        })
+    }
+}
+
+/// Keeps track of the progress of each stage.
+#[derive(Debug)]
+pub struct Supervisor {
+    /// How long a stage may go without progress before it counts as stalled.
+    timeout: Duration,
+    started: Instant,
+    /// When each stage last made progress while busy, in milliseconds since `started` plus
+    /// one, or 0 while it is idle.
+    since: [AtomicU64; 3],
+    /// Set once a stall has been found and the pipeline is being restarted.
+    restarting: AtomicBool,
+}
+
+impl Supervisor {
+    /// Creates a supervisor with every stage idle.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a stage may go without progress before it counts as stalled.
+    pub fn new(timeout: Duration) -> Self {
+        Supervisor { timeout, started: Instant::now(), since: Default::default(), restarting: AtomicBool::new(false) }
+    }
+
+    /// Returns how long a stage may go without progress before it counts as stalled.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64 + 1
+    }
+
+    /// Records that a stage has work, starting its clock unless it is already running.
+    pub fn busy(&self, stage: Stage) {
+        let _ = self.since[stage as usize].compare_exchange(0, self.now(), Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// Records that a stage made progress and has more work, restarting its clock.
+    pub fn progressed(&self, stage: Stage) {
+        self.since[stage as usize].store(self.now(), Ordering::Relaxed);
+    }
+
+    /// Records that a stage has finished its work, stopping its clock.
+    pub fn done(&self, stage: Stage) {
+        self.since[stage as usize].store(0, Ordering::Relaxed);
+    }
+
+    /// Returns the first stage that has stalled, if any, and how long it has gone without
+    /// progress.
+    pub fn stalled(&self) -> Option<(Stage, Duration)> {
+        let now = self.now();
+        Stage::ALL.into_iter().find_map(|stage| {
+            let since = self.since[stage as usize].load(Ordering::Relaxed);
+            let stalled = Duration::from_millis(now.saturating_sub(since));
+            (since != 0 && stalled >= self.timeout).then_some((stage, stalled))
+        })
+    }
+
+    /// Records that the pipeline is being restarted.
+    pub fn set_restarting(&self) {
+        self.restarting.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the pipeline is being restarted.
+    pub fn restarting(&self) -> bool {
+        self.restarting.load(Ordering::Relaxed)
+    }
+}
+
+/// Replaces the process with a fresh run of the same program, with the same arguments and
+/// environment.
+///
+/// # Returns
+///
+/// The error, as this only returns if the program could not be run.
+#[cfg(unix)]
+pub fn restart() -> io::Error {
+    use std::os::unix::process::CommandExt;
+
+    match std::env::current_exe() {
+        Ok(program) => std::process::Command::new(program).args(std::env::args_os().skip(1)).exec(),
+        Err(err) => err,
+    }
+}
+
+/// Replaces the process with a fresh run of the same program, which is only supported on
+/// Unix.
+///
+/// # Returns
+///
+/// The error.
+#[cfg(not(unix))]
+pub fn restart() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "restarting in place is only supported on Unix")
+}
@@ -0,0 +1,174 @@
+//! This module keeps the latest known state of every aircraft in view, updated from each
+//! parsed message, so that what an aircraft sent in earlier messages is at hand: for the
+//! aircraft API and WebSocket sinks, for reporting aircraft that have been lost, and to fill
+//! in what a message leaves out, e.g. the callsign of a position message.
+//!
+//! Aircraft are forgotten once they have not been heard from for the expiry time.
+
+use crate::parse::{Icao24, SBS1Message};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often aircraft are checked for having expired.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What is known about an aircraft.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Aircraft {
+    pub callsign: Option<String>,
+    pub altitude: Option<i32>,
+    pub ground_speed: Option<f32>,
+    pub track: Option<f32>,
+    pub vertical_rate: Option<i32>,
+    pub squawk: Option<i32>,
+    pub on_ground: Option<bool>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    /// When the aircraft was last heard and last reported a position, in nanoseconds since
+    /// the UNIX epoch.
+    pub last_seen: i64,
+    pub last_position: Option<i64>,
+    pub messages: u64,
+}
+
+impl Aircraft {
+    /// Takes in what a message from the aircraft reports, keeping what it leaves out.
+    fn update(&mut self, message: &SBS1Message) {
+        let timestamp: i64 = message.timestamp.parse().unwrap_or_default();
+        self.last_seen = self.last_seen.max(timestamp);
+        self.messages += 1;
+        if message.callsign.is_some() {
+            self.callsign = message.callsign.clone();
+        }
+        if message.altitude_baro.is_some() {
+            self.altitude = message.altitude_baro;
+        }
+        if message.ground_speed.is_some() {
+            self.ground_speed = message.ground_speed;
+        }
+        if message.track.is_some() {
+            self.track = message.track;
+        }
+        if message.vertical_rate.is_some() {
+            self.vertical_rate = message.vertical_rate;
+        }
+        if message.squawk.is_some() {
+            self.squawk = message.squawk;
+        }
+        if message.on_ground.is_some() {
+            self.on_ground = message.on_ground;
+        }
+        if let (Some(lat), Some(lon)) = (message.lat, message.lon) {
+            self.lat = Some(lat);
+            self.lon = Some(lon);
+            self.last_position = Some(timestamp);
+        }
+    }
+
+    /// Returns the last known position as (lon, lat), if any.
+    pub fn position(&self) -> Option<(f64, f64)> {
+        self.lon.zip(self.lat)
+    }
+}
+
+/// The aircraft table, with when it was last checked for expired aircraft.
+struct Table {
+    aircraft: HashMap<Icao24, Aircraft>,
+    checked: Instant,
+}
+
+/// Keeps the latest state of each aircraft, shared by the pipeline that updates it and the
+/// sinks that read it.
+pub struct Tracker {
+    table: Mutex<Table>,
+    /// How long an aircraft stays in the table after it was last heard.
+    expire: Duration,
+}
+
+impl Tracker {
+    /// Creates an empty tracker.
+    ///
+    /// # Arguments
+    ///
+    /// * `expire` - How long an aircraft stays in the table after it was last heard.
+    pub fn new(expire: Duration) -> Self {
+        Tracker { table: Mutex::new(Table { aircraft: HashMap::new(), checked: Instant::now() }), expire }
+    }
+
+    /// Updates the state of the aircraft a message is from, and forgets the aircraft that
+    /// have expired, at most once per check interval.
+    pub fn update(&self, message: &SBS1Message) {
+        let mut table = self.table.lock().unwrap();
+        if let Some(icao24) = message.icao24 {
+            table.aircraft.entry(icao24).or_default().update(message);
+        }
+        if table.checked.elapsed() >= EXPIRE_INTERVAL {
+            table.checked = Instant::now();
+            let oldest = now() - self.expire.as_nanos() as i64;
+            table.aircraft.retain(|_, aircraft| aircraft.last_seen >= oldest);
+        }
+    }
+
+    /// Fills in the callsign, squawk and position a message leaves out from what its aircraft
+    /// last reported, and lists the fields filled in in `enriched`.
+    pub fn enrich(&self, message: &mut SBS1Message) {
+        let table = self.table.lock().unwrap();
+        let Some(aircraft) = message.icao24.and_then(|icao24| table.aircraft.get(&icao24)) else {
+            return;
+        };
+        if message.callsign.is_none() && aircraft.callsign.is_some() {
+            message.callsign = aircraft.callsign.clone();
+            message.enriched.push("callsign".to_string());
+        }
+        if message.squawk.is_none() && aircraft.squawk.is_some() {
+            message.squawk = aircraft.squawk;
+            message.enriched.push("squawk".to_string());
+        }
+        if let (None, None, Some((lon, lat))) = (message.lat, message.lon, aircraft.position()) {
+            (message.lat, message.lon) = (Some(lat), Some(lon));
+            message.enriched.extend(["lat".to_string(), "lon".to_string()]);
+        }
+    }
+
+    /// Returns what is known about an aircraft, if it is in the table.
+    pub fn get(&self, icao24: Icao24) -> Option<Aircraft> {
+        self.table.lock().unwrap().aircraft.get(&icao24).cloned()
+    }
+
+    /// Returns every aircraft heard from within `max_age`, ordered by address.
+    pub fn seen_within(&self, max_age: Duration) -> Vec<(Icao24, Aircraft)> {
+        let oldest = now() - max_age.as_nanos() as i64;
+        let table = self.table.lock().unwrap();
+        let mut aircraft: Vec<(Icao24, Aircraft)> = table
+            .aircraft
+            .iter()
+            .filter(|(_, aircraft)| aircraft.last_seen >= oldest)
+            .map(|(icao24, aircraft)| (*icao24, aircraft.clone()))
+            .collect();
+        aircraft.sort_by_key(|(icao24, _)| *icao24);
+        aircraft
+    }
+
+    /// Returns the addresses of the aircraft that have not been heard from for `unheard`.
+    pub fn unheard_for(&self, unheard: Duration) -> Vec<Icao24> {
+        let latest = now() - unheard.as_nanos() as i64;
+        let table = self.table.lock().unwrap();
+        table.aircraft.iter().filter(|(_, aircraft)| aircraft.last_seen < latest).map(|(icao24, _)| *icao24).collect()
+    }
+
+    /// Returns the number of aircraft in the table.
+    pub fn len(&self) -> usize {
+        self.table.lock().unwrap().aircraft.len()
+    }
+
+    /// Returns whether the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Returns the current time in nanoseconds since the UNIX epoch, like message timestamps.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as i64
+}
@@ -0,0 +1,80 @@
+//! This module checks parsed messages for physically impossible values.
+
+use crate::parse::SBS1Message;
+use std::str::FromStr;
+
+/// Highest altitude, in feet, accepted as plausible.
+pub const MAX_ALTITUDE_FT: i32 = 60_000;
+
+/// Lowest altitude, in feet, accepted as plausible: a little below the lowest airports
+/// and the lowest altitude Mode C can encode (-1,200 ft).
+pub const MIN_ALTITUDE_FT: i32 = -1_500;
+
+/// Highest ground speed, in knots, accepted as plausible.
+pub const MAX_GROUND_SPEED_KT: f32 = 1_200.0;
+
+/// What to do with a message that holds impossible values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationMode {
+    /// Forward every message untouched.
+    Off,
+    /// Clear the impossible fields and list them in `invalid_fields`.
+    Sanitize,
+    /// Drop the whole message.
+    Reject,
+}
+
+impl FromStr for ValidationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(ValidationMode::Off),
+            "sanitize" => Ok(ValidationMode::Sanitize),
+            "reject" => Ok(ValidationMode::Reject),
+            _ => Err(format!("unknown validation mode {:?} (expected off, sanitize or reject)", s)),
+        }
+    }
+}
+
+/// Returns the names of the fields of `msg` that hold impossible values.
+pub fn check(msg: &SBS1Message) -> Vec<&'static str> {
+    let mut invalid = Vec::new();
+    if msg.lat.is_some_and(|lat| !(-90.0..=90.0).contains(&lat)) {
+        invalid.push("lat");
+    }
+    if msg.lon.is_some_and(|lon| !(-180.0..=180.0).contains(&lon)) {
+        invalid.push("lon");
+    }
+    if msg.altitude_baro.is_some_and(|altitude| !(MIN_ALTITUDE_FT..=MAX_ALTITUDE_FT).contains(&altitude)) {
+        invalid.push("altitude_baro");
+    }
+    if msg.altitude_geom.is_some_and(|altitude| !(MIN_ALTITUDE_FT..=MAX_ALTITUDE_FT).contains(&altitude)) {
+        invalid.push("altitude_geom");
+    }
+    if msg.ground_speed.is_some_and(|speed| !(0.0..=MAX_GROUND_SPEED_KT).contains(&speed)) {
+        invalid.push("ground_speed");
+    }
+    invalid
+}
+
+/// Clears the impossible fields of `msg` and records their names in `invalid_fields`.
+///
+/// # Returns
+///
+/// `true` if any field was cleared.
+pub fn sanitize(msg: &mut SBS1Message) -> bool {
+    let invalid = check(msg);
+    for &field in invalid.iter() {
+        match field {
+            "lat" => msg.lat = None,
+            "lon" => msg.lon = None,
+            "altitude_baro" => msg.altitude_baro = None,
+            "altitude_geom" => msg.altitude_geom = None,
+            "ground_speed" => msg.ground_speed = None,
+            _ => {}
+        }
+    }
+    msg.invalid_fields.extend(invalid.iter().map(|field| field.to_string()));
+    !invalid.is_empty()
+}
@@ -0,0 +1,99 @@
+use adsb::modes::{crc, Decoder, Frame};
+use adsb::parse::parse;
+use adsb::validate;
+
+const ADDRESS: u32 = 0x4840D6;
+
+/// Builds a DF17 frame from `ADDRESS` carrying `me`, with valid parity.
+fn extended_squitter(me: u64) -> Frame {
+    let mut data = vec![0x8D, (ADDRESS >> 16) as u8, (ADDRESS >> 8) as u8, ADDRESS as u8];
+    data.extend_from_slice(&me.to_be_bytes()[1..]);
+    data.extend_from_slice(&[0, 0, 0]);
+    let parity = crc::residual(&data);
+    data[11..].copy_from_slice(&parity.to_be_bytes()[1..]);
+    Frame { data, mlat_timestamp: None, signal: None }
+}
+
+/// Builds a DF17 airborne position (type code 11) with the given 12-bit altitude code.
+fn airborne_position(ac12: u64) -> Frame {
+    extended_squitter((11 << 51) | (ac12 << 36))
+}
+
+/// Builds a DF4 altitude reply from `ADDRESS` with the given 13-bit altitude code.
+fn altitude_reply(ac13: u32) -> Frame {
+    let mut data = vec![0x20, 0x00, (ac13 >> 8) as u8, ac13 as u8, 0, 0, 0];
+    let parity = crc::residual(&data) ^ ADDRESS;
+    data[4..].copy_from_slice(&parity.to_be_bytes()[1..]);
+    Frame { data, mlat_timestamp: None, signal: None }
+}
+
+/// Builds a DF17 ground speed velocity message (subtype 1) with the given vertical rate
+/// sign and raw rate field.
+fn velocity(descending: bool, rate: u64) -> Frame {
+    let speed = (1 << 10) | 101;
+    extended_squitter((19 << 51) | (1 << 48) | (speed << 24) | ((descending as u64) << 19) | (rate << 10))
+}
+
+fn decode(decoder: &mut Decoder, frame: Frame) -> adsb::parse::SBS1Message {
+    decoder.decode(&frame).expect("frame should decode")
+}
+
+#[test]
+fn decodes_negative_altitudes_in_25_ft_steps() {
+    let mut decoder = Decoder::new();
+    // n = 0 is -1,000 ft; each step is 25 ft. The Q bit is 0x010.
+    for (n, expected) in [(0u64, -1000), (28, -300), (39, -25), (40, 0), (1_440, 35_000)] {
+        let ac12 = ((n & 0x7F0) << 1) | 0x010 | (n & 0x0F);
+        assert_eq!(decode(&mut decoder, airborne_position(ac12)).altitude_baro, Some(expected));
+    }
+}
+
+#[test]
+fn decodes_gillham_altitudes_in_100_ft_steps() {
+    let mut decoder = Decoder::new();
+    for (ac12, expected) in [(0x080, -1200), (0x282, -400), (0x28A, -100), (0x22A, 1_500), (0x2C8, 4_900), (0x661, 35_000)] {
+        assert_eq!(decode(&mut decoder, airborne_position(ac12)).altitude_baro, Some(expected));
+    }
+    // No 100 ft digit set is not a valid Gillham code.
+    assert_eq!(decode(&mut decoder, airborne_position(0x002)).altitude_baro, None);
+}
+
+#[test]
+fn decodes_mode_c_only_altitude_replies() {
+    let mut decoder = Decoder::new();
+    // The extended squitter makes the address known so address/parity replies are accepted.
+    decode(&mut decoder, airborne_position(0x080));
+    for (ac13, expected) in [(0x0100, -1200), (0x0502, -400), (0x042A, 1_500), (0x0CA1, 35_000)] {
+        assert_eq!(decode(&mut decoder, altitude_reply(ac13)).altitude_baro, Some(expected));
+    }
+    // Q bit set in the 13-bit layout: 25 ft steps.
+    assert_eq!(decode(&mut decoder, altitude_reply(0x0010)).altitude_baro, Some(-1000));
+    // Metric altitudes (M bit) are not decoded.
+    assert_eq!(decode(&mut decoder, altitude_reply(0x0040)).altitude_baro, None);
+}
+
+#[test]
+fn decodes_vertical_rate_sign_and_scale() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decode(&mut decoder, velocity(false, 0)).vertical_rate, None);
+    assert_eq!(decode(&mut decoder, velocity(false, 1)).vertical_rate, Some(0));
+    assert_eq!(decode(&mut decoder, velocity(false, 10)).vertical_rate, Some(576));
+    assert_eq!(decode(&mut decoder, velocity(true, 10)).vertical_rate, Some(-576));
+    assert_eq!(decode(&mut decoder, velocity(true, 511)).vertical_rate, Some(-32_640));
+
+    let message = decode(&mut decoder, adsb::modes::avr::parse_frame("*8D485020994409940838175B284F;").unwrap());
+    assert_eq!(message.vertical_rate, Some(-832));
+}
+
+#[test]
+fn keeps_negative_sbs_altitudes() {
+    let line = "MSG,3,1,1,4CA2D6,1,2023/09/10,12:34:56.789,2023/09/10,12:34:56.790,,-75,,,31.7,35.5,,,0,0,0,0";
+    let mut message = parse(line).unwrap();
+    assert_eq!(message.altitude_baro, Some(-75));
+    assert!(!validate::sanitize(&mut message));
+    assert_eq!(message.altitude_baro, Some(-75));
+
+    let mut message = parse(&line.replace(",-75,", ",-2000,")).unwrap();
+    assert!(validate::sanitize(&mut message));
+    assert_eq!(message.altitude_baro, None);
+}
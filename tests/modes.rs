@@ -0,0 +1,126 @@
+use adsb::modes::{avr, beast, cpr, crc, Decoder, Frame};
+use std::io::Cursor;
+
+/// The even and odd airborne positions of KLM1023 used as the worked example in "The
+/// 1090 Megahertz Riddle".
+const EVEN: &str = "*8D40621D58C382D690C8AC2863A7;";
+const ODD: &str = "*8D40621D58C386435CC412692AD6;";
+
+fn frame(line: &str) -> Frame {
+    avr::parse_frame(line).expect("frame should parse")
+}
+
+fn assert_near(actual: (f64, f64), expected: (f64, f64)) {
+    assert!((actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4, "{:?} is not {:?}", actual, expected);
+}
+
+#[test]
+fn resolves_a_global_airborne_position() {
+    // The CPR latitude and longitude of the even and odd frames.
+    let (even, odd) = ((93_000, 51_372), (74_158, 50_194));
+    assert_near(cpr::global_airborne(even, odd, false).unwrap(), (52.2572, 3.9194));
+    assert_near(cpr::global_airborne(even, odd, true).unwrap(), (52.2658, 3.9389));
+}
+
+#[test]
+fn resolves_a_local_airborne_position() {
+    assert_near(cpr::local_airborne((52.258, 3.918), (93_000, 51_372), false), (52.2572, 3.9194));
+    assert_near(cpr::local_airborne((52.258, 3.918), (74_158, 50_194), true), (52.2658, 3.9389));
+}
+
+#[test]
+fn counts_longitude_zones() {
+    assert_eq!(cpr::nl(0.0), 59);
+    assert_eq!(cpr::nl(52.2572), 36);
+    assert_eq!(cpr::nl(-52.2572), 36);
+    assert_eq!(cpr::nl(87.0), 2);
+    assert_eq!(cpr::nl(89.0), 1);
+}
+
+#[test]
+fn decodes_positions_from_frame_pairs_and_then_locally() {
+    let mut decoder = Decoder::new();
+    // One frame alone gives no position.
+    let message = decoder.decode(&frame(ODD)).unwrap();
+    assert_eq!(message.icao24.unwrap().to_string(), "40621d");
+    assert_eq!((message.lat, message.lon), (None, None));
+    assert_eq!(message.altitude_baro, Some(38_000));
+
+    let message = decoder.decode(&frame(EVEN)).unwrap();
+    assert_near((message.lat.unwrap(), message.lon.unwrap()), (52.2572, 3.9194));
+
+    // The position just resolved is the reference for the next frame.
+    let message = decoder.decode(&frame(ODD)).unwrap();
+    assert_near((message.lat.unwrap(), message.lon.unwrap()), (52.2658, 3.9389));
+}
+
+#[test]
+fn checks_parity() {
+    for line in [EVEN, ODD, "*8D406B902015A678D4D220AA4BDA;"] {
+        assert_eq!(crc::residual(&frame(line).data), 0);
+    }
+    // An all-call reply leaves at most the interrogator code.
+    assert_eq!(crc::residual(&frame("*5D484FDEA248F5;").data), 0x16);
+    // Address/parity replies leave the address.
+    for (line, address) in [
+        ("*A0001839CA3800315800007448D9;", 0x400940),
+        ("*A000139381951536E024D4CCF6B5;", 0x3C4DD2),
+        ("*A000029CFFBAA11E2004727281F1;", 0x4243D0),
+    ] {
+        assert_eq!(crc::residual(&frame(line).data), address);
+    }
+    // A flipped bit breaks the parity, and the decoder drops the frame.
+    let mut corrupt = frame(EVEN);
+    corrupt.data[6] ^= 0x01;
+    assert_ne!(crc::residual(&corrupt.data), 0);
+    assert!(Decoder::new().decode(&corrupt).is_none());
+}
+
+#[test]
+fn rejects_all_call_replies_with_a_bad_parity() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(&frame("*5D484FDEA248F5;")).unwrap().transmission_type, Some(8));
+    let mut corrupt = frame("*5D484FDEA248F5;");
+    corrupt.data[3] ^= 0x80;
+    assert!(decoder.decode(&corrupt).is_none());
+}
+
+#[test]
+fn round_trips_beast_frames() {
+    let frames = [
+        Frame { data: frame(EVEN).data, mlat_timestamp: Some(0x1A1A_0000_1A01), signal: Some(0x1A) },
+        // Every byte of the message is the escape byte.
+        Frame { data: vec![0x1A; 7], mlat_timestamp: Some(0), signal: Some(200) },
+        Frame { data: vec![0x1A, 0x2B], mlat_timestamp: Some(0xFFFF_FFFF_FFFF), signal: Some(0) },
+    ];
+    let mut stream = Vec::new();
+    for frame in &frames {
+        let encoded = beast::encode_frame(frame).unwrap();
+        assert!(encoded.len() > 2 + 7 + frame.data.len());
+        stream.extend(encoded);
+    }
+
+    let mut reader = Cursor::new(stream);
+    for frame in &frames {
+        assert_eq!(beast::read_frame(&mut reader).unwrap().as_ref(), Some(frame));
+    }
+    assert_eq!(beast::read_frame(&mut reader).unwrap(), None);
+}
+
+#[test]
+fn resynchronizes_after_a_broken_beast_frame() {
+    let whole = Frame { data: frame(ODD).data, mlat_timestamp: Some(12), signal: Some(34) };
+    // Noise, then a frame cut short by the start of the next one.
+    let mut stream = vec![0x00, 0x42];
+    stream.extend(&beast::encode_frame(&whole).unwrap()[..10]);
+    stream.extend(beast::encode_frame(&whole).unwrap());
+
+    let mut reader = Cursor::new(stream);
+    assert_eq!(beast::read_frame(&mut reader).unwrap(), Some(whole));
+    assert_eq!(beast::read_frame(&mut reader).unwrap(), None);
+}
+
+#[test]
+fn refuses_to_encode_frames_of_invalid_length() {
+    assert_eq!(beast::encode_frame(&Frame { data: vec![0; 5], mlat_timestamp: None, signal: None }), None);
+}
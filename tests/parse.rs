@@ -0,0 +1,126 @@
+use adsb::parse::{parse, parse_lenient, ParseError};
+use serde_json::{json, Value};
+
+/// Builds a position (`MSG,3`) line with the given latitude and longitude columns.
+fn position_line(lat: &str, lon: &str) -> String {
+    format!(
+        "MSG,3,1,1,4CA2D6,1,2023/09/10,12:34:56.789,2023/09/10,12:34:56.790,,35000,,,{},{},,,0,0,0,0",
+        lat, lon
+    )
+}
+
+/// Builds a position line with one column replaced.
+fn with_column(column: usize, value: &str) -> String {
+    let line = position_line("53.12345", "-6.54321");
+    let mut columns: Vec<&str> = line.split(',').collect();
+    columns[column] = value;
+    columns.join(",")
+}
+
+#[test]
+fn position_round_trips_exactly() {
+    for (lat, lon) in [
+        ("53.12345", "-6.54321"),
+        ("51.470022", "-0.454296"),
+        ("-33.946110", "151.177222"),
+        ("40.63980", "-73.77890"),
+        ("0.00001", "179.99999"),
+    ] {
+        let message = parse(&position_line(lat, lon)).unwrap();
+
+        assert_eq!(message.lat, Some(lat.parse::<f64>().unwrap()));
+        assert_eq!(message.lon, Some(lon.parse::<f64>().unwrap()));
+    }
+}
+
+#[test]
+fn position_serializes_without_rounding_noise() {
+    let message = parse(&position_line("53.12345", "-6.54321")).unwrap();
+    let serialized: Value = serde_json::to_value(&message).unwrap();
+
+    assert_eq!(serialized["lat"], json!(53.12345));
+    assert_eq!(serialized["lon"], json!(-6.54321));
+    assert_eq!(serde_json::to_string(&serialized["lat"]).unwrap(), "53.12345");
+    assert_eq!(serde_json::to_string(&serialized["lon"]).unwrap(), "-6.54321");
+}
+
+#[test]
+fn rejects_unknown_message_classes() {
+    for line in ["SEL,,496,2286,4CA4E5,27215,2010/02/19,18:06:07.710,2010/02/19,18:06:07.710,RYR1427", "AIR,,1,1,4CA2D6,1", ""] {
+        let class = line.split(',').next().unwrap().to_string();
+        let error = ParseError::UnknownMessageClass(class);
+        assert_eq!(parse(line).unwrap_err(), error);
+        assert_eq!(parse_lenient(line).unwrap_err(), error);
+        assert_eq!(error.column(), Some(0));
+    }
+    // mlat-client's class is understood.
+    assert!(parse(&position_line("53.12345", "-6.54321").replacen("MSG", "MLAT", 1)).unwrap().mlat);
+}
+
+#[test]
+fn rejects_short_lines_only_when_strict() {
+    let line = "MSG,8,1,1,4CA2D6,1,2023/09/10,12:34:56.789,2023/09/10,12:34:56.790";
+    assert_eq!(parse(line).unwrap_err(), ParseError::FieldCount { expected: 22, found: 10 });
+    assert_eq!(ParseError::FieldCount { expected: 22, found: 10 }.column(), None);
+
+    let message = parse_lenient(line).unwrap();
+    assert_eq!(message.icao24.unwrap().to_string(), "4ca2d6");
+    assert_eq!((message.transmission_type, message.altitude_baro, message.on_ground), (Some(8), None, None));
+}
+
+#[test]
+fn rejects_bad_numbers_only_when_strict() {
+    for (column, value) in [(1, "x"), (11, "35k"), (12, "fast"), (14, "53.1.2"), (17, "7700a"), (21, "yes")] {
+        let line = with_column(column, value);
+        let error = parse(&line).err().unwrap_or_else(|| panic!("{:?} should not parse strictly", line));
+        assert_eq!(error, ParseError::BadNumber { column, value: value.to_string() });
+        assert_eq!(error.column(), Some(column));
+        assert!(parse_lenient(&line).is_ok(), "{}", line);
+    }
+    let message = parse_lenient(&with_column(11, "high")).unwrap();
+    assert_eq!((message.altitude_baro, message.lat), (None, Some(53.12345)));
+}
+
+#[test]
+fn rejects_bad_dates_only_when_strict() {
+    for (column, value) in [(6, "2023-09-10"), (7, "noon"), (8, "2023/13/10"), (9, "25:00:00.000")] {
+        let line = with_column(column, value);
+        let date_column = column - (column % 2);
+        let Err(ParseError::BadDate { column: found, value: combined }) = parse(&line) else {
+            panic!("{:?} should have a bad date", line);
+        };
+        assert_eq!(found, date_column, "{}", line);
+        assert!(combined.contains(value), "{:?} does not contain {:?}", combined, value);
+        let message = parse_lenient(&line).unwrap();
+        assert_eq!(message.generated_date.is_none(), date_column == 6, "{}", line);
+        assert_eq!(message.logged_date.is_none(), date_column == 8, "{}", line);
+    }
+}
+
+#[test]
+fn rejects_bad_addresses_even_when_lenient() {
+    for value in ["4CA2D", "4CA2D6F", "4CA2G6", "~~4CA2D6"] {
+        let line = with_column(4, value);
+        let error = ParseError::BadIcao24 { column: 4, value: value.to_string() };
+        assert_eq!(parse(&line).unwrap_err(), error);
+        assert_eq!(parse_lenient(&line).unwrap_err(), error);
+    }
+    assert_eq!(parse(&with_column(4, "~4ca2d6")).unwrap().icao24.unwrap().to_string(), "~4ca2d6");
+    assert_eq!(parse(&with_column(4, "")).unwrap().icao24, None);
+}
+
+#[test]
+fn keeps_extra_columns_whether_strict_or_not() {
+    let line = format!("{},MLAT,,extra", position_line("53.12345", "-6.54321"));
+    for message in [parse(&line).unwrap(), parse_lenient(&line).unwrap()] {
+        assert!(message.mlat);
+        assert_eq!(message.extras.into_iter().collect::<Vec<_>>(), [("col24".to_string(), "extra".to_string())]);
+    }
+    // Extra columns are not checked, so even strictly they may hold anything.
+    let line = format!("{},not a number", position_line("53.12345", "-6.54321"));
+    assert_eq!(parse(&line).unwrap().extras.get("col22").map(String::as_str), Some("not a number"));
+
+    // A short line has no extra columns, even if its last column says MLAT.
+    let message = parse_lenient("MSG,3,1,1,4CA2D6,1,MLAT").unwrap();
+    assert!(!message.mlat && message.extras.is_empty());
+}